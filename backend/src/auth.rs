@@ -1,92 +1,376 @@
 // I am importing the necessary crates for password hashing, time handling, JWT, and concurrency
-use bcrypt::{hash, verify, DEFAULT_COST};
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Request, State},
+    http::{header::AUTHORIZATION, request::Parts, HeaderMap},
+    middleware::Next,
+    response::Response,
+};
 use chrono::{Duration, Utc};
 use dashmap::DashMap;
 use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use std::collections::{HashSet, VecDeque};
+use std::net::IpAddr;
 use std::sync::Arc;
 use uuid::Uuid;
 
 // I am importing my own error and model types
 use crate::{
     error::{AppError, Result},
-    models::{Claims, User, UserResponse},
+    handlers::AppState,
+    ids::{IdGenerator, RandomIdGenerator},
+    models::{AuthResponse, Claims, LoginEvent, User, UserResponse, UserRole},
+    password::{verify_password, PasswordHashing, PasswordPolicy},
 };
 
+// I am defining the extractor that protected handlers take to require a valid bearer token
+#[derive(Debug, Clone)]
+pub struct AuthenticatedUser {
+    pub user_id: Uuid,
+    pub claims: Claims,
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for AuthenticatedUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self> {
+        let claims = state.auth_service.validate_token(bearer_token(&parts.headers)?)?;
+        let user_id = Uuid::parse_str(&claims.sub)
+            .map_err(|_| AppError::AuthError("Invalid token subject".to_string()))?;
+
+        Ok(Self { user_id, claims })
+    }
+}
+
+// I am defining the extractor for routes that also serve anonymous callers. Without an Authorization header the
+// caller is anonymous, but a header whose token is invalid or expired is refused rather than ignored
+#[derive(Debug, Clone)]
+pub struct OptionalUser(pub Option<AuthenticatedUser>);
+
+#[async_trait]
+impl FromRequestParts<AppState> for OptionalUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self> {
+        if !parts.headers.contains_key(AUTHORIZATION) {
+            return Ok(Self(None));
+        }
+
+        AuthenticatedUser::from_request_parts(parts, state).await.map(|user| Self(Some(user)))
+    }
+}
+
+// I am extracting the bearer token from the Authorization header
+pub fn bearer_token(headers: &HeaderMap) -> Result<&str> {
+    let auth_header = headers
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .ok_or_else(|| AppError::AuthError("Missing Authorization header".to_string()))?;
+
+    auth_header
+        .strip_prefix("Bearer ")
+        .ok_or_else(|| AppError::AuthError("Invalid Authorization header format".to_string()))
+}
+
+// I am defining the role a route requires, for use with the require_role middleware
+#[derive(Debug, Clone, Copy)]
+pub struct RequireRole(pub UserRole);
+
+// I am rejecting requests whose token doesn't carry the role the route requires
+pub async fn require_role(
+    State((auth_service, RequireRole(required))): State<(Arc<AuthService>, RequireRole)>,
+    request: Request,
+    next: Next,
+) -> Result<Response> {
+    let claims = auth_service.validate_token(bearer_token(request.headers())?)?;
+
+    if !claims.role.satisfies(required) {
+        tracing::warn!("User {} lacks the {:?} role required for {}", claims.sub, required, request.uri());
+        return Err(AppError::AuthzError("You do not have permission to perform this action".to_string()));
+    }
+
+    Ok(next.run(request).await)
+}
+
+// I am reading the comma-separated emails that are made admins when they register
+fn admin_emails_from_env() -> HashSet<String> {
+    std::env::var("ADMIN_EMAILS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|email| email.trim().to_lowercase())
+        .filter(|email| !email.is_empty())
+        .collect()
+}
+
+// Domains whose mailboxes ignore dots and "+tag" suffixes in the local part
+const GMAIL_DOMAINS: &[&str] = &["gmail.com", "googlemail.com"];
+
+// I am normalizing an email for storage and lookup so accounts are case-insensitive; with `canonicalize_gmail`,
+// Gmail addresses also drop dots and "+tag" suffixes, since those all reach the same inbox
+pub fn normalize_email(email: &str, canonicalize_gmail: bool) -> String {
+    let email = email.trim().to_lowercase();
+    if !canonicalize_gmail {
+        return email;
+    }
+
+    let canonical = email
+        .rsplit_once('@')
+        .filter(|(_, domain)| GMAIL_DOMAINS.contains(domain))
+        .map(|(local, _)| local.split('+').next().unwrap_or_default().replace('.', ""))
+        .filter(|local| !local.is_empty());
+    match canonical {
+        Some(local) => format!("{}@gmail.com", local),
+        None => email,
+    }
+}
+
+// Only used outside production, where tokens signed with it are worthless anyway
+const DEFAULT_JWT_SECRET: &str = "your-secret-key-change-this-in-production";
+const MIN_JWT_SECRET_BYTES: usize = 32;
+
+// I am resolving the JWT signing secret, refusing a missing or short one when APP_ENV=production
+fn jwt_secret_from_env() -> Result<String> {
+    let production = std::env::var("APP_ENV").is_ok_and(|env| env.eq_ignore_ascii_case("production"));
+    resolve_jwt_secret(std::env::var("JWT_SECRET").ok(), production)
+}
+
+fn resolve_jwt_secret(secret: Option<String>, production: bool) -> Result<String> {
+    match secret.filter(|secret| !secret.is_empty()) {
+        Some(secret) if secret.len() >= MIN_JWT_SECRET_BYTES => Ok(secret),
+        Some(_) if production => Err(AppError::ConfigError(format!(
+            "JWT_SECRET must be at least {} bytes when APP_ENV=production",
+            MIN_JWT_SECRET_BYTES
+        ))),
+        Some(secret) => {
+            tracing::warn!("JWT_SECRET is shorter than {} bytes; use a longer secret in production", MIN_JWT_SECRET_BYTES);
+            Ok(secret)
+        }
+        None if production => Err(AppError::ConfigError(
+            "JWT_SECRET must be set when APP_ENV=production".to_string(),
+        )),
+        None => {
+            tracing::warn!("JWT_SECRET is not set; signing tokens with the insecure built-in default. Never run like this in production");
+            Ok(DEFAULT_JWT_SECRET.to_string())
+        }
+    }
+}
+
+const JWT_EXPIRATION_HOURS_RANGE: std::ops::RangeInclusive<i64> = 1..=720;
+
+// I am reading how long access tokens stay valid, defaulting to 24 hours
+fn jwt_expiration_hours_from_env() -> Result<i64> {
+    parse_jwt_expiration_hours(std::env::var("JWT_EXPIRATION_HOURS").ok().as_deref())
+}
+
+fn parse_jwt_expiration_hours(value: Option<&str>) -> Result<i64> {
+    let Some(value) = value else {
+        return Ok(24);
+    };
+
+    value
+        .trim()
+        .parse()
+        .ok()
+        .filter(|hours| JWT_EXPIRATION_HOURS_RANGE.contains(hours))
+        .ok_or_else(|| {
+            AppError::ConfigError(format!(
+                "JWT_EXPIRATION_HOURS must be a whole number of hours between {} and {}, got {:?}",
+                JWT_EXPIRATION_HOURS_RANGE.start(),
+                JWT_EXPIRATION_HOURS_RANGE.end(),
+                value
+            ))
+        })
+}
+
+// I am defining a stored refresh token, which lets a client mint new access tokens without a password
+#[derive(Debug, Clone)]
+struct RefreshTokenEntry {
+    user_id: Uuid,
+    expires_at: chrono::DateTime<Utc>,
+}
+
+// Logins kept per user for GET /auth/me/logins; older ones are dropped
+const MAX_LOGIN_HISTORY: usize = 20;
+
 // I am defining the authentication service, which manages users and JWTs
 #[derive(Clone)]
 pub struct AuthService {
     // In production, this would be a proper database
     users: Arc<DashMap<String, User>>, // email -> User
+    refresh_tokens: Arc<DashMap<String, RefreshTokenEntry>>, // refresh token -> owner and expiry
+    revoked_tokens: Arc<DashMap<String, usize>>, // jti -> token expiry, kept until the token would expire anyway
+    reset_tokens: Arc<DashMap<String, (String, usize)>>, // password reset token -> (email, expiry)
+    verification_tokens: Arc<DashMap<String, (String, usize)>>, // email verification token -> (email, expiry)
+    login_history: Arc<DashMap<Uuid, VecDeque<LoginEvent>>>, // user id -> most recent logins, newest first
+    tokens_valid_after: Arc<DashMap<Uuid, usize>>, // user id -> access tokens issued before this second are rejected
     jwt_secret: String,
     jwt_expiration_hours: i64,
+    jwt_issuer: String,
+    jwt_audience: String,
+    refresh_expiration_days: i64,
+    reset_expiration_minutes: i64,
+    verification_expiration_hours: i64,
+    password_hashing: PasswordHashing,
+    password_policy: PasswordPolicy,
+    admin_emails: Arc<HashSet<String>>,
+    // Set by CANONICALIZE_GMAIL_ADDRESSES
+    canonicalize_gmail: bool,
+    id_generator: Arc<dyn IdGenerator>,
 }
 
 impl AuthService {
-    // I am creating a new AuthService, loading the JWT secret from the environment or using a default
-    pub fn new() -> Self {
-        // In production, load this from environment variables
-        let jwt_secret = std::env::var("JWT_SECRET")
-            .unwrap_or_else(|_| "your-secret-key-change-this-in-production".to_string());
+    // I am creating a new AuthService, failing if the JWT secret is unsafe for a production deployment
+    pub fn new(id_generator: Arc<dyn IdGenerator>) -> Result<Self> {
+        let jwt_secret = jwt_secret_from_env()?;
+        let jwt_expiration_hours = jwt_expiration_hours_from_env()?;
+        let canonicalize_gmail = std::env::var("CANONICALIZE_GMAIL_ADDRESSES")
+            .map(|v| v == "true")
+            .unwrap_or(false);
 
-        Self {
+        Ok(Self {
             users: Arc::new(DashMap::new()),
+            refresh_tokens: Arc::new(DashMap::new()),
+            revoked_tokens: Arc::new(DashMap::new()),
+            reset_tokens: Arc::new(DashMap::new()),
+            verification_tokens: Arc::new(DashMap::new()),
+            login_history: Arc::new(DashMap::new()),
+            tokens_valid_after: Arc::new(DashMap::new()),
             jwt_secret,
-            jwt_expiration_hours: 24, // 24 hours
-        }
+            jwt_expiration_hours,
+            jwt_issuer: std::env::var("JWT_ISSUER").unwrap_or_else(|_| "quickscan".to_string()),
+            jwt_audience: std::env::var("JWT_AUDIENCE").unwrap_or_else(|_| "quickscan-api".to_string()),
+            refresh_expiration_days: 30,
+            reset_expiration_minutes: 30,
+            verification_expiration_hours: 24,
+            password_hashing: PasswordHashing::from_env()?,
+            password_policy: PasswordPolicy::from_env()?,
+            admin_emails: Arc::new(
+                admin_emails_from_env()
+                    .iter()
+                    .map(|email| normalize_email(email, canonicalize_gmail))
+                    .collect(),
+            ),
+            canonicalize_gmail,
+            id_generator,
+        })
+    }
+
+    pub fn password_policy(&self) -> &PasswordPolicy {
+        &self.password_policy
+    }
+
+    fn normalize_email(&self, email: &str) -> String {
+        normalize_email(email, self.canonicalize_gmail)
     }
 
-    // I am registering a new user, hashing their password and storing them in memory
-    pub async fn register_user(&self, email: String, password: String) -> Result<UserResponse> {
+    // I am registering a new, inactive user and returning the token that will activate them
+    pub async fn register_user(&self, email: String, password: String) -> Result<(UserResponse, String)> {
+        let email = self.normalize_email(&email);
+
         // Check if user already exists
         if self.users.contains_key(&email) {
             return Err(AppError::ValidationError("User already exists".to_string()));
         }
 
         // Hash password
-        let password_hash = hash(password, DEFAULT_COST)
-            .map_err(|e| AppError::InternalError(format!("Failed to hash password: {}", e)))?;
+        let password_hash = self.password_hashing.hash(&password)?;
 
         // Create user
         let user = User {
-            id: Uuid::new_v4(),
+            id: self.id_generator.new_id(),
             email: email.clone(),
             password_hash,
-            created_at: Utc::now().to_rfc3339(),
-            updated_at: Utc::now().to_rfc3339(),
-            is_active: true,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            // Accounts stay inactive until the email address is verified
+            is_active: false,
+            role: if self.admin_emails.contains(&email) { UserRole::Admin } else { UserRole::User },
+            last_login_at: None,
         };
 
         let user_response = UserResponse::from(user.clone());
         
         // Store user
-        self.users.insert(email, user);
+        self.users.insert(email.clone(), user);
 
-        Ok(user_response)
+        // Verification tokens are secrets, so they come from the OS RNG rather than the id generator
+        let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        let exp = (Utc::now() + Duration::hours(self.verification_expiration_hours)).timestamp() as usize;
+        self.verification_tokens.insert(token.clone(), (email, exp));
+
+        Ok((user_response, token))
     }
 
-    // I am authenticating a user by verifying their password
-    pub async fn authenticate_user(&self, email: String, password: String) -> Result<UserResponse> {
-        // Find user
-        let user = self
+    // I am consuming an email verification token and activating the account it belongs to
+    pub async fn verify_email(&self, token: &str) -> Result<UserResponse> {
+        let (_, (email, exp)) = self
+            .verification_tokens
+            .remove(token)
+            .ok_or_else(|| AppError::AuthError("Invalid or already used verification token".to_string()))?;
+
+        if exp < Utc::now().timestamp() as usize {
+            return Err(AppError::AuthError("Verification token has expired".to_string()));
+        }
+
+        let mut user = self
             .users
-            .get(&email)
-            .ok_or_else(|| AppError::AuthError("Invalid credentials".to_string()))?;
+            .get_mut(&email)
+            .ok_or_else(|| AppError::AuthError("Invalid or already used verification token".to_string()))?;
+        user.is_active = true;
+        user.updated_at = Utc::now();
 
-        // Verify password
-        let password_valid = verify(password, &user.password_hash)
-            .map_err(|e| AppError::InternalError(format!("Failed to verify password: {}", e)))?;
+        Ok(UserResponse::from(user.clone()))
+    }
 
-        if !password_valid {
-            return Err(AppError::AuthError("Invalid credentials".to_string()));
-        }
+    // I am authenticating a user by verifying their password, recording the login and where it came from
+    pub async fn authenticate_user(&self, email: String, password: String, source_ip: Option<IpAddr>) -> Result<UserResponse> {
+        let email = self.normalize_email(&email);
+        {
+            // Find user
+            let user = self
+                .users
+                .get(&email)
+                .ok_or_else(|| AppError::AuthError("Invalid credentials".to_string()))?;
 
-        if !user.is_active {
-            return Err(AppError::AuthError("Account is inactive".to_string()));
+            // Verify password
+            let password_valid = verify_password(&password, &user.password_hash)?;
+
+            if !password_valid {
+                return Err(AppError::AuthError("Invalid credentials".to_string()));
+            }
+
+            if !user.is_active {
+                return Err(AppError::AuthError("Account is inactive".to_string()));
+            }
         }
 
+        // The read guard is dropped first, since hashing is slow and a write guard would block the shard
+        let mut user = self
+            .users
+            .get_mut(&email)
+            .ok_or_else(|| AppError::AuthError("Invalid credentials".to_string()))?;
+        let now = Utc::now();
+        user.last_login_at = Some(now);
+        self.record_login(user.id, LoginEvent { timestamp: now, ip: source_ip });
+
         Ok(UserResponse::from(user.clone()))
     }
 
+    fn record_login(&self, user_id: Uuid, event: LoginEvent) {
+        let mut history = self.login_history.entry(user_id).or_default();
+        history.push_front(event);
+        history.truncate(MAX_LOGIN_HISTORY);
+    }
+
+    // I am listing a user's most recent logins, newest first
+    pub fn login_history(&self, user_id: Uuid) -> Vec<LoginEvent> {
+        self.login_history
+            .get(&user_id)
+            .map(|history| history.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
     // I am generating a JWT token for a user
     pub fn generate_token(&self, user: &UserResponse) -> Result<(String, String)> {
         let expiration = Utc::now() + Duration::hours(self.jwt_expiration_hours);
@@ -98,6 +382,10 @@ impl AuthService {
             email: user.email.clone(),
             exp,
             iat,
+            jti: Uuid::new_v4().to_string(),
+            iss: self.jwt_issuer.clone(),
+            aud: self.jwt_audience.clone(),
+            role: user.role,
         };
 
         let token = encode(
@@ -110,18 +398,231 @@ impl AuthService {
         Ok((token, expiration.to_rfc3339()))
     }
 
+    // I am issuing an opaque refresh token for a user, valid much longer than the access token
+    fn generate_refresh_token(&self, user_id: Uuid) -> (String, String) {
+        // Refresh tokens are secrets, so they always come from the OS RNG rather than the id generator
+        let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        let expires_at = Utc::now() + Duration::days(self.refresh_expiration_days);
+
+        self.refresh_tokens.insert(token.clone(), RefreshTokenEntry { user_id, expires_at });
+
+        (token, expires_at.to_rfc3339())
+    }
+
+    // I am issuing both an access token and a refresh token for a freshly authenticated user
+    pub fn issue_session(&self, user: UserResponse) -> Result<AuthResponse> {
+        let (token, expires_at) = self.generate_token(&user)?;
+        let (refresh_token, refresh_expires_at) = self.generate_refresh_token(user.id);
+
+        Ok(AuthResponse {
+            user,
+            token,
+            expires_at,
+            refresh_token,
+            refresh_expires_at,
+        })
+    }
+
+    // I am exchanging a refresh token for a new session, rotating the refresh token so each is single-use
+    pub async fn refresh_session(&self, refresh_token: &str) -> Result<AuthResponse> {
+        let (_, entry) = self
+            .refresh_tokens
+            .remove(refresh_token)
+            .ok_or_else(|| AppError::AuthError("Invalid refresh token".to_string()))?;
+
+        if entry.expires_at < Utc::now() {
+            return Err(AppError::AuthError("Refresh token has expired".to_string()));
+        }
+
+        let user = self
+            .get_user_by_id(&entry.user_id.to_string())
+            .await
+            .map_err(|_| AppError::AuthError("Invalid refresh token".to_string()))?;
+
+        if !user.is_active {
+            return Err(AppError::AuthError("Account is inactive".to_string()));
+        }
+
+        self.issue_session(user)
+    }
+
     // I am validating a JWT token and extracting its claims
     pub fn validate_token(&self, token: &str) -> Result<Claims> {
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.set_issuer(&[&self.jwt_issuer]);
+        validation.set_audience(&[&self.jwt_audience]);
+
         let token_data = decode::<Claims>(
             token,
             &DecodingKey::from_secret(self.jwt_secret.as_ref()),
-            &Validation::new(Algorithm::HS256),
+            &validation,
         )
         .map_err(|e| AppError::AuthError(format!("Invalid token: {}", e)))?;
 
+        if self.revoked_tokens.contains_key(&token_data.claims.jti) {
+            return Err(AppError::AuthError("Token has been revoked".to_string()));
+        }
+        let issued_too_early = Uuid::parse_str(&token_data.claims.sub)
+            .ok()
+            .and_then(|user_id| self.tokens_valid_after.get(&user_id).map(|after| token_data.claims.iat < *after))
+            .unwrap_or(false);
+        if issued_too_early {
+            return Err(AppError::AuthError("Token was issued before the password changed".to_string()));
+        }
+
         Ok(token_data.claims)
     }
 
+    // I am revoking an access token and every refresh token belonging to its user
+    pub fn logout(&self, claims: &Claims) {
+        self.revoked_tokens.insert(claims.jti.clone(), claims.exp);
+
+        if let Ok(user_id) = Uuid::parse_str(&claims.sub) {
+            self.refresh_tokens.retain(|_, entry| entry.user_id != user_id);
+        }
+    }
+
+    // I am creating a single-use password reset token, or None when no such user exists
+    pub fn create_password_reset(&self, email: &str) -> Option<String> {
+        let email = self.normalize_email(email);
+        if !self.users.contains_key(&email) {
+            return None;
+        }
+
+        // Reset tokens are secrets, so like refresh tokens they come from the OS RNG
+        let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        let exp = (Utc::now() + Duration::minutes(self.reset_expiration_minutes)).timestamp() as usize;
+        self.reset_tokens.insert(token.clone(), (email, exp));
+
+        Some(token)
+    }
+
+    // I am consuming a reset token and replacing the user's password, ending their existing sessions
+    pub async fn reset_password(&self, token: &str, new_password: String) -> Result<()> {
+        let (_, (email, exp)) = self
+            .reset_tokens
+            .remove(token)
+            .ok_or_else(|| AppError::AuthError("Invalid or already used reset token".to_string()))?;
+
+        if exp < Utc::now().timestamp() as usize {
+            return Err(AppError::AuthError("Reset token has expired".to_string()));
+        }
+
+        let password_hash = self.password_hashing.hash(&new_password)?;
+
+        let user_id = {
+            let mut user = self
+                .users
+                .get_mut(&email)
+                .ok_or_else(|| AppError::AuthError("Invalid or already used reset token".to_string()))?;
+            user.password_hash = password_hash;
+            user.updated_at = Utc::now();
+            user.id
+        };
+
+        // Anyone holding an old access or refresh token must log in again with the new password
+        self.refresh_tokens.retain(|_, entry| entry.user_id != user_id);
+        self.revoke_tokens_issued_before_now(user_id);
+        self.reset_tokens.retain(|_, (pending_email, _)| *pending_email != email);
+
+        Ok(())
+    }
+
+    // I am changing a signed-in user's password after checking the current one, then ending their sessions
+    pub async fn change_password(&self, claims: &Claims, current_password: &str, new_password: String) -> Result<()> {
+        // The hash is copied out so the slow verify and hash below don't hold a lock on the users map
+        let current_hash = self
+            .users
+            .get(&claims.email)
+            .filter(|user| user.id.to_string() == claims.sub)
+            .map(|user| user.password_hash.clone())
+            .ok_or_else(|| AppError::NotFoundError("User not found".to_string()))?;
+
+        let hashing = self.password_hashing;
+        let (current_password, checked_hash) = (current_password.to_string(), current_hash.clone());
+        let new_hash = tokio::task::spawn_blocking(move || {
+            if !verify_password(&current_password, &checked_hash)? {
+                return Err(AppError::AuthError("Current password is incorrect".to_string()));
+            }
+            if new_password == current_password {
+                return Err(AppError::ValidationError(
+                    "New password must be different from the current password".to_string(),
+                ));
+            }
+            hashing.hash(&new_password)
+        })
+        .await
+        .map_err(|e| AppError::InternalError(format!("Password hashing task failed: {}", e)))??;
+
+        let user_id = {
+            let mut user = self
+                .users
+                .get_mut(&claims.email)
+                .filter(|user| user.id.to_string() == claims.sub)
+                .ok_or_else(|| AppError::NotFoundError("User not found".to_string()))?;
+            // Another change or reset landed while this one was hashing; its password wins
+            if user.password_hash != current_hash {
+                return Err(AppError::ConflictError("The password was changed by another request".to_string()));
+            }
+            user.password_hash = new_hash;
+            user.updated_at = Utc::now();
+            user.id
+        };
+
+        // Every access and refresh token of the user stops working; they log in again with the new password
+        self.logout(claims);
+        self.revoke_tokens_issued_before_now(user_id);
+        self.reset_tokens.retain(|_, (pending_email, _)| *pending_email != claims.email);
+
+        Ok(())
+    }
+
+    // I am rejecting the user's access tokens issued before this second, wherever they are held. A token issued
+    // later in the same second still works, which is why the caller also revokes the one it was given
+    fn revoke_tokens_issued_before_now(&self, user_id: Uuid) {
+        self.tokens_valid_after.insert(user_id, Utc::now().timestamp() as usize);
+    }
+
+    // I am deleting a user's account and revoking the token they used, along with every other credential
+    pub fn delete_user(&self, claims: &Claims) -> Result<UserResponse> {
+        let (_, user) = self
+            .users
+            .remove(&claims.email)
+            .filter(|(_, user)| user.id.to_string() == claims.sub)
+            .ok_or_else(|| AppError::NotFoundError("User not found".to_string()))?;
+
+        self.logout(claims);
+        self.reset_tokens.retain(|_, (email, _)| *email != user.email);
+        self.verification_tokens.retain(|_, (email, _)| *email != user.email);
+        self.login_history.remove(&user.id);
+
+        Ok(UserResponse::from(user))
+    }
+
+    // I am dropping revoked, refresh, reset, and verification tokens that have expired, since they can no longer be used
+    pub fn prune_expired_tokens(&self) {
+        let now = Utc::now();
+        let timestamp = now.timestamp() as usize;
+        self.revoked_tokens.retain(|_, exp| *exp > timestamp);
+        self.refresh_tokens.retain(|_, entry| entry.expires_at > now);
+        self.reset_tokens.retain(|_, (_, exp)| *exp > timestamp);
+        self.verification_tokens.retain(|_, (_, exp)| *exp > timestamp);
+        // Once the longest-lived access token from before a password change has expired, the cutoff is moot
+        let oldest_live = (now - Duration::hours(self.jwt_expiration_hours)).timestamp() as usize;
+        self.tokens_valid_after.retain(|_, after| *after > oldest_live);
+    }
+
+    // I am listing every registered user, oldest first so pages stay stable
+    pub fn list_users(&self) -> Vec<UserResponse> {
+        let mut users: Vec<UserResponse> = self
+            .users
+            .iter()
+            .map(|entry| UserResponse::from(entry.value().clone()))
+            .collect();
+        users.sort_by(|a, b| a.created_at.cmp(&b.created_at).then(a.email.cmp(&b.email)));
+        users
+    }
+
     // I am retrieving a user by their UUID
     pub async fn get_user_by_id(&self, user_id: &str) -> Result<UserResponse> {
         let uuid = Uuid::parse_str(user_id)
@@ -137,16 +638,6 @@ impl AuthService {
         Err(AppError::NotFoundError("User not found".to_string()))
     }
 
-    // I am retrieving a user by their email address
-    pub async fn get_user_by_email(&self, email: &str) -> Result<UserResponse> {
-        let user = self
-            .users
-            .get(email)
-            .ok_or_else(|| AppError::NotFoundError("User not found".to_string()))?;
-
-        Ok(UserResponse::from(user.clone()))
-    }
-
     // I am authenticating using a static API token (for demo or service use)
     pub async fn authenticate_with_token(&self, token: &str) -> Result<UserResponse> {
         // For simplicity, we'll use a predefined token
@@ -164,10 +655,13 @@ impl AuthService {
         // Create a dummy user for token-based auth
         // In production, tokens would be associated with real users
         Ok(UserResponse {
-            id: Uuid::new_v4(),
+            id: self.id_generator.new_id(),
             email: "token-user@quickscan.app".to_string(),
-            created_at: Utc::now().to_rfc3339(),
+            created_at: Utc::now(),
             is_active: true,
+            role: UserRole::User,
+            // Token users aren't stored, so they have no login history beyond this one
+            last_login_at: Some(Utc::now()),
         })
     }
 }
@@ -175,6 +669,280 @@ impl AuthService {
 // I am providing a default implementation for AuthService
 impl Default for AuthService {
     fn default() -> Self {
-        Self::new()
+        Self::new(Arc::new(RandomIdGenerator)).expect("JWT_SECRET is not valid for this APP_ENV")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // I am registering a user and verifying their email so they can log in
+    async fn verified_user(auth_service: &AuthService, email: &str, password: &str) -> UserResponse {
+        let (_, token) = auth_service
+            .register_user(email.to_string(), password.to_string())
+            .await
+            .unwrap();
+        auth_service.verify_email(&token).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_login_requires_verified_email() {
+        let auth_service = AuthService::default();
+        let (user, token) = auth_service
+            .register_user("verify@example.com".to_string(), "password123".to_string())
+            .await
+            .unwrap();
+        assert!(!user.is_active);
+
+        let login = auth_service.authenticate_user("verify@example.com".to_string(), "password123".to_string(), None).await;
+        assert!(matches!(login, Err(AppError::AuthError(message)) if message == "Account is inactive"));
+
+        assert!(auth_service.verify_email(&token).await.unwrap().is_active);
+        assert!(auth_service.authenticate_user("verify@example.com".to_string(), "password123".to_string(), None).await.is_ok());
+        assert!(auth_service.verify_email(&token).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_logins_are_recorded() {
+        let auth_service = AuthService::default();
+        let user = verified_user(&auth_service, "history@example.com", "password123").await;
+        assert!(user.last_login_at.is_none());
+        assert!(auth_service.login_history(user.id).is_empty());
+
+        for last_octet in 1..=(MAX_LOGIN_HISTORY as u8 + 2) {
+            let ip = IpAddr::from([203, 0, 113, last_octet]);
+            auth_service
+                .authenticate_user("history@example.com".to_string(), "password123".to_string(), Some(ip))
+                .await
+                .unwrap();
+        }
+        // A failed attempt is not a login
+        assert!(auth_service
+            .authenticate_user("history@example.com".to_string(), "wrong-password".to_string(), None)
+            .await
+            .is_err());
+
+        let history = auth_service.login_history(user.id);
+        assert_eq!(history.len(), MAX_LOGIN_HISTORY);
+        assert_eq!(history[0].ip, Some(IpAddr::from([203, 0, 113, MAX_LOGIN_HISTORY as u8 + 2])));
+        let current = auth_service.get_user_by_id(&user.id.to_string()).await.unwrap();
+        assert_eq!(current.last_login_at, Some(history[0].timestamp));
     }
-} 
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_emails_are_case_insensitive() {
+        let auth_service = AuthService::default();
+        let user = verified_user(&auth_service, "  Mixed.Case@Example.COM ", "password123").await;
+        assert_eq!(user.email, "mixed.case@example.com");
+
+        let login = auth_service
+            .authenticate_user("mixed.case@example.com".to_string(), "password123".to_string(), None)
+            .await
+            .unwrap();
+        assert_eq!(login.id, user.id);
+
+        let duplicate = auth_service
+            .register_user("MIXED.CASE@example.com".to_string(), "password123".to_string())
+            .await;
+        assert!(matches!(duplicate, Err(AppError::ValidationError(_))));
+        assert!(auth_service.create_password_reset("Mixed.Case@example.com").is_some());
+    }
+
+    #[test]
+    fn test_gmail_canonicalization_is_opt_in() {
+        assert_eq!(normalize_email("First.Last+news@GMail.com", false), "first.last+news@gmail.com");
+        assert_eq!(normalize_email("First.Last+news@GMail.com", true), "firstlast@gmail.com");
+        assert_eq!(normalize_email("first.last@googlemail.com", true), "firstlast@gmail.com");
+        // Other providers may treat dots and tags as significant
+        assert_eq!(normalize_email("first.last+news@example.com", true), "first.last+news@example.com");
+        assert_eq!(normalize_email("+news@gmail.com", true), "+news@gmail.com");
+    }
+
+    #[tokio::test]
+    async fn test_change_password_checks_current_and_ends_sessions() {
+        let auth_service = AuthService::default();
+        let user = verified_user(&auth_service, "change@example.com", "password123").await;
+        let session = auth_service.issue_session(user).unwrap();
+        let claims = auth_service.validate_token(&session.token).unwrap();
+
+        let wrong = auth_service.change_password(&claims, "not-my-password", "newpassword456".to_string()).await;
+        assert!(matches!(wrong, Err(AppError::AuthError(_))));
+        let same = auth_service.change_password(&claims, "password123", "password123".to_string()).await;
+        assert!(matches!(same, Err(AppError::ValidationError(_))));
+
+        // A second session, say on another device, is ended too
+        let mut other_claims = claims.clone();
+        other_claims.iat -= 1;
+        other_claims.jti = Uuid::new_v4().to_string();
+        let other_token = encode(
+            &Header::default(),
+            &other_claims,
+            &EncodingKey::from_secret(auth_service.jwt_secret.as_ref()),
+        )
+        .unwrap();
+        assert!(auth_service.validate_token(&other_token).is_ok());
+
+        auth_service.change_password(&claims, "password123", "newpassword456".to_string()).await.unwrap();
+        assert!(auth_service.validate_token(&session.token).is_err());
+        assert!(auth_service.validate_token(&other_token).is_err());
+        assert!(auth_service.refresh_session(&session.refresh_token).await.is_err());
+
+        let old_login = auth_service.authenticate_user("change@example.com".to_string(), "password123".to_string(), None).await;
+        assert!(old_login.is_err());
+        assert!(auth_service
+            .authenticate_user("change@example.com".to_string(), "newpassword456".to_string(), None)
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_refresh_token_rotates_and_is_single_use() {
+        let auth_service = AuthService::default();
+        let user = verified_user(&auth_service, "refresh@example.com", "password123").await;
+
+        let session = auth_service.issue_session(user.clone()).unwrap();
+        let refreshed = auth_service.refresh_session(&session.refresh_token).await.unwrap();
+        assert_eq!(refreshed.user.id, user.id);
+        assert_ne!(refreshed.refresh_token, session.refresh_token);
+        assert!(auth_service.validate_token(&refreshed.token).is_ok());
+
+        assert!(auth_service.refresh_session(&session.refresh_token).await.is_err());
+        assert!(auth_service.refresh_session("not-a-token").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_logout_revokes_access_and_refresh_tokens() {
+        let auth_service = AuthService::default();
+        let user = verified_user(&auth_service, "logout@example.com", "password123").await;
+
+        let session = auth_service.issue_session(user).unwrap();
+        let claims = auth_service.validate_token(&session.token).unwrap();
+
+        auth_service.logout(&claims);
+        assert!(auth_service.validate_token(&session.token).is_err());
+        assert!(auth_service.refresh_session(&session.refresh_token).await.is_err());
+
+        // Revoked entries are kept until the token itself expires
+        auth_service.prune_expired_tokens();
+        assert!(auth_service.validate_token(&session.token).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_password_reset_is_single_use_and_expires() {
+        let auth_service = AuthService::default();
+        let user = verified_user(&auth_service, "reset@example.com", "old-password").await;
+        let session = auth_service.issue_session(user).unwrap();
+
+        assert!(auth_service.create_password_reset("nobody@example.com").is_none());
+
+        let token = auth_service.create_password_reset("reset@example.com").unwrap();
+        auth_service.reset_password(&token, "new-password".to_string()).await.unwrap();
+
+        assert!(auth_service.authenticate_user("reset@example.com".to_string(), "old-password".to_string(), None).await.is_err());
+        assert!(auth_service.authenticate_user("reset@example.com".to_string(), "new-password".to_string(), None).await.is_ok());
+        assert!(auth_service.refresh_session(&session.refresh_token).await.is_err());
+        assert!(auth_service.reset_password(&token, "another-password".to_string()).await.is_err());
+
+        let expired = auth_service.create_password_reset("reset@example.com").unwrap();
+        auth_service.reset_tokens.alter(&expired, |_, (email, _)| (email, 0));
+        assert!(auth_service.reset_password(&expired, "another-password".to_string()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_delete_user_removes_account_and_revokes_session() {
+        let auth_service = AuthService::default();
+        let user = verified_user(&auth_service, "delete@example.com", "password123").await;
+        let session = auth_service.issue_session(user).unwrap();
+        let claims = auth_service.validate_token(&session.token).unwrap();
+
+        auth_service.delete_user(&claims).unwrap();
+        assert!(auth_service.validate_token(&session.token).is_err());
+        assert!(auth_service.refresh_session(&session.refresh_token).await.is_err());
+        assert!(auth_service.authenticate_user("delete@example.com".to_string(), "password123".to_string(), None).await.is_err());
+        assert!(auth_service.delete_user(&claims).is_err());
+    }
+
+    #[test]
+    fn test_jwt_secret_rejected_in_production_when_missing_or_short() {
+        let strong = "0123456789abcdef0123456789abcdef".to_string();
+
+        assert!(matches!(resolve_jwt_secret(None, true), Err(AppError::ConfigError(_))));
+        assert!(matches!(resolve_jwt_secret(Some("short".to_string()), true), Err(AppError::ConfigError(_))));
+        assert_eq!(resolve_jwt_secret(Some(strong.clone()), true).unwrap(), strong);
+
+        // Development keeps working, falling back to the default secret
+        assert_eq!(resolve_jwt_secret(None, false).unwrap(), DEFAULT_JWT_SECRET);
+        assert_eq!(resolve_jwt_secret(Some("short".to_string()), false).unwrap(), "short");
+    }
+
+    #[tokio::test]
+    async fn test_token_expiry_follows_configured_hours() {
+        assert_eq!(parse_jwt_expiration_hours(None).unwrap(), 24);
+        assert_eq!(parse_jwt_expiration_hours(Some("1")).unwrap(), 1);
+        assert!(matches!(parse_jwt_expiration_hours(Some("0")), Err(AppError::ConfigError(_))));
+        assert!(matches!(parse_jwt_expiration_hours(Some("721")), Err(AppError::ConfigError(_))));
+        assert!(matches!(parse_jwt_expiration_hours(Some("a day")), Err(AppError::ConfigError(_))));
+
+        let auth_service = AuthService {
+            jwt_expiration_hours: 1,
+            ..AuthService::default()
+        };
+        let user = verified_user(&auth_service, "hourly@example.com", "password123").await;
+        let (token, _) = auth_service.generate_token(&user).unwrap();
+
+        let claims = auth_service.validate_token(&token).unwrap();
+        // exp and iat are read from the clock separately, so allow a second of drift
+        assert!((3599..=3600).contains(&(claims.exp - claims.iat)));
+    }
+
+    #[tokio::test]
+    async fn test_token_rejected_for_other_audience() {
+        let auth_service = AuthService::default();
+        let user = verified_user(&auth_service, "aud@example.com", "password123").await;
+        let (token, _) = auth_service.generate_token(&user).unwrap();
+
+        let claims = auth_service.validate_token(&token).unwrap();
+        assert_eq!(claims.iss, "quickscan");
+        assert_eq!(claims.aud, "quickscan-api");
+        assert!(!claims.jti.is_empty());
+
+        // Same secret and issuer, but minted for another API
+        let other_api = AuthService {
+            jwt_audience: "billing-api".to_string(),
+            ..auth_service.clone()
+        };
+        let (foreign_token, _) = other_api.generate_token(&user).unwrap();
+        assert!(matches!(auth_service.validate_token(&foreign_token), Err(AppError::AuthError(_))));
+
+        let other_issuer = AuthService {
+            jwt_issuer: "someone-else".to_string(),
+            ..auth_service.clone()
+        };
+        assert!(other_issuer.validate_token(&token).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_list_users_and_bootstrap_admin_role() {
+        let auth_service = AuthService {
+            admin_emails: Arc::new(HashSet::from(["ops@example.com".to_string()])),
+            ..AuthService::default()
+        };
+
+        let admin = verified_user(&auth_service, "Ops@example.com", "password123").await;
+        let member = verified_user(&auth_service, "member@example.com", "password123").await;
+
+        let users = auth_service.list_users();
+        assert_eq!(users.len(), 2);
+        assert!(users.iter().any(|user| user.id == member.id));
+
+        let admin_claims = auth_service.validate_token(&auth_service.issue_session(admin).unwrap().token).unwrap();
+        let member_claims = auth_service.validate_token(&auth_service.issue_session(member).unwrap().token).unwrap();
+        assert_eq!(admin_claims.role, UserRole::Admin);
+        assert_eq!(member_claims.role, UserRole::User);
+
+        assert!(admin_claims.role.satisfies(UserRole::Admin));
+        assert!(admin_claims.role.satisfies(UserRole::User));
+        assert!(!member_claims.role.satisfies(UserRole::Admin));
+    }
+}
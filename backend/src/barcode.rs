@@ -0,0 +1,353 @@
+// I am importing the grayscale image type the scanlines are read from
+use image::GrayImage;
+
+// Module widths of the Code 128 symbols, indexed by symbol value; 103-105 are the start codes
+const CODE128_PATTERNS: [[u8; 6]; 106] = [
+    [2, 1, 2, 2, 2, 2], [2, 2, 2, 1, 2, 2], [2, 2, 2, 2, 2, 1], [1, 2, 1, 2, 2, 3], [1, 2, 1, 3, 2, 2],
+    [1, 3, 1, 2, 2, 2], [1, 2, 2, 2, 1, 3], [1, 2, 2, 3, 1, 2], [1, 3, 2, 2, 1, 2], [2, 2, 1, 2, 1, 3],
+    [2, 2, 1, 3, 1, 2], [2, 3, 1, 2, 1, 2], [1, 1, 2, 2, 3, 2], [1, 2, 2, 1, 3, 2], [1, 2, 2, 2, 3, 1],
+    [1, 1, 3, 2, 2, 2], [1, 2, 3, 1, 2, 2], [1, 2, 3, 2, 2, 1], [2, 2, 3, 2, 1, 1], [2, 2, 1, 1, 3, 2],
+    [2, 2, 1, 2, 3, 1], [2, 1, 3, 2, 1, 2], [2, 2, 3, 1, 1, 2], [3, 1, 2, 1, 3, 1], [3, 1, 1, 2, 2, 2],
+    [3, 2, 1, 1, 2, 2], [3, 2, 1, 2, 2, 1], [3, 1, 2, 2, 1, 2], [3, 2, 2, 1, 1, 2], [3, 2, 2, 2, 1, 1],
+    [2, 1, 2, 1, 2, 3], [2, 1, 2, 3, 2, 1], [2, 3, 2, 1, 2, 1], [1, 1, 1, 3, 2, 3], [1, 3, 1, 1, 2, 3],
+    [1, 3, 1, 3, 2, 1], [1, 1, 2, 3, 1, 3], [1, 3, 2, 1, 1, 3], [1, 3, 2, 3, 1, 1], [2, 1, 1, 3, 1, 3],
+    [2, 3, 1, 1, 1, 3], [2, 3, 1, 3, 1, 1], [1, 1, 2, 1, 3, 3], [1, 1, 2, 3, 3, 1], [1, 3, 2, 1, 3, 1],
+    [1, 1, 3, 1, 2, 3], [1, 1, 3, 3, 2, 1], [1, 3, 3, 1, 2, 1], [3, 1, 3, 1, 2, 1], [2, 1, 1, 3, 3, 1],
+    [2, 3, 1, 1, 3, 1], [2, 1, 3, 1, 1, 3], [2, 1, 3, 3, 1, 1], [2, 1, 3, 1, 3, 1], [3, 1, 1, 1, 2, 3],
+    [3, 1, 1, 3, 2, 1], [3, 3, 1, 1, 2, 1], [3, 1, 2, 1, 1, 3], [3, 1, 2, 3, 1, 1], [3, 3, 2, 1, 1, 1],
+    [3, 1, 4, 1, 1, 1], [2, 2, 1, 4, 1, 1], [4, 3, 1, 1, 1, 1], [1, 1, 1, 2, 2, 4], [1, 1, 1, 4, 2, 2],
+    [1, 2, 1, 1, 2, 4], [1, 2, 1, 4, 2, 1], [1, 4, 1, 1, 2, 2], [1, 4, 1, 2, 2, 1], [1, 1, 2, 2, 1, 4],
+    [1, 1, 2, 4, 1, 2], [1, 2, 2, 1, 1, 4], [1, 2, 2, 4, 1, 1], [1, 4, 2, 1, 1, 2], [1, 4, 2, 2, 1, 1],
+    [2, 4, 1, 2, 1, 1], [2, 2, 1, 1, 1, 4], [4, 1, 3, 1, 1, 1], [2, 4, 1, 1, 1, 2], [1, 3, 4, 1, 1, 1],
+    [1, 1, 1, 2, 4, 2], [1, 2, 1, 1, 4, 2], [1, 2, 1, 2, 4, 1], [1, 1, 4, 2, 1, 2], [1, 2, 4, 1, 1, 2],
+    [1, 2, 4, 2, 1, 1], [4, 1, 1, 2, 1, 2], [4, 2, 1, 1, 1, 2], [4, 2, 1, 2, 1, 1], [2, 1, 2, 1, 4, 1],
+    [2, 1, 4, 1, 2, 1], [4, 1, 2, 1, 2, 1], [1, 1, 1, 1, 4, 3], [1, 1, 1, 3, 4, 1], [1, 3, 1, 1, 4, 1],
+    [1, 1, 4, 1, 1, 3], [1, 1, 4, 3, 1, 1], [4, 1, 1, 1, 1, 3], [4, 1, 1, 3, 1, 1], [1, 1, 3, 1, 4, 1],
+    [1, 1, 4, 1, 3, 1], [3, 1, 1, 1, 4, 1], [4, 1, 1, 1, 3, 1], [2, 1, 1, 4, 1, 2], [2, 1, 1, 2, 1, 4],
+    [2, 1, 1, 2, 3, 2],
+];
+const CODE128_STOP: [u8; 7] = [2, 3, 3, 1, 1, 1, 2];
+const CODE128_START_A: usize = 103;
+const CODE128_START_C: usize = 105;
+
+// Module widths of the EAN-13 digits in the L code; the R code has the same widths and the G code reverses them
+const EAN_DIGIT_PATTERNS: [[u8; 4]; 10] = [
+    [3, 2, 1, 1], [2, 2, 2, 1], [2, 1, 2, 2], [1, 4, 1, 1], [1, 1, 3, 2],
+    [1, 2, 3, 1], [1, 1, 1, 4], [1, 3, 1, 2], [1, 2, 1, 3], [3, 1, 1, 2],
+];
+// Which of the six left-hand digits use the G code, one bit per digit from the left, indexed by the first digit
+const EAN_FIRST_DIGIT_PARITY: [u8; 10] = [
+    0b000000, 0b001011, 0b001101, 0b001110, 0b010011, 0b011001, 0b011100, 0b010101, 0b010110, 0b011010,
+];
+// Runs from the start of an EAN-13 symbol's start guard to the end of its end guard
+const EAN13_RUNS: usize = 59;
+
+// A run may be off by this many modules on average and still match, allowing for blur and print spread
+const MAX_RUN_DEVIATION: f32 = 0.4;
+// Rows whose darkest and lightest pixels are closer than this have no bars worth reading
+const MIN_ROW_CONTRAST: u8 = 48;
+// Rows read across the image; barcodes are much wider than tall, so a sample of rows is enough
+const MAX_SCANLINES: u32 = 100;
+// Light space required on either side of a symbol, in modules
+const MIN_QUIET_ZONE_MODULES: f32 = 3.0;
+
+// I am defining the 1D barcode types that can be read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Barcode {
+    // EAN-13, which also covers UPC-A codes as 13 digits with a leading 0
+    Ean13,
+    Code128,
+}
+
+// I am reading the horizontal 1D barcodes in an image, either way up; each distinct code is returned once
+pub fn decode_barcodes(image: &GrayImage) -> Vec<(Barcode, String)> {
+    let (width, height) = image.dimensions();
+    let mut found: Vec<(Barcode, String)> = Vec::new();
+    if width == 0 {
+        return found;
+    }
+
+    let step = (height / MAX_SCANLINES).max(1);
+    for y in (0..height).step_by(step as usize) {
+        let row: Vec<u8> = (0..width).map(|x| image.get_pixel(x, y).0[0]).collect();
+        let Some(dark) = binarize(&row) else {
+            continue;
+        };
+        let reversed: Vec<bool> = dark.iter().rev().copied().collect();
+        for runs in [runs_from(&dark), runs_from(&reversed)] {
+            for code in decode_runs(&runs) {
+                if !found.contains(&code) {
+                    found.push(code);
+                }
+            }
+        }
+    }
+    found
+}
+
+// Splits a row into dark and light pixels around the midpoint of its range, or None if it is nearly flat
+fn binarize(row: &[u8]) -> Option<Vec<bool>> {
+    let (min, max) = row.iter().fold((u8::MAX, u8::MIN), |(min, max), &v| (min.min(v), max.max(v)));
+    if max - min < MIN_ROW_CONTRAST {
+        return None;
+    }
+    let threshold = min + (max - min) / 2;
+    Some(row.iter().map(|&v| v < threshold).collect())
+}
+
+// Run lengths of alternating light and dark pixels; the first run is always light, even if empty, so dark runs
+// sit at odd indices
+fn runs_from(dark: &[bool]) -> Vec<u32> {
+    let mut runs = vec![0];
+    let mut current = false;
+    for &pixel in dark {
+        if pixel != current {
+            runs.push(0);
+            current = pixel;
+        }
+        *runs.last_mut().expect("runs starts non-empty") += 1;
+    }
+    runs
+}
+
+fn decode_runs(runs: &[u32]) -> Vec<(Barcode, String)> {
+    (1..runs.len())
+        .step_by(2)
+        .filter_map(|start| {
+            decode_ean13(runs, start)
+                .map(|digits| (Barcode::Ean13, digits))
+                .or_else(|| decode_code128(runs, start).map(|text| (Barcode::Code128, text)))
+        })
+        .collect()
+}
+
+// How far some runs are from a pattern, in modules per run, after scaling the runs to the pattern's total width
+fn pattern_deviation(runs: &[u32], pattern: &[u8]) -> f32 {
+    let total: u32 = runs.iter().sum();
+    let modules: u32 = pattern.iter().map(|&w| u32::from(w)).sum();
+    let scale = modules as f32 / total as f32;
+    let deviation: f32 = runs
+        .iter()
+        .zip(pattern)
+        .map(|(&run, &width)| (run as f32 * scale - f32::from(width)).abs())
+        .sum();
+    deviation / runs.len() as f32
+}
+
+// The index of the pattern closest to some runs, if it is close enough to count as a match
+fn best_match<const N: usize>(runs: &[u32], patterns: &[[u8; N]]) -> Option<usize> {
+    patterns
+        .iter()
+        .map(|pattern| pattern_deviation(runs, pattern))
+        .enumerate()
+        .filter(|(_, deviation)| *deviation < MAX_RUN_DEVIATION)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(index, _)| index)
+}
+
+// Whether the light runs around a symbol are wide enough to be its quiet zones
+fn has_quiet_zones(runs: &[u32], start: usize, end: usize, module: f32) -> bool {
+    let before = runs[start - 1] as f32;
+    let after = runs.get(end).map_or(0.0, |&run| run as f32);
+    before >= MIN_QUIET_ZONE_MODULES * module && after >= MIN_QUIET_ZONE_MODULES * module
+}
+
+fn decode_ean13(runs: &[u32], start: usize) -> Option<String> {
+    let symbol = runs.get(start..start + EAN13_RUNS)?;
+    let module = symbol.iter().sum::<u32>() as f32 / 95.0;
+    if !has_quiet_zones(runs, start, start + EAN13_RUNS, module) {
+        return None;
+    }
+
+    // The start, middle and end guards are all one module wide
+    let guard = |offset: usize, len: usize| {
+        symbol[offset..offset + len].iter().all(|&run| (run as f32 / module - 1.0).abs() < 0.5)
+    };
+    if !(guard(0, 3) && guard(27, 5) && guard(56, 3)) {
+        return None;
+    }
+
+    let g_patterns: Vec<[u8; 4]> = EAN_DIGIT_PATTERNS.iter().map(|&[a, b, c, d]| [d, c, b, a]).collect();
+    let mut digits = Vec::with_capacity(13);
+    let mut parity = 0u8;
+    for index in 0..6 {
+        let group = &symbol[3 + 4 * index..7 + 4 * index];
+        let closest = |patterns: &[[u8; 4]]| {
+            best_match(group, patterns).map(|digit| (digit, pattern_deviation(group, &patterns[digit])))
+        };
+        let (digit, uses_g) = match (closest(&EAN_DIGIT_PATTERNS), closest(&g_patterns)) {
+            (Some((_, l_deviation)), Some((g, g_deviation))) if g_deviation < l_deviation => (g, true),
+            (Some((l, _)), _) => (l, false),
+            (None, Some((g, _))) => (g, true),
+            (None, None) => return None,
+        };
+        digits.push(digit as u8);
+        parity |= u8::from(uses_g) << (5 - index);
+    }
+    for index in 0..6 {
+        digits.push(best_match(&symbol[32 + 4 * index..36 + 4 * index], &EAN_DIGIT_PATTERNS)? as u8);
+    }
+    let first = EAN_FIRST_DIGIT_PARITY.iter().position(|&pattern| pattern == parity)? as u8;
+    digits.insert(0, first);
+
+    let weighted: u32 = digits[..12]
+        .iter()
+        .enumerate()
+        .map(|(position, &digit)| u32::from(digit) * if position % 2 == 0 { 1 } else { 3 })
+        .sum();
+    if (10 - weighted % 10) % 10 != u32::from(digits[12]) {
+        return None;
+    }
+    Some(digits.iter().map(|digit| char::from(b'0' + digit)).collect())
+}
+
+fn decode_code128(runs: &[u32], start: usize) -> Option<String> {
+    let start_code = best_match(runs.get(start..start + 6)?, &CODE128_PATTERNS)?;
+    if start_code < CODE128_START_A {
+        return None;
+    }
+    let module = runs[start..start + 6].iter().sum::<u32>() as f32 / 11.0;
+
+    let mut values = vec![start_code];
+    let mut position = start + 6;
+    let end = loop {
+        if let Some(stop) = runs.get(position..position + 7) {
+            if pattern_deviation(stop, &CODE128_STOP) < MAX_RUN_DEVIATION {
+                break position + 7;
+            }
+        }
+        let value = best_match(runs.get(position..position + 6)?, &CODE128_PATTERNS)?;
+        // Start codes never appear inside a symbol
+        if value >= CODE128_START_A {
+            return None;
+        }
+        values.push(value);
+        position += 6;
+    };
+    if !has_quiet_zones(runs, start, end, module) {
+        return None;
+    }
+
+    // The last value is the check symbol, over the start code and every value weighted by its position
+    let (&check, data) = values[1..].split_last()?;
+    let weighted: usize = start_code + data.iter().enumerate().map(|(index, &value)| (index + 1) * value).sum::<usize>();
+    if data.is_empty() || weighted % 103 != check {
+        return None;
+    }
+    code128_text(start_code, data)
+}
+
+// Turns Code 128 values into text, following the switches between the A, B and C character sets
+fn code128_text(start_code: usize, data: &[usize]) -> Option<String> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Set {
+        A,
+        B,
+        C,
+    }
+
+    let mut set = match start_code {
+        CODE128_START_A => Set::A,
+        CODE128_START_C => Set::C,
+        _ => Set::B,
+    };
+    let mut shifted = None;
+    let mut text = String::new();
+    for &value in data {
+        let current = shifted.take().unwrap_or(set);
+        match (current, value) {
+            (Set::C, 0..=99) => text.push_str(&format!("{:02}", value)),
+            (Set::A, 0..=63) | (Set::B, 0..=95) => text.push(char::from(32 + value as u8)),
+            (Set::A, 64..=95) => text.push(char::from(value as u8 - 64)),
+            (Set::A, 98) => shifted = Some(Set::B),
+            (Set::B, 98) => shifted = Some(Set::A),
+            (Set::A | Set::B, 99) => set = Set::C,
+            (Set::A | Set::C, 100) => set = Set::B,
+            (Set::B | Set::C, 101) => set = Set::A,
+            // FNC1-4 carry no text of their own
+            _ => {}
+        }
+    }
+    (!text.is_empty()).then_some(text)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // I am drawing bars from module widths (dark first) with quiet zones, three pixels to the module
+    fn render(widths: &[u8]) -> GrayImage {
+        let mut dark = vec![false; 10];
+        for (index, &width) in widths.iter().enumerate() {
+            dark.extend(std::iter::repeat_n(index % 2 == 0, usize::from(width)));
+        }
+        dark.extend([false; 10]);
+        GrayImage::from_fn(dark.len() as u32 * 3, 30, |x, _| image::Luma([if dark[x as usize / 3] { 20 } else { 235 }]))
+    }
+
+    pub(crate) fn render_ean13(digits: &str) -> GrayImage {
+        render(&ean13(digits))
+    }
+
+    fn ean13(digits: &str) -> Vec<u8> {
+        let digits: Vec<usize> = digits.bytes().map(|b| usize::from(b - b'0')).collect();
+        let mut widths = vec![1, 1, 1];
+        for (index, &digit) in digits[1..7].iter().enumerate() {
+            let pattern = EAN_DIGIT_PATTERNS[digit];
+            if EAN_FIRST_DIGIT_PARITY[digits[0]] & (1 << (5 - index)) != 0 {
+                widths.extend(pattern.iter().rev());
+            } else {
+                widths.extend(pattern);
+            }
+        }
+        widths.extend([1, 1, 1, 1, 1]);
+        for &digit in &digits[7..] {
+            widths.extend(EAN_DIGIT_PATTERNS[digit]);
+        }
+        widths.extend([1, 1, 1]);
+        widths
+    }
+
+    fn code128(start_code: usize, data: &[usize]) -> Vec<u8> {
+        let check = (start_code + data.iter().enumerate().map(|(index, &value)| (index + 1) * value).sum::<usize>()) % 103;
+        let mut widths: Vec<u8> = CODE128_PATTERNS[start_code].to_vec();
+        for &value in data.iter().chain([check].iter()) {
+            widths.extend(CODE128_PATTERNS[value]);
+        }
+        widths.extend(CODE128_STOP);
+        widths
+    }
+
+    #[test]
+    fn test_decodes_ean13_and_code128_either_way_up() {
+        let image = render(&ean13("4006381333931"));
+        assert_eq!(decode_barcodes(&image), vec![(Barcode::Ean13, "4006381333931".to_string())]);
+        // A UPC-A code reads as EAN-13 with a leading 0
+        let upside_down = image::imageops::rotate180(&render(&ean13("0036000291452")));
+        assert_eq!(decode_barcodes(&upside_down), vec![(Barcode::Ean13, "0036000291452".to_string())]);
+
+        let text: Vec<usize> = "Hello-1".bytes().map(|b| usize::from(b - 32)).collect();
+        assert_eq!(
+            decode_barcodes(&render(&code128(104, &text))),
+            vec![(Barcode::Code128, "Hello-1".to_string())]
+        );
+        // Set C packs digit pairs, and switching to set B reads the rest as characters
+        let mixed = [12, 34, 56, 100, usize::from(b'x' - 32)];
+        assert_eq!(
+            decode_barcodes(&render(&code128(CODE128_START_C, &mixed))),
+            vec![(Barcode::Code128, "123456x".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_rejects_bad_check_digits_and_blank_rows() {
+        // The same bars with the last digit changed no longer check out
+        assert!(decode_barcodes(&render(&ean13("4006381333932"))).is_empty());
+        let mut widths = code128(104, &[40, 41]);
+        let check_symbol = widths.len() - 13;
+        widths[check_symbol..check_symbol + 6].copy_from_slice(&CODE128_PATTERNS[0]);
+        assert!(decode_barcodes(&render(&widths)).is_empty());
+
+        assert!(decode_barcodes(&GrayImage::from_pixel(64, 64, image::Luma([255]))).is_empty());
+    }
+}
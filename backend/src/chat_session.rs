@@ -0,0 +1,82 @@
+// I am importing the queue used for the history and the OpenAI message type it holds
+use std::collections::VecDeque;
+
+use crate::models::OpenAIMessage;
+
+// Same rough estimate the summarizer uses: 1 token ≈ 3 characters
+const CHARS_PER_TOKEN: usize = 3;
+
+pub const DEFAULT_HISTORY_TOKEN_BUDGET: usize = 4000;
+
+// I am holding one conversation's history, dropping the oldest turns once it exceeds the token budget
+pub struct ChatSession {
+    messages: VecDeque<OpenAIMessage>,
+    token_budget: usize,
+}
+
+impl ChatSession {
+    pub fn new(token_budget: usize) -> Self {
+        Self {
+            messages: VecDeque::new(),
+            token_budget,
+        }
+    }
+
+    pub fn budget_from_env() -> usize {
+        std::env::var("CHAT_HISTORY_TOKEN_BUDGET")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_HISTORY_TOKEN_BUDGET)
+    }
+
+    pub fn push(&mut self, role: &str, content: String) {
+        self.messages.push_back(OpenAIMessage {
+            role: role.to_string(),
+            content,
+        });
+        self.trim();
+    }
+
+    pub fn messages(&self) -> Vec<OpenAIMessage> {
+        self.messages.iter().cloned().collect()
+    }
+
+    fn estimated_tokens(&self) -> usize {
+        self.messages
+            .iter()
+            .map(|message| message.content.chars().count().div_ceil(CHARS_PER_TOKEN))
+            .sum()
+    }
+
+    // The newest message is always kept so an oversized turn still reaches OpenAI
+    fn trim(&mut self) {
+        while self.messages.len() > 1 && self.estimated_tokens() > self.token_budget {
+            self.messages.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_drops_oldest_turns_beyond_budget() {
+        // Each 30-character turn is roughly 10 tokens
+        let mut session = ChatSession::new(25);
+        session.push("user", "a".repeat(30));
+        session.push("assistant", "b".repeat(30));
+        assert_eq!(session.messages().len(), 2);
+
+        session.push("user", "c".repeat(30));
+        let messages = session.messages();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].role, "assistant");
+        assert_eq!(messages[1].content, "c".repeat(30));
+
+        session.push("user", "d".repeat(300));
+        let messages = session.messages();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].content, "d".repeat(300));
+    }
+}
@@ -0,0 +1,108 @@
+// I am importing the concurrent map used to hold conversations and the history type each one keeps
+use chrono::{DateTime, Duration, Utc};
+use dashmap::DashMap;
+use uuid::Uuid;
+
+use crate::{chat_session::ChatSession, models::OpenAIMessage};
+
+// How long a conversation is kept after its last turn
+pub const CONVERSATION_TTL_HOURS: i64 = 24;
+
+// I am pairing a conversation's history with the user who started it; None for anonymous callers
+struct Conversation {
+    owner_id: Option<Uuid>,
+    session: ChatSession,
+    last_active: DateTime<Utc>,
+}
+
+// I am defining the conversation store behind /chat/completion, keeping histories in memory until a real database is wired in
+pub struct ConversationStore {
+    conversations: DashMap<Uuid, Conversation>,
+    token_budget: usize,
+    ttl: Duration,
+}
+
+impl ConversationStore {
+    pub fn new(token_budget: usize, ttl: Duration) -> Self {
+        Self {
+            conversations: DashMap::new(),
+            token_budget,
+            ttl,
+        }
+    }
+
+    // Prior turns of one of the caller's conversations, or None if they have no such live conversation
+    pub fn history(&self, owner_id: Option<Uuid>, id: &Uuid) -> Option<Vec<OpenAIMessage>> {
+        let cutoff = Utc::now() - self.ttl;
+        self.conversations
+            .get(id)
+            .filter(|conversation| conversation.owner_id == owner_id && conversation.last_active > cutoff)
+            .map(|conversation| conversation.session.messages())
+    }
+
+    // I am appending a completed exchange, creating the conversation on its first turn;
+    // someone else's conversation is never written to
+    pub fn record_turn(&self, owner_id: Option<Uuid>, id: Uuid, user_content: String, assistant_content: String) {
+        let mut conversation = self.conversations.entry(id).or_insert_with(|| Conversation {
+            owner_id,
+            session: ChatSession::new(self.token_budget),
+            last_active: Utc::now(),
+        });
+        if conversation.owner_id != owner_id {
+            return;
+        }
+        conversation.session.push("user", user_content);
+        conversation.session.push("assistant", assistant_content);
+        conversation.last_active = Utc::now();
+    }
+
+    // I am dropping conversations idle for longer than the TTL, bounding memory
+    pub fn prune_expired(&self) {
+        let cutoff = Utc::now() - self.ttl;
+        self.conversations.retain(|_, conversation| conversation.last_active > cutoff);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversation_round_trip() {
+        let store = ConversationStore::new(1000, Duration::hours(CONVERSATION_TTL_HOURS));
+        let (owner, id) = (Some(Uuid::new_v4()), Uuid::new_v4());
+        assert!(store.history(owner, &id).is_none());
+
+        store.record_turn(owner, id, "What is 2 + 2?".to_string(), "4".to_string());
+        store.record_turn(owner, id, "And doubled?".to_string(), "8".to_string());
+
+        let history = store.history(owner, &id).unwrap();
+        let turns: Vec<(&str, &str)> = history.iter().map(|m| (m.role.as_str(), m.content.as_str())).collect();
+        assert_eq!(
+            turns,
+            vec![("user", "What is 2 + 2?"), ("assistant", "4"), ("user", "And doubled?"), ("assistant", "8")]
+        );
+    }
+
+    #[test]
+    fn test_conversations_are_private_and_expire() {
+        let store = ConversationStore::new(1000, Duration::hours(CONVERSATION_TTL_HOURS));
+        let (alice, id) = (Some(Uuid::new_v4()), Uuid::new_v4());
+        store.record_turn(alice, id, "secret".to_string(), "noted".to_string());
+
+        // Another user, or an anonymous caller, can neither read nor extend it
+        let bob = Some(Uuid::new_v4());
+        assert!(store.history(bob, &id).is_none());
+        assert!(store.history(None, &id).is_none());
+        store.record_turn(bob, id, "injected".to_string(), "reply".to_string());
+        assert_eq!(store.history(alice, &id).unwrap().len(), 2);
+
+        let expiring = ConversationStore::new(1000, Duration::zero());
+        expiring.record_turn(alice, id, "hello".to_string(), "hi".to_string());
+        assert!(expiring.history(alice, &id).is_none());
+        expiring.prune_expired();
+        assert!(expiring.conversations.is_empty());
+        store.prune_expired();
+        assert_eq!(store.conversations.len(), 1);
+    }
+}
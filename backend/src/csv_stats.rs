@@ -0,0 +1,223 @@
+// I am importing the CSV reader and the column statistics types returned to clients
+use std::collections::HashSet;
+
+use crate::{
+    error::{AppError, Result},
+    models::{CsvColumnStats, CsvColumnType},
+};
+
+// Cells that count as missing besides empty ones, compared case-insensitively
+const NULL_MARKERS: &[&str] = &["null", "na", "n/a"];
+
+// I am defining the statistics for a whole CSV file
+#[derive(Debug, Clone)]
+pub struct CsvStats {
+    pub rows: usize,
+    pub columns: Vec<CsvColumnStats>,
+}
+
+impl CsvStats {
+    // I am describing the statistics compactly, as the input for a natural-language overview
+    pub fn describe(&self) -> String {
+        let mut description = format!("CSV file with {} rows and {} columns:", self.rows, self.columns.len());
+        for column in &self.columns {
+            // The variant names match the lowercase names clients see
+            let type_name = format!("{:?}", column.column_type).to_lowercase();
+            description.push_str(&format!(
+                "\n- {} ({}): {} values, {} nulls",
+                column.name, type_name, column.count, column.nulls
+            ));
+            if let (Some(min), Some(max), Some(mean)) = (column.min, column.max, column.mean) {
+                description.push_str(&format!(", min {}, max {}, mean {:.2}", min, max, mean));
+            }
+            if let Some(distinct) = column.distinct {
+                description.push_str(&format!(", {} distinct", distinct));
+            }
+        }
+        description
+    }
+}
+
+// I am accumulating one column while rows stream past, narrowing its possible types as values arrive
+struct ColumnAccumulator {
+    name: String,
+    count: usize,
+    nulls: usize,
+    integers: bool,
+    floats: bool,
+    booleans: bool,
+    min: f64,
+    max: f64,
+    sum: f64,
+    distinct: HashSet<String>,
+}
+
+impl ColumnAccumulator {
+    fn new(name: String) -> Self {
+        Self {
+            name,
+            count: 0,
+            nulls: 0,
+            integers: true,
+            floats: true,
+            booleans: true,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            sum: 0.0,
+            distinct: HashSet::new(),
+        }
+    }
+
+    fn add(&mut self, value: &str) {
+        if value.is_empty() || NULL_MARKERS.iter().any(|marker| value.eq_ignore_ascii_case(marker)) {
+            self.nulls += 1;
+            return;
+        }
+
+        self.count += 1;
+        self.integers &= value.parse::<i64>().is_ok();
+        self.booleans &= value.eq_ignore_ascii_case("true") || value.eq_ignore_ascii_case("false");
+        // "NaN" and "inf" parse as floats but would poison the mean
+        match value.parse::<f64>().ok().filter(|number| number.is_finite()) {
+            Some(number) if self.floats => {
+                self.min = self.min.min(number);
+                self.max = self.max.max(number);
+                self.sum += number;
+            }
+            _ => self.floats = false,
+        }
+        if !self.distinct.contains(value) {
+            self.distinct.insert(value.to_string());
+        }
+    }
+
+    fn finish(self) -> CsvColumnStats {
+        // An all-null column has nothing to infer from
+        let column_type = if self.count == 0 {
+            CsvColumnType::Text
+        } else if self.integers {
+            CsvColumnType::Integer
+        } else if self.floats {
+            CsvColumnType::Float
+        } else if self.booleans {
+            CsvColumnType::Boolean
+        } else {
+            CsvColumnType::Text
+        };
+        let numeric = matches!(column_type, CsvColumnType::Integer | CsvColumnType::Float);
+
+        CsvColumnStats {
+            name: self.name,
+            column_type,
+            count: self.count,
+            nulls: self.nulls,
+            min: numeric.then_some(self.min),
+            max: numeric.then_some(self.max),
+            mean: numeric.then(|| self.sum / self.count as f64),
+            distinct: (!numeric).then_some(self.distinct.len()),
+        }
+    }
+}
+
+// I am computing per-column statistics for a CSV file whose first row holds the column names
+pub async fn analyze_csv(data: Vec<u8>) -> Result<CsvStats> {
+    // Large files take a while to scan, so keep it off the async workers
+    tokio::task::spawn_blocking(move || compute_stats(&data))
+        .await
+        .map_err(|_| AppError::BadRequestError("CSV could not be parsed".to_string()))?
+}
+
+fn compute_stats(data: &[u8]) -> Result<CsvStats> {
+    let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(data);
+
+    let headers = reader.headers().map_err(malformed_csv)?.clone();
+    if headers.is_empty() {
+        return Err(AppError::BadRequestError("CSV file has no header row".to_string()));
+    }
+
+    let mut columns: Vec<ColumnAccumulator> = headers
+        .iter()
+        .enumerate()
+        .map(|(index, name)| {
+            let name = if name.is_empty() { format!("column_{}", index + 1) } else { name.to_string() };
+            ColumnAccumulator::new(name)
+        })
+        .collect();
+
+    let mut rows = 0;
+    for record in reader.records() {
+        let record = record.map_err(malformed_csv)?;
+        for (column, value) in columns.iter_mut().zip(record.iter()) {
+            column.add(value);
+        }
+        rows += 1;
+    }
+
+    Ok(CsvStats {
+        rows,
+        columns: columns.into_iter().map(ColumnAccumulator::finish).collect(),
+    })
+}
+
+// I am pointing a parse failure at the row that caused it; the header is row 0, so data rows count from 1
+fn malformed_csv(e: csv::Error) -> AppError {
+    let reason = match e.kind() {
+        csv::ErrorKind::UnequalLengths { expected_len, len, .. } => {
+            format!("expected {} fields, found {}", expected_len, len)
+        }
+        csv::ErrorKind::Utf8 { .. } => "not valid UTF-8".to_string(),
+        _ => e.to_string(),
+    };
+
+    match e.position() {
+        Some(position) => AppError::BadRequestError(format!(
+            "Malformed CSV at row {} (line {}): {}",
+            position.record(),
+            position.line(),
+            reason
+        )),
+        None => AppError::BadRequestError(format!("Malformed CSV: {}", reason)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_infers_column_types_and_stats() {
+        let csv = "id,price,city,active,note\n1,2.5,Paris,true,\n2,4,Lyon,false,NA\n3,,Paris,TRUE,x\n";
+        let stats = analyze_csv(csv.as_bytes().to_vec()).await.unwrap();
+        assert_eq!(stats.rows, 3);
+
+        let [id, price, city, active, note] = &stats.columns[..] else {
+            panic!("expected five columns");
+        };
+        assert_eq!(id.column_type, CsvColumnType::Integer);
+        assert_eq!((id.min, id.max, id.mean, id.distinct), (Some(1.0), Some(3.0), Some(2.0), None));
+
+        assert_eq!(price.column_type, CsvColumnType::Float);
+        assert_eq!((price.count, price.nulls, price.mean), (2, 1, Some(3.25)));
+
+        assert_eq!(city.column_type, CsvColumnType::Text);
+        assert_eq!((city.distinct, city.min), (Some(2), None));
+
+        assert_eq!(active.column_type, CsvColumnType::Boolean);
+        assert_eq!((note.column_type, note.count, note.nulls), (CsvColumnType::Text, 1, 2));
+
+        assert!(stats.describe().contains("- price (float): 2 values, 1 nulls, min 2.5, max 4, mean 3.25"));
+    }
+
+    #[tokio::test]
+    async fn test_malformed_csv_reports_the_row() {
+        let csv = "a,b\n1,2\n3\n";
+        match analyze_csv(csv.as_bytes().to_vec()).await {
+            Err(AppError::BadRequestError(message)) => {
+                assert_eq!(message, "Malformed CSV at row 2 (line 3): expected 2 fields, found 1")
+            }
+            other => panic!("expected a bad request, got {:?}", other.map(|stats| stats.rows)),
+        }
+
+        assert!(matches!(analyze_csv(Vec::new()).await, Err(AppError::BadRequestError(_))));
+    }
+}
@@ -0,0 +1,111 @@
+// I am importing HMAC-SHA256, used to sign time-limited download links for temporary files
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::error::{AppError, Result};
+
+type HmacSha256 = Hmac<Sha256>;
+
+// Length of the random key used when DOWNLOAD_SIGNING_KEY is not set
+const GENERATED_KEY_BYTES: usize = 32;
+
+// I am defining the signer for temporary-storage download links; a token covers one file until one instant
+#[derive(Clone)]
+pub struct DownloadTokenSigner {
+    key: Vec<u8>,
+}
+
+impl std::fmt::Debug for DownloadTokenSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("DownloadTokenSigner(..)")
+    }
+}
+
+impl DownloadTokenSigner {
+    pub fn new(key: impl Into<Vec<u8>>) -> Self {
+        Self { key: key.into() }
+    }
+
+    // I am using the configured key, or a random one that invalidates every issued link on restart
+    pub fn from_key(key: Option<&str>) -> Self {
+        match key.filter(|key| !key.is_empty()) {
+            Some(key) => Self::new(key.as_bytes()),
+            None => {
+                tracing::warn!("DOWNLOAD_SIGNING_KEY is not set; download links stop working when the server restarts");
+                let mut key = vec![0u8; GENERATED_KEY_BYTES];
+                rand::thread_rng().fill_bytes(&mut key);
+                Self::new(key)
+            }
+        }
+    }
+
+    fn mac(&self, file_id: Uuid, expires_at: i64) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC accepts keys of any length");
+        mac.update(format!("{}:{}", file_id, expires_at).as_bytes());
+        mac
+    }
+
+    // I am signing a file id and expiry (Unix seconds), returning the token as hex
+    pub fn sign(&self, file_id: Uuid, expires_at: i64) -> String {
+        hex::encode(self.mac(file_id, expires_at).finalize().into_bytes())
+    }
+
+    // I am building the download path for a file, valid for `expires_in` seconds
+    pub fn signed_path(&self, file_id: Uuid, expires_in: u64) -> String {
+        let expires_at = Utc::now().timestamp().saturating_add(expires_in as i64);
+        format!(
+            "/api/files/{}/download?token={}&exp={}",
+            file_id,
+            self.sign(file_id, expires_at),
+            expires_at
+        )
+    }
+
+    // I am accepting a token only if it was signed for this file and expiry, and the expiry hasn't passed
+    pub fn verify(&self, file_id: Uuid, token: &str, expires_at: i64) -> Result<()> {
+        let signature = hex::decode(token)
+            .map_err(|_| AppError::AuthError("Invalid download token".to_string()))?;
+        // verify_slice compares in constant time
+        self.mac(file_id, expires_at)
+            .verify_slice(&signature)
+            .map_err(|_| AppError::AuthError("Invalid download token".to_string()))?;
+
+        if expires_at <= Utc::now().timestamp() {
+            return Err(AppError::AuthError("Download link has expired".to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokens_are_bound_to_file_and_expiry() {
+        let signer = DownloadTokenSigner::new("test-signing-key");
+        let file_id = Uuid::new_v4();
+        let expires_at = Utc::now().timestamp() + 60;
+        let token = signer.sign(file_id, expires_at);
+
+        assert!(signer.verify(file_id, &token, expires_at).is_ok());
+        // Stretching the expiry, reusing the token for another file, or forging one all fail
+        assert!(matches!(signer.verify(file_id, &token, expires_at + 3600), Err(AppError::AuthError(_))));
+        assert!(matches!(signer.verify(Uuid::new_v4(), &token, expires_at), Err(AppError::AuthError(_))));
+        assert!(matches!(signer.verify(file_id, "not-hex", expires_at), Err(AppError::AuthError(_))));
+        assert!(DownloadTokenSigner::new("other-key").verify(file_id, &token, expires_at).is_err());
+
+        let expired_at = Utc::now().timestamp() - 1;
+        let expired = signer.sign(file_id, expired_at);
+        match signer.verify(file_id, &expired, expired_at) {
+            Err(AppError::AuthError(message)) => assert_eq!(message, "Download link has expired"),
+            other => panic!("expected an expired link, got {:?}", other),
+        }
+
+        let path = signer.signed_path(file_id, 300);
+        assert!(path.starts_with(&format!("/api/files/{}/download?token=", file_id)));
+    }
+}
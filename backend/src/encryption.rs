@@ -0,0 +1,97 @@
+// I am importing AES-256-GCM, used to encrypt temporary files at rest
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Key, Nonce,
+};
+
+use crate::error::{AppError, Result};
+
+// AES-GCM's standard 96-bit nonce, stored in front of each file's ciphertext
+const NONCE_LENGTH: usize = 12;
+const KEY_LENGTH: usize = 32;
+
+// I am defining the cipher for files at rest; each file gets a fresh random nonce
+#[derive(Clone)]
+pub struct FileCipher {
+    cipher: Aes256Gcm,
+}
+
+impl std::fmt::Debug for FileCipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("FileCipher(..)")
+    }
+}
+
+impl FileCipher {
+    // I am parsing a 32-byte key given as 64 hex characters, as STORAGE_ENCRYPTION_KEY holds it
+    pub fn from_hex_key(hex_key: &str) -> Result<Self> {
+        let key = hex::decode(hex_key.trim())
+            .ok()
+            .filter(|key| key.len() == KEY_LENGTH)
+            .ok_or_else(|| {
+                AppError::ConfigError(format!(
+                    "STORAGE_ENCRYPTION_KEY must be {} hex characters ({} bytes)",
+                    KEY_LENGTH * 2,
+                    KEY_LENGTH
+                ))
+            })?;
+
+        Ok(Self {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)),
+        })
+    }
+
+    // I am encrypting file contents, returning the nonce followed by the ciphertext and tag
+    pub fn encrypt(&self, plaintext: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt file"))?;
+
+        let mut sealed = Vec::with_capacity(NONCE_LENGTH + ciphertext.len());
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    // I am reversing encrypt, failing if the data was altered or sealed under another key
+    pub fn decrypt(&self, sealed: &[u8]) -> anyhow::Result<Vec<u8>> {
+        if sealed.len() < NONCE_LENGTH {
+            return Err(anyhow::anyhow!("Encrypted file is truncated"));
+        }
+
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LENGTH);
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow::anyhow!("Failed to decrypt file; it was altered or encrypted with another key"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_and_tamper_detection() {
+        let key = "00112233445566778899aabbccddeeff00112233445566778899aabbccddeeff";
+        let cipher = FileCipher::from_hex_key(key).unwrap();
+
+        let sealed = cipher.encrypt(b"patient record").unwrap();
+        assert_ne!(&sealed[NONCE_LENGTH..NONCE_LENGTH + 14], b"patient record");
+        assert_eq!(cipher.decrypt(&sealed).unwrap(), b"patient record");
+
+        // Fresh nonces mean the same contents never encrypt the same way twice
+        assert_ne!(cipher.encrypt(b"patient record").unwrap(), sealed);
+
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(cipher.decrypt(&tampered).is_err());
+
+        let other = FileCipher::from_hex_key(&"ab".repeat(32)).unwrap();
+        assert!(other.decrypt(&sealed).is_err());
+
+        assert!(matches!(FileCipher::from_hex_key("abcd"), Err(AppError::ConfigError(_))));
+        assert!(matches!(FileCipher::from_hex_key(&"zz".repeat(32)), Err(AppError::ConfigError(_))));
+    }
+}
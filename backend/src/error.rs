@@ -3,12 +3,15 @@ use axum::{
     response::{IntoResponse, Response},
     Json,
 };
-use serde_json::json;
+use serde::Serialize;
 use thiserror::Error;
 
+use crate::models::ApiResponse;
+
 pub type Result<T> = std::result::Result<T, AppError>;
 
 #[derive(Error, Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum AppError {
     #[error("Validation error: {0}")]
     ValidationError(String),
@@ -46,8 +49,14 @@ pub enum AppError {
     #[error("Authorization failed: {0}")]
     AuthzError(String),
 
+    #[error("Range not satisfiable: {0}")]
+    RangeNotSatisfiableError(String),
+
     #[error("Bad request: {0}")]
     BadRequestError(String),
+
+    #[error("Conflict: {0}")]
+    ConflictError(String),
 }
 
 impl AppError {
@@ -55,7 +64,9 @@ impl AppError {
         match self {
             AppError::ValidationError(_) => StatusCode::BAD_REQUEST,
             AppError::BadRequestError(_) => StatusCode::BAD_REQUEST,
+            AppError::ConflictError(_) => StatusCode::CONFLICT,
             AppError::NotFoundError(_) => StatusCode::NOT_FOUND,
+            AppError::RangeNotSatisfiableError(_) => StatusCode::RANGE_NOT_SATISFIABLE,
             AppError::AuthError(_) => StatusCode::UNAUTHORIZED,
             AppError::AuthzError(_) => StatusCode::FORBIDDEN,
             AppError::TimeoutError => StatusCode::REQUEST_TIMEOUT,
@@ -73,7 +84,9 @@ impl AppError {
         match self {
             AppError::ValidationError(_) => "validation_error",
             AppError::BadRequestError(_) => "bad_request",
+            AppError::ConflictError(_) => "conflict",
             AppError::NotFoundError(_) => "not_found",
+            AppError::RangeNotSatisfiableError(_) => "range_not_satisfiable",
             AppError::AuthError(_) => "authentication_error",
             AppError::AuthzError(_) => "authorization_error",
             AppError::TimeoutError => "timeout_error",
@@ -88,21 +101,44 @@ impl AppError {
     }
 }
 
+// I am defining the error body: the usual ApiResponse envelope plus the original error object and timestamp
+#[derive(Serialize)]
+struct ErrorResponse {
+    #[serde(flatten)]
+    envelope: ApiResponse<()>,
+    error: ErrorDetail,
+    timestamp: String,
+}
+
+#[derive(Serialize)]
+struct ErrorDetail {
+    #[serde(rename = "type")]
+    error_type: &'static str,
+    message: String,
+    status: u16,
+}
+
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
         let status = self.status_code();
         let error_type = self.error_type();
         let message = self.to_string();
+        crate::telemetry::record_error(error_type);
 
-        let body = Json(json!({
-            "success": false,
-            "error": {
-                "type": error_type,
-                "message": message,
-                "status": status.as_u16()
+        let mut envelope = ApiResponse::error(error_type, &message);
+        if let AppError::ValidationError(detail) = &self {
+            envelope.validation_errors = Some(vec![detail.clone()]);
+        }
+
+        let body = Json(ErrorResponse {
+            envelope,
+            error: ErrorDetail {
+                error_type,
+                message,
+                status: status.as_u16(),
             },
-            "timestamp": chrono::Utc::now().to_rfc3339()
-        }));
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        });
 
         (status, body).into_response()
     }
@@ -153,4 +189,29 @@ impl From<validator::ValidationErrors> for AppError {
         
         AppError::ValidationError(error_messages.join(", "))
     }
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_error_body_uses_api_response_envelope() {
+        let response = AppError::NotFoundError("File not found".to_string()).into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["success"], false);
+        assert_eq!(body["data"], serde_json::Value::Null);
+        assert_eq!(body["message"], "Not found: File not found");
+        assert_eq!(body["error_code"], "not_found");
+        assert_eq!(body["error"]["type"], "not_found");
+        assert_eq!(body["error"]["status"], 404);
+        assert!(body["timestamp"].is_string());
+
+        // The success envelope carries the same keys, with error_code left null
+        let success = serde_json::to_value(ApiResponse::success(1, "ok")).unwrap();
+        assert_eq!(success["error_code"], serde_json::Value::Null);
+    }
+}
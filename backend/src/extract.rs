@@ -0,0 +1,146 @@
+// I am importing the error types used when turning stored files into plain text
+use crate::error::{AppError, Result};
+use quick_xml::{events::Event, Reader};
+use std::io::{Cursor, Read};
+
+const PDF_CONTENT_TYPE: &str = "application/pdf";
+const DOCX_CONTENT_TYPE: &str = "application/vnd.openxmlformats-officedocument.wordprocessingml.document";
+// The part of a DOCX archive that holds the main document body
+const DOCX_DOCUMENT_PART: &str = "word/document.xml";
+
+// I am extracting plain text from a stored file so it can be summarized
+pub async fn extract_text(content_type: Option<&str>, data: Vec<u8>) -> Result<String> {
+    // Ignore parameters such as "; charset=utf-8" when matching the media type
+    let media_type = content_type
+        .and_then(|content_type| content_type.split(';').next())
+        .map(|media_type| media_type.trim().to_ascii_lowercase());
+
+    match media_type.as_deref() {
+        Some(PDF_CONTENT_TYPE) => {
+            // PDF parsing is CPU-bound and can panic on malformed input, so keep it off the async workers
+            tokio::task::spawn_blocking(move || pdf_extract::extract_text_from_mem(&data))
+                .await
+                .map_err(|_| AppError::BadRequestError("PDF could not be parsed".to_string()))?
+                .map_err(|e| AppError::BadRequestError(format!("Failed to extract text from PDF: {}", e)))
+        }
+        Some(DOCX_CONTENT_TYPE) => tokio::task::spawn_blocking(move || extract_docx_text(&data))
+            .await
+            .map_err(|_| AppError::BadRequestError("DOCX could not be parsed".to_string()))?,
+        Some(media_type) if media_type.starts_with("text/") => String::from_utf8(data)
+            .map_err(|_| AppError::BadRequestError("Text file is not valid UTF-8".to_string())),
+        other => Err(AppError::BadRequestError(format!(
+            "Cannot summarize files of type {}; only PDF, DOCX and text files are supported",
+            other.unwrap_or("unknown")
+        ))),
+    }
+}
+
+// I am reading the document body out of a DOCX archive, one line per paragraph
+fn extract_docx_text(data: &[u8]) -> Result<String> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(data))
+        .map_err(|e| AppError::BadRequestError(format!("DOCX file is not a valid archive: {}", e)))?;
+
+    let mut xml = String::new();
+    archive
+        .by_name(DOCX_DOCUMENT_PART)
+        .map_err(|_| AppError::BadRequestError(format!("DOCX file has no {}", DOCX_DOCUMENT_PART)))?
+        .read_to_string(&mut xml)
+        .map_err(|e| AppError::BadRequestError(format!("Failed to read {}: {}", DOCX_DOCUMENT_PART, e)))?;
+
+    docx_xml_to_text(&xml)
+}
+
+// I am collecting the text runs (w:t) of WordprocessingML, turning paragraphs, breaks and tabs into whitespace
+fn docx_xml_to_text(xml: &str) -> Result<String> {
+    let mut reader = Reader::from_str(xml);
+    let mut text = String::new();
+    let mut in_text_run = false;
+
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|e| AppError::BadRequestError(format!("Malformed {}: {}", DOCX_DOCUMENT_PART, e)))?;
+
+        match event {
+            Event::Start(element) if element.local_name().as_ref() == b"t" => in_text_run = true,
+            Event::End(element) => match element.local_name().as_ref() {
+                b"t" => in_text_run = false,
+                b"p" => text.push('\n'),
+                _ => {}
+            },
+            Event::Empty(element) => match element.local_name().as_ref() {
+                b"tab" => text.push('\t'),
+                b"br" | b"cr" => text.push('\n'),
+                _ => {}
+            },
+            Event::Text(run) if in_text_run => {
+                let run = run
+                    .unescape()
+                    .map_err(|e| AppError::BadRequestError(format!("Malformed {}: {}", DOCX_DOCUMENT_PART, e)))?;
+                text.push_str(&run);
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(text.trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    // I am building a minimal DOCX archive holding only the given document body
+    fn docx_with_body(body: &str) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        writer.start_file(DOCX_DOCUMENT_PART, zip::write::SimpleFileOptions::default()).unwrap();
+        write!(
+            writer,
+            r#"<?xml version="1.0" encoding="UTF-8"?><w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main"><w:body>{}</w:body></w:document>"#,
+            body
+        )
+        .unwrap();
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[tokio::test]
+    async fn test_extract_text_by_content_type() {
+        let text = extract_text(Some("text/plain; charset=utf-8"), b"hello".to_vec()).await.unwrap();
+        assert_eq!(text, "hello");
+
+        assert!(matches!(
+            extract_text(Some("image/png"), vec![0x89, 0x50]).await,
+            Err(AppError::BadRequestError(_))
+        ));
+        assert!(matches!(extract_text(None, b"hello".to_vec()).await, Err(AppError::BadRequestError(_))));
+        assert!(matches!(
+            extract_text(Some("application/pdf"), b"not a pdf".to_vec()).await,
+            Err(AppError::BadRequestError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_extract_text_from_docx() {
+        let docx = docx_with_body(
+            r#"<w:p><w:r><w:t>Quarterly </w:t></w:r><w:r><w:t>report</w:t></w:r></w:p><w:p><w:r><w:t>Revenue</w:t><w:tab/><w:t>up &amp; rising</w:t></w:r></w:p>"#,
+        );
+        let text = extract_text(Some(DOCX_CONTENT_TYPE), docx).await.unwrap();
+        assert_eq!(text, "Quarterly report\nRevenue\tup & rising");
+
+        assert!(matches!(
+            extract_text(Some(DOCX_CONTENT_TYPE), b"PK\x03\x04 truncated".to_vec()).await,
+            Err(AppError::BadRequestError(_))
+        ));
+
+        // A valid zip that is not a Word document
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        writer.start_file("notes.txt", zip::write::SimpleFileOptions::default()).unwrap();
+        let not_docx = writer.finish().unwrap().into_inner();
+        assert!(matches!(
+            extract_text(Some(DOCX_CONTENT_TYPE), not_docx).await,
+            Err(AppError::BadRequestError(_))
+        ));
+    }
+}
@@ -1,54 +1,155 @@
-use axum::{extract::{Path, Multipart, State}, Json, response::Response, body::Body, http::{StatusCode, HeaderMap, header}};
+use axum::{
+    extract::{multipart::Field, ws::{Message, WebSocket, WebSocketUpgrade}, ConnectInfo, Path, Multipart, Query, State},
+    Json,
+    response::{sse::{Event, KeepAlive, Sse}, Html, IntoResponse, Response},
+    body::Body,
+    http::{StatusCode, HeaderMap, header},
+};
+use futures_util::{Stream, StreamExt};
+use std::convert::Infallible;
 use chrono::Utc;
 use uuid::Uuid;
 use validator::Validate;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::SocketAddr;
 use std::sync::Arc;
-use std::collections::HashMap;
-use tokio::sync::RwLock;
 
 use crate::{
-    auth::AuthService,
+    auth::{AuthService, AuthenticatedUser, OptionalUser},
+    chat_session::ChatSession,
+    conversations::{ConversationStore, CONVERSATION_TTL_HOURS},
+    csv_stats::analyze_csv,
     error::{AppError, Result},
+    extract::extract_text,
+    idempotency::{Claim, IdempotencyStore, IDEMPOTENCY_KEY_TTL_HOURS},
+    ids::{id_generator_from_env, IdGenerator},
+    llm::{provider_from_env, LlmProvider, SummarizeLimits},
+    mailer::Mailer,
+    malware::MalwareScanner,
     models::{
-        ApiResponse, CreateScanRequest, HealthResponse, ScanResponse, UploadResponse, 
-        SummarizeRequest, SummarizeResponse, ChatCompletionRequest, ChatCompletionResponse,
-        OpenAIConfig, FileDownloadResponse, FileListResponse,
+        ApiResponse, CleanupResponse, ConfigOptionsResponse, CreateScanRequest, CsvAnalysisQuery, CsvAnalysisResponse, DeleteFileQuery, DeleteScansQuery, DependencyStatus, HealthResponse, ReadinessResponse, ResumableUploadInitRequest, ResumableUploadResponse, ScanListQuery, ScanResponse, ScanSearchQuery, ScanSearchResult, UploadFromUrlRequest, UploadResponse, 
+        SummarizeQuery, SummarizeRequest, SummarizeFileRequest, SummarizeInputFormat, SummarizeResponse, SummarizeStrategy, TokenUsage, ChatCompletionRequest, ChatCompletionResponse, ChatSocketMessage, ChatStreamDone,
+        OpenAIConfig, OpenAIMessage, DownloadTokenQuery, DownloadUrlQuery, EmbeddingRequest, EmbeddingResponse, FileDownloadResponse, FileListQuery, FileListResponse, FileStatsResponse, MigrateFileRequest, PaginationParams, RenameFileRequest, ThumbnailQuery, UsageReportQuery, UsageReportResponse, UserUsageResponse,
         // Authentication models
-        RegisterRequest, LoginRequest, TokenLoginRequest, AuthResponse, TokenResponse, UserResponse
+        RegisterRequest, LoginRequest, TokenLoginRequest, RefreshTokenRequest, VerifyEmailRequest, ForgotPasswordRequest, ResetPasswordRequest, ChangePasswordRequest, AuthResponse, TokenResponse,
+        UserResponse, UserListResponse, LoginEvent,
+        matches_tag, parse_tag_filter, summary_language, validate_metadata, SCAN_FORMATS, SUMMARY_LANGUAGES,
     },
-    openai::OpenAIService,
-    storage::{StorageService, StorageConfig, StoredFile},
+    ocr::OcrClient,
+    qr::decode_codes,
+    openapi,
+    openai::{ChatStreamEvent, ConcurrencyPool, OpenAIService, EMBEDDING_MODELS},
+    redact,
+    resumable::{ResumableUploadStore, RESUMABLE_UPLOAD_TTL_HOURS, UPLOAD_LENGTH_HEADER, UPLOAD_OFFSET_HEADER},
+    registry::FileRegistry,
+    scans::ScanStore,
+    summary_cache::SummaryCache,
+    storage::{content_hash, sanitize_filename, StorageService, StorageConfig, StorageType, StoredFile, TemporaryUpload, CONTENT_SNIFF_BYTES},
+    telemetry,
+    thumbnails::{render_thumbnail, ThumbnailCache, DEFAULT_THUMBNAIL_WIDTH, MAX_THUMBNAIL_WIDTH},
+    url_fetch::UrlFetcher,
+    usage::{CostTagSource, UsageTracker},
+    webhooks::WebhookNotifier,
 };
 
 // Application state to hold shared services
 #[derive(Clone)]
 pub struct AppState {
     pub openai_service: Arc<OpenAIService>,
+    // Answers /chat/completion and /summarize; OpenAI unless LLM_PROVIDER says otherwise
+    pub llm_provider: Arc<dyn LlmProvider>,
     pub storage_service: Arc<StorageService>,
-    pub file_registry: Arc<RwLock<HashMap<Uuid, StoredFile>>>,
+    pub file_registry: Arc<FileRegistry>,
     pub auth_service: Arc<AuthService>,
+    pub id_generator: Arc<dyn IdGenerator>,
+    pub usage_tracker: Arc<UsageTracker>,
+    pub scan_store: Arc<ScanStore>,
+    pub allowed_models: Arc<HashSet<String>>,
+    pub thumbnail_cache: Arc<ThumbnailCache>,
+    pub ocr_client: Arc<OcrClient>,
+    pub chat_history_token_budget: usize,
+    pub summarize_limits: SummarizeLimits,
+    pub conversation_store: Arc<ConversationStore>,
+    pub webhook_notifier: Arc<WebhookNotifier>,
+    pub idempotency_store: Arc<IdempotencyStore>,
+    pub resumable_uploads: Arc<ResumableUploadStore>,
+    pub summary_cache: Arc<SummaryCache>,
+    // Checks uploads with ClamAV before they are stored, when CLAMAV_ADDR is set
+    pub malware_scanner: Arc<MalwareScanner>,
+    pub url_fetcher: Arc<UrlFetcher>,
+    // Delivers account emails such as password reset links
+    pub mailer: Arc<Mailer>,
 }
 
 impl AppState {
-    pub fn new() -> Result<Self> {
+    pub async fn new() -> Result<Self> {
+        let id_generator = id_generator_from_env();
+
         let openai_config = OpenAIConfig::default();
-        let openai_service = Arc::new(OpenAIService::new(openai_config)?);
+        let openai_service = Arc::new(OpenAIService::new(openai_config, id_generator.clone())?);
+        let llm_provider = provider_from_env(openai_service.clone(), id_generator.clone())?;
         
         let storage_config = StorageConfig::default();
-        let storage_service = Arc::new(StorageService::new(storage_config)
-            .map_err(|e| AppError::StorageError(e.to_string()))?);
+        let storage_service = Arc::new(StorageService::new(storage_config, id_generator.clone())?);
         
-        let auth_service = Arc::new(AuthService::new());
+        let auth_service = Arc::new(AuthService::new(id_generator.clone())?);
+
+        let chat_history_token_budget = ChatSession::budget_from_env();
+
+        let file_registry = Arc::new(FileRegistry::load(FileRegistry::path_from_env())
+            .await
+            .map_err(|e| AppError::StorageError(e.to_string()))?);
+
+        // Files from uploads that crashed mid-write have no registry entry and would otherwise pile up
+        if storage_service.clean_orphans_on_start() {
+            match file_registry.cleanup_orphans(&storage_service).await {
+                Ok(removed) => tracing::info!("Removed {} orphaned temporary files at startup", removed.len()),
+                Err(e) => tracing::warn!("Startup orphan cleanup failed: {}", e),
+            }
+        }
         
         Ok(Self {
             openai_service,
+            allowed_models: Arc::new(llm_provider.allowed_models()),
+            llm_provider,
             storage_service,
-            file_registry: Arc::new(RwLock::new(HashMap::new())),
+            file_registry,
             auth_service,
+            id_generator,
+            usage_tracker: Arc::new(UsageTracker::new(CostTagSource::from_env())),
+            scan_store: Arc::new(ScanStore::new()),
+            thumbnail_cache: Arc::new(ThumbnailCache::new(ThumbnailCache::dir_from_env())),
+            ocr_client: Arc::new(OcrClient::from_env()),
+            chat_history_token_budget,
+            summarize_limits: SummarizeLimits::from_env(),
+            conversation_store: Arc::new(ConversationStore::new(
+                chat_history_token_budget,
+                chrono::Duration::hours(CONVERSATION_TTL_HOURS),
+            )),
+            webhook_notifier: Arc::new(WebhookNotifier::from_env()),
+            idempotency_store: Arc::new(IdempotencyStore::new(chrono::Duration::hours(IDEMPOTENCY_KEY_TTL_HOURS))),
+            resumable_uploads: Arc::new(ResumableUploadStore::new(
+                ResumableUploadStore::dir_from_env(),
+                chrono::Duration::hours(RESUMABLE_UPLOAD_TTL_HOURS),
+            )),
+            summary_cache: Arc::new(SummaryCache::from_env()),
+            malware_scanner: Arc::new(MalwareScanner::from_env()),
+            url_fetcher: Arc::new(UrlFetcher::from_env()),
+            mailer: Arc::new(Mailer::from_env()),
         })
     }
 }
 
+// I am serving the OpenAPI document describing every route
+pub async fn openapi_spec() -> Json<serde_json::Value> {
+    Json(openapi::openapi_document())
+}
+
+// I am serving Swagger UI for the OpenAPI document
+pub async fn api_docs() -> Html<String> {
+    Html(openapi::docs_page())
+}
+
 pub async fn health_check() -> Result<Json<HealthResponse>> {
     let response = HealthResponse {
         status: "healthy".to_string(),
@@ -59,10 +160,99 @@ pub async fn health_check() -> Result<Json<HealthResponse>> {
     Ok(Json(response))
 }
 
+// Read from the same lists and limits the validators use, so the two can't drift apart
+pub async fn config_options(State(state): State<AppState>) -> Result<Json<ApiResponse<ConfigOptionsResponse>>> {
+    let mut models: Vec<String> = state.allowed_models.iter().cloned().collect();
+    models.sort();
+
+    let response = ConfigOptionsResponse {
+        scan_formats: SCAN_FORMATS.iter().map(|format| format.to_string()).collect(),
+        models,
+        embedding_models: EMBEDDING_MODELS.iter().map(|model| model.to_string()).collect(),
+        max_upload_bytes: state.storage_service.max_upload_bytes(),
+        max_files_per_upload: state.storage_service.max_files_per_upload(),
+        max_summarize_chars: state.summarize_limits.max_chars,
+        summary_languages: SUMMARY_LANGUAGES.iter().map(|(code, _)| code.to_string()).collect(),
+    };
+
+    Ok(Json(ApiResponse::success(response, "Options retrieved successfully")))
+}
+
+pub async fn readiness_check(State(state): State<AppState>) -> Result<(StatusCode, Json<ReadinessResponse>)> {
+    let mut dependencies = BTreeMap::new();
+
+    // Storage is critical: without it uploads and downloads cannot work
+    let storage = state.storage_service.check_ready().await;
+    dependencies.insert("storage".to_string(), dependency_status(storage.map_err(|e| e.to_string()), true));
+
+    // OpenAI is reported but not critical, since scans still succeed without AI analysis
+    let openai = match state.openai_service.check_ready().await {
+        Some(result) => dependency_status(result.map_err(|e| e.to_string()), false),
+        None => DependencyStatus {
+            status: "skipped".to_string(),
+            critical: false,
+            error: None,
+        },
+    };
+    dependencies.insert("openai".to_string(), openai);
+
+    let ready = dependencies
+        .values()
+        .all(|dependency| !dependency.critical || dependency.status == "up");
+
+    for (name, dependency) in dependencies.iter().filter(|(_, dependency)| dependency.status == "down") {
+        tracing::warn!("Readiness check: {} is down: {:?}", name, dependency.error);
+    }
+
+    let response = ReadinessResponse {
+        status: if ready { "ready" } else { "not_ready" }.to_string(),
+        dependencies,
+        timestamp: Utc::now().to_rfc3339(),
+    };
+
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    Ok((status, Json(response)))
+}
+
+fn dependency_status(result: std::result::Result<(), String>, critical: bool) -> DependencyStatus {
+    match result {
+        Ok(()) => DependencyStatus { status: "up".to_string(), critical, error: None },
+        Err(error) => DependencyStatus { status: "down".to_string(), critical, error: Some(error) },
+    }
+}
+
+pub async fn metrics(State(state): State<AppState>) -> Result<Response<Body>> {
+    let openai = &state.openai_service;
+    let body = telemetry::render(
+        openai.in_flight(ConcurrencyPool::Interactive),
+        openai.in_flight(ConcurrencyPool::Background),
+    );
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+        .body(Body::from(body))
+        .unwrap())
+}
+
 pub async fn create_scan(
     State(state): State<AppState>,
+    user: AuthenticatedUser,
+    headers: HeaderMap,
     Json(payload): Json<CreateScanRequest>
 ) -> Result<Json<ApiResponse<ScanResponse>>> {
+    // A retried request gets the scan created the first time instead of a second one
+    let reservation = match IdempotencyStore::key_from_headers(&headers, user.user_id, "scans")? {
+        Some(key) => match state.idempotency_store.claim(&key)? {
+            Claim::Replay(cached) => {
+                tracing::info!("Replaying scan creation for user {} from its idempotency key", user.user_id);
+                return Ok(Json(cached));
+            }
+            Claim::Fresh(reservation) => Some(reservation),
+        },
+        None => None,
+    };
+
     // Validate the request
     if let Err(validation_errors) = payload.validate() {
         return Ok(Json(ApiResponse::validation_error(
@@ -79,268 +269,1910 @@ pub async fn create_scan(
         )));
     }
 
-    tracing::info!("Creating new scan with data: {}", payload.data);
+    tracing::info!("Creating new scan for user {} with data: {}", user.user_id, redact::content(&payload.data));
 
     let format = payload.format.unwrap_or_else(|| "text".to_string());
-    
-    // Use OpenAI to analyze the scan data
-    let analysis = match state.openai_service.analyze_scan_data(&payload.data, &format).await {
-        Ok(analysis) => Some(analysis),
+    let scan = analyze_and_store_scan(&state, &headers, user.user_id, payload.data, format, payload.structured, payload.metadata).await;
+    state.webhook_notifier.notify("scan.completed", &scan);
+
+    let response = ApiResponse::success(scan, "Scan created and analyzed successfully");
+    if let Some(reservation) = reservation {
+        reservation.complete(&response);
+    }
+    Ok(Json(response))
+}
+
+// Run AI analysis on scan data (best effort) and save the resulting scan
+async fn analyze_and_store_scan(
+    state: &AppState,
+    headers: &HeaderMap,
+    owner_id: Uuid,
+    data: String,
+    format: String,
+    structured: bool,
+    metadata: HashMap<String, String>,
+) -> ScanResponse {
+    let tag = state.usage_tracker.attribution_tag(headers, &state.auth_service);
+    // Use OpenAI to analyze the scan data, embedding it for similarity search at the same time
+    let (result, embedding) = tokio::join!(
+        analyze_scan(state, &tag, &data, &format, structured),
+        state.openai_service.embed_scan_data(&data)
+    );
+
+    let (analysis, structured_analysis) = match result {
+        Ok((analysis, structured_analysis)) => (Some(analysis), structured_analysis),
         Err(e) => {
             tracing::warn!("Failed to analyze scan data with AI: {}", e);
+            (None, None)
+        }
+    };
+
+    let embedding = match embedding {
+        Ok(embedding) => {
+            state.usage_tracker.record(&tag, &embedding.model, &embedding.usage).await;
+            Some(embedding.vector)
+        }
+        Err(e) => {
+            tracing::warn!("Failed to embed scan data: {}", e);
             None
         }
     };
 
     let scan = ScanResponse {
-        id: Uuid::new_v4(),
-        data: payload.data,
+        id: state.id_generator.new_id(),
+        data,
         format,
-        timestamp: Utc::now().to_rfc3339(),
+        timestamp: Utc::now(),
         status: if analysis.is_some() { "analyzed" } else { "processed" }.to_string(),
+        analysis,
+        structured_analysis,
+        metadata,
+        codes: None,
     };
 
-    if let Some(analysis) = analysis {
-        tracing::info!("AI Analysis: {}", analysis);
+    if let Some(analysis) = &scan.analysis {
+        tracing::info!("AI Analysis: {}", redact::content(analysis));
     }
 
-    let response = ApiResponse::success(scan, "Scan created and analyzed successfully");
+    state.scan_store.insert(owner_id, scan.clone(), embedding);
+    scan
+}
+
+// Ask the model for a scan's analysis, plus its parsed JSON when structured, recording the usage
+async fn analyze_scan(
+    state: &AppState,
+    tag: &str,
+    data: &str,
+    format: &str,
+    structured: bool,
+) -> Result<(String, Option<serde_json::Value>)> {
+    let (completion, structured_analysis) = if structured {
+        let (completion, value) = state.openai_service.analyze_scan_data_structured(data, format).await?;
+        (completion, Some(value))
+    } else {
+        (state.openai_service.analyze_scan_data(data, format).await?, None)
+    };
+    state.usage_tracker.record(tag, &completion.model, &completion.usage).await;
+    Ok((completion.content, structured_analysis))
+}
+
+pub async fn create_embedding(
+    State(state): State<AppState>,
+    OptionalUser(user): OptionalUser,
+    headers: HeaderMap,
+    Json(payload): Json<EmbeddingRequest>
+) -> Result<Json<ApiResponse<EmbeddingResponse>>> {
+    // Validate the request
+    if let Err(validation_errors) = payload.validate() {
+        return Ok(Json(ApiResponse::validation_error(
+            "Validation failed",
+            validation_errors
+                .field_errors()
+                .iter()
+                .flat_map(|(field, errors)| {
+                    errors.iter().map(move |error| {
+                        format!("{}: {}", field, error.message.as_ref().unwrap_or(&std::borrow::Cow::Borrowed("Invalid value")))
+                    })
+                })
+                .collect(),
+        )));
+    }
+
+    if let Some(model) = payload.model.as_deref().filter(|model| !EMBEDDING_MODELS.contains(model)) {
+        return Ok(Json(ApiResponse::validation_error(
+            "Validation failed",
+            vec![format!("model: Invalid embedding model specified: {}", model)],
+        )));
+    }
+
+    tracing::info!("Processing embedding request");
+
+    let embedding = state.openai_service
+        .create_embedding(&payload.input, payload.model.as_deref())
+        .await?;
+
+    let tag = state.usage_tracker.attribution_tag(&headers, &state.auth_service);
+    state.usage_tracker.record(&tag, &embedding.model, &embedding.usage).await;
+    if let Some(user) = &user {
+        state.usage_tracker.record_for_user(user.user_id, &embedding.usage);
+    }
+
+    let response = EmbeddingResponse {
+        id: state.id_generator.new_id(),
+        embedding: embedding.vector,
+        model: embedding.model,
+        usage: embedding.usage,
+        timestamp: Utc::now().to_rfc3339(),
+    };
+
+    Ok(Json(ApiResponse::success(response, "Embedding generated successfully")))
+}
+
+pub async fn ocr_file(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(file_id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<ScanResponse>>> {
+    tracing::info!("User {} running OCR on file with id: {}", user.user_id, file_id);
+
+    let stored_file = find_accessible_file(&state, &user, file_id).await?;
+
+    let content_type = stored_file.content_type.clone()
+        .filter(|content_type| content_type.starts_with("image/"))
+        .ok_or_else(|| AppError::BadRequestError("OCR is only available for image files".to_string()))?;
+
+    let file_data = state.storage_service
+        .get_file(&stored_file)
+        .await
+        .map_err(|e| AppError::StorageError(e.to_string()))?;
+
+    let text = state.ocr_client.extract_text(file_data, &content_type).await?;
+    if text.trim().is_empty() {
+        return Err(AppError::BadRequestError("No text was found in the image".to_string()));
+    }
+
+    let scan = analyze_and_store_scan(&state, &headers, user.user_id, text, "ocr".to_string(), false, HashMap::new()).await;
+
+    let response = ApiResponse::success(scan, "Text extracted and analyzed successfully");
     Ok(Json(response))
 }
 
-pub async fn get_scan(Path(id): Path<Uuid>) -> Result<Json<ApiResponse<ScanResponse>>> {
-    tracing::info!("Retrieving scan with id: {}", id);
+// I am reading the QR codes and barcodes in one of the user's images into a scan; an image without one is not an error
+pub async fn decode_file(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(file_id): Path<Uuid>,
+) -> Result<Json<ApiResponse<ScanResponse>>> {
+    tracing::info!("User {} decoding codes in file with id: {}", user.user_id, file_id);
+
+    let stored_file = find_accessible_file(&state, &user, file_id).await?;
+
+    let is_image = stored_file.content_type.as_deref().is_some_and(|content_type| content_type.starts_with("image/"));
+    if !is_image {
+        return Err(AppError::BadRequestError("Decoding is only available for image files".to_string()));
+    }
+
+    let file_data = state.storage_service
+        .get_file(&stored_file)
+        .await
+        .map_err(|e| AppError::StorageError(e.to_string()))?;
+
+    // Locating codes scans the whole image, so keep it off the async workers
+    let codes = tokio::task::spawn_blocking(move || decode_codes(&file_data))
+        .await
+        .map_err(|e| AppError::InternalError(format!("Decode task failed: {}", e)))??;
 
-    // For now, return a mock scan. In a real application, you'd fetch this from a database.
     let scan = ScanResponse {
-        id,
-        data: "Sample scan data".to_string(),
-        format: "text".to_string(),
-        timestamp: Utc::now().to_rfc3339(),
-        status: "processed".to_string(),
+        id: state.id_generator.new_id(),
+        data: codes.iter().map(|code| code.payload.as_str()).collect::<Vec<_>>().join("\n"),
+        // QR codes come first, so a scan is a barcode scan only when barcodes are all it found
+        format: codes.first().map_or("qr", |code| code.symbology.scan_format()).to_string(),
+        timestamp: Utc::now(),
+        status: if codes.is_empty() { "no_codes_found" } else { "decoded" }.to_string(),
+        analysis: None,
+        structured_analysis: None,
+        metadata: HashMap::new(),
+        codes: Some(codes),
+    };
+
+    // Only scans with something in them are kept
+    let message = if scan.data.is_empty() {
+        "No QR code or barcode was found in the image"
+    } else {
+        state.scan_store.insert(user.user_id, scan.clone(), None);
+        "Codes decoded successfully"
+    };
+
+    Ok(Json(ApiResponse::success(scan, message)))
+}
+
+pub async fn analyze_csv_file(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(file_id): Path<Uuid>,
+    headers: HeaderMap,
+    Query(query): Query<CsvAnalysisQuery>,
+) -> Result<Json<ApiResponse<CsvAnalysisResponse>>> {
+    tracing::info!("User {} analyzing CSV file with id: {}", user.user_id, file_id);
+
+    let stored_file = find_accessible_file(&state, &user, file_id).await?;
+
+    // CSV has no magic bytes, so uploads are stored as text/plain (or the text/* type the client claimed)
+    let is_text = stored_file.content_type.as_deref().is_some_and(|content_type| content_type.starts_with("text/"));
+    if !is_text {
+        return Err(AppError::BadRequestError("CSV analysis is only available for text files".to_string()));
+    }
+
+    let file_data = state.storage_service
+        .get_file(&stored_file)
+        .await
+        .map_err(|e| AppError::StorageError(e.to_string()))?;
+
+    let stats = analyze_csv(file_data).await?;
+
+    // The overview is best effort, like scan analysis: the statistics are returned even if the model fails
+    let overview = if query.overview {
+        match state.openai_service.analyze_scan_data(&stats.describe(), "csv").await {
+            Ok(completion) => {
+                let tag = state.usage_tracker.attribution_tag(&headers, &state.auth_service);
+                state.usage_tracker.record(&tag, &completion.model, &completion.usage).await;
+                Some(completion.content)
+            }
+            Err(e) => {
+                tracing::warn!("Failed to describe CSV statistics with AI: {}", e);
+                None
+            }
+        }
+    } else {
+        None
     };
 
+    let response = CsvAnalysisResponse {
+        file_id,
+        rows: stats.rows,
+        columns: stats.columns,
+        overview,
+    };
+
+    Ok(Json(ApiResponse::success(response, "CSV analyzed successfully")))
+}
+
+pub async fn get_scan(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ApiResponse<ScanResponse>>> {
+    tracing::info!("User {} retrieving scan with id: {}", user.user_id, id);
+
+    let scan = state.scan_store
+        .get(user.user_id, &id)
+        .ok_or_else(|| AppError::NotFoundError("Scan not found".to_string()))?;
+
     let response = ApiResponse::success(scan, "Scan retrieved successfully");
     Ok(Json(response))
 }
 
-pub async fn list_scans() -> Result<Json<ApiResponse<Vec<ScanResponse>>>> {
-    tracing::info!("Listing all scans");
+pub async fn reanalyze_scan(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ApiResponse<ScanResponse>>> {
+    tracing::info!("User {} reanalyzing scan with id: {}", user.user_id, id);
+
+    let scan = state.scan_store
+        .get(user.user_id, &id)
+        .ok_or_else(|| AppError::NotFoundError("Scan not found".to_string()))?;
+
+    // Scans created with structured: true stay structured; a failed call leaves the stored scan as it was
+    let tag = state.usage_tracker.attribution_tag(&headers, &state.auth_service);
+    let (analysis, structured_analysis) =
+        analyze_scan(&state, &tag, &scan.data, &scan.format, scan.structured_analysis.is_some()).await?;
+
+    let scan = state.scan_store
+        .update(user.user_id, &id, |scan| {
+            scan.analysis = Some(analysis);
+            scan.structured_analysis = structured_analysis;
+            scan.status = "analyzed".to_string();
+        })
+        // Deleted while the model was answering
+        .ok_or_else(|| AppError::NotFoundError("Scan not found".to_string()))?;
+
+    let response = ApiResponse::success(scan, "Scan reanalyzed successfully");
+    Ok(Json(response))
+}
+
+pub async fn list_scans(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Query(pagination): Query<PaginationParams>,
+    Query(list_query): Query<ScanListQuery>,
+) -> Result<Json<ApiResponse<Vec<ScanResponse>>>> {
+    if let Err(message) = check_tag_filter(list_query.tag.as_deref()) {
+        return Ok(Json(ApiResponse::validation_error("Validation failed", vec![message])));
+    }
 
-    // For now, return mock data. In a real application, you'd fetch this from a database.
-    let scans = vec![
-        ScanResponse {
-            id: Uuid::new_v4(),
-            data: "Sample scan 1".to_string(),
-            format: "text".to_string(),
-            timestamp: Utc::now().to_rfc3339(),
-            status: "processed".to_string(),
-        },
-        ScanResponse {
-            id: Uuid::new_v4(),
-            data: "Sample scan 2".to_string(),
-            format: "qr".to_string(),
-            timestamp: Utc::now().to_rfc3339(),
-            status: "analyzed".to_string(),
-        },
-    ];
+    tracing::info!(
+        "User {} listing scans (limit: {}, offset: {})",
+        user.user_id,
+        pagination.limit(),
+        pagination.offset()
+    );
+
+    let scans: Vec<ScanResponse> = state.scan_store
+        .list(user.user_id)
+        .into_iter()
+        .filter(|scan| matches_tag(&scan.metadata, list_query.tag.as_deref()))
+        .collect();
+    let scans = pagination.paginate(scans);
 
     let response = ApiResponse::success(scans, "Scans retrieved successfully");
     Ok(Json(response))
 }
 
-pub async fn delete_scan(Path(id): Path<Uuid>) -> Result<Json<ApiResponse<String>>> {
-    tracing::info!("Deleting scan with id: {}", id);
+pub async fn search_scans(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    headers: HeaderMap,
+    Query(query): Query<ScanSearchQuery>,
+) -> Result<Json<ApiResponse<Vec<ScanSearchResult>>>> {
+    // Validate the request
+    if let Err(validation_errors) = query.validate() {
+        return Ok(Json(ApiResponse::validation_error(
+            "Validation failed",
+            validation_errors
+                .field_errors()
+                .iter()
+                .flat_map(|(field, errors)| {
+                    errors.iter().map(move |error| {
+                        format!("{}: {}", field, error.message.as_ref().unwrap_or(&std::borrow::Cow::Borrowed("Invalid value")))
+                    })
+                })
+                .collect(),
+        )));
+    }
+
+    tracing::info!("User {} searching scans (limit: {})", user.user_id, query.limit());
+
+    // Scans are embedded with the default model, so the query must be too
+    let embedding = state.openai_service.create_embedding(&query.q, None).await?;
+
+    let tag = state.usage_tracker.attribution_tag(&headers, &state.auth_service);
+    state.usage_tracker.record(&tag, &embedding.model, &embedding.usage).await;
+    state.usage_tracker.record_for_user(user.user_id, &embedding.usage);
+
+    let results = state.scan_store
+        .search(user.user_id, &embedding.vector, query.limit())
+        .into_iter()
+        .map(|(scan, score)| ScanSearchResult { scan, score })
+        .collect();
+
+    let response = ApiResponse::success(results, "Scans searched successfully");
+    Ok(Json(response))
+}
+
+pub async fn delete_scan(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ApiResponse<String>>> {
+    tracing::info!("User {} deleting scan with id: {}", user.user_id, id);
 
-    // In a real application, you'd delete the scan from the database here
-    // For now, we'll just simulate a successful deletion
+    state.scan_store
+        .remove(user.user_id, &id)
+        .ok_or_else(|| AppError::NotFoundError("Scan not found".to_string()))?;
 
     let response = ApiResponse::success(format!("Scan {} deleted", id), "Scan deleted successfully");
     Ok(Json(response))
 }
 
+pub async fn delete_all_scans(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Query(query): Query<DeleteScansQuery>,
+) -> Result<Json<ApiResponse<usize>>> {
+    if query.confirm != Some(true) {
+        return Err(AppError::BadRequestError(
+            "Deleting all scans requires ?confirm=true".to_string(),
+        ));
+    }
+
+    let deleted = state.scan_store.remove_all_for_owner(user.user_id);
+    tracing::info!("User {} deleted all {} of their scans", user.user_id, deleted);
+
+    let response = ApiResponse::success(deleted, "Scans deleted successfully");
+    Ok(Json(response))
+}
+
 pub async fn upload_file(
     State(state): State<AppState>,
+    user: AuthenticatedUser,
+    headers: HeaderMap,
     mut multipart: Multipart
-) -> Result<Json<ApiResponse<UploadResponse>>> {
-    tracing::info!("Processing file upload");
+) -> Result<Json<ApiResponse<Vec<UploadResponse>>>> {
+    tracing::info!("Processing file upload for user {}", user.user_id);
+
+    // A retried request gets the files stored the first time instead of new copies
+    let reservation = match IdempotencyStore::key_from_headers(&headers, user.user_id, "upload")? {
+        Some(key) => match state.idempotency_store.claim(&key)? {
+            Claim::Replay(cached) => {
+                tracing::info!("Replaying upload for user {} from its idempotency key", user.user_id);
+                return Ok(Json(cached));
+            }
+            Claim::Fresh(reservation) => Some(reservation),
+        },
+        None => None,
+    };
 
-    let mut filename = String::new();
-    let mut file_data: Option<Vec<u8>> = None;
-    let mut content_type: Option<String> = None;
+    let max_files = state.storage_service.max_files_per_upload();
+    let mut uploads = Vec::new();
+    let mut failures = Vec::new();
+    let mut last_error = None;
+    let mut file_count = 0;
+    let mut metadata = HashMap::new();
 
     while let Some(field) = multipart.next_field().await.map_err(|e| {
         AppError::ValidationError(format!("Error reading multipart field: {}", e))
     })? {
-        let field_name = field.name().unwrap_or("unknown").to_string();
-        
-        if field_name == "file" {
-            filename = field.file_name().unwrap_or("unknown").to_string();
-            content_type = field.content_type().map(|ct| ct.to_string());
-            
-            let data = field.bytes().await.map_err(|e| {
-                AppError::ValidationError(format!("Error reading file data: {}", e))
-            })?;
-            
-            // Validate file size (10MB limit)
-            if data.len() > 10 * 1024 * 1024 {
-                return Err(AppError::ValidationError("File size exceeds 10MB limit".to_string()));
+        // Every part carrying a filename is a file; of the plain form fields only metadata is read
+        let Some(filename) = field.file_name().map(str::to_string) else {
+            if field.name() == Some("metadata") {
+                match parse_upload_metadata(field).await {
+                    Ok(parsed) => metadata = parsed,
+                    // The whole request is refused, so files stored from earlier parts must not stay behind
+                    Err(e) => {
+                        discard_new_uploads(&state, &uploads).await;
+                        return Err(e);
+                    }
+                }
+            }
+            continue;
+        };
+        file_count += 1;
+
+        let result = if file_count > max_files {
+            Err(AppError::ValidationError(format!("Too many files in one upload (max {})", max_files)))
+        } else {
+            store_upload_field(&state, user.user_id, field, &filename).await
+        };
+
+        // One bad file is reported by name without undoing the ones already stored
+        match result {
+            Ok(upload) => {
+                telemetry::record_upload(&upload.status, upload.file_size);
+                uploads.push(upload);
+            }
+            Err(e) => {
+                telemetry::record_upload("failed", 0);
+                tracing::warn!("Upload of {} failed: {}", filename, e);
+                failures.push(format!("{}: {}", filename, e));
+                last_error = Some(e);
             }
-            
-            file_data = Some(data.to_vec());
-            tracing::info!("Uploaded file: {} ({} bytes)", filename, data.len());
         }
     }
 
-    if filename.is_empty() || file_data.is_none() {
+    if file_count == 0 {
         return Err(AppError::ValidationError("No file found in upload".to_string()));
     }
 
-    let data = file_data.unwrap();
-    
-    // Store the file using the storage service
-    let stored_file = state.storage_service
-        .store_file(&filename, content_type, &data)
-        .await
-        .map_err(|e| AppError::StorageError(e.to_string()))?;
+    if uploads.is_empty() {
+        return match (file_count, last_error) {
+            // A lone file keeps its own error status, as it did before multi-file uploads
+            (1, Some(e)) => Err(e),
+            _ => Ok(Json(ApiResponse::validation_error("No files were uploaded", failures))),
+        };
+    }
 
-    // Add to file registry
-    state.file_registry.write().await.insert(stored_file.id, stored_file.clone());
+    // The metadata field may follow the files, so tags are applied once every part has been read
+    if !metadata.is_empty() {
+        for upload in uploads.iter_mut().filter(|upload| upload.status == "uploaded") {
+            state.file_registry
+                .set_metadata(&upload.id, metadata.clone())
+                .await
+                .map_err(|e| AppError::StorageError(e.to_string()))?;
+            upload.metadata = metadata.clone();
+        }
+    }
+
+    for upload in &uploads {
+        state.webhook_notifier.notify("file.uploaded", upload);
+    }
 
-    let upload_response = UploadResponse::from(stored_file);
-    let response = ApiResponse::success(upload_response, "File uploaded successfully");
+    let message = match (failures.is_empty(), uploads.len()) {
+        (false, _) => "Some files failed to upload",
+        (true, 1) => "File uploaded successfully",
+        (true, _) => "Files uploaded successfully",
+    };
+    let mut response = ApiResponse::success(uploads, message);
+    if !failures.is_empty() {
+        response.validation_errors = Some(failures);
+    }
+    if let Some(reservation) = reservation {
+        reservation.complete(&response);
+    }
     Ok(Json(response))
 }
 
-pub async fn download_file(
+pub async fn upload_from_url(
     State(state): State<AppState>,
-    Path(file_id): Path<Uuid>,
-) -> Result<Response<Body>> {
-    tracing::info!("Downloading file with id: {}", file_id);
+    user: AuthenticatedUser,
+    Json(payload): Json<UploadFromUrlRequest>,
+) -> Result<Json<ApiResponse<UploadResponse>>> {
+    if let Err(validation_errors) = payload.validate() {
+        return Ok(Json(ApiResponse::validation_error(
+            "Validation failed",
+            validation_errors
+                .field_errors()
+                .iter()
+                .flat_map(|(field, errors)| {
+                    errors.iter().map(move |error| {
+                        format!("{}: {}", field, error.message.as_ref().unwrap_or(&std::borrow::Cow::Borrowed("Invalid value")))
+                    })
+                })
+                .collect(),
+        )));
+    }
 
-    let file_registry = state.file_registry.read().await;
-    let stored_file = file_registry.get(&file_id)
-        .ok_or_else(|| AppError::NotFoundError("File not found".to_string()))?;
+    tracing::info!("User {} uploading file from {}", user.user_id, payload.url);
 
-    let file_data = state.storage_service
-        .get_file(stored_file)
-        .await
-        .map_err(|e| AppError::StorageError(e.to_string()))?;
+    let fetched = state.url_fetcher
+        .fetch(&payload.url, state.storage_service.max_upload_bytes())
+        .await?;
+    let filename = payload.filename.unwrap_or(fetched.filename);
 
-    let mut headers = HeaderMap::new();
-    headers.insert(
-        header::CONTENT_DISPOSITION,
-        format!("attachment; filename=\"{}\"", stored_file.filename)
-            .parse()
-            .unwrap(),
-    );
+    // The downloaded bytes go through the same checks, scanning and deduplication as a multipart upload
+    let upload = match store_upload_data(&state, user.user_id, &filename, fetched.content_type, &fetched.data).await {
+        Ok(upload) => upload,
+        Err(e) => {
+            telemetry::record_upload("failed", 0);
+            return Err(e);
+        }
+    };
+    telemetry::record_upload(&upload.status, upload.file_size);
+    state.webhook_notifier.notify("file.uploaded", &upload);
+
+    let response = ApiResponse::success(upload, "File uploaded successfully");
+    Ok(Json(response))
+}
+
+// I am reading the JSON object of tags sent in the upload's metadata field
+async fn parse_upload_metadata(field: Field<'_>) -> Result<HashMap<String, String>> {
+    let text = field.text().await.map_err(|e| {
+        AppError::ValidationError(format!("Error reading metadata field: {}", e))
+    })?;
+    let metadata: HashMap<String, String> = serde_json::from_str(&text).map_err(|e| {
+        AppError::ValidationError(format!("metadata: Must be a JSON object of string values: {}", e))
+    })?;
+
+    validate_metadata(&metadata).map_err(|e| {
+        AppError::ValidationError(format!("metadata: {}", e.message.unwrap_or_default()))
+    })?;
+    Ok(metadata)
+}
+
+// I am removing the files a refused upload stored; deduplicated entries point at older files and are left alone
+async fn discard_new_uploads(state: &AppState, uploads: &[UploadResponse]) {
+    for upload in uploads.iter().filter(|upload| upload.status == "uploaded") {
+        match state.file_registry.remove(&upload.id).await {
+            Ok(Some(file)) => {
+                if let Err(e) = state.storage_service.delete_file(&file).await {
+                    tracing::warn!("Failed to remove file {} from a refused upload: {}", file.id, e);
+                }
+            }
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Failed to unregister file {} from a refused upload: {}", upload.id, e),
+        }
+    }
+}
+
+// I am storing and registering one uploaded file, or describing the existing copy if it is a duplicate
+async fn store_upload_field(
+    state: &AppState,
+    user_id: Uuid,
+    field: Field<'_>,
+    filename: &str,
+) -> Result<UploadResponse> {
+    let content_type = field.content_type().map(|ct| ct.to_string());
+
+    // Unencrypted temporary storage streams straight to disk; everything else still buffers the whole file
+    if state.storage_service.streams_uploads_to_disk() {
+        let stored_file = stream_upload_to_temporary_storage(&state.storage_service, field, filename, content_type).await?;
+        // The streamed copy is only complete once written, so it is scanned there and removed if rejected
+        if let Err(e) = state.malware_scanner.scan_file(std::path::Path::new(&stored_file.storage_path)).await {
+            if let Err(delete_error) = state.storage_service.delete_file(&stored_file).await {
+                tracing::warn!("Failed to remove rejected upload {}: {}", stored_file.id, delete_error);
+            }
+            return Err(e);
+        }
+        register_upload(state, user_id, stored_file).await
+    } else {
+        let data = field.bytes().await.map_err(|e| {
+            AppError::ValidationError(format!("Error reading file data: {}", e))
+        })?;
+        store_upload_data(state, user_id, filename, content_type, &data).await
+    }
+}
+
+// I am storing and registering a fully received file, or describing the existing copy if it is a duplicate
+async fn store_upload_data(
+    state: &AppState,
+    user_id: Uuid,
+    filename: &str,
+    content_type: Option<String>,
+    data: &[u8],
+) -> Result<UploadResponse> {
+    check_upload_size(&state.storage_service, data.len() as u64)?;
+
+    if data.is_empty() {
+        return Err(AppError::ValidationError("Uploaded file is empty".to_string()));
+    }
+
+    // Store the sniffed type rather than trusting the client's claim
+    let content_type = state.storage_service
+        .validate_content_type(content_type.as_deref(), data)
+        .map_err(|e| AppError::ValidationError(e.to_string()))?;
+
+    state.malware_scanner.scan(data).await?;
+
+    // Skip the upload entirely if this user already stored identical contents
+    if let Some(existing) = state.file_registry
+        .find_duplicate(user_id, &content_hash(data))
+        .await
+    {
+        return Ok(deduplicated_upload(existing));
+    }
+
+    let stored_file = state.storage_service
+        .store_file(filename, Some(content_type), data)
+        .await
+        .map_err(|e| AppError::StorageError(e.to_string()))?;
+
+    register_upload(state, user_id, stored_file).await
+}
+
+// I am adding a newly stored file to the registry under its owner
+async fn register_upload(state: &AppState, user_id: Uuid, mut stored_file: StoredFile) -> Result<UploadResponse> {
+    tracing::info!("Uploaded file: {} ({} bytes)", stored_file.filename, stored_file.file_size);
+    stored_file.owner_id = Some(user_id);
+
+    // Streamed uploads are only hashed once written, so drop the new copy if it duplicates an existing file
+    if let Some(existing) = state.file_registry
+        .find_duplicate(user_id, &stored_file.content_hash)
+        .await
+    {
+        if let Err(e) = state.storage_service.delete_file(&stored_file).await {
+            tracing::warn!("Failed to remove duplicate upload {}: {}", stored_file.id, e);
+        }
+        return Ok(deduplicated_upload(existing));
+    }
+
+    // Add to file registry; a file the registry doesn't know about would never be served or cleaned up, so drop it
+    if let Err(e) = state.file_registry.insert(stored_file.clone()).await {
+        if let Err(delete_error) = state.storage_service.delete_file(&stored_file).await {
+            tracing::warn!("Failed to remove unregistered upload {}: {}", stored_file.id, delete_error);
+        }
+        return Err(AppError::StorageError(e.to_string()));
+    }
+
+    Ok(UploadResponse::from(stored_file))
+}
+
+// Describe an already-stored file in place of a duplicate upload
+fn deduplicated_upload(existing: StoredFile) -> UploadResponse {
+    tracing::info!("Upload duplicates existing file {}", existing.id);
+
+    let mut upload_response = UploadResponse::from(existing);
+    upload_response.status = "deduplicated".to_string();
+    upload_response
+}
+
+// Validate file size against the configured limit
+fn check_upload_size(storage: &StorageService, size: u64) -> Result<()> {
+    let max_upload_bytes = storage.max_upload_bytes();
+    if size > max_upload_bytes {
+        return Err(AppError::ValidationError(format!(
+            "File size exceeds the {} byte upload limit",
+            max_upload_bytes
+        )));
+    }
+    Ok(())
+}
+
+// Writes a multipart field to temporary storage as chunks arrive, removing the partial file on failure
+async fn stream_upload_to_temporary_storage<E: std::fmt::Display>(
+    storage: &StorageService,
+    chunks: impl Stream<Item = std::result::Result<axum::body::Bytes, E>>,
+    filename: &str,
+    content_type: Option<String>,
+) -> Result<StoredFile> {
+    let mut chunks = std::pin::pin!(chunks);
+    let mut upload = storage
+        .begin_temporary_upload(filename)
+        .await
+        .map_err(|e| AppError::StorageError(e.to_string()))?;
+    // The first CONTENT_SNIFF_BYTES are held back until the type is settled, so nothing hits disk before then;
+    // a single network chunk can be a handful of bytes, too few to tell what the file is
+    let mut head = Vec::new();
+    let mut detected_content_type = None;
+
+    loop {
+        let chunk = match chunks.next().await {
+            Some(Ok(chunk)) => chunk,
+            None => break,
+            Some(Err(e)) => {
+                upload.abort().await;
+                return Err(AppError::ValidationError(format!("Error reading file data: {}", e)));
+            }
+        };
+
+        let received = upload.bytes_written() + (head.len() + chunk.len()) as u64;
+        if let Err(e) = check_upload_size(storage, received) {
+            upload.abort().await;
+            return Err(e);
+        }
+
+        if detected_content_type.is_some() {
+            if let Err(e) = upload.write_chunk(&chunk).await {
+                upload.abort().await;
+                return Err(AppError::StorageError(e.to_string()));
+            }
+            continue;
+        }
+
+        head.extend_from_slice(&chunk);
+        if head.len() >= CONTENT_SNIFF_BYTES {
+            match write_sniffed_head(storage, &mut upload, content_type.as_deref(), &head).await {
+                // The head now counts in bytes_written, so it must not be added to the size again
+                Ok(detected) => {
+                    detected_content_type = Some(detected);
+                    head.clear();
+                }
+                Err(e) => {
+                    upload.abort().await;
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    // A file shorter than the sniffing window is checked once it has been read in full
+    if detected_content_type.is_none() && !head.is_empty() {
+        match write_sniffed_head(storage, &mut upload, content_type.as_deref(), &head).await {
+            Ok(detected) => detected_content_type = Some(detected),
+            Err(e) => {
+                upload.abort().await;
+                return Err(e);
+            }
+        }
+    }
+
+    if upload.bytes_written() == 0 {
+        upload.abort().await;
+        return Err(AppError::ValidationError("Uploaded file is empty".to_string()));
+    }
+
+    upload
+        .finish(detected_content_type)
+        .await
+        .map_err(|e| AppError::StorageError(e.to_string()))
+}
+
+// I am checking the type of an upload's held-back head and then writing the head out
+async fn write_sniffed_head(
+    storage: &StorageService,
+    upload: &mut TemporaryUpload,
+    content_type: Option<&str>,
+    head: &[u8],
+) -> Result<String> {
+    let detected = storage
+        .validate_content_type(content_type, head)
+        .map_err(|e| AppError::ValidationError(e.to_string()))?;
+    upload.write_chunk(head).await.map_err(|e| AppError::StorageError(e.to_string()))?;
+    Ok(detected)
+}
+
+pub async fn init_resumable_upload(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Json(payload): Json<ResumableUploadInitRequest>,
+) -> Result<Json<ApiResponse<ResumableUploadResponse>>> {
+    if let Err(validation_errors) = payload.validate() {
+        return Ok(Json(ApiResponse::validation_error(
+            "Validation failed",
+            validation_errors
+                .field_errors()
+                .iter()
+                .flat_map(|(field, errors)| {
+                    errors.iter().map(move |error| {
+                        format!("{}: {}", field, error.message.as_ref().unwrap_or(&std::borrow::Cow::Borrowed("Invalid value")))
+                    })
+                })
+                .collect(),
+        )));
+    }
+
+    if payload.length == 0 {
+        return Ok(Json(ApiResponse::validation_error(
+            "Validation failed",
+            vec!["length: Uploaded file is empty".to_string()],
+        )));
+    }
+    // The whole file is checked up front, so chunks can never add up past the limit
+    check_upload_size(&state.storage_service, payload.length)?;
+
+    let upload = state.resumable_uploads
+        .create(state.id_generator.new_id(), user.user_id, payload.filename, payload.content_type, payload.length)
+        .await
+        .map_err(|e| AppError::StorageError(e.to_string()))?;
+    tracing::info!("User {} started resumable upload {} of {} bytes", user.user_id, upload.id, upload.length);
+
+    let response = ApiResponse::success(upload.response(None), "Upload started");
+    Ok(Json(response))
+}
+
+pub async fn append_resumable_upload(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(upload_id): Path<Uuid>,
+    headers: HeaderMap,
+    body: axum::body::Bytes,
+) -> Result<Response> {
+    let offset = headers
+        .get(UPLOAD_OFFSET_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .ok_or_else(|| AppError::ValidationError(format!("{} header must be a byte offset", UPLOAD_OFFSET_HEADER)))?;
+
+    let upload = state.resumable_uploads.get(upload_id, user.user_id).await?;
+    let mut upload = upload.lock().await;
+
+    // The type is sniffed once the first CONTENT_SNIFF_BYTES are in, however small the chunks carrying them,
+    // so a disallowed type is refused before the rest is sent
+    if let Some(head) = state.resumable_uploads
+        .completed_head(&upload, offset, &body, CONTENT_SNIFF_BYTES as u64)
+        .await?
+    {
+        state.storage_service
+            .validate_content_type(upload.content_type.as_deref(), &head)
+            .map_err(|e| AppError::ValidationError(e.to_string()))?;
+    }
+
+    let new_offset = state.resumable_uploads.append(&mut upload, offset, &body).await?;
+    tracing::info!("Resumable upload {} is at {} of {} bytes", upload_id, new_offset, upload.length);
+
+    // Once every byte is in, the file goes through the same checks and registration as a normal upload
+    let file = if upload.is_complete() {
+        let data = state.resumable_uploads.take_completed(&upload).await?;
+        match store_upload_data(&state, user.user_id, &upload.filename, upload.content_type.clone(), &data).await {
+            Ok(file) => {
+                telemetry::record_upload(&file.status, file.file_size);
+                state.webhook_notifier.notify("file.uploaded", &file);
+                Some(file)
+            }
+            Err(e) => {
+                telemetry::record_upload("failed", 0);
+                return Err(e);
+            }
+        }
+    } else {
+        None
+    };
+
+    let message = if file.is_some() { "Upload completed" } else { "Chunk received" };
+    let response = ApiResponse::success(upload.response(file), message);
+    Ok(([(UPLOAD_OFFSET_HEADER, new_offset.to_string())], Json(response)).into_response())
+}
+
+pub async fn resumable_upload_offset(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(upload_id): Path<Uuid>,
+) -> Result<Response> {
+    let upload = state.resumable_uploads.get(upload_id, user.user_id).await?;
+    let upload = upload.lock().await;
+
+    // The offset changes with every chunk, so it must never be served from a cache
+    Ok([
+        (UPLOAD_OFFSET_HEADER, upload.offset.to_string()),
+        (UPLOAD_LENGTH_HEADER, upload.length.to_string()),
+        ("cache-control", "no-store".to_string()),
+    ]
+    .into_response())
+}
+
+pub async fn get_file_metadata(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(file_id): Path<Uuid>,
+) -> Result<Json<ApiResponse<UploadResponse>>> {
+    tracing::info!("User {} retrieving metadata for file with id: {}", user.user_id, file_id);
+
+    let stored_file = find_accessible_file(&state, &user, file_id).await?;
+
+    let response = ApiResponse::success(UploadResponse::from(stored_file), "File retrieved successfully");
+    Ok(Json(response))
+}
+
+// I am serving a file to its owner, or to anyone holding a valid signed link from /files/:id/url
+pub async fn download_file(
+    State(state): State<AppState>,
+    user: Result<AuthenticatedUser>,
+    Path(file_id): Path<Uuid>,
+    Query(link): Query<DownloadTokenQuery>,
+    request_headers: HeaderMap,
+) -> Result<Response<Body>> {
+    let stored_file = match (link.token, link.exp) {
+        (Some(token), Some(expires_at)) => {
+            state.storage_service.verify_download_token(file_id, &token, expires_at)?;
+            tracing::info!("Downloading file with id {} through a signed link", file_id);
+            state.file_registry.get(&file_id).await
+                .filter(|stored_file| !stored_file.is_trashed())
+                .ok_or_else(|| AppError::NotFoundError("File not found".to_string()))?
+        }
+        (None, None) => {
+            let user = user?;
+            tracing::info!("User {} downloading file with id: {}", user.user_id, file_id);
+            find_accessible_file(&state, &user, file_id).await?
+        }
+        _ => return Err(AppError::AuthError("Invalid download token".to_string())),
+    };
+
+    let mut headers = HeaderMap::new();
+    if let Ok(disposition) = format!("attachment; filename=\"{}\"", stored_file.filename).parse() {
+        headers.insert(header::CONTENT_DISPOSITION, disposition);
+    }
+
+    if let Some(content_type) = stored_file.content_type.as_ref().and_then(|ct| ct.parse().ok()) {
+        headers.insert(header::CONTENT_TYPE, content_type);
+    }
+
+    // Range requests are only served for temporary storage, where we can seek within the file on disk
+    let supports_ranges = matches!(stored_file.storage_type, StorageType::Temporary);
+    let range_header = request_headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .filter(|_| supports_ranges);
+
+    let (status, file_data) = match range_header {
+        Some(range) => {
+            let Some((start, end)) = parse_byte_range(range, stored_file.file_size) else {
+                let mut response = AppError::RangeNotSatisfiableError(range.to_string()).into_response();
+                if let Ok(value) = format!("bytes */{}", stored_file.file_size).parse() {
+                    response.headers_mut().insert(header::CONTENT_RANGE, value);
+                }
+                return Ok(response);
+            };
+
+            let data = state.storage_service
+                .get_file_range(&stored_file, start, end)
+                .await
+                .map_err(|e| AppError::StorageError(e.to_string()))?;
+
+            if let Ok(value) = format!("bytes {}-{}/{}", start, end, stored_file.file_size).parse() {
+                headers.insert(header::CONTENT_RANGE, value);
+            }
+            (StatusCode::PARTIAL_CONTENT, data)
+        }
+        None => {
+            let data = state.storage_service
+                .get_file(&stored_file)
+                .await
+                .map_err(|e| AppError::StorageError(e.to_string()))?;
+            (StatusCode::OK, data)
+        }
+    };
+
+    if supports_ranges {
+        headers.insert(header::ACCEPT_RANGES, header::HeaderValue::from_static("bytes"));
+    }
+
+    let mut response = Response::new(Body::from(file_data));
+    *response.status_mut() = status;
+    *response.headers_mut() = headers;
+    Ok(response)
+}
+
+// Parse a single "bytes=start-end" range into inclusive offsets, or None if it can't be satisfied
+fn parse_byte_range(range: &str, file_size: u64) -> Option<(u64, u64)> {
+    let spec = range.trim().strip_prefix("bytes=")?;
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+
+    let (start, end) = if start.is_empty() {
+        // Suffix range: the last N bytes
+        let suffix: u64 = end.parse().ok()?;
+        if suffix == 0 {
+            return None;
+        }
+        (file_size.saturating_sub(suffix), file_size.checked_sub(1)?)
+    } else {
+        let start: u64 = start.parse().ok()?;
+        let end = if end.is_empty() {
+            file_size.checked_sub(1)?
+        } else {
+            end.parse::<u64>().ok()?.min(file_size.checked_sub(1)?)
+        };
+        (start, end)
+    };
+
+    (start <= end && start < file_size).then_some((start, end))
+}
+
+pub async fn get_file_thumbnail(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(file_id): Path<Uuid>,
+    Query(query): Query<ThumbnailQuery>,
+) -> Result<Response<Body>> {
+    let width = query.w.unwrap_or(DEFAULT_THUMBNAIL_WIDTH).min(MAX_THUMBNAIL_WIDTH);
+    if width == 0 {
+        return Err(AppError::ValidationError("w must be at least 1".to_string()));
+    }
+
+    tracing::info!("User {} requesting {}px thumbnail for file {}", user.user_id, width, file_id);
+
+    let stored_file = find_accessible_file(&state, &user, file_id).await?;
+
+    let is_image = stored_file.content_type.as_deref().is_some_and(|ct| ct.starts_with("image/"));
+    if !is_image {
+        return Err(AppError::BadRequestError("Thumbnails are only available for image files".to_string()));
+    }
+
+    let thumbnail = match state.thumbnail_cache.get(file_id, width).await {
+        Some(thumbnail) => thumbnail,
+        None => {
+            let file_data = state.storage_service
+                .get_file(&stored_file)
+                .await
+                .map_err(|e| AppError::StorageError(e.to_string()))?;
+
+            // Decoding and resizing is CPU-bound, so keep it off the async workers
+            let thumbnail = tokio::task::spawn_blocking(move || render_thumbnail(&file_data, width))
+                .await
+                .map_err(|e| AppError::InternalError(format!("Thumbnail task failed: {}", e)))??;
+
+            state.thumbnail_cache.put(file_id, width, &thumbnail).await;
+            thumbnail
+        }
+    };
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, thumbnail.content_type)
+        .body(Body::from(thumbnail.data))
+        .unwrap())
+}
+
+pub async fn get_file_download_url(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(file_id): Path<Uuid>,
+    Query(query): Query<DownloadUrlQuery>,
+) -> Result<Json<ApiResponse<FileDownloadResponse>>> {
+    tracing::info!("User {} getting download URL for file: {}", user.user_id, file_id);
+
+    let stored_file = find_accessible_file(&state, &user, file_id).await?;
+
+    let expires_in = query.expires_in();
+    let download_url = state.storage_service
+        .get_download_url(&stored_file, expires_in)
+        .await
+        .map_err(|e| AppError::StorageError(e.to_string()))?;
+
+    let expires_at = (Utc::now() + chrono::Duration::seconds(expires_in as i64)).to_rfc3339();
+
+    let response_data = FileDownloadResponse {
+        id: file_id,
+        filename: stored_file.filename.clone(),
+        download_url,
+        expires_at,
+    };
+
+    let response = ApiResponse::success(response_data, "Download URL generated successfully");
+    Ok(Json(response))
+}
+
+pub async fn list_files(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Query(pagination): Query<PaginationParams>,
+    Query(list_query): Query<FileListQuery>,
+) -> Result<Json<ApiResponse<FileListResponse>>> {
+    if let Err(message) = check_tag_filter(list_query.tag.as_deref()) {
+        return Ok(Json(ApiResponse::validation_error("Validation failed", vec![message])));
+    }
+    let after = match list_query.cursor(&pagination) {
+        Ok(after) => after,
+        Err(message) => return Ok(Json(ApiResponse::validation_error("Validation failed", vec![message]))),
+    };
+
+    tracing::info!(
+        "User {} listing uploaded files (limit: {}, offset: {})",
+        user.user_id,
+        pagination.limit(),
+        pagination.offset()
+    );
+
+    let files: Vec<UploadResponse> = state.file_registry
+        .list()
+        .await
+        .into_iter()
+        .filter(|stored_file| stored_file.owner_id == Some(user.user_id))
+        .filter(|stored_file| stored_file.is_trashed() == list_query.trashed.unwrap_or(false))
+        .map(UploadResponse::from)
+        .collect();
+    let files = list_query.apply(files);
+    let total_count = files.len();
+    let (files, next_cursor) = list_query.paginate(files, &pagination, after);
+
+    let response_data = FileListResponse {
+        files,
+        total_count,
+        next_cursor,
+    };
+
+    let response = ApiResponse::success(response_data, "Files retrieved successfully");
+    Ok(Json(response))
+}
+
+pub async fn file_stats(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+) -> Result<Json<ApiResponse<FileStatsResponse>>> {
+    tracing::info!("User {} retrieving file statistics", user.user_id);
+
+    let stats = state.file_registry.stats(user.user_id).await;
+
+    let response = ApiResponse::success(stats, "File statistics retrieved successfully");
+    Ok(Json(response))
+}
+
+pub async fn delete_file(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(file_id): Path<Uuid>,
+    Query(query): Query<DeleteFileQuery>,
+) -> Result<Json<ApiResponse<String>>> {
+    if !query.permanent.unwrap_or(false) {
+        tracing::info!("User {} moving file {} to the trash", user.user_id, file_id);
+        find_accessible_file(&state, &user, file_id).await?;
+
+        state.file_registry
+            .set_deleted_at(&file_id, Some(Utc::now()))
+            .await
+            .map_err(|e| AppError::StorageError(e.to_string()))?;
+
+        let response = ApiResponse::success(
+            format!("File {} moved to the trash", file_id),
+            "File moved to the trash"
+        );
+        return Ok(Json(response));
+    }
+
+    tracing::info!("User {} permanently deleting file with id: {}", user.user_id, file_id);
+
+    // Permanent deletion also empties a file that is already in the trash
+    let stored_file = find_accessible_file_in_trash(&state, &user, file_id).await?;
+
+    // Delete from storage
+    state.storage_service
+        .delete_file(&stored_file)
+        .await
+        .map_err(|e| AppError::StorageError(e.to_string()))?;
+
+    // Remove from registry
+    state.file_registry
+        .remove(&file_id)
+        .await
+        .map_err(|e| AppError::StorageError(e.to_string()))?;
+
+    state.thumbnail_cache.remove(file_id).await;
+
+    let response = ApiResponse::success(
+        format!("File {} deleted", file_id),
+        "File deleted successfully"
+    );
+    Ok(Json(response))
+}
+
+pub async fn restore_file(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(file_id): Path<Uuid>,
+) -> Result<Json<ApiResponse<UploadResponse>>> {
+    tracing::info!("User {} restoring file {} from the trash", user.user_id, file_id);
+
+    let stored_file = find_accessible_file_in_trash(&state, &user, file_id).await?;
+    if !stored_file.is_trashed() {
+        return Err(AppError::ConflictError("File is not in the trash".to_string()));
+    }
+
+    let restored = state.file_registry
+        .set_deleted_at(&file_id, None)
+        .await
+        .map_err(|e| AppError::StorageError(e.to_string()))?
+        .ok_or_else(|| AppError::NotFoundError("File not found".to_string()))?;
+
+    let response = ApiResponse::success(UploadResponse::from(restored), "File restored successfully");
+    Ok(Json(response))
+}
+
+// I am sanitizing a new display name; one that tries to leave its directory is refused rather than rewritten
+fn display_filename(filename: &str) -> Option<String> {
+    let filename = filename.trim();
+    let traverses = filename.contains(['/', '\\']) || filename.contains("..");
+    if filename.is_empty() || traverses {
+        return None;
+    }
+    Some(sanitize_filename(filename))
+}
+
+pub async fn rename_file(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(file_id): Path<Uuid>,
+    Json(payload): Json<RenameFileRequest>,
+) -> Result<Json<ApiResponse<UploadResponse>>> {
+    if let Err(validation_errors) = payload.validate() {
+        return Ok(Json(ApiResponse::validation_error(
+            "Validation failed",
+            validation_errors
+                .field_errors()
+                .iter()
+                .flat_map(|(field, errors)| {
+                    errors.iter().map(move |error| {
+                        format!("{}: {}", field, error.message.as_ref().unwrap_or(&std::borrow::Cow::Borrowed("Invalid value")))
+                    })
+                })
+                .collect(),
+        )));
+    }
+
+    let Some(filename) = display_filename(&payload.filename) else {
+        return Ok(Json(ApiResponse::validation_error(
+            "Validation failed",
+            vec!["filename: Must be a plain file name without path separators or \"..\"".to_string()],
+        )));
+    };
+
+    tracing::info!("User {} renaming file {} to {}", user.user_id, file_id, filename);
+
+    find_accessible_file(&state, &user, file_id).await?;
+
+    let renamed = state.file_registry
+        .set_filename(&file_id, filename)
+        .await
+        .map_err(|e| AppError::StorageError(e.to_string()))?
+        .ok_or_else(|| AppError::NotFoundError("File not found".to_string()))?;
+
+    let response = ApiResponse::success(UploadResponse::from(renamed), "File renamed successfully");
+    Ok(Json(response))
+}
+
+pub async fn migrate_file(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(file_id): Path<Uuid>,
+    Json(payload): Json<MigrateFileRequest>,
+) -> Result<Json<ApiResponse<UploadResponse>>> {
+    tracing::info!("User {} migrating file {} to {:?} storage", user.user_id, file_id, payload.target);
+
+    let stored_file = find_accessible_file(&state, &user, file_id).await?;
+
+    if stored_file.storage_type == payload.target {
+        let response = ApiResponse::success(UploadResponse::from(stored_file), "File is already in the requested storage");
+        return Ok(Json(response));
+    }
+
+    // An unconfigured target is the caller's mistake, not a server fault
+    state.storage_service
+        .check_backend(&payload.target)
+        .map_err(|e| match e {
+            AppError::ConfigError(message) => AppError::BadRequestError(message),
+            other => other,
+        })?;
+
+    let migrated = state.storage_service
+        .copy_file_to(&stored_file, &payload.target)
+        .await
+        .map_err(|e| AppError::StorageError(e.to_string()))?;
+
+    // The registry points at the copy before the original goes, so the file is never missing
+    state.file_registry
+        .insert(migrated.clone())
+        .await
+        .map_err(|e| AppError::StorageError(e.to_string()))?;
+
+    if let Err(e) = state.storage_service.delete_file(&stored_file).await {
+        tracing::warn!("Migrated file {} but failed to delete the original: {}", file_id, e);
+    }
+
+    let response = ApiResponse::success(UploadResponse::from(migrated), "File migrated successfully");
+    Ok(Json(response))
+}
+
+// Look up a file, rejecting callers who don't own it
+async fn find_accessible_file(state: &AppState, user: &AuthenticatedUser, file_id: Uuid) -> Result<StoredFile> {
+    let stored_file = find_accessible_file_in_trash(state, user, file_id).await?;
+    // Trashed files are only reachable through restore and permanent deletion
+    if stored_file.is_trashed() {
+        return Err(AppError::NotFoundError("File not found".to_string()));
+    }
+    Ok(stored_file)
+}
+
+// I am looking up a file the user may access, whether or not it is in the trash
+async fn find_accessible_file_in_trash(state: &AppState, user: &AuthenticatedUser, file_id: Uuid) -> Result<StoredFile> {
+    let stored_file = state.file_registry.get(&file_id).await
+        .ok_or_else(|| AppError::NotFoundError("File not found".to_string()))?;
+
+    if !stored_file.is_accessible_by(user.user_id) {
+        return Err(AppError::AuthzError("You do not have access to this file".to_string()));
+    }
+
+    Ok(stored_file)
+}
+
+pub async fn cleanup_temp_files(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+) -> Result<Json<ApiResponse<CleanupResponse>>> {
+    tracing::info!("User {} cleaning up expired temporary and trashed files", user.user_id);
+
+    let mut removed_file_ids = state.file_registry
+        .cleanup_expired(&state.storage_service, state.storage_service.temp_file_max_age_hours())
+        .await
+        .map_err(|e| AppError::StorageError(e.to_string()))?;
+    let purged_file_ids = state.file_registry
+        .purge_trash(&state.storage_service, state.storage_service.trash_retention_days())
+        .await
+        .map_err(|e| AppError::StorageError(e.to_string()))?;
+    for file_id in &purged_file_ids {
+        state.thumbnail_cache.remove(*file_id).await;
+    }
+    removed_file_ids.extend(purged_file_ids);
+
+    let response = ApiResponse::success(
+        CleanupResponse {
+            removed_count: removed_file_ids.len(),
+            removed_file_ids,
+        },
+        "Cleanup completed successfully"
+    );
+    Ok(Json(response))
+}
+
+pub async fn summarize_document(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    headers: HeaderMap,
+    Query(query): Query<SummarizeQuery>,
+    Json(payload): Json<SummarizeRequest>
+) -> Result<Json<ApiResponse<SummarizeResponse>>> {
+    // Validate the request
+    if let Err(validation_errors) = payload.validate() {
+        return Ok(Json(ApiResponse::validation_error(
+            "Validation failed",
+            validation_errors
+                .field_errors()
+                .iter()
+                .flat_map(|(field, errors)| {
+                    errors.iter().map(move |error| {
+                        format!("{}: {}", field, error.message.as_ref().unwrap_or(&std::borrow::Cow::Borrowed("Invalid value")))
+                    })
+                })
+                .collect(),
+        )));
+    }
+
+    // The stripped text is what gets summarized, cached and counted
+    let content = payload.input_format.to_plain_text(payload.content);
+    if content.trim().is_empty() {
+        return Ok(Json(ApiResponse::validation_error(
+            "Validation failed",
+            vec!["content: No text is left once the markup is removed".to_string()],
+        )));
+    }
+
+    let max_chars = state.summarize_limits.max_chars;
+    if content.chars().count() > max_chars {
+        return Ok(Json(ApiResponse::validation_error(
+            "Validation failed",
+            vec![format!("content: Content must be at most {} characters", max_chars)],
+        )));
+    }
+
+    tracing::info!(
+        "Summarizing document content for user {}: {}",
+        user.user_id,
+        redact::content(&content)
+    );
+
+    let original_length = content.len();
+    let max_length = payload.max_length.unwrap_or(200);
+    // Already validated, so the lookup only normalizes the code and finds the name for the prompt
+    let target_language = payload.target_language.as_deref().and_then(summary_language);
+    let language_code = target_language.map(|(code, _)| code);
+    let language_name = target_language.map(|(_, name)| name);
+
+    // Identical requests are answered from the cache; the content already passed moderation the first time
+    let cache_key = SummaryCache::key(
+        &content,
+        max_length,
+        state.llm_provider.summary_model(),
+        query.strategy,
+        language_code,
+    );
+    if let Some(cached) = state.summary_cache.get(&cache_key) {
+        tracing::info!("Serving cached summary for user {}", user.user_id);
+        let summarize_response = SummarizeResponse {
+            id: state.id_generator.new_id(),
+            usage: TokenUsage::default(),
+            cached: true,
+            timestamp: Utc::now().to_rfc3339(),
+            ..cached
+        };
+        return Ok(Json(ApiResponse::success(summarize_response, "Document summary served from cache")));
+    }
+
+    state.openai_service.check_moderation(&content).await?;
+    
+    // Use the configured LLM provider, splitting content too long for one request into chunks
+    let chunk_chars = state.summarize_limits.chunk_chars;
+    let (summary, chunks) = match query.strategy {
+        SummarizeStrategy::Auto if content.chars().count() <= chunk_chars => {
+            (state.llm_provider.summarize_text(&content, max_length, language_name).await?, 1)
+        }
+        SummarizeStrategy::Single => (state.llm_provider.summarize_text(&content, max_length, language_name).await?, 1),
+        SummarizeStrategy::Auto | SummarizeStrategy::MapReduce => {
+            state.llm_provider.summarize_long(&content, max_length, chunk_chars, language_name).await?
+        }
+    };
+
+    let tag = state.usage_tracker.attribution_tag(&headers, &state.auth_service);
+    state.usage_tracker.record(&tag, &summary.model, &summary.usage).await;
+    state.usage_tracker.record_for_user(user.user_id, &summary.usage);
+
+    let usage = summary.usage;
+    let summary = summary.text;
+
+    let summary_length = summary.len();
+
+    let summarize_response = SummarizeResponse {
+        id: state.id_generator.new_id(),
+        original_content: content,
+        summary,
+        original_length,
+        summary_length,
+        usage,
+        chunks,
+        cached: false,
+        target_language: language_code.map(str::to_string),
+        timestamp: Utc::now().to_rfc3339(),
+    };
+    state.summary_cache.insert(cache_key, summarize_response.clone());
+
+    let response = ApiResponse::success(summarize_response, "Document summarized successfully using AI");
+    Ok(Json(response))
+}
+
+pub async fn summarize_file(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Path(file_id): Path<Uuid>,
+    headers: HeaderMap,
+    query: Query<SummarizeQuery>,
+    payload: Option<Json<SummarizeFileRequest>>,
+) -> Result<Json<ApiResponse<SummarizeResponse>>> {
+    tracing::info!("User {} summarizing file with id: {}", user.user_id, file_id);
+
+    let stored_file = find_accessible_file(&state, &user, file_id).await?;
+
+    let file_data = state.storage_service
+        .get_file(&stored_file)
+        .await
+        .map_err(|e| AppError::StorageError(e.to_string()))?;
+
+    let content = extract_text(stored_file.content_type.as_deref(), file_data).await?;
+
+    // The extracted text goes through the same validation and accounting as a direct summarize request
+    let Json(options) = payload.unwrap_or_default();
+    let request = SummarizeRequest {
+        content,
+        max_length: options.max_length,
+        // Extracted file text is already plain
+        input_format: SummarizeInputFormat::Plain,
+        target_language: options.target_language,
+    };
+
+    summarize_document(State(state), user, headers, query, Json(request)).await
+}
+
+// New OpenAI-specific handlers
+pub async fn chat_completion(
+    State(state): State<AppState>,
+    OptionalUser(user): OptionalUser,
+    headers: HeaderMap,
+    Json(payload): Json<ChatCompletionRequest>
+) -> Result<Json<ApiResponse<ChatCompletionResponse>>> {
+    // Validate the request
+    if let Err(validation_errors) = payload.validate() {
+        return Ok(Json(ApiResponse::validation_error(
+            "Validation failed",
+            validation_errors
+                .field_errors()
+                .iter()
+                .flat_map(|(field, errors)| {
+                    errors.iter().map(move |error| {
+                        format!("{}: {}", field, error.message.as_ref().unwrap_or(&std::borrow::Cow::Borrowed("Invalid value")))
+                    })
+                })
+                .collect(),
+        )));
+    }
+
+    if let Err(message) = check_model_allowed(&state, payload.model.as_deref()) {
+        return Ok(Json(ApiResponse::validation_error("Validation failed", vec![message])));
+    }
+
+    tracing::info!("Processing chat completion request: {}", redact::content(&payload.content));
+
+    state.openai_service.check_moderation(&payload.content).await?;
+
+    let owner_id = user.as_ref().map(|user| user.user_id);
+    let (conversation_id, history) = resolve_conversation(&state, owner_id, payload.conversation_id)?;
+    let user_content = payload.content.clone();
+
+    let mut completion_response = state
+        .llm_provider
+        .chat_completion(payload, &history)
+        .await?;
+
+    state
+        .conversation_store
+        .record_turn(owner_id, conversation_id, user_content, completion_response.content.clone());
+    completion_response.conversation_id = Some(conversation_id);
+
+    let tag = state.usage_tracker.attribution_tag(&headers, &state.auth_service);
+    state.usage_tracker.record(&tag, &completion_response.model, &completion_response.usage).await;
+    if let Some(user) = &user {
+        state.usage_tracker.record_for_user(user.user_id, &completion_response.usage);
+    }
+
+    let response = ApiResponse::success(completion_response, "Chat completion generated successfully");
+    Ok(Json(response))
+}
+
+// I am loading the history of the conversation a chat continues, or picking an id for a new one
+fn resolve_conversation(
+    state: &AppState,
+    owner_id: Option<Uuid>,
+    conversation_id: Option<Uuid>,
+) -> Result<(Uuid, Vec<OpenAIMessage>)> {
+    match conversation_id {
+        Some(id) => {
+            let history = state
+                .conversation_store
+                .history(owner_id, &id)
+                .ok_or_else(|| AppError::NotFoundError(format!("Conversation {} not found", id)))?;
+            Ok((id, history))
+        }
+        None => Ok((state.id_generator.new_id(), Vec::new())),
+    }
+}
+
+// I am rejecting tag filters that are not in key:value form
+fn check_tag_filter(tag: Option<&str>) -> std::result::Result<(), String> {
+    match tag {
+        Some(tag) if parse_tag_filter(tag).is_none() => {
+            Err(format!("tag: Tag filter must look like key:value, got {}", tag))
+        }
+        _ => Ok(()),
+    }
+}
+
+// I am rejecting client-chosen models that are not in the configured allow-list
+fn check_model_allowed(state: &AppState, model: Option<&str>) -> std::result::Result<(), String> {
+    match model {
+        Some(model) if !state.allowed_models.contains(model) => {
+            Err(format!("model: Invalid model specified: {}", model))
+        }
+        _ => Ok(()),
+    }
+}
+
+pub async fn chat_stream(
+    State(state): State<AppState>,
+    OptionalUser(user): OptionalUser,
+    headers: HeaderMap,
+    Json(payload): Json<ChatCompletionRequest>
+) -> Result<Sse<impl Stream<Item = std::result::Result<Event, Infallible>>>> {
+    // Validate the request
+    payload.validate()?;
+    check_model_allowed(&state, payload.model.as_deref()).map_err(AppError::ValidationError)?;
+
+    tracing::info!("Processing streaming chat completion request: {}", redact::content(&payload.content));
+
+    state.openai_service.check_moderation(&payload.content).await?;
+
+    let user_id = user.map(|user| user.user_id);
+    let (conversation_id, history) = resolve_conversation(&state, user_id, payload.conversation_id)?;
+    let user_content = payload.content.clone();
+
+    let events = state
+        .llm_provider
+        .chat_completion_stream(payload, &history)
+        .await?;
+
+    let tag = state.usage_tracker.attribution_tag(&headers, &state.auth_service);
+    let usage_tracker = state.usage_tracker.clone();
+    let conversation_store = state.conversation_store.clone();
+
+    // The reply is gathered from its deltas so the finished turn can be recorded like a plain completion
+    let events = events.scan(String::new(), |reply, event| {
+        let finished = match &event {
+            Ok(ChatStreamEvent::Delta(content)) => {
+                reply.push_str(content);
+                None
+            }
+            Ok(ChatStreamEvent::Done { .. }) => Some(std::mem::take(reply)),
+            Err(_) => None,
+        };
+        std::future::ready(Some((event, finished)))
+    });
+
+    let stream = events.then(move |(event, finished)| {
+        let tag = tag.clone();
+        let usage_tracker = usage_tracker.clone();
+        let conversation_store = conversation_store.clone();
+        let user_content = user_content.clone();
+        async move {
+            let event = match event {
+                Ok(ChatStreamEvent::Delta(content)) => Event::default().event("delta").data(content),
+                Ok(ChatStreamEvent::Done { model, usage }) => {
+                    if let Some(reply) = finished {
+                        conversation_store.record_turn(user_id, conversation_id, user_content, reply);
+                    }
+                    if let Some(usage) = &usage {
+                        usage_tracker.record(&tag, &model, usage).await;
+                        if let Some(user_id) = user_id {
+                            usage_tracker.record_for_user(user_id, usage);
+                        }
+                    }
+                    Event::default()
+                        .event("done")
+                        .json_data(ChatStreamDone { model, usage, conversation_id })
+                        .unwrap_or_else(|_| Event::default().event("done"))
+                }
+                Err(e) => Event::default().event("error").data(e.to_string()),
+            };
+            Ok(event)
+        }
+    });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+pub async fn chat_ws(
+    State(state): State<AppState>,
+    OptionalUser(user): OptionalUser,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let tag = state.usage_tracker.attribution_tag(&headers, &state.auth_service);
+    let user_id = user.map(|user| user.user_id);
+
+    ws.on_upgrade(move |socket| run_chat_session(socket, state, tag, user_id))
+}
+
+// I am running one interactive chat; its history lives only as long as the socket
+async fn run_chat_session(mut socket: WebSocket, state: AppState, tag: String, user_id: Option<Uuid>) {
+    let mut session = ChatSession::new(state.chat_history_token_budget);
+
+    while let Some(Ok(message)) = socket.recv().await {
+        let content = match message {
+            Message::Text(content) => content,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        if content.trim().is_empty() {
+            continue;
+        }
+
+        // A turn joins the history only once answered, so a refused message is never resent to the model
+        let history = session.messages();
+        match stream_chat_turn(&mut socket, &state, content.clone(), &history, &tag, user_id).await {
+            Ok(Some(reply)) => {
+                session.push("user", content);
+                session.push("assistant", reply);
+            }
+            Ok(None) => {}
+            // The client went away mid-reply
+            Err(_) => break,
+        }
+    }
+
+    tracing::info!("Chat session closed");
+}
+
+// I am streaming one reply over the socket and returning its full text, or None if the message was refused or
+// the provider failed
+async fn stream_chat_turn(
+    socket: &mut WebSocket,
+    state: &AppState,
+    content: String,
+    history: &[OpenAIMessage],
+    tag: &str,
+    user_id: Option<Uuid>,
+) -> std::result::Result<Option<String>, axum::Error> {
+    if let Err(e) = state.openai_service.check_moderation(&content).await {
+        send_socket_message(socket, ChatSocketMessage::Error { message: e.to_string() }).await?;
+        return Ok(None);
+    }
 
-    if let Some(content_type) = &stored_file.content_type {
-        headers.insert(header::CONTENT_TYPE, content_type.parse().unwrap());
+    let request = ChatCompletionRequest {
+        content,
+        model: None,
+        temperature: None,
+        max_tokens: None,
+        system_prompt: None,
+        base_url_override: None,
+        conversation_id: None,
+        timeout_seconds: None,
+    };
+    let mut events = match state.llm_provider.chat_completion_stream(request, history).await {
+        Ok(events) => events,
+        Err(e) => {
+            send_socket_message(socket, ChatSocketMessage::Error { message: e.to_string() }).await?;
+            return Ok(None);
+        }
+    };
+
+    let mut reply = String::new();
+    while let Some(event) = events.next().await {
+        match event {
+            Ok(ChatStreamEvent::Delta(content)) => {
+                reply.push_str(&content);
+                send_socket_message(socket, ChatSocketMessage::Delta { content }).await?;
+            }
+            Ok(ChatStreamEvent::Done { model, usage }) => {
+                if let Some(usage) = &usage {
+                    state.usage_tracker.record(tag, &model, usage).await;
+                    if let Some(user_id) = user_id {
+                        state.usage_tracker.record_for_user(user_id, usage);
+                    }
+                }
+                send_socket_message(socket, ChatSocketMessage::Done { model, usage }).await?;
+            }
+            Err(e) => {
+                send_socket_message(socket, ChatSocketMessage::Error { message: e.to_string() }).await?;
+                return Ok(None);
+            }
+        }
     }
 
-    Ok(Response::builder()
-        .status(StatusCode::OK)
-        .body(Body::from(file_data))
-        .unwrap())
+    Ok(Some(reply))
 }
 
-pub async fn get_file_download_url(
-    State(state): State<AppState>,
-    Path(file_id): Path<Uuid>,
-) -> Result<Json<ApiResponse<FileDownloadResponse>>> {
-    tracing::info!("Getting download URL for file: {}", file_id);
+async fn send_socket_message(socket: &mut WebSocket, message: ChatSocketMessage) -> std::result::Result<(), axum::Error> {
+    let text = serde_json::to_string(&message).unwrap_or_default();
+    socket.send(Message::Text(text)).await
+}
 
-    let file_registry = state.file_registry.read().await;
-    let stored_file = file_registry.get(&file_id)
-        .ok_or_else(|| AppError::NotFoundError("File not found".to_string()))?;
+pub async fn usage_report(
+    State(state): State<AppState>,
+    Query(query): Query<UsageReportQuery>,
+) -> Result<Json<ApiResponse<UsageReportResponse>>> {
+    let window_hours = query.window_hours.unwrap_or(24);
+    if !(1..=24 * 30).contains(&window_hours) {
+        return Err(AppError::ValidationError("window_hours must be between 1 and 720".to_string()));
+    }
 
-    let download_url = state.storage_service
-        .get_download_url(stored_file, 3600) // 1 hour expiry
-        .await
-        .map_err(|e| AppError::StorageError(e.to_string()))?;
+    tracing::info!("Aggregating OpenAI usage over the last {} hours", window_hours);
 
-    let expires_at = (Utc::now() + chrono::Duration::hours(1)).to_rfc3339();
+    let since = Utc::now() - chrono::Duration::hours(window_hours);
+    let tags = state.usage_tracker.aggregate_since(since).await;
 
-    let response_data = FileDownloadResponse {
-        id: file_id,
-        filename: stored_file.filename.clone(),
-        download_url,
-        expires_at,
+    let report = UsageReportResponse {
+        window_hours,
+        since: since.to_rfc3339(),
+        tags,
     };
 
-    let response = ApiResponse::success(response_data, "Download URL generated successfully");
+    let response = ApiResponse::success(report, "Usage report generated successfully");
     Ok(Json(response))
 }
 
-pub async fn list_files(
+pub async fn my_usage(
     State(state): State<AppState>,
-) -> Result<Json<ApiResponse<FileListResponse>>> {
-    tracing::info!("Listing all uploaded files");
-
-    let file_registry = state.file_registry.read().await;
-    let files: Vec<UploadResponse> = file_registry
-        .values()
-        .map(|stored_file| UploadResponse::from(stored_file.clone()))
-        .collect();
+    user: AuthenticatedUser,
+) -> Result<Json<ApiResponse<UserUsageResponse>>> {
+    tracing::info!("User {} retrieving their OpenAI usage", user.user_id);
 
-    let response_data = FileListResponse {
-        total_count: files.len(),
-        files,
+    let usage = UserUsageResponse {
+        user_id: user.user_id,
+        usage: state.usage_tracker.usage_for_user(user.user_id),
     };
 
-    let response = ApiResponse::success(response_data, "Files retrieved successfully");
+    let response = ApiResponse::success(usage, "Usage retrieved successfully");
     Ok(Json(response))
 }
 
-pub async fn delete_file(
+pub async fn list_users(
     State(state): State<AppState>,
-    Path(file_id): Path<Uuid>,
-) -> Result<Json<ApiResponse<String>>> {
-    tracing::info!("Deleting file with id: {}", file_id);
-
-    let mut file_registry = state.file_registry.write().await;
-    let stored_file = file_registry.get(&file_id)
-        .ok_or_else(|| AppError::NotFoundError("File not found".to_string()))?
-        .clone();
-
-    // Delete from storage
-    state.storage_service
-        .delete_file(&stored_file)
-        .await
-        .map_err(|e| AppError::StorageError(e.to_string()))?;
+    user: AuthenticatedUser,
+    Query(pagination): Query<PaginationParams>,
+) -> Result<Json<ApiResponse<UserListResponse>>> {
+    tracing::info!(
+        "Admin {} listing users (limit: {}, offset: {})",
+        user.user_id,
+        pagination.limit(),
+        pagination.offset()
+    );
 
-    // Remove from registry
-    file_registry.remove(&file_id);
+    let users = state.auth_service.list_users();
+    let response_data = UserListResponse {
+        total_count: users.len(),
+        users: pagination.paginate(users),
+    };
 
-    let response = ApiResponse::success(
-        format!("File {} deleted", file_id),
-        "File deleted successfully"
-    );
+    let response = ApiResponse::success(response_data, "Users retrieved successfully");
     Ok(Json(response))
 }
 
-pub async fn cleanup_temp_files(
+// MARK: - Authentication Handlers
+
+pub async fn register(
     State(state): State<AppState>,
-) -> Result<Json<ApiResponse<String>>> {
-    tracing::info!("Cleaning up expired temporary files");
+    Json(payload): Json<RegisterRequest>
+) -> Result<Json<ApiResponse<UserResponse>>> {
+    // Validate the request
+    if let Err(validation_errors) = payload.validate() {
+        return Ok(Json(ApiResponse::validation_error(
+            "Validation failed",
+            validation_errors
+                .field_errors()
+                .iter()
+                .flat_map(|(field, errors)| {
+                    errors.iter().map(move |error| {
+                        format!("{}: {}", field, error.message.as_ref().unwrap_or(&std::borrow::Cow::Borrowed("Invalid value")))
+                    })
+                })
+                .collect(),
+        )));
+    }
 
-    let deleted_count = state.storage_service
-        .cleanup_expired_temp_files(24) // 24 hours
-        .await
-        .map_err(|e| AppError::StorageError(e.to_string()))?;
+    let violations = state.auth_service.password_policy().violations(&payload.password);
+    if !violations.is_empty() {
+        return Ok(Json(ApiResponse::validation_error(
+            "Validation failed",
+            violations.into_iter().map(|violation| format!("password: {}", violation)).collect(),
+        )));
+    }
 
-    let response = ApiResponse::success(
-        format!("Cleaned up {} expired files", deleted_count),
-        "Cleanup completed successfully"
+    tracing::info!("Registering new user: {}", redact::email(&payload.email));
+
+    // Register the user; the account stays inactive until the email is verified
+    let (user, verification_token) = state
+        .auth_service
+        .register_user(payload.email, payload.password)
+        .await?;
+
+    state.mailer.send(
+        &user.email,
+        "Verify your email",
+        &format!("Use this token to activate your account: {}", verification_token),
     );
+
+    let response = ApiResponse::success(user, "User registered successfully; verify your email to activate the account");
     Ok(Json(response))
 }
 
-pub async fn summarize_document(
+pub async fn verify_email(
     State(state): State<AppState>,
-    Json(payload): Json<SummarizeRequest>
-) -> Result<Json<ApiResponse<SummarizeResponse>>> {
+    Json(payload): Json<VerifyEmailRequest>
+) -> Result<Json<ApiResponse<UserResponse>>> {
     // Validate the request
     if let Err(validation_errors) = payload.validate() {
         return Ok(Json(ApiResponse::validation_error(
@@ -357,37 +2189,19 @@ pub async fn summarize_document(
         )));
     }
 
-    tracing::info!("Summarizing document content (length: {} chars)", payload.content.len());
-
-    let original_length = payload.content.len();
-    let max_length = payload.max_length.unwrap_or(200);
-    
-    // Use OpenAI to generate a proper summary
-    let summary = state
-        .openai_service
-        .summarize_text(&payload.content, max_length)
-        .await?;
-
-    let summary_length = summary.len();
+    tracing::info!("Verifying email with a verification token");
 
-    let summarize_response = SummarizeResponse {
-        id: Uuid::new_v4(),
-        original_content: payload.content,
-        summary,
-        original_length,
-        summary_length,
-        timestamp: Utc::now().to_rfc3339(),
-    };
+    let user = state.auth_service.verify_email(&payload.token).await?;
 
-    let response = ApiResponse::success(summarize_response, "Document summarized successfully using AI");
+    let response = ApiResponse::success(user, "Email verified successfully");
     Ok(Json(response))
 }
 
-// New OpenAI-specific handlers
-pub async fn chat_completion(
+pub async fn login(
     State(state): State<AppState>,
-    Json(payload): Json<ChatCompletionRequest>
-) -> Result<Json<ApiResponse<ChatCompletionResponse>>> {
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    Json(payload): Json<LoginRequest>
+) -> Result<Json<ApiResponse<AuthResponse>>> {
     // Validate the request
     if let Err(validation_errors) = payload.validate() {
         return Ok(Json(ApiResponse::validation_error(
@@ -404,22 +2218,24 @@ pub async fn chat_completion(
         )));
     }
 
-    tracing::info!("Processing chat completion request");
+    tracing::info!("User login attempt: {}", redact::email(&payload.email));
 
-    let completion_response = state
-        .openai_service
-        .chat_completion(payload)
+    // Authenticate the user
+    let user = state
+        .auth_service
+        .authenticate_user(payload.email, payload.password, connect_info.map(|ConnectInfo(addr)| addr.ip()))
         .await?;
 
-    let response = ApiResponse::success(completion_response, "Chat completion generated successfully");
+    // Issue an access token and a refresh token
+    let auth_response = state.auth_service.issue_session(user)?;
+
+    let response = ApiResponse::success(auth_response, "Login successful");
     Ok(Json(response))
 }
 
-// MARK: - Authentication Handlers
-
-pub async fn register(
+pub async fn token_login(
     State(state): State<AppState>,
-    Json(payload): Json<RegisterRequest>
+    Json(payload): Json<TokenLoginRequest>
 ) -> Result<Json<ApiResponse<AuthResponse>>> {
     // Validate the request
     if let Err(validation_errors) = payload.validate() {
@@ -437,31 +2253,62 @@ pub async fn register(
         )));
     }
 
-    tracing::info!("Registering new user: {}", payload.email);
+    tracing::info!("Token-based authentication attempt");
 
-    // Register the user
+    // Authenticate with token
     let user = state
         .auth_service
-        .register_user(payload.email, payload.password)
+        .authenticate_with_token(&payload.token)
         .await?;
 
-    // Generate JWT token
-    let (token, expires_at) = state.auth_service.generate_token(&user)?;
+    // Issue an access token and a refresh token
+    let auth_response = state.auth_service.issue_session(user)?;
 
-    let auth_response = AuthResponse {
-        user,
-        token,
-        expires_at,
-    };
+    let response = ApiResponse::success(auth_response, "Token authentication successful");
+    Ok(Json(response))
+}
+
+pub async fn forgot_password(
+    State(state): State<AppState>,
+    Json(payload): Json<ForgotPasswordRequest>
+) -> Result<Json<ApiResponse<String>>> {
+    // Validate the request
+    if let Err(validation_errors) = payload.validate() {
+        return Ok(Json(ApiResponse::validation_error(
+            "Validation failed",
+            validation_errors
+                .field_errors()
+                .iter()
+                .flat_map(|(field, errors)| {
+                    errors.iter().map(move |error| {
+                        format!("{}: {}", field, error.message.as_ref().unwrap_or(&std::borrow::Cow::Borrowed("Invalid value")))
+                    })
+                })
+                .collect(),
+        )));
+    }
+
+    // The response is the same whether or not the account exists, so emails can't be probed
+    match state.auth_service.create_password_reset(&payload.email) {
+        Some(token) => state.mailer.send(
+            &payload.email,
+            "Reset your password",
+            &format!("Use this token within 30 minutes to set a new password: {}", token),
+        ),
+        None => tracing::info!("Password reset requested for unknown email: {}", redact::email(&payload.email)),
+    }
 
-    let response = ApiResponse::success(auth_response, "User registered successfully");
+    let response = ApiResponse::success(
+        "If that account exists, a password reset token has been issued".to_string(),
+        "Password reset requested",
+    );
     Ok(Json(response))
 }
 
-pub async fn login(
+pub async fn reset_password(
     State(state): State<AppState>,
-    Json(payload): Json<LoginRequest>
-) -> Result<Json<ApiResponse<AuthResponse>>> {
+    Json(payload): Json<ResetPasswordRequest>
+) -> Result<Json<ApiResponse<String>>> {
     // Validate the request
     if let Err(validation_errors) = payload.validate() {
         return Ok(Json(ApiResponse::validation_error(
@@ -478,30 +2325,28 @@ pub async fn login(
         )));
     }
 
-    tracing::info!("User login attempt: {}", payload.email);
+    let violations = state.auth_service.password_policy().violations(&payload.new_password);
+    if !violations.is_empty() {
+        return Ok(Json(ApiResponse::validation_error(
+            "Validation failed",
+            violations.into_iter().map(|violation| format!("new_password: {}", violation)).collect(),
+        )));
+    }
+
+    tracing::info!("Resetting password with a reset token");
 
-    // Authenticate the user
-    let user = state
+    state
         .auth_service
-        .authenticate_user(payload.email, payload.password)
+        .reset_password(&payload.token, payload.new_password)
         .await?;
 
-    // Generate JWT token
-    let (token, expires_at) = state.auth_service.generate_token(&user)?;
-
-    let auth_response = AuthResponse {
-        user,
-        token,
-        expires_at,
-    };
-
-    let response = ApiResponse::success(auth_response, "Login successful");
+    let response = ApiResponse::success("Password has been reset".to_string(), "Password reset successfully");
     Ok(Json(response))
 }
 
-pub async fn token_login(
+pub async fn refresh_token(
     State(state): State<AppState>,
-    Json(payload): Json<TokenLoginRequest>
+    Json(payload): Json<RefreshTokenRequest>
 ) -> Result<Json<ApiResponse<AuthResponse>>> {
     // Validate the request
     if let Err(validation_errors) = payload.validate() {
@@ -519,24 +2364,14 @@ pub async fn token_login(
         )));
     }
 
-    tracing::info!("Token-based authentication attempt");
+    tracing::info!("Refreshing access token");
 
-    // Authenticate with token
-    let user = state
+    let auth_response = state
         .auth_service
-        .authenticate_with_token(&payload.token)
+        .refresh_session(&payload.refresh_token)
         .await?;
 
-    // Generate JWT token for consistent response format
-    let (token, expires_at) = state.auth_service.generate_token(&user)?;
-
-    let auth_response = AuthResponse {
-        user,
-        token,
-        expires_at,
-    };
-
-    let response = ApiResponse::success(auth_response, "Token authentication successful");
+    let response = ApiResponse::success(auth_response, "Token refreshed successfully");
     Ok(Json(response))
 }
 
@@ -561,29 +2396,176 @@ pub async fn verify_token(
 
 pub async fn get_current_user(
     State(state): State<AppState>,
-    headers: HeaderMap,
+    user: AuthenticatedUser,
 ) -> Result<Json<ApiResponse<UserResponse>>> {
     tracing::info!("Getting current user information");
 
-    // Extract token from Authorization header
-    let auth_header = headers
-        .get("Authorization")
-        .and_then(|h| h.to_str().ok())
-        .ok_or_else(|| AppError::AuthError("Missing Authorization header".to_string()))?;
-
-    let token = auth_header
-        .strip_prefix("Bearer ")
-        .ok_or_else(|| AppError::AuthError("Invalid Authorization header format".to_string()))?;
-
-    // Validate the token
-    let claims = state.auth_service.validate_token(token)?;
-
     // Get user information
     let user = state
         .auth_service
-        .get_user_by_id(&claims.sub)
+        .get_user_by_id(&user.claims.sub)
         .await?;
 
     let response = ApiResponse::success(user, "User information retrieved successfully");
     Ok(Json(response))
-} 
\ No newline at end of file
+}
+
+pub async fn login_history(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+) -> Result<Json<ApiResponse<Vec<LoginEvent>>>> {
+    tracing::info!("User {} listing their recent logins", user.user_id);
+
+    let logins = state.auth_service.login_history(user.user_id);
+    let response = ApiResponse::success(logins, "Login history retrieved successfully");
+    Ok(Json(response))
+}
+
+pub async fn change_password(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Json(payload): Json<ChangePasswordRequest>
+) -> Result<Json<ApiResponse<String>>> {
+    // Validate the request
+    if let Err(validation_errors) = payload.validate() {
+        return Ok(Json(ApiResponse::validation_error(
+            "Validation failed",
+            validation_errors
+                .field_errors()
+                .iter()
+                .flat_map(|(field, errors)| {
+                    errors.iter().map(move |error| {
+                        format!("{}: {}", field, error.message.as_ref().unwrap_or(&std::borrow::Cow::Borrowed("Invalid value")))
+                    })
+                })
+                .collect(),
+        )));
+    }
+
+    let violations = state.auth_service.password_policy().violations(&payload.new_password);
+    if !violations.is_empty() {
+        return Ok(Json(ApiResponse::validation_error(
+            "Validation failed",
+            violations.into_iter().map(|violation| format!("new_password: {}", violation)).collect(),
+        )));
+    }
+
+    tracing::info!("Changing password for user {}", user.user_id);
+
+    state
+        .auth_service
+        .change_password(&user.claims, &payload.current_password, payload.new_password)
+        .await?;
+
+    let response = ApiResponse::success("Password has been changed; log in again".to_string(), "Password changed successfully");
+    Ok(Json(response))
+}
+
+pub async fn logout(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+) -> Result<Json<ApiResponse<String>>> {
+    tracing::info!("Logging out user {}", user.user_id);
+
+    state.auth_service.logout(&user.claims);
+
+    let response = ApiResponse::success("Tokens revoked".to_string(), "Logged out successfully");
+    Ok(Json(response))
+}
+
+pub async fn delete_current_user(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+) -> Result<Json<ApiResponse<String>>> {
+    tracing::info!("Deleting account for user {}", user.user_id);
+
+    // Removing the account also revokes this token, so the session ends immediately
+    state.auth_service.delete_user(&user.claims)?;
+
+    // Files are cleaned up best-effort; the account is already gone either way
+    let owned_files: Vec<StoredFile> = state.file_registry
+        .list()
+        .await
+        .into_iter()
+        .filter(|file| file.owner_id == Some(user.user_id))
+        .collect();
+
+    let mut deleted_files = 0;
+    for file in owned_files {
+        if let Err(e) = state.storage_service.delete_file(&file).await {
+            tracing::warn!("Failed to delete file {} for deleted user {}: {}", file.id, user.user_id, e);
+            continue;
+        }
+        if let Err(e) = state.file_registry.remove(&file.id).await {
+            tracing::warn!("Failed to unregister file {} for deleted user {}: {}", file.id, user.user_id, e);
+        }
+        state.thumbnail_cache.remove(file.id).await;
+        deleted_files += 1;
+    }
+
+    let response = ApiResponse::success(
+        format!("Account {} deleted along with {} files", user.user_id, deleted_files),
+        "Account deleted successfully",
+    );
+    Ok(Json(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_filename_rejects_traversal() {
+        assert_eq!(display_filename("  Q3 report.pdf "), Some("Q3_report.pdf".to_string()));
+        assert_eq!(display_filename("   "), None);
+        assert_eq!(display_filename("../secrets.txt"), None);
+        assert_eq!(display_filename("notes..txt"), None);
+        assert_eq!(display_filename("dir/notes.txt"), None);
+        assert_eq!(display_filename("dir\\notes.txt"), None);
+    }
+
+    #[tokio::test]
+    async fn test_streamed_upload_of_exactly_the_size_limit_is_accepted() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let max_upload_bytes = 3 * CONTENT_SNIFF_BYTES as u64;
+        let storage = StorageService::new(
+            StorageConfig { max_upload_bytes, ..crate::storage::test_storage_config(temp_dir.path()) },
+            Arc::new(crate::ids::DeterministicIdGenerator::new(1)),
+        )
+        .unwrap();
+        let chunks = |len: usize| {
+            let data = vec![b'a'; len];
+            let chunks: Vec<_> = data
+                .chunks(4096)
+                .map(|chunk| Ok::<_, std::io::Error>(axum::body::Bytes::copy_from_slice(chunk)))
+                .collect();
+            futures_util::stream::iter(chunks)
+        };
+
+        let stored = stream_upload_to_temporary_storage(&storage, chunks(max_upload_bytes as usize), "full.txt", None)
+            .await
+            .unwrap();
+        assert_eq!(stored.file_size, max_upload_bytes);
+
+        // The limit still holds for the streamed bytes after the held-back head
+        let over = stream_upload_to_temporary_storage(&storage, chunks(max_upload_bytes as usize + 1), "over.txt", None)
+            .await;
+        assert!(matches!(over, Err(AppError::ValidationError(_))));
+    }
+
+    #[test]
+    fn test_parse_byte_range() {
+        assert_eq!(parse_byte_range("bytes=0-99", 1000), Some((0, 99)));
+        assert_eq!(parse_byte_range("bytes=500-", 1000), Some((500, 999)));
+        assert_eq!(parse_byte_range("bytes=-100", 1000), Some((900, 999)));
+        assert_eq!(parse_byte_range("bytes=900-5000", 1000), Some((900, 999)));
+        assert_eq!(parse_byte_range("bytes=-5000", 1000), Some((0, 999)));
+
+        assert_eq!(parse_byte_range("bytes=1000-", 1000), None);
+        assert_eq!(parse_byte_range("bytes=50-10", 1000), None);
+        assert_eq!(parse_byte_range("bytes=-0", 1000), None);
+        assert_eq!(parse_byte_range("bytes=0-1,5-6", 1000), None);
+        assert_eq!(parse_byte_range("items=0-10", 1000), None);
+        assert_eq!(parse_byte_range("bytes=0-10", 0), None);
+    }
+}
@@ -0,0 +1,192 @@
+// I am importing the concurrent map that holds cached responses and the serde traits used to replay them
+use axum::http::HeaderMap;
+use chrono::{DateTime, Duration, Utc};
+use dashmap::{mapref::entry::Entry, DashMap};
+use serde::{de::DeserializeOwned, Serialize};
+use uuid::Uuid;
+
+use crate::error::{AppError, Result};
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+// How long a key replays its first response before it is forgotten
+pub const IDEMPOTENCY_KEY_TTL_HOURS: i64 = 24;
+const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 255;
+
+// I am defining a response kept for replay, stored as JSON so one map can hold every route's response type
+pub struct CachedResponse {
+    body: serde_json::Value,
+    expires_at: DateTime<Utc>,
+}
+
+// I am defining what a key holds: a request still being handled, or the response it finished with
+enum KeyState {
+    InFlight,
+    Done(CachedResponse),
+}
+
+// I am defining the outcome of claiming a key: the earlier response to replay, or the right to handle the request
+pub enum Claim<'a, T> {
+    Replay(T),
+    Fresh(Reservation<'a>),
+}
+
+// I am holding a claimed key until the response is stored; dropping it first (an error, or the client going
+// away) releases the key so a retry can run
+pub struct Reservation<'a> {
+    store: &'a IdempotencyStore,
+    key: Option<String>,
+}
+
+impl Reservation<'_> {
+    pub fn complete<T: Serialize>(mut self, response: &T) {
+        if let Some(key) = self.key.take() {
+            self.store.insert(key, response);
+        }
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.store.responses.remove_if(&key, |_, state| matches!(state, KeyState::InFlight));
+        }
+    }
+}
+
+// I am defining the store behind Idempotency-Key, which answers retried requests with the first response
+pub struct IdempotencyStore {
+    responses: DashMap<String, KeyState>,
+    ttl: Duration,
+}
+
+impl IdempotencyStore {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            responses: DashMap::new(),
+            ttl,
+        }
+    }
+
+    // I am reading the request's Idempotency-Key, scoped to the user and route so keys can't collide across them
+    pub fn key_from_headers(headers: &HeaderMap, user_id: Uuid, route: &str) -> Result<Option<String>> {
+        let Some(value) = headers.get(IDEMPOTENCY_KEY_HEADER) else {
+            return Ok(None);
+        };
+
+        let key = value
+            .to_str()
+            .map(str::trim)
+            .map_err(|_| AppError::ValidationError("Idempotency-Key must be visible ASCII".to_string()))?;
+        if key.is_empty() || key.len() > MAX_IDEMPOTENCY_KEY_LENGTH {
+            return Err(AppError::ValidationError(format!(
+                "Idempotency-Key must be between 1 and {} characters",
+                MAX_IDEMPOTENCY_KEY_LENGTH
+            )));
+        }
+
+        Ok(Some(format!("{}:{}:{}", user_id, route, key)))
+    }
+
+    // I am claiming a key in one step, so of two concurrent requests with it only one runs; the other gets a
+    // conflict while the first is in flight, and its response once it has finished
+    pub fn claim<T: DeserializeOwned>(&self, key: &str) -> Result<Claim<'_, T>> {
+        match self.responses.entry(key.to_string()) {
+            Entry::Occupied(mut occupied) => {
+                match occupied.get() {
+                    KeyState::InFlight => {
+                        return Err(AppError::ConflictError(
+                            "A request with this Idempotency-Key is still being processed".to_string(),
+                        ));
+                    }
+                    KeyState::Done(cached) if cached.expires_at > Utc::now() => {
+                        if let Ok(response) = serde_json::from_value(cached.body.clone()) {
+                            return Ok(Claim::Replay(response));
+                        }
+                    }
+                    KeyState::Done(_) => {}
+                }
+                // An expired (or unreadable) response no longer counts, so the key starts over
+                occupied.insert(KeyState::InFlight);
+            }
+            Entry::Vacant(vacant) => {
+                vacant.insert(KeyState::InFlight);
+            }
+        }
+
+        Ok(Claim::Fresh(Reservation { store: self, key: Some(key.to_string()) }))
+    }
+
+    fn insert<T: Serialize>(&self, key: String, response: &T) {
+        match serde_json::to_value(response) {
+            Ok(body) => {
+                self.responses.insert(key, KeyState::Done(CachedResponse { body, expires_at: Utc::now() + self.ttl }));
+            }
+            Err(e) => {
+                tracing::warn!("Not caching response for idempotency key: {}", e);
+                self.responses.remove(&key);
+            }
+        }
+    }
+
+    // I am dropping responses whose keys have expired, bounding memory
+    pub fn prune_expired(&self) {
+        let now = Utc::now();
+        self.responses.retain(|_, state| match state {
+            KeyState::InFlight => true,
+            KeyState::Done(cached) => cached.expires_at > now,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ApiResponse;
+
+    #[test]
+    fn test_replays_until_expiry_and_scopes_keys() {
+        let user_id = Uuid::new_v4();
+        let mut headers = HeaderMap::new();
+        assert_eq!(IdempotencyStore::key_from_headers(&headers, user_id, "scans").unwrap(), None);
+
+        headers.insert(IDEMPOTENCY_KEY_HEADER, "retry-1".parse().unwrap());
+        let scan_key = IdempotencyStore::key_from_headers(&headers, user_id, "scans").unwrap().unwrap();
+        let upload_key = IdempotencyStore::key_from_headers(&headers, user_id, "upload").unwrap().unwrap();
+        let other_user_key = IdempotencyStore::key_from_headers(&headers, Uuid::new_v4(), "scans").unwrap().unwrap();
+
+        let store = IdempotencyStore::new(Duration::hours(24));
+        let Ok(Claim::Fresh(reservation)) = store.claim::<ApiResponse<i32>>(&scan_key) else { panic!("key already claimed") };
+        reservation.complete(&ApiResponse::success(7, "created"));
+
+        let Ok(Claim::Replay(replayed)) = store.claim::<ApiResponse<i32>>(&scan_key) else { panic!("no replay") };
+        assert_eq!(replayed.data, Some(7));
+        assert!(matches!(store.claim::<ApiResponse<i32>>(&upload_key), Ok(Claim::Fresh(_))));
+        assert!(matches!(store.claim::<ApiResponse<i32>>(&other_user_key), Ok(Claim::Fresh(_))));
+
+        let expired = IdempotencyStore::new(Duration::zero());
+        let Ok(Claim::Fresh(reservation)) = expired.claim::<ApiResponse<i32>>(&scan_key) else { panic!("key already claimed") };
+        reservation.complete(&ApiResponse::success(7, "created"));
+        expired.prune_expired();
+        assert!(expired.responses.is_empty());
+        assert!(matches!(expired.claim::<ApiResponse<i32>>(&scan_key), Ok(Claim::Fresh(_))));
+
+        headers.insert(IDEMPOTENCY_KEY_HEADER, "x".repeat(256).parse().unwrap());
+        assert!(IdempotencyStore::key_from_headers(&headers, user_id, "scans").is_err());
+    }
+
+    #[test]
+    fn test_concurrent_claims_conflict_until_released() {
+        let store = IdempotencyStore::new(Duration::hours(24));
+        let first = store.claim::<ApiResponse<i32>>("key");
+        assert!(matches!(first, Ok(Claim::Fresh(_))));
+        assert!(matches!(store.claim::<ApiResponse<i32>>("key"), Err(AppError::ConflictError(_))));
+
+        // A request that fails drops its reservation, so a retry runs instead of waiting forever
+        drop(first);
+        let Ok(Claim::Fresh(retry)) = store.claim::<ApiResponse<i32>>("key") else { panic!("key was not released") };
+        store.prune_expired();
+        assert!(matches!(store.claim::<ApiResponse<i32>>("key"), Err(AppError::ConflictError(_))));
+        retry.complete(&ApiResponse::success(1, "created"));
+        assert!(matches!(store.claim::<ApiResponse<i32>>("key"), Ok(Claim::Replay(_))));
+    }
+}
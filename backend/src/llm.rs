@@ -0,0 +1,316 @@
+// I am importing the async trait support and the chat types every provider speaks
+use axum::async_trait;
+use futures_util::{Stream, StreamExt, TryStreamExt};
+use std::collections::HashSet;
+use std::pin::Pin;
+use std::sync::Arc;
+
+// I am importing my own error, id and model types
+use crate::{
+    error::{AppError, Result},
+    ids::IdGenerator,
+    models::{ChatCompletionRequest, ChatCompletionResponse, OpenAIMessage, TokenUsage},
+    ollama::OllamaService,
+    openai::{ChatStreamEvent, OpenAIService, Summary},
+};
+
+// I am naming the stream of deltas a provider sends back for a streamed chat reply
+pub type ChatEventStream = Pin<Box<dyn Stream<Item = Result<ChatStreamEvent>> + Send>>;
+
+// I am defining what handlers need from a chat model, so the backend behind /chat and /summarize can be swapped
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    // Answers the request as the next turn after the given earlier messages
+    async fn chat_completion(
+        &self,
+        request: ChatCompletionRequest,
+        history: &[OpenAIMessage],
+    ) -> Result<ChatCompletionResponse>;
+
+    // `language` is the English name of the language to write the summary in; None keeps the source language
+    async fn summarize_text(&self, content: &str, max_length: usize, language: Option<&str>) -> Result<Summary>;
+
+    // Streams the reply to the request as it is generated; providers that can't stream refuse it
+    async fn chat_completion_stream(
+        &self,
+        _request: ChatCompletionRequest,
+        _history: &[OpenAIMessage],
+    ) -> Result<ChatEventStream> {
+        Err(AppError::BadRequestError(
+            "Streaming chat is not supported by the configured LLM provider".to_string(),
+        ))
+    }
+
+    // The model summarize_text asks for
+    fn summary_model(&self) -> &str;
+
+    // The model names clients may ask this provider for
+    fn allowed_models(&self) -> HashSet<String>;
+
+    // I am summarizing text that may not fit one request (map-reduce): overlapping chunks are summarized
+    // a few at a time, then the partial summaries are combined into one within `max_length`.
+    // Returns the summary, with usage covering every call, and how many chunks the content was split into
+    async fn summarize_long(
+        &self,
+        content: &str,
+        max_length: usize,
+        chunk_chars: usize,
+        language: Option<&str>,
+    ) -> Result<(Summary, usize)> {
+        let chunks = split_into_sentence_chunks(content, chunk_chars);
+        if chunks.len() <= 1 {
+            return Ok((self.summarize_text(content, max_length, language).await?, 1));
+        }
+
+        // The calls are built up front; a lazy map closure over borrowed chunks isn't Send here
+        let calls: Vec<_> = chunks.iter().map(|chunk| self.summarize_text(chunk, max_length, language)).collect();
+        let partials: Vec<Summary> = futures_util::stream::iter(calls)
+            .buffered(SUMMARIZE_CHUNK_CONCURRENCY)
+            .try_collect()
+            .await?;
+
+        let mut usage = TokenUsage::default();
+        for partial in &partials {
+            add_usage(&mut usage, &partial.usage);
+        }
+
+        // The partial summaries can themselves be too long when there are very many chunks
+        let combined = partials.into_iter().map(|partial| partial.text).collect::<Vec<_>>().join("\n\n");
+        // Only recurse while that shrinks the text, so a tiny chunk size can't loop forever
+        let summary = if combined.chars().count() < content.chars().count() {
+            self.summarize_long(&combined, max_length, chunk_chars, language).await?.0
+        } else {
+            self.summarize_text(&combined, max_length, language).await?
+        };
+        add_usage(&mut usage, &summary.usage);
+
+        Ok((Summary { usage, ..summary }, chunks.len()))
+    }
+}
+
+// I am building the summarization request shared by every provider
+pub fn summary_request(content: &str, max_length: usize, model: String, language: Option<&str>) -> ChatCompletionRequest {
+    let mut system_prompt = format!(
+        "You are a helpful assistant that summarizes text. Please provide a concise summary of the given text in approximately {} characters or less. Focus on the main points and key information.",
+        max_length
+    );
+    if let Some(language) = language {
+        system_prompt.push_str(&format!(" Write the summary in {}, whatever language the text is in.", language));
+    }
+
+    ChatCompletionRequest {
+        content: content.to_string(),
+        model: Some(model),
+        temperature: Some(0.3), // Lower temperature for more consistent summaries
+        max_tokens: Some((max_length / 3) as u32), // Rough estimate: 1 token ≈ 3 characters
+        system_prompt: Some(system_prompt),
+        base_url_override: None,
+        conversation_id: None,
+        timeout_seconds: None,
+    }
+}
+
+// Matches the hard ceiling on SummarizeRequest.content
+const SUMMARIZE_CHARS_CEILING: usize = 1_000_000;
+const DEFAULT_MAX_SUMMARIZE_CHARS: usize = 50_000;
+// Roughly 4000 tokens at ~3 characters per token, which leaves room for the prompt and reply
+const DEFAULT_SUMMARIZE_CHUNK_CHARS: usize = 12_000;
+
+// I am defining how much text /summarize accepts and how much goes to the model in one call
+#[derive(Debug, Clone, Copy)]
+pub struct SummarizeLimits {
+    pub max_chars: usize,
+    pub chunk_chars: usize,
+}
+
+impl SummarizeLimits {
+    // I am reading MAX_SUMMARIZE_CHARS and SUMMARIZE_CHUNK_CHARS, never exceeding the request ceiling
+    pub fn from_env() -> Self {
+        Self {
+            max_chars: std::env::var("MAX_SUMMARIZE_CHARS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_MAX_SUMMARIZE_CHARS)
+                .min(SUMMARIZE_CHARS_CEILING),
+            chunk_chars: std::env::var("SUMMARIZE_CHUNK_CHARS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|chars| *chars > 0)
+                .unwrap_or(DEFAULT_SUMMARIZE_CHUNK_CHARS),
+        }
+    }
+}
+
+// Chunk summaries requested at once by summarize_long; provider pools may queue them further
+const SUMMARIZE_CHUNK_CONCURRENCY: usize = 4;
+
+// I am splitting text into pieces of at most `chunk_chars` characters, never cutting a character in half
+fn split_into_chunks(content: &str, chunk_chars: usize) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut rest = content;
+    while !rest.is_empty() {
+        let end = rest.char_indices().nth(chunk_chars).map(|(index, _)| index).unwrap_or(rest.len());
+        let (chunk, remainder) = rest.split_at(end);
+        chunks.push(chunk);
+        rest = remainder;
+    }
+    chunks
+}
+
+// I am splitting text after sentence-ending punctuation or line breaks, keeping the trailing whitespace
+fn split_sentences(content: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = content.char_indices().peekable();
+    while let Some((_, c)) = chars.next() {
+        let ends_sentence = matches!(c, '.' | '!' | '?' | '\n');
+        if ends_sentence && chars.peek().is_none_or(|(_, next)| next.is_whitespace()) {
+            // Swallow the whitespace so the next sentence starts at a word
+            while let Some((_, next)) = chars.peek() {
+                if !next.is_whitespace() {
+                    break;
+                }
+                chars.next();
+            }
+            let end = chars.peek().map(|(index, _)| *index).unwrap_or(content.len());
+            sentences.push(&content[start..end]);
+            start = end;
+        }
+    }
+    if start < content.len() {
+        sentences.push(&content[start..]);
+    }
+    sentences
+}
+
+// I am packing whole sentences into chunks of at most `chunk_chars` characters, repeating each chunk's
+// last sentence at the start of the next so no chunk loses the context it follows on from
+fn split_into_sentence_chunks(content: &str, chunk_chars: usize) -> Vec<String> {
+    // Sentences longer than a whole chunk are cut at character boundaries instead
+    let sentences: Vec<&str> = split_sentences(content)
+        .into_iter()
+        .flat_map(|sentence| split_into_chunks(sentence, chunk_chars))
+        .collect();
+
+    let mut chunks = Vec::new();
+    let mut current: Vec<&str> = Vec::new();
+    let mut current_chars = 0;
+    for sentence in sentences {
+        let sentence_chars = sentence.chars().count();
+        if current_chars + sentence_chars > chunk_chars && !current.is_empty() {
+            chunks.push(current.concat());
+            let overlap = current.last().copied().filter(|last| last.chars().count() + sentence_chars <= chunk_chars);
+            current = overlap.into_iter().collect();
+            current_chars = current.iter().map(|part| part.chars().count()).sum();
+        }
+        current.push(sentence);
+        current_chars += sentence_chars;
+    }
+    if !current.is_empty() {
+        chunks.push(current.concat());
+    }
+    chunks
+}
+
+fn add_usage(total: &mut TokenUsage, usage: &TokenUsage) {
+    total.prompt_tokens += usage.prompt_tokens;
+    total.completion_tokens += usage.completion_tokens;
+    total.total_tokens += usage.total_tokens;
+}
+
+// I am choosing the chat provider from LLM_PROVIDER ("openai" by default, or "ollama")
+pub fn provider_from_env(
+    openai_service: Arc<OpenAIService>,
+    id_generator: Arc<dyn IdGenerator>,
+) -> Result<Arc<dyn LlmProvider>> {
+    match std::env::var("LLM_PROVIDER").unwrap_or_default().to_lowercase().as_str() {
+        "" | "openai" => Ok(openai_service),
+        "ollama" => Ok(Arc::new(OllamaService::from_env(id_generator)?)),
+        other => Err(AppError::ConfigError(format!(
+            "Unknown LLM_PROVIDER {:?}; expected \"openai\" or \"ollama\"",
+            other
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    // I am recording every text asked to be summarized and answering with its first word
+    struct RecordingProvider {
+        inputs: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl LlmProvider for RecordingProvider {
+        async fn chat_completion(
+            &self,
+            _request: ChatCompletionRequest,
+            _history: &[OpenAIMessage],
+        ) -> Result<ChatCompletionResponse> {
+            Err(AppError::InternalError("Only summaries are recorded by this provider".to_string()))
+        }
+
+        async fn summarize_text(&self, content: &str, _max_length: usize, _language: Option<&str>) -> Result<Summary> {
+            self.inputs.lock().unwrap().push(content.to_string());
+            Ok(Summary {
+                text: content.split_whitespace().next().unwrap_or_default().to_string(),
+                model: "test-model".to_string(),
+                usage: TokenUsage { prompt_tokens: 10, completion_tokens: 2, total_tokens: 12 },
+            })
+        }
+
+        fn summary_model(&self) -> &str {
+            "test-model"
+        }
+
+        fn allowed_models(&self) -> HashSet<String> {
+            HashSet::from(["test-model".to_string()])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_long_content_is_summarized_in_chunks() {
+        let provider = RecordingProvider { inputs: Mutex::new(Vec::new()) };
+
+        let (summary, chunks) = provider.summarize_long("short text", 200, 20, None).await.unwrap();
+        assert_eq!((summary.text.as_str(), chunks), ("short", 1));
+
+        provider.inputs.lock().unwrap().clear();
+        let content = "Alpha one. Beta two! Gamma three? Delta four.";
+        let (summary, chunks) = provider.summarize_long(content, 200, 24, None).await.unwrap();
+        assert_eq!(chunks, 3);
+        // Each chunk repeats the previous chunk's last sentence, and chunks are summarized in order
+        assert_eq!(
+            *provider.inputs.lock().unwrap(),
+            vec![
+                "Alpha one. Beta two! ",
+                "Beta two! Gamma three? ",
+                "Gamma three? Delta four.",
+                "Alpha\n\nBeta\n\nGamma",
+            ]
+        );
+        assert_eq!(summary.text, "Alpha");
+        // Three chunk calls plus the combining call
+        assert_eq!(summary.usage.total_tokens, 48);
+    }
+
+    #[test]
+    fn test_summary_prompt_names_the_target_language() {
+        let prompt = |language| summary_request("text", 200, "gpt-4o-mini".to_string(), language).system_prompt.unwrap();
+        assert!(prompt(Some("Japanese")).ends_with("Write the summary in Japanese, whatever language the text is in."));
+        assert!(!prompt(None).contains("Write the summary in"));
+    }
+
+    #[test]
+    fn test_sentence_chunks_fall_back_to_character_splits() {
+        assert_eq!(split_sentences("One. Two?\nThree 3.5 four"), vec!["One. ", "Two?\n", "Three 3.5 four"]);
+        assert_eq!(split_into_chunks("héllo wörld", 4), vec!["héll", "o wö", "rld"]);
+        assert_eq!(
+            split_into_sentence_chunks("abcdefghij. Next.", 6),
+            vec!["abcdef", "ghij. ", "Next."]
+        );
+    }
+}
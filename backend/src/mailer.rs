@@ -0,0 +1,30 @@
+// I am importing the redaction helper so recipients stay masked when messages are dropped
+use crate::redact;
+
+// I am defining the outgoing mail hook for account emails. There is no real email sender yet, so messages are
+// dropped unless DEV_MAILER_LOG=true writes them, secrets included, to the log for local development
+pub struct Mailer {
+    log_messages: bool,
+}
+
+impl Mailer {
+    pub fn new(log_messages: bool) -> Self {
+        Self { log_messages }
+    }
+
+    pub fn from_env() -> Self {
+        let log_messages = std::env::var("DEV_MAILER_LOG").map(|v| v == "true").unwrap_or(false);
+        if log_messages {
+            tracing::warn!("DEV_MAILER_LOG is on: account emails, including their tokens, are written to the log");
+        }
+        Self::new(log_messages)
+    }
+
+    pub fn send(&self, to: &str, subject: &str, body: &str) {
+        if self.log_messages {
+            tracing::warn!("Dev mailer message to {} ({}): {}", to, subject, body);
+        } else {
+            tracing::info!("No mailer configured; dropped \"{}\" email to {}", subject, redact::email(to));
+        }
+    }
+}
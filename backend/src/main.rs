@@ -4,23 +4,62 @@ mod handlers;
 mod error;
 mod routes;
 mod openai;
+mod llm;
+mod ollama;
 mod storage;
+mod encryption;
+mod download_tokens;
+mod s3;
 mod auth;
+mod password;
+mod ids;
+mod usage;
+mod scans;
+mod registry;
+mod rate_limit;
+mod extract;
+mod csv_stats;
+mod markup;
+mod malware;
+mod url_fetch;
+mod thumbnails;
+mod ocr;
+mod barcode;
+mod qr;
+mod chat_session;
+mod conversations;
+mod webhooks;
+mod telemetry;
+mod redact;
+mod mailer;
+mod idempotency;
+mod resumable;
+mod summary_cache;
+mod openapi;
 
 // I am importing the necessary types and traits from the Axum web framework and related libraries
 use axum::{
+    extract::{Request, State},
     http::{
         header::{CONTENT_TYPE, AUTHORIZATION},
-        Method,
+        Extensions, HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Version,
     },
+    middleware::{self, Next},
+    response::Response,
     Router,
 };
-use std::net::SocketAddr;
-use tower_http::cors::{Any, CorsLayer};
+use std::{net::{IpAddr, SocketAddr}, sync::Arc};
+use tower_http::{
+    compression::{
+        predicate::{NotForContentType, Predicate},
+        CompressionLayer, DefaultPredicate,
+    },
+    cors::{Any, CorsLayer},
+};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 // I am bringing in the route creation and application state from my own modules
-use crate::{routes::create_routes, handlers::AppState};
+use crate::{routes::create_routes, handlers::AppState, usage::CostTagSource};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -30,21 +69,99 @@ async fn main() -> anyhow::Result<()> {
         tracing::warn!("Could not load .env file: {}", e);
     }
 
-    // I am initializing the tracing subscriber for logging and debugging
+    // I am initializing the tracing subscriber for logging and debugging;
+    // LOG_FORMAT=json writes one JSON object per line, with the request span's fields as keys
+    let log_format = std::env::var("LOG_FORMAT").unwrap_or_default().to_lowercase();
+    let json_logs = log_format == "json";
     tracing_subscriber::registry()
         .with(
             tracing_subscriber::EnvFilter::try_from_default_env()
                 .unwrap_or_else(|_| "quickscan_backend=debug,tower_http=debug".into()),
         )
-        .with(tracing_subscriber::fmt::layer())
+        .with(json_logs.then(|| tracing_subscriber::fmt::layer().json().flatten_event(true).with_span_list(false)))
+        .with((!json_logs).then(tracing_subscriber::fmt::layer))
         .init();
+    if !matches!(log_format.as_str(), "" | "json" | "pretty") {
+        tracing::warn!("Unknown LOG_FORMAT {:?}; using the pretty format", log_format);
+    }
+
+    // I am installing the Prometheus recorder before anything records metrics
+    telemetry::handle();
 
     // I am creating the main application state, which holds all shared services
-    let app_state = AppState::new().map_err(|e| {
+    let app_state = AppState::new().await.map_err(|e| {
         tracing::error!("Failed to initialize application state: {}", e);
         anyhow::anyhow!("Failed to initialize application state: {}", e)
     })?;
 
+    // I am pruning expired entries from the token denylist in the background
+    let auth_service = app_state.auth_service.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(600));
+        loop {
+            interval.tick().await;
+            auth_service.prune_expired_tokens();
+        }
+    });
+
+    // I am forgetting expired idempotency keys in the background
+    let idempotency_store = app_state.idempotency_store.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(600));
+        loop {
+            interval.tick().await;
+            idempotency_store.prune_expired();
+        }
+    });
+
+    // I am forgetting idle chat conversations in the background
+    let conversation_store = app_state.conversation_store.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(600));
+        loop {
+            interval.tick().await;
+            conversation_store.prune_expired();
+        }
+    });
+
+    // I am dropping abandoned resumable uploads and their partial files in the background
+    let resumable_uploads = app_state.resumable_uploads.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(600));
+        loop {
+            interval.tick().await;
+            let removed = resumable_uploads.prune_expired().await;
+            if removed > 0 {
+                tracing::info!("Removed {} expired resumable uploads", removed);
+            }
+        }
+    });
+
+    // I am sweeping expired temporary files and old trash on a timer when CLEANUP_INTERVAL_SECS is set
+    if let Some(interval_secs) = app_state.storage_service.cleanup_interval_secs() {
+        let state = app_state.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_secs));
+            loop {
+                interval.tick().await;
+                let max_age_hours = state.storage_service.temp_file_max_age_hours();
+                match state.file_registry.cleanup_expired(&state.storage_service, max_age_hours).await {
+                    Ok(removed) => tracing::info!("Scheduled cleanup removed {} expired files", removed.len()),
+                    Err(e) => tracing::warn!("Scheduled cleanup failed: {}", e),
+                }
+                let retention_days = state.storage_service.trash_retention_days();
+                match state.file_registry.purge_trash(&state.storage_service, retention_days).await {
+                    Ok(purged) => {
+                        for file_id in purged {
+                            state.thumbnail_cache.remove(file_id).await;
+                        }
+                    }
+                    Err(e) => tracing::warn!("Scheduled trash purge failed: {}", e),
+                }
+            }
+        });
+    }
+
     // I am checking if the OpenAI API key is set, and logging the AI feature status
     if std::env::var("OPENAI_API_KEY").is_ok() {
         tracing::info!("OpenAI API key found - AI features enabled");
@@ -52,26 +169,312 @@ async fn main() -> anyhow::Result<()> {
         tracing::warn!("OpenAI API key not found - AI features will fail. Set OPENAI_API_KEY environment variable.");
     }
 
-    // I am configuring CORS to allow requests from any origin and common HTTP methods
-    let cors = CorsLayer::new()
-        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
-        .allow_headers([CONTENT_TYPE, AUTHORIZATION])
-        .allow_origin(Any);
+    // I am configuring CORS from CORS_ALLOWED_ORIGINS, allowing any origin only when it is unset
+    let cost_tag_header = match CostTagSource::from_env() {
+        CostTagSource::Header(name) => Some(name),
+        CostTagSource::UserDomain => None,
+    };
+    let cors = build_cors_layer(
+        std::env::var("CORS_ALLOWED_ORIGINS").ok().as_deref(),
+        cost_tag_header.as_deref(),
+    )?;
+
+    // I am loading the headers that get stamped onto every response
+    let response_headers = Arc::new(ResponseHeadersConfig::from_env());
 
     // I am building the main Axum router, nesting all API routes under /api, and applying middleware
     let app = Router::new()
-        .nest("/api", create_routes())
+        .nest("/api", create_routes(
+            app_state.storage_service.max_upload_bytes(),
+            app_state.storage_service.max_files_per_upload(),
+            app_state.auth_service.clone(),
+        ))
+        .layer(middleware::from_fn_with_state(response_headers, apply_response_headers))
+        .layer(compression_layer())
         .layer(cors)
         .layer(tower_http::trace::TraceLayer::new_for_http())
         .with_state(app_state);
 
-    // I am setting the address for the server to listen on (localhost:3000)
-    let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
+    // I am resolving the address to listen on from BIND_ADDR, or HOST and PORT (default 127.0.0.1:3000)
+    let addr = parse_bind_address(
+        std::env::var("BIND_ADDR").ok().as_deref(),
+        std::env::var("HOST").ok().as_deref(),
+        std::env::var("PORT").ok().as_deref(),
+    )?;
     tracing::info!("QuickScan backend server starting on {} with AI capabilities", addr);
     
     // I am binding a TCP listener and starting the Axum server
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
 
     Ok(())
 }
+
+// I am compressing responses for clients that accept gzip or brotli, skipping formats that are already compressed
+fn compression_layer() -> CompressionLayer<impl Predicate> {
+    // The default predicate already skips images, event streams, and tiny bodies
+    let predicate = DefaultPredicate::new()
+        .and(NotForContentType::const_new("application/pdf"))
+        .and(NotForContentType::const_new("application/zip"))
+        // DOCX and the other Office Open XML formats are zip archives too
+        .and(NotForContentType::const_new("application/vnd.openxmlformats-officedocument"))
+        .and(NotForContentType::const_new("application/gzip"))
+        .and(NotForContentType::const_new("audio/"))
+        .and(NotForContentType::const_new("video/"))
+        // Byte ranges refer to the uncompressed file, so partial responses must go out as-is
+        .and(|status: StatusCode, _: Version, _: &HeaderMap, _: &Extensions| status != StatusCode::PARTIAL_CONTENT);
+
+    CompressionLayer::new().gzip(true).br(true).compress_when(predicate)
+}
+
+// I am building the CORS layer, restricting it to the given comma-separated origins when there are any; browsers
+// may also send the cost attribution header when costs are tagged by header
+fn build_cors_layer(allowed_origins: Option<&str>, cost_tag_header: Option<&str>) -> anyhow::Result<CorsLayer> {
+    let upload_offset = HeaderName::from_static("upload-offset");
+    let mut allowed_headers = vec![
+        CONTENT_TYPE,
+        AUTHORIZATION,
+        HeaderName::from_static("idempotency-key"),
+        upload_offset.clone(),
+        HeaderName::from_static("upload-length"),
+    ];
+    if let Some(name) = cost_tag_header {
+        allowed_headers.push(
+            HeaderName::from_bytes(name.as_bytes())
+                .map_err(|e| anyhow::anyhow!("Invalid COST_TAG_HEADER {:?}: {}", name, e))?,
+        );
+    }
+
+    let cors = CorsLayer::new()
+        .allow_methods([Method::GET, Method::HEAD, Method::POST, Method::PUT, Method::PATCH, Method::DELETE])
+        .allow_headers(allowed_headers)
+        // Resumable upload clients read the offset to know where to continue
+        .expose_headers([upload_offset]);
+
+    let origins = allowed_origins
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|origin| !origin.is_empty())
+        .map(|origin| {
+            HeaderValue::from_str(origin)
+                .map_err(|e| anyhow::anyhow!("Invalid origin {:?} in CORS_ALLOWED_ORIGINS: {}", origin, e))
+        })
+        .collect::<anyhow::Result<Vec<HeaderValue>>>()?;
+
+    if origins.is_empty() {
+        tracing::warn!("CORS_ALLOWED_ORIGINS is not set - allowing requests from any origin");
+        return Ok(cors.allow_origin(Any));
+    }
+
+    tracing::info!("CORS restricted to {} origin(s)", origins.len());
+    Ok(cors.allow_origin(origins).allow_credentials(true))
+}
+
+// I am parsing the listen address, preferring a combined BIND_ADDR over separate HOST and PORT values
+fn parse_bind_address(bind_addr: Option<&str>, host: Option<&str>, port: Option<&str>) -> anyhow::Result<SocketAddr> {
+    if let Some(bind_addr) = bind_addr {
+        return bind_addr
+            .trim()
+            .parse()
+            .map_err(|e| anyhow::anyhow!("Invalid BIND_ADDR {:?} (expected e.g. 0.0.0.0:3000): {}", bind_addr, e));
+    }
+
+    let host: IpAddr = host
+        .unwrap_or("127.0.0.1")
+        .trim()
+        .parse()
+        .map_err(|e| anyhow::anyhow!("Invalid HOST {:?} (expected an IP address such as 0.0.0.0): {}", host.unwrap_or_default(), e))?;
+    let port: u16 = port
+        .unwrap_or("3000")
+        .trim()
+        .parse()
+        .map_err(|e| anyhow::anyhow!("Invalid PORT {:?} (expected 0-65535): {}", port.unwrap_or_default(), e))?;
+
+    Ok(SocketAddr::new(host, port))
+}
+
+// I am defining the set of headers that the response middleware adds to every response
+#[derive(Debug, Clone, Default)]
+struct ResponseHeadersConfig {
+    headers: Vec<(HeaderName, HeaderValue)>,
+}
+
+impl ResponseHeadersConfig {
+    // I am reading the security header toggles and any custom headers from the environment
+    fn from_env() -> Self {
+        let enabled = |name: &str| std::env::var(name).map(|v| v == "true").unwrap_or(false);
+
+        let mut headers = Vec::new();
+        if enabled("SECURITY_HEADERS") {
+            headers.extend(security_headers(enabled("SECURITY_HEADERS_HSTS")));
+        }
+        if let Ok(custom) = std::env::var("CUSTOM_RESPONSE_HEADERS") {
+            headers.extend(parse_custom_headers(&custom));
+        }
+
+        Self { headers }
+    }
+}
+
+// I am building the default security headers, only adding HSTS when we know we are served over HTTPS
+fn security_headers(include_hsts: bool) -> Vec<(HeaderName, HeaderValue)> {
+    let mut headers = vec![
+        (HeaderName::from_static("x-content-type-options"), HeaderValue::from_static("nosniff")),
+        (HeaderName::from_static("x-frame-options"), HeaderValue::from_static("DENY")),
+        (HeaderName::from_static("referrer-policy"), HeaderValue::from_static("strict-origin-when-cross-origin")),
+    ];
+
+    if include_hsts {
+        headers.push((
+            HeaderName::from_static("strict-transport-security"),
+            HeaderValue::from_static("max-age=31536000; includeSubDomains"),
+        ));
+    }
+
+    headers
+}
+
+// I am parsing custom headers written as "Name: value" pairs separated by "|"
+fn parse_custom_headers(raw: &str) -> Vec<(HeaderName, HeaderValue)> {
+    raw.split('|')
+        .filter(|entry| !entry.trim().is_empty())
+        .filter_map(|entry| {
+            let parsed = entry.split_once(':').and_then(|(name, value)| {
+                let name = HeaderName::try_from(name.trim()).ok()?;
+                let value = HeaderValue::try_from(value.trim()).ok()?;
+                Some((name, value))
+            });
+            if parsed.is_none() {
+                tracing::warn!("Ignoring invalid custom response header: {}", entry);
+            }
+            parsed
+        })
+        .collect()
+}
+
+// I am adding the configured headers to each response without overriding headers a handler already set
+async fn apply_response_headers(
+    State(config): State<Arc<ResponseHeadersConfig>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+
+    for (name, value) in &config.headers {
+        if !headers.contains_key(name) {
+            headers.insert(name.clone(), value.clone());
+        }
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::header::CONTENT_DISPOSITION, routing::get};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_configured_headers_are_added_without_clobbering() {
+        let mut headers = security_headers(true);
+        headers.extend(parse_custom_headers("X-Team: quickscan | Content-Disposition: inline"));
+        let config = Arc::new(ResponseHeadersConfig { headers });
+
+        let app = Router::new()
+            .route("/sample", get(|| async { ([(CONTENT_DISPOSITION, "attachment")], "ok") }))
+            .layer(middleware::from_fn_with_state(config, apply_response_headers));
+
+        let response = app
+            .oneshot(Request::builder().uri("/sample").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        let headers = response.headers();
+        assert_eq!(headers["x-content-type-options"], "nosniff");
+        assert_eq!(headers["x-frame-options"], "DENY");
+        assert_eq!(headers["referrer-policy"], "strict-origin-when-cross-origin");
+        assert!(headers.contains_key("strict-transport-security"));
+        assert_eq!(headers["x-team"], "quickscan");
+        assert_eq!(headers[CONTENT_DISPOSITION], "attachment");
+    }
+
+    #[test]
+    fn test_parse_bind_address() {
+        assert_eq!(parse_bind_address(None, None, None).unwrap(), SocketAddr::from(([127, 0, 0, 1], 3000)));
+        assert_eq!(parse_bind_address(None, Some("0.0.0.0"), Some("8080")).unwrap(), SocketAddr::from(([0, 0, 0, 0], 8080)));
+        assert_eq!(parse_bind_address(Some("[::]:9000"), Some("10.0.0.1"), None).unwrap().port(), 9000);
+
+        assert!(parse_bind_address(None, Some("not-an-ip"), None).is_err());
+        assert!(parse_bind_address(None, None, Some("70000")).is_err());
+        assert!(parse_bind_address(Some("0.0.0.0"), None, None).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cors_allows_only_configured_origins() {
+        assert!(build_cors_layer(Some("https://app.example.com, bad\norigin"), None).is_err());
+
+        let app = Router::new()
+            .route("/sample", get(|| async { "ok" }))
+            .layer(build_cors_layer(Some("https://app.example.com, https://admin.example.com"), None).unwrap());
+
+        let request = |origin: &str| Request::builder().uri("/sample").header("Origin", origin).body(Body::empty()).unwrap();
+
+        let allowed = app.clone().oneshot(request("https://admin.example.com")).await.unwrap();
+        assert_eq!(allowed.headers()["access-control-allow-origin"], "https://admin.example.com");
+        assert_eq!(allowed.headers()["access-control-allow-credentials"], "true");
+
+        let denied = app.oneshot(request("https://evil.example.com")).await.unwrap();
+        assert!(!denied.headers().contains_key("access-control-allow-origin"));
+    }
+
+    #[tokio::test]
+    async fn test_cors_preflight_allows_the_api_headers() {
+        assert!(build_cors_layer(None, Some("bad header")).is_err());
+
+        let app = Router::new()
+            .route("/sample", get(|| async { "ok" }))
+            .layer(build_cors_layer(None, Some("X-Cost-Center")).unwrap());
+
+        let preflight = Request::builder()
+            .method(Method::OPTIONS)
+            .uri("/sample")
+            .header("Origin", "https://app.example.com")
+            .header("Access-Control-Request-Method", "POST")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(preflight).await.unwrap();
+        let allowed_headers = response.headers()["access-control-allow-headers"].to_str().unwrap().to_string();
+        assert!(allowed_headers.contains("x-cost-center"), "{}", allowed_headers);
+        assert!(allowed_headers.contains("idempotency-key"), "{}", allowed_headers);
+        assert!(allowed_headers.contains("upload-offset") && allowed_headers.contains("upload-length"), "{}", allowed_headers);
+        let allowed_methods = response.headers()["access-control-allow-methods"].to_str().unwrap();
+        assert!(allowed_methods.contains("PATCH") && allowed_methods.contains("HEAD"), "{}", allowed_methods);
+
+        let request = Request::builder().uri("/sample").header("Origin", "https://app.example.com").body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.headers()["access-control-expose-headers"], "upload-offset");
+    }
+
+    #[tokio::test]
+    async fn test_compression_skips_already_compressed_types() {
+        let body = "x".repeat(4096);
+        let json_body = body.clone();
+        let app = Router::new()
+            .route("/json", get(move || async move { ([(CONTENT_TYPE, "application/json")], json_body) }))
+            .route("/pdf", get(move || async move { ([(CONTENT_TYPE, "application/pdf")], body) }))
+            .layer(compression_layer());
+
+        let request = |uri: &str| {
+            Request::builder().uri(uri).header("Accept-Encoding", "gzip").body(Body::empty()).unwrap()
+        };
+
+        let json = app.clone().oneshot(request("/json")).await.unwrap();
+        assert_eq!(json.headers()["content-encoding"], "gzip");
+
+        let pdf = app.oneshot(request("/pdf")).await.unwrap();
+        assert!(!pdf.headers().contains_key("content-encoding"));
+    }
+}
@@ -0,0 +1,189 @@
+// I am importing the TCP and async IO pieces used to speak clamd's INSTREAM protocol
+use std::path::Path;
+use std::time::Duration;
+use tokio::{
+    fs,
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use crate::error::{AppError, Result};
+
+const DEFAULT_CLAMAV_TIMEOUT_SECS: u64 = 30;
+
+// Bytes sent per INSTREAM chunk; well under clamd's default StreamMaxLength
+const INSTREAM_CHUNK_BYTES: usize = 64 * 1024;
+
+// What clamd made of the stream
+#[derive(Debug, PartialEq, Eq)]
+enum Verdict {
+    Clean,
+    Infected(String),
+}
+
+// I am defining the optional ClamAV scanner every upload passes through before it is stored;
+// with no CLAMAV_ADDR it accepts everything
+pub struct MalwareScanner {
+    addr: Option<String>,
+    // Whether files are accepted when clamd can't be reached or answers with an error
+    fail_open: bool,
+    timeout: Duration,
+}
+
+impl MalwareScanner {
+    pub fn new(addr: Option<String>, fail_open: bool, timeout: Duration) -> Self {
+        Self { addr, fail_open, timeout }
+    }
+
+    // I am reading CLAMAV_ADDR (host:port), CLAMAV_FAIL_OPEN and CLAMAV_TIMEOUT_SECS
+    pub fn from_env() -> Self {
+        Self::new(
+            std::env::var("CLAMAV_ADDR").ok().filter(|addr| !addr.is_empty()),
+            std::env::var("CLAMAV_FAIL_OPEN").map(|v| v == "true").unwrap_or(false),
+            Duration::from_secs(
+                std::env::var("CLAMAV_TIMEOUT_SECS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(DEFAULT_CLAMAV_TIMEOUT_SECS),
+            ),
+        )
+    }
+
+    // I am scanning an upload held in memory
+    pub async fn scan(&self, data: &[u8]) -> Result<()> {
+        self.check(data).await
+    }
+
+    // I am scanning an upload already written to disk, streaming it to clamd without loading it whole
+    pub async fn scan_file(&self, path: &Path) -> Result<()> {
+        if self.addr.is_none() {
+            return Ok(());
+        }
+        let file = fs::File::open(path)
+            .await
+            .map_err(|e| AppError::StorageError(format!("Failed to read upload for malware scan: {}", e)))?;
+        self.check(file).await
+    }
+
+    // I am turning clamd's verdict into the upload's outcome, applying the fail-open setting to scanner errors
+    async fn check(&self, reader: impl AsyncRead + Unpin) -> Result<()> {
+        let Some(addr) = self.addr.as_deref() else {
+            return Ok(());
+        };
+
+        let verdict = tokio::time::timeout(self.timeout, instream(addr, reader))
+            .await
+            .unwrap_or_else(|_| Err(format!("no answer within {} seconds", self.timeout.as_secs())));
+
+        match verdict {
+            Ok(Verdict::Clean) => Ok(()),
+            Ok(Verdict::Infected(signature)) => {
+                tracing::warn!("Rejected upload flagged by ClamAV as {}", signature);
+                Err(AppError::BadRequestError("File failed malware scan".to_string()))
+            }
+            Err(e) if self.fail_open => {
+                tracing::warn!("ClamAV scan failed, accepting the upload unscanned: {}", e);
+                Ok(())
+            }
+            Err(e) => {
+                tracing::error!("ClamAV scan failed, rejecting the upload: {}", e);
+                Err(AppError::ExternalServiceError("Malware scanner is unavailable".to_string()))
+            }
+        }
+    }
+}
+
+// I am sending the bytes as length-prefixed chunks ended by a zero length, then reading clamd's one-line reply
+async fn instream(addr: &str, mut reader: impl AsyncRead + Unpin) -> std::result::Result<Verdict, String> {
+    let mut stream = TcpStream::connect(addr)
+        .await
+        .map_err(|e| format!("failed to connect to {}: {}", addr, e))?;
+    let io_error = |e: std::io::Error| format!("lost connection to {}: {}", addr, e);
+
+    stream.write_all(b"zINSTREAM\0").await.map_err(io_error)?;
+    let mut chunk = vec![0u8; INSTREAM_CHUNK_BYTES];
+    loop {
+        let read = reader.read(&mut chunk).await.map_err(|e| format!("failed to read upload: {}", e))?;
+        if read == 0 {
+            break;
+        }
+        stream.write_all(&(read as u32).to_be_bytes()).await.map_err(io_error)?;
+        stream.write_all(&chunk[..read]).await.map_err(io_error)?;
+    }
+    stream.write_all(&0u32.to_be_bytes()).await.map_err(io_error)?;
+
+    let mut reply = Vec::new();
+    stream.read_to_end(&mut reply).await.map_err(io_error)?;
+    parse_reply(&String::from_utf8_lossy(&reply))
+}
+
+// Replies look like "stream: OK", "stream: Eicar-Signature FOUND" or "... ERROR"
+fn parse_reply(reply: &str) -> std::result::Result<Verdict, String> {
+    let reply = reply.trim_end_matches(['\0', '\n']).trim();
+    let result = reply.strip_prefix("stream:").map(str::trim).unwrap_or(reply);
+    if result == "OK" {
+        Ok(Verdict::Clean)
+    } else if let Some(signature) = result.strip_suffix(" FOUND") {
+        Ok(Verdict::Infected(signature.to_string()))
+    } else {
+        Err(format!("unexpected reply {:?}", reply))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    // I am answering INSTREAM requests like clamd, flagging any stream that contains "EICAR"
+    async fn fake_clamd() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut command = [0u8; 10];
+                socket.read_exact(&mut command).await.unwrap();
+                assert_eq!(&command, b"zINSTREAM\0");
+
+                let mut data = Vec::new();
+                loop {
+                    let length = socket.read_u32().await.unwrap() as usize;
+                    if length == 0 {
+                        break;
+                    }
+                    let mut chunk = vec![0u8; length];
+                    socket.read_exact(&mut chunk).await.unwrap();
+                    data.extend_from_slice(&chunk);
+                }
+
+                let infected = data.windows(5).any(|window| window == b"EICAR");
+                let reply: &[u8] = if infected { b"stream: Eicar-Signature FOUND\0" } else { b"stream: OK\0" };
+                socket.write_all(reply).await.unwrap();
+            }
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_infected_uploads_are_rejected() {
+        let scanner = MalwareScanner::new(Some(fake_clamd().await), false, Duration::from_secs(5));
+        assert!(scanner.scan(b"quarterly report").await.is_ok());
+        // Larger than one chunk, so the signature straddles chunk boundaries
+        let mut infected = vec![b'x'; INSTREAM_CHUNK_BYTES - 2];
+        infected.extend_from_slice(b"EICAR-TEST");
+        match scanner.scan(&infected).await {
+            Err(AppError::BadRequestError(message)) => assert_eq!(message, "File failed malware scan"),
+            other => panic!("expected a malware rejection, got {:?}", other),
+        }
+
+        // Nothing listens on a port just released, so the scanner is unreachable
+        let closed = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().to_string();
+        let fail_closed = MalwareScanner::new(Some(closed.clone()), false, Duration::from_secs(5));
+        assert!(matches!(fail_closed.scan(b"data").await, Err(AppError::ExternalServiceError(_))));
+        let fail_open = MalwareScanner::new(Some(closed), true, Duration::from_secs(5));
+        assert!(fail_open.scan(b"data").await.is_ok());
+
+        assert!(MalwareScanner::new(None, false, Duration::from_secs(5)).scan(b"EICAR").await.is_ok());
+        assert!(parse_reply("INSTREAM size limit exceeded. ERROR\0").is_err());
+    }
+}
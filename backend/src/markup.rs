@@ -0,0 +1,112 @@
+// I am importing the HTML and Markdown parsers used to reduce pasted markup to plain text
+use ego_tree::iter::Edge;
+use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+use scraper::{Html, Node};
+
+// Elements whose text is code or styling rather than content
+const SKIPPED_ELEMENTS: &[&str] = &["head", "title", "script", "style", "noscript", "template"];
+
+// Elements that end a line, so "<p>a</p><p>b</p>" doesn't run together as "ab"
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address", "article", "aside", "blockquote", "dd", "div", "dl", "dt", "figcaption", "figure", "footer",
+    "h1", "h2", "h3", "h4", "h5", "h6", "header", "hr", "li", "main", "nav", "ol", "p", "pre", "section",
+    "table", "td", "th", "tr", "ul",
+];
+
+// I am extracting the readable text of an HTML document or fragment, one line per block element
+pub fn html_to_text(html: &str) -> String {
+    let document = Html::parse_document(html);
+    let mut text = String::new();
+    // Depth inside skipped elements; their text nodes are dropped
+    let mut skipping = 0usize;
+
+    for edge in document.tree.root().traverse() {
+        match edge {
+            Edge::Open(node) => match node.value() {
+                Node::Element(element) if SKIPPED_ELEMENTS.contains(&element.name()) => skipping += 1,
+                Node::Element(element) if element.name() == "br" && skipping == 0 => text.push('\n'),
+                // List items read the same as they would in Markdown
+                Node::Element(element) if element.name() == "li" && skipping == 0 => text.push_str("\n- "),
+                Node::Text(content) if skipping == 0 => {
+                    // Source line breaks and indentation inside text are only formatting
+                    let collapsed = content.split_whitespace().collect::<Vec<_>>().join(" ");
+                    if content.starts_with(char::is_whitespace) {
+                        text.push(' ');
+                    }
+                    text.push_str(&collapsed);
+                    if content.ends_with(char::is_whitespace) && !collapsed.is_empty() {
+                        text.push(' ');
+                    }
+                }
+                _ => {}
+            },
+            Edge::Close(node) => match node.value() {
+                Node::Element(element) if SKIPPED_ELEMENTS.contains(&element.name()) => skipping -= 1,
+                Node::Element(element) if BLOCK_ELEMENTS.contains(&element.name()) && skipping == 0 => text.push('\n'),
+                _ => {}
+            },
+        }
+    }
+
+    tidy_lines(&text)
+}
+
+// I am extracting the text of a Markdown document, dropping its syntax; embedded HTML is stripped too
+pub fn markdown_to_text(markdown: &str) -> String {
+    let mut text = String::new();
+    // Raw HTML blocks arrive line by line, so they are parsed once they end
+    let mut html_block = String::new();
+
+    for event in Parser::new(markdown) {
+        match event {
+            Event::Text(content) | Event::Code(content) => text.push_str(&content),
+            Event::Html(html) => html_block.push_str(&html),
+            Event::InlineHtml(html) => text.push_str(&html_to_text(&html)),
+            Event::SoftBreak => text.push(' '),
+            Event::HardBreak | Event::Rule => text.push('\n'),
+            Event::Start(Tag::Item) => text.push_str("- "),
+            Event::End(TagEnd::HtmlBlock) => {
+                text.push_str(&html_to_text(&html_block));
+                text.push('\n');
+                html_block.clear();
+            }
+            Event::End(
+                TagEnd::Paragraph | TagEnd::Heading(_) | TagEnd::BlockQuote(_) | TagEnd::CodeBlock | TagEnd::Item,
+            ) => text.push('\n'),
+            _ => {}
+        }
+    }
+
+    tidy_lines(&text)
+}
+
+// I am trimming every line and dropping the blank ones that nested blocks leave behind
+fn tidy_lines(text: &str) -> String {
+    text.lines().map(str::trim).filter(|line| !line.is_empty()).collect::<Vec<_>>().join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_keeps_text_and_line_structure() {
+        let html = r#"<html><head><title>Ignored</title><style>p { color: red }</style></head>
+            <body><h1>Quarterly   report</h1>
+            <p>Revenue <b>grew</b> 5% &amp; costs fell.</p><script>track("x")</script>
+            <ul><li>First</li><li>Second<br>line</li></ul></body></html>"#;
+        assert_eq!(
+            html_to_text(html),
+            "Quarterly report\nRevenue grew 5% & costs fell.\n- First\n- Second\nline"
+        );
+    }
+
+    #[test]
+    fn test_markdown_drops_syntax() {
+        let markdown = "# Title\n\nSome *emphasis* and a [link](https://example.com) with `code`.\n\n- one\n- two\n\n<div>Raw <em>html</em></div>\n";
+        assert_eq!(
+            markdown_to_text(markdown),
+            "Title\nSome emphasis and a link with code.\n- one\n- two\nRaw html"
+        );
+    }
+}
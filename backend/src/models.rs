@@ -1,8 +1,12 @@
 // I am importing serialization, UUID, and validation libraries for my data models
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, SecondsFormat, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::IpAddr;
 use uuid::Uuid;
 use validator::{Validate, ValidationError};
-use crate::storage::{StoredFile, StorageType};
+use crate::{qr::DecodedCode, storage::{StoredFile, StorageType}};
 
 // I am defining the response for the health check endpoint
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -12,14 +16,35 @@ pub struct HealthResponse {
     pub timestamp: String,
 }
 
-// I am defining the request structure for scanning, with validation
-#[derive(Debug, Serialize, Deserialize, Clone, Validate)]
-pub struct ScanRequest {
-    #[validate(length(min = 1, max = 10000, message = "Data must be between 1 and 10000 characters"))]
-    pub data: String,
-    
-    #[validate(custom(function = "validate_format"))]
-    pub format: String,
+// I am defining what the server currently accepts, so clients can build their choices from it
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ConfigOptionsResponse {
+    pub scan_formats: Vec<String>,
+    // Models accepted in `model` by chat and summarize requests, sorted
+    pub models: Vec<String>,
+    pub embedding_models: Vec<String>,
+    pub max_upload_bytes: u64,
+    pub max_files_per_upload: usize,
+    // Longest `content` /summarize accepts, in characters
+    pub max_summarize_chars: usize,
+    // Codes accepted in `target_language` by summarize requests
+    pub summary_languages: Vec<String>,
+}
+
+// I am defining the result of probing one downstream dependency for readiness
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DependencyStatus {
+    pub status: String,
+    pub critical: bool,
+    pub error: Option<String>,
+}
+
+// I am defining the response for the readiness endpoint, keyed by dependency name
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReadinessResponse {
+    pub status: String,
+    pub dependencies: BTreeMap<String, DependencyStatus>,
+    pub timestamp: String,
 }
 
 // I am defining the response structure for a scan
@@ -28,8 +53,19 @@ pub struct ScanResponse {
     pub id: Uuid,
     pub data: String,
     pub format: String,
-    pub timestamp: String,
+    pub timestamp: DateTime<Utc>,
     pub status: String,
+    // AI insight for the scan; None when analysis failed and status stays "processed"
+    pub analysis: Option<String>,
+    // Parsed analysis for scans created with structured: true
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub structured_analysis: Option<serde_json::Value>,
+    // Caller-defined tags such as project=alpha
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+    // Codes read from an image by POST /files/:id/decode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub codes: Option<Vec<DecodedCode>>,
 }
 
 // I am defining the request structure for creating a scan, with optional format and validation
@@ -40,6 +76,14 @@ pub struct CreateScanRequest {
     
     #[validate(custom(function = "validate_optional_format"))]
     pub format: Option<String>,
+
+    // Ask for the analysis as a JSON object, returned in structured_analysis
+    #[serde(default)]
+    pub structured: bool,
+
+    #[serde(default)]
+    #[validate(custom(function = "validate_metadata"))]
+    pub metadata: HashMap<String, String>,
 }
 
 // I am defining the response structure for a file upload
@@ -49,10 +93,46 @@ pub struct UploadResponse {
     pub filename: String,
     pub file_size: u64,
     pub content_type: Option<String>,
-    pub timestamp: String,
+    pub timestamp: DateTime<Utc>,
     pub status: String,
     pub storage_type: StorageType,
     pub download_url: Option<String>,
+    pub content_hash: String,
+    pub metadata: HashMap<String, String>,
+    // Only set for files in the trash
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+// I am defining the request to store a file the server downloads from a public URL
+#[derive(Debug, Serialize, Deserialize, Clone, Validate)]
+pub struct UploadFromUrlRequest {
+    #[validate(url(message = "Must be a valid URL"))]
+    pub url: String,
+    // Defaults to the last segment of the URL's path
+    #[validate(length(min = 1, max = 255, message = "Filename must be between 1 and 255 characters"))]
+    pub filename: Option<String>,
+}
+
+// I am defining the request that starts a resumable upload of `length` bytes
+#[derive(Debug, Serialize, Deserialize, Clone, Validate)]
+pub struct ResumableUploadInitRequest {
+    #[validate(length(min = 1, max = 255, message = "Filename must be between 1 and 255 characters"))]
+    pub filename: String,
+    pub length: u64,
+    pub content_type: Option<String>,
+}
+
+// I am defining the state of a resumable upload; `file` is set once the last chunk has been stored
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ResumableUploadResponse {
+    pub id: Uuid,
+    pub filename: String,
+    pub offset: u64,
+    pub length: u64,
+    pub expires_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<UploadResponse>,
 }
 
 // I am implementing a conversion from StoredFile to UploadResponse
@@ -67,10 +147,26 @@ impl From<StoredFile> for UploadResponse {
             status: "uploaded".to_string(),
             storage_type: stored_file.storage_type,
             download_url: stored_file.download_url,
+            content_hash: stored_file.content_hash,
+            metadata: stored_file.metadata,
+            deleted_at: stored_file.deleted_at,
         }
     }
 }
 
+// I am defining the request body for moving a file to another storage backend
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MigrateFileRequest {
+    pub target: StorageType,
+}
+
+// I am defining the request body for changing a stored file's display name
+#[derive(Debug, Serialize, Deserialize, Clone, Validate)]
+pub struct RenameFileRequest {
+    #[validate(length(min = 1, max = 255, message = "Filename must be between 1 and 255 characters"))]
+    pub filename: String,
+}
+
 // I am defining the response structure for a file download
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct FileDownloadResponse {
@@ -85,16 +181,468 @@ pub struct FileDownloadResponse {
 pub struct FileListResponse {
     pub files: Vec<UploadResponse>,
     pub total_count: usize,
+    // Pass as ?after= to get the next page; absent on the last page and for offset or non-date requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+// I am defining the totals behind GET /files/stats, so dashboards needn't page through every file
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct FileStatsResponse {
+    pub total_count: usize,
+    pub total_bytes: u64,
+    // Files without a recorded type are counted under application/octet-stream
+    pub by_content_type: BTreeMap<String, usize>,
+    pub by_storage_type: BTreeMap<String, usize>,
+}
+
+// I am defining the result of a temporary file cleanup, listing the files that were removed
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CleanupResponse {
+    pub removed_count: usize,
+    pub removed_file_ids: Vec<Uuid>,
+}
+
+// I am defining the limit/offset query parameters shared by the list endpoints
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct PaginationParams {
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+impl PaginationParams {
+    pub const DEFAULT_LIMIT: usize = 50;
+    pub const MAX_LIMIT: usize = 500;
+
+    pub fn limit(&self) -> usize {
+        self.limit.unwrap_or(Self::DEFAULT_LIMIT).min(Self::MAX_LIMIT)
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset.unwrap_or(0)
+    }
+
+    // I am slicing out the requested page; an offset past the end yields an empty page
+    pub fn paginate<T>(&self, items: Vec<T>) -> Vec<T> {
+        items.into_iter().skip(self.offset()).take(self.limit()).collect()
+    }
+}
+
+// I am defining the query parameters for semantic scan search
+#[derive(Debug, Serialize, Deserialize, Clone, Validate)]
+pub struct ScanSearchQuery {
+    #[validate(length(min = 1, max = 30000, message = "Query must be between 1 and 30000 characters"))]
+    pub q: String,
+    pub limit: Option<usize>,
+}
+
+impl ScanSearchQuery {
+    pub const DEFAULT_LIMIT: usize = 10;
+    pub const MAX_LIMIT: usize = 100;
+
+    pub fn limit(&self) -> usize {
+        self.limit.unwrap_or(Self::DEFAULT_LIMIT).min(Self::MAX_LIMIT)
+    }
+}
+
+// I am defining one search hit: the scan plus its cosine similarity to the query
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ScanSearchResult {
+    #[serde(flatten)]
+    pub scan: ScanResponse,
+    pub score: f32,
+}
+
+// I am defining the fields the file list can be sorted by
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum FileSortField {
+    Size,
+    #[default]
+    Date,
+    Name,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+// I am defining the sort and filter query parameters for the file list, applied before pagination
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct FileListQuery {
+    pub sort: Option<FileSortField>,
+    pub order: Option<SortOrder>,
+    pub content_type: Option<String>,
+    pub filename_contains: Option<String>,
+    // key:value, matched exactly against the file's metadata
+    pub tag: Option<String>,
+    // next_cursor from the previous page; the page starts right after the file it names
+    pub after: Option<String>,
+    // List the files in the trash instead of the live ones
+    pub trashed: Option<bool>,
+}
+
+// I am defining a file list cursor: the (timestamp, id) of the last file on a page, which stays
+// meaningful when files before it are added or deleted, unlike an offset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileCursor {
+    timestamp: DateTime<Utc>,
+    id: Uuid,
+}
+
+impl FileCursor {
+    fn of(file: &UploadResponse) -> Self {
+        Self { timestamp: file.timestamp, id: file.id }
+    }
+
+    // Opaque to clients; URL-safe so it can go straight into ?after=
+    pub fn encode(&self) -> String {
+        // Full nanosecond precision, so files uploaded within the same microsecond aren't skipped or repeated
+        let timestamp = self.timestamp.to_rfc3339_opts(SecondsFormat::Nanos, true);
+        URL_SAFE_NO_PAD.encode(format!("{}|{}", timestamp, self.id))
+    }
+
+    pub fn decode(cursor: &str) -> Option<Self> {
+        let decoded = String::from_utf8(URL_SAFE_NO_PAD.decode(cursor).ok()?).ok()?;
+        let (timestamp, id) = decoded.split_once('|')?;
+        Some(Self {
+            timestamp: DateTime::parse_from_rfc3339(timestamp).ok()?.with_timezone(&Utc),
+            id: id.parse().ok()?,
+        })
+    }
+}
+
+impl FileListQuery {
+    // Names read naturally A-Z, while dates and sizes are most useful newest/largest first
+    fn order(&self, field: FileSortField) -> SortOrder {
+        self.order.unwrap_or(match field {
+            FileSortField::Name => SortOrder::Asc,
+            FileSortField::Size | FileSortField::Date => SortOrder::Desc,
+        })
+    }
+
+    // I am filtering and sorting the files, breaking ties by id so pages stay stable between requests
+    pub fn apply(&self, files: Vec<UploadResponse>) -> Vec<UploadResponse> {
+        let filename_contains = self.filename_contains.as_deref().map(str::to_lowercase);
+
+        let mut files: Vec<UploadResponse> = files
+            .into_iter()
+            .filter(|file| match &self.content_type {
+                Some(content_type) => file
+                    .content_type
+                    .as_deref()
+                    .is_some_and(|actual| actual.eq_ignore_ascii_case(content_type)),
+                None => true,
+            })
+            .filter(|file| match &filename_contains {
+                Some(needle) => file.filename.to_lowercase().contains(needle.as_str()),
+                None => true,
+            })
+            .filter(|file| matches_tag(&file.metadata, self.tag.as_deref()))
+            .collect();
+
+        let field = self.sort.unwrap_or_default();
+        let order = self.order(field);
+        files.sort_by(|a, b| {
+            let ordering = match field {
+                FileSortField::Size => a.file_size.cmp(&b.file_size),
+                FileSortField::Date => a.timestamp.cmp(&b.timestamp),
+                FileSortField::Name => a.filename.to_lowercase().cmp(&b.filename.to_lowercase()),
+            }
+            .then(a.id.cmp(&b.id));
+
+            match order {
+                SortOrder::Asc => ordering,
+                SortOrder::Desc => ordering.reverse(),
+            }
+        });
+        files
+    }
+
+    // I am reading the `after` cursor, which only makes sense for the date order and instead of an offset
+    pub fn cursor(&self, pagination: &PaginationParams) -> std::result::Result<Option<FileCursor>, String> {
+        let Some(after) = self.after.as_deref() else {
+            return Ok(None);
+        };
+        if self.sort.unwrap_or_default() != FileSortField::Date {
+            return Err("after: Cursors can only be used with sort=date".to_string());
+        }
+        if pagination.offset.is_some() {
+            return Err("after: Use either after or offset, not both".to_string());
+        }
+        FileCursor::decode(after)
+            .map(Some)
+            .ok_or_else(|| "after: Invalid cursor".to_string())
+    }
+
+    // I am slicing out a page of files already sorted by `apply`, starting after the cursor, and returning
+    // the cursor for the next page when more files follow. Offset requests and non-date sorts get no cursor
+    pub fn paginate(
+        &self,
+        files: Vec<UploadResponse>,
+        pagination: &PaginationParams,
+        after: Option<FileCursor>,
+    ) -> (Vec<UploadResponse>, Option<String>) {
+        let field = self.sort.unwrap_or_default();
+        if field != FileSortField::Date || pagination.offset.is_some() {
+            return (pagination.paginate(files), None);
+        }
+
+        // Files are in (timestamp, id) order, so the page starts at the first file past the cursor
+        let order = self.order(field);
+        let past_cursor = |file: &UploadResponse| match after {
+            Some(after) => {
+                let position = (file.timestamp, file.id).cmp(&(after.timestamp, after.id));
+                match order {
+                    SortOrder::Asc => position.is_gt(),
+                    SortOrder::Desc => position.is_lt(),
+                }
+            }
+            None => true,
+        };
+
+        let limit = pagination.limit();
+        let mut page: Vec<UploadResponse> = files.into_iter().filter(past_cursor).take(limit + 1).collect();
+        let next_cursor = if page.len() > limit {
+            page.truncate(limit);
+            page.last().map(|file| FileCursor::of(file).encode())
+        } else {
+            None
+        };
+        (page, next_cursor)
+    }
+}
+
+// I am defining the optional tag filter for the scan list
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ScanListQuery {
+    // key:value, matched exactly against the scan's metadata
+    pub tag: Option<String>,
+}
+
+// I am splitting a key:value tag filter at its first colon
+pub fn parse_tag_filter(tag: &str) -> Option<(&str, &str)> {
+    tag.split_once(':').filter(|(key, _)| !key.is_empty())
+}
+
+// Without a filter everything matches; a malformed filter matches nothing
+pub fn matches_tag(metadata: &HashMap<String, String>, tag: Option<&str>) -> bool {
+    match tag {
+        Some(tag) => parse_tag_filter(tag)
+            .is_some_and(|(key, value)| metadata.get(key).is_some_and(|actual| actual == value)),
+        None => true,
+    }
+}
+
+pub const MAX_METADATA_TAGS: usize = 20;
+pub const MAX_METADATA_KEY_LENGTH: usize = 64;
+pub const MAX_METADATA_VALUE_LENGTH: usize = 256;
+
+// I am capping tag count and size so metadata can't be used to bloat the stores
+pub fn validate_metadata(metadata: &HashMap<String, String>) -> Result<(), ValidationError> {
+    let message = if metadata.len() > MAX_METADATA_TAGS {
+        format!("At most {} metadata tags are allowed", MAX_METADATA_TAGS)
+    } else if let Some(key) = metadata.keys().find(|key| key.is_empty() || key.contains(':')) {
+        format!("Metadata key {:?} must be non-empty and must not contain ':'", key)
+    } else if let Some(key) = metadata.keys().find(|key| key.chars().count() > MAX_METADATA_KEY_LENGTH) {
+        format!("Metadata key {:?} exceeds {} characters", key, MAX_METADATA_KEY_LENGTH)
+    } else if let Some(key) = metadata
+        .iter()
+        .find(|(_, value)| value.chars().count() > MAX_METADATA_VALUE_LENGTH)
+        .map(|(key, _)| key)
+    {
+        format!("Metadata value for {:?} exceeds {} characters", key, MAX_METADATA_VALUE_LENGTH)
+    } else {
+        return Ok(());
+    };
+
+    Err(ValidationError::new("metadata").with_message(message.into()))
+}
+
+// I am defining the confirmation flag required before clearing all of a user's scans
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DeleteScansQuery {
+    pub confirm: Option<bool>,
+}
+
+// I am defining the query for DELETE /files/:id; without permanent=true the file only goes to the trash
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DeleteFileQuery {
+    pub permanent: Option<bool>,
+}
+
+// I am defining the signed-link parameters a download may carry instead of a bearer token
+#[derive(Debug, Deserialize, Default)]
+pub struct DownloadTokenQuery {
+    pub token: Option<String>,
+    // Unix seconds after which the link stops working
+    pub exp: Option<i64>,
+}
+
+// I am defining the requested lifetime of a signed download URL
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DownloadUrlQuery {
+    pub expires_in: Option<u64>,
+}
+
+impl DownloadUrlQuery {
+    pub const DEFAULT_EXPIRES_IN: u64 = 60 * 60;
+    pub const MIN_EXPIRES_IN: u64 = 60;
+    pub const MAX_EXPIRES_IN: u64 = 7 * 24 * 60 * 60;
+
+    // Seconds the URL should stay valid, clamped to between a minute and a week
+    pub fn expires_in(&self) -> u64 {
+        self.expires_in
+            .unwrap_or(Self::DEFAULT_EXPIRES_IN)
+            .clamp(Self::MIN_EXPIRES_IN, Self::MAX_EXPIRES_IN)
+    }
+}
+
+// I am defining the query parameters for a thumbnail request
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ThumbnailQuery {
+    pub w: Option<u32>,
 }
 
 // I am defining the request structure for summarizing a document, with validation
 #[derive(Debug, Serialize, Deserialize, Clone, Validate)]
 pub struct SummarizeRequest {
-    #[validate(length(min = 10, max = 50000, message = "Content must be between 10 and 50000 characters"))]
+    // Hard ceiling; the handler applies the lower MAX_SUMMARIZE_CHARS limit
+    #[validate(length(min = 10, max = 1000000, message = "Content must be between 10 and 1000000 characters"))]
     pub content: String,
     
     #[validate(range(min = 50, max = 2000, message = "Max length must be between 50 and 2000 characters"))]
     pub max_length: Option<usize>,
+
+    // Markup in the content is stripped to plain text before summarizing
+    #[serde(default)]
+    pub input_format: SummarizeInputFormat,
+
+    // ISO 639-1 code of the language to write the summary in; unset keeps the source language
+    #[validate(custom(function = "validate_summary_language"))]
+    pub target_language: Option<String>,
+}
+
+// Languages a summary can be requested in, by ISO 639-1 code
+pub const SUMMARY_LANGUAGES: &[(&str, &str)] = &[
+    ("ar", "Arabic"), ("de", "German"), ("en", "English"), ("es", "Spanish"), ("fr", "French"),
+    ("hi", "Hindi"), ("id", "Indonesian"), ("it", "Italian"), ("ja", "Japanese"), ("ko", "Korean"),
+    ("nl", "Dutch"), ("pl", "Polish"), ("pt", "Portuguese"), ("ru", "Russian"), ("sv", "Swedish"),
+    ("tr", "Turkish"), ("uk", "Ukrainian"), ("vi", "Vietnamese"), ("zh", "Chinese"),
+];
+
+// I am looking up a summary language by code, ignoring case; returns the normalized code and English name
+pub fn summary_language(code: &str) -> Option<(&'static str, &'static str)> {
+    SUMMARY_LANGUAGES.iter().copied().find(|(known, _)| known.eq_ignore_ascii_case(code.trim()))
+}
+
+fn validate_summary_language(code: &str) -> Result<(), ValidationError> {
+    match summary_language(code) {
+        Some(_) => Ok(()),
+        None => Err(ValidationError::new("target_language").with_message(
+            format!(
+                "Unsupported language {:?}; expected one of: {}",
+                code,
+                SUMMARY_LANGUAGES.iter().map(|(code, _)| *code).collect::<Vec<_>>().join(", ")
+            )
+            .into(),
+        )),
+    }
+}
+
+// I am defining what /summarize content is written in: plain text is sent as-is, HTML and Markdown are
+// reduced to their text first so tags and syntax don't end up in the summary
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SummarizeInputFormat {
+    #[default]
+    Plain,
+    Html,
+    Markdown,
+}
+
+impl SummarizeInputFormat {
+    pub fn to_plain_text(self, content: String) -> String {
+        match self {
+            SummarizeInputFormat::Plain => content,
+            SummarizeInputFormat::Html => crate::markup::html_to_text(&content),
+            SummarizeInputFormat::Markdown => crate::markup::markdown_to_text(&content),
+        }
+    }
+}
+
+// I am defining how /summarize treats long content: "auto" chunks only content too long for one request,
+// "single" always sends one request, and "mapreduce" always goes through chunked summarization
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SummarizeStrategy {
+    #[default]
+    Auto,
+    Single,
+    MapReduce,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SummarizeQuery {
+    #[serde(default)]
+    pub strategy: SummarizeStrategy,
+}
+
+// I am defining the optional body for summarizing a stored file
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SummarizeFileRequest {
+    pub max_length: Option<usize>,
+    pub target_language: Option<String>,
+}
+
+// I am defining the query for CSV analysis; overview=true also asks the model to describe the statistics
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct CsvAnalysisQuery {
+    #[serde(default)]
+    pub overview: bool,
+}
+
+// I am defining the type inferred for a CSV column from every non-null value in it
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CsvColumnType {
+    Integer,
+    Float,
+    Boolean,
+    Text,
+}
+
+// I am defining one column's statistics: min/max/mean for numeric columns, a distinct count for the rest
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CsvColumnStats {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub column_type: CsvColumnType,
+    // Non-null values
+    pub count: usize,
+    pub nulls: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mean: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distinct: Option<usize>,
+}
+
+// I am defining the response structure for analyzing an uploaded CSV file
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CsvAnalysisResponse {
+    pub file_id: Uuid,
+    pub rows: usize,
+    pub columns: Vec<CsvColumnStats>,
+    // Natural-language description of the statistics, when requested and the model answered
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub overview: Option<String>,
 }
 
 // I am defining the response structure for a document summary
@@ -105,6 +653,15 @@ pub struct SummarizeResponse {
     pub summary: String,
     pub original_length: usize,
     pub summary_length: usize,
+    pub usage: TokenUsage,
+    // How many pieces the content was split into; 1 when it fit in a single request
+    pub chunks: usize,
+    // Whether this summary was served from the cache of earlier identical requests, costing nothing
+    #[serde(default)]
+    pub cached: bool,
+    // Code of the language the summary was written in, when target_language was set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_language: Option<String>,
     pub timestamp: String,
 }
 
@@ -114,7 +671,7 @@ pub struct ChatCompletionRequest {
     #[validate(length(min = 1, max = 50000, message = "Content must be between 1 and 50000 characters"))]
     pub content: String,
     
-    #[validate(custom(function = "validate_optional_model"))]
+    // Checked against the configured allow-list in the handler
     pub model: Option<String>,
     
     #[validate(range(min = 0.0, max = 2.0, message = "Temperature must be between 0.0 and 2.0"))]
@@ -124,6 +681,17 @@ pub struct ChatCompletionRequest {
     pub max_tokens: Option<u32>,
     
     pub system_prompt: Option<String>,
+
+    // Only honored for hosts listed in BASE_URL_OVERRIDE_HOSTS
+    #[validate(url(message = "Base URL override must be a valid URL"))]
+    pub base_url_override: Option<String>,
+
+    // Continues an earlier /chat/completion conversation; a new one is started when absent
+    pub conversation_id: Option<Uuid>,
+
+    // Overrides OpenAIConfig.timeout_seconds for this call
+    #[validate(range(min = 1, max = 600, message = "Timeout must be between 1 and 600 seconds"))]
+    pub timeout_seconds: Option<u64>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -132,18 +700,86 @@ pub struct ChatCompletionResponse {
     pub content: String,
     pub model: String,
     pub usage: TokenUsage,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conversation_id: Option<Uuid>,
     pub timestamp: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
 pub struct TokenUsage {
     pub prompt_tokens: u32,
     pub completion_tokens: u32,
     pub total_tokens: u32,
 }
 
-// OpenAI API Internal Models (for API communication)
+// I am defining the request structure for generating an embedding, with validation
+#[derive(Debug, Serialize, Deserialize, Clone, Validate)]
+pub struct EmbeddingRequest {
+    #[validate(length(min = 1, max = 30000, message = "Input must be between 1 and 30000 characters"))]
+    pub input: String,
+
+    // One of the supported embedding models; defaults to OpenAIConfig.embedding_model
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EmbeddingResponse {
+    pub id: Uuid,
+    pub embedding: Vec<f32>,
+    pub model: String,
+    pub usage: TokenUsage,
+    pub timestamp: String,
+}
+
+// Accumulated OpenAI token usage for the calling user
 #[derive(Debug, Serialize, Deserialize)]
+pub struct UserUsageResponse {
+    pub user_id: Uuid,
+    pub usage: TokenUsage,
+}
+
+// Final event of a streamed chat completion
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChatStreamDone {
+    pub model: String,
+    pub usage: Option<TokenUsage>,
+    pub conversation_id: Uuid,
+}
+
+// Frames the server sends back on the /chat/ws socket
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ChatSocketMessage {
+    Delta { content: String },
+    Done { model: String, usage: Option<TokenUsage> },
+    Error { message: String },
+}
+
+// Cost attribution models
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UsageByTag {
+    pub tag: String,
+    pub request_count: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_tokens: u64,
+    pub estimated_cost_usd: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UsageReportQuery {
+    pub window_hours: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UsageReportResponse {
+    pub window_hours: i64,
+    pub since: String,
+    pub tags: Vec<UsageByTag>,
+}
+
+// OpenAI API Internal Models (for API communication)
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct OpenAIMessage {
     pub role: String,
     pub content: String,
@@ -157,6 +793,51 @@ pub struct OpenAIChatRequest {
     pub temperature: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<OpenAIStreamOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<OpenAIResponseFormat>,
+}
+
+// Asks OpenAI to constrain the reply format, e.g. {"type": "json_object"}
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenAIResponseFormat {
+    #[serde(rename = "type")]
+    pub format_type: String,
+}
+
+impl OpenAIResponseFormat {
+    pub fn json_object() -> Self {
+        Self {
+            format_type: "json_object".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenAIStreamOptions {
+    pub include_usage: bool,
+}
+
+// Streaming responses arrive as a series of chunks carrying content deltas
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenAIStreamChunk {
+    pub model: String,
+    #[serde(default)]
+    pub choices: Vec<OpenAIStreamChoice>,
+    pub usage: Option<OpenAIUsage>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenAIStreamChoice {
+    pub delta: OpenAIStreamDelta,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenAIStreamDelta {
+    pub content: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -172,6 +853,48 @@ pub struct OpenAIUsage {
     pub total_tokens: u32,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenAIEmbeddingRequest {
+    pub model: String,
+    pub input: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenAIEmbeddingData {
+    pub embedding: Vec<f32>,
+}
+
+// Embeddings only consume prompt tokens
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenAIEmbeddingUsage {
+    pub prompt_tokens: u32,
+    pub total_tokens: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenAIEmbeddingResponse {
+    pub data: Vec<OpenAIEmbeddingData>,
+    pub model: String,
+    pub usage: OpenAIEmbeddingUsage,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenAIModerationRequest {
+    pub input: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenAIModerationResult {
+    pub flagged: bool,
+    // Category name -> whether this input was flagged for it
+    pub categories: HashMap<String, bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenAIModerationResponse {
+    pub results: Vec<OpenAIModerationResult>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct OpenAIChatResponse {
     pub id: String,
@@ -189,6 +912,8 @@ pub struct ApiResponse<T> {
     pub data: Option<T>,
     pub message: String,
     pub validation_errors: Option<Vec<String>>,
+    // Same value as AppError::error_type; null on success
+    pub error_code: Option<String>,
 }
 
 impl<T> ApiResponse<T> {
@@ -198,15 +923,17 @@ impl<T> ApiResponse<T> {
             data: Some(data),
             message: message.to_string(),
             validation_errors: None,
+            error_code: None,
         }
     }
 
-    pub fn error(message: &str) -> Self {
+    pub fn error(error_code: &str, message: &str) -> Self {
         Self {
             success: false,
             data: None,
             message: message.to_string(),
             validation_errors: None,
+            error_code: Some(error_code.to_string()),
         }
     }
     
@@ -216,6 +943,7 @@ impl<T> ApiResponse<T> {
             data: None,
             message: message.to_string(),
             validation_errors: Some(errors),
+            error_code: Some("validation_error".to_string()),
         }
     }
 }
@@ -226,7 +954,31 @@ pub struct OpenAIConfig {
     pub api_key: String,
     pub base_url: Option<String>,
     pub default_model: String,
+    pub embedding_model: String,
+    // Sent as OpenAI-Organization / OpenAI-Project so usage is billed to the right place
+    pub organization: Option<String>,
+    pub project: Option<String>,
     pub timeout_seconds: u64,
+    pub max_interactive_concurrency: usize,
+    pub max_background_concurrency: usize,
+    // Cap on concurrent OpenAI calls across both pools, so bursts stay under account-level rate limits
+    pub max_concurrency: usize,
+    // How long a call waits for a concurrency permit before it is refused with a rate limit error
+    pub permit_timeout_ms: u64,
+    pub max_retries: u32,
+    pub retry_base_delay_ms: u64,
+    // Hosts ("host" or "host:port") a chat request may name as its base URL instead of base_url; the server's
+    // API key is sent there, so an empty set refuses every override
+    pub base_url_override_hosts: HashSet<String>,
+    // Whether chat and summarize input is checked with /v1/moderations first
+    pub moderation_enabled: bool,
+    // Answer every call with canned, zero-cost responses instead of calling OpenAI (for offline testing)
+    pub mock_mode: bool,
+    // Scan format -> system prompt used by analyze_scan_data instead of the generic one
+    pub scan_prompts: HashMap<String, String>,
+    // Sent when a chat request leaves temperature or max_tokens unset; None leaves OpenAI's own default
+    pub default_temperature: Option<f64>,
+    pub default_max_tokens: Option<u32>,
 }
 
 impl Default for OpenAIConfig {
@@ -235,15 +987,83 @@ impl Default for OpenAIConfig {
             api_key: std::env::var("OPENAI_API_KEY").unwrap_or_default(),
             base_url: None,
             default_model: "gpt-4o-mini".to_string(),
+            embedding_model: std::env::var("OPENAI_EMBEDDING_MODEL")
+                .unwrap_or_else(|_| "text-embedding-3-small".to_string()),
+            organization: std::env::var("OPENAI_ORG").ok().filter(|v| !v.is_empty()),
+            project: std::env::var("OPENAI_PROJECT").ok().filter(|v| !v.is_empty()),
             timeout_seconds: 30,
+            max_interactive_concurrency: std::env::var("OPENAI_MAX_INTERACTIVE_CONCURRENCY")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(8),
+            max_background_concurrency: std::env::var("OPENAI_MAX_BACKGROUND_CONCURRENCY")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(2),
+            max_concurrency: std::env::var("OPENAI_MAX_CONCURRENCY")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(10),
+            permit_timeout_ms: std::env::var("OPENAI_PERMIT_TIMEOUT_MS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(10_000),
+            max_retries: std::env::var("OPENAI_MAX_RETRIES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(3),
+            retry_base_delay_ms: 500,
+            base_url_override_hosts: std::env::var("BASE_URL_OVERRIDE_HOSTS")
+                .map(|hosts| {
+                    hosts.split(',')
+                        .map(|host| host.trim().to_ascii_lowercase())
+                        .filter(|host| !host.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            moderation_enabled: std::env::var("ENABLE_MODERATION")
+                .map(|v| v == "true")
+                .unwrap_or(false),
+            mock_mode: std::env::var("OPENAI_MOCK")
+                .map(|v| v == "true")
+                .unwrap_or(false),
+            scan_prompts: std::env::var("OPENAI_SCAN_PROMPTS_PATH")
+                .map(|path| load_scan_prompts(&path))
+                .unwrap_or_default(),
+            // Out-of-range values are ignored rather than sent, matching the limits on request values
+            default_temperature: std::env::var("OPENAI_DEFAULT_TEMPERATURE")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|temperature| (0.0..=2.0).contains(temperature)),
+            default_max_tokens: std::env::var("OPENAI_DEFAULT_MAX_TOKENS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|max_tokens| (1..=4096).contains(max_tokens)),
         }
     }
 }
 
+// I am loading format-specific scan prompts from a JSON object file, keeping the generic prompt on any error
+fn load_scan_prompts(path: &str) -> HashMap<String, String> {
+    let prompts = std::fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|contents| serde_json::from_str(&contents).map_err(|e| e.to_string()));
+
+    match prompts {
+        Ok(prompts) => prompts,
+        Err(e) => {
+            tracing::warn!("Ignoring scan prompts file {}: {}", path, e);
+            HashMap::new()
+        }
+    }
+}
+
+// Scan formats accepted by CreateScanRequest; also listed by GET /config/options
+pub const SCAN_FORMATS: &[&str] = &["text", "qr", "barcode", "ocr"];
+
 // Custom validation functions
 fn validate_format(format: &str) -> Result<(), ValidationError> {
-    let valid_formats = ["text", "qr", "barcode", "ocr"];
-    if valid_formats.contains(&format) {
+    if SCAN_FORMATS.contains(&format) {
         Ok(())
     } else {
         Err(ValidationError::new("Format must be one of: text, qr, barcode, ocr"))
@@ -254,32 +1074,59 @@ fn validate_optional_format(format: &str) -> Result<(), ValidationError> {
     validate_format(format)
 }
 
-fn validate_optional_model(model: &str) -> Result<(), ValidationError> {
-    let valid_models = ["gpt-3.5-turbo", "gpt-4", "gpt-4-turbo", "gpt-4o", "gpt-4o-mini"];
-    if valid_models.contains(&model) {
-        Ok(())
-    } else {
-        Err(ValidationError::new("Invalid model specified"))
-    }
-}
-
 // User Authentication Models
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct User {
     pub id: Uuid,
     pub email: String,
     pub password_hash: String,
-    pub created_at: String,
-    pub updated_at: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
     pub is_active: bool,
+    pub role: UserRole,
+    // None until the first successful password login
+    #[serde(default)]
+    pub last_login_at: Option<DateTime<Utc>>,
+}
+
+// I am defining one successful login, as listed by GET /auth/me/logins
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct LoginEvent {
+    pub timestamp: DateTime<Utc>,
+    // Address the login came from; None when the connection's address wasn't available
+    pub ip: Option<IpAddr>,
+}
+
+// I am defining the roles a user can hold; admins can do everything a user can
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum UserRole {
+    #[default]
+    User,
+    Admin,
+}
+
+impl UserRole {
+    pub fn satisfies(self, required: UserRole) -> bool {
+        self == UserRole::Admin || self == required
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct UserResponse {
     pub id: Uuid,
     pub email: String,
-    pub created_at: String,
+    pub created_at: DateTime<Utc>,
     pub is_active: bool,
+    pub role: UserRole,
+    pub last_login_at: Option<DateTime<Utc>>,
+}
+
+// I am defining the response structure for the admin user listing
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UserListResponse {
+    pub users: Vec<UserResponse>,
+    pub total_count: usize,
 }
 
 impl From<User> for UserResponse {
@@ -289,6 +1136,8 @@ impl From<User> for UserResponse {
             email: user.email,
             created_at: user.created_at,
             is_active: user.is_active,
+            role: user.role,
+            last_login_at: user.last_login_at,
         }
     }
 }
@@ -325,6 +1174,44 @@ pub struct AuthResponse {
     pub user: UserResponse,
     pub token: String,
     pub expires_at: String,
+    pub refresh_token: String,
+    pub refresh_expires_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Validate)]
+pub struct RefreshTokenRequest {
+    #[validate(length(min = 1, message = "Refresh token is required"))]
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Validate)]
+pub struct VerifyEmailRequest {
+    #[validate(length(min = 1, message = "Verification token is required"))]
+    pub token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Validate)]
+pub struct ForgotPasswordRequest {
+    #[validate(email(message = "Must be a valid email address"))]
+    pub email: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Validate)]
+pub struct ResetPasswordRequest {
+    #[validate(length(min = 1, message = "Reset token is required"))]
+    pub token: String,
+
+    #[validate(length(min = 8, max = 128, message = "Password must be between 8 and 128 characters"))]
+    pub new_password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Validate)]
+pub struct ChangePasswordRequest {
+    #[validate(length(min = 1, message = "Current password is required"))]
+    pub current_password: String,
+
+    #[validate(length(min = 8, max = 128, message = "Password must be between 8 and 128 characters"))]
+    pub new_password: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -340,4 +1227,167 @@ pub struct Claims {
     pub email: String,
     pub exp: usize, // Expiration time
     pub iat: usize, // Issued at
-} 
\ No newline at end of file
+    pub jti: String, // Token ID, used to revoke the token on logout
+    pub iss: String, // Issuer, checked against JWT_ISSUER
+    pub aud: String, // Audience, checked against JWT_AUDIENCE
+    #[serde(default)]
+    pub role: UserRole, // Tokens issued before roles existed decode as ordinary users
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pagination_defaults_and_bounds() {
+        let items: Vec<usize> = (0..600).collect();
+
+        let default_page = PaginationParams::default().paginate(items.clone());
+        assert_eq!(default_page.len(), 50);
+        assert_eq!(default_page[0], 0);
+
+        let capped = PaginationParams { limit: Some(10_000), offset: None };
+        assert_eq!(capped.paginate(items.clone()).len(), 500);
+
+        let page = PaginationParams { limit: Some(5), offset: Some(598) };
+        assert_eq!(page.paginate(items.clone()), vec![598, 599]);
+
+        let out_of_range = PaginationParams { limit: Some(5), offset: Some(1000) };
+        assert!(out_of_range.paginate(items).is_empty());
+    }
+
+    #[test]
+    fn test_download_url_expiry_is_clamped() {
+        assert_eq!(DownloadUrlQuery::default().expires_in(), 3600);
+        assert_eq!(DownloadUrlQuery { expires_in: Some(5) }.expires_in(), 60);
+        assert_eq!(DownloadUrlQuery { expires_in: Some(900) }.expires_in(), 900);
+        assert_eq!(DownloadUrlQuery { expires_in: Some(u64::MAX) }.expires_in(), 7 * 24 * 60 * 60);
+    }
+
+    fn upload(n: u64, filename: &str, file_size: u64, content_type: &str) -> UploadResponse {
+        UploadResponse {
+            id: Uuid::from_u64_pair(0, n),
+            filename: filename.to_string(),
+            file_size,
+            content_type: Some(content_type.to_string()),
+            timestamp: DateTime::from_timestamp(n as i64, 0).unwrap(),
+            status: "uploaded".to_string(),
+            storage_type: StorageType::Temporary,
+            download_url: None,
+            content_hash: String::new(),
+            metadata: HashMap::new(),
+            deleted_at: None,
+        }
+    }
+
+    #[test]
+    fn test_file_list_query_sorts_and_filters() {
+        let files = vec![
+            upload(1, "b-report.pdf", 300, "application/pdf"),
+            upload(2, "a-photo.png", 100, "image/png"),
+            upload(3, "C-Report.png", 200, "image/png"),
+        ];
+        let names = |files: Vec<UploadResponse>| files.into_iter().map(|f| f.filename).collect::<Vec<_>>();
+
+        // Newest first by default
+        assert_eq!(
+            names(FileListQuery::default().apply(files.clone())),
+            vec!["C-Report.png", "a-photo.png", "b-report.pdf"]
+        );
+
+        let by_name = FileListQuery { sort: Some(FileSortField::Name), ..Default::default() };
+        assert_eq!(names(by_name.apply(files.clone())), vec!["a-photo.png", "b-report.pdf", "C-Report.png"]);
+
+        let by_size_asc = FileListQuery {
+            sort: Some(FileSortField::Size),
+            order: Some(SortOrder::Asc),
+            ..Default::default()
+        };
+        assert_eq!(names(by_size_asc.apply(files.clone())), vec!["a-photo.png", "C-Report.png", "b-report.pdf"]);
+
+        let filtered = FileListQuery {
+            content_type: Some("IMAGE/PNG".to_string()),
+            filename_contains: Some("report".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(names(filtered.apply(files)), vec!["C-Report.png"]);
+    }
+
+    #[test]
+    fn test_file_cursor_pages_survive_changes() {
+        let query = FileListQuery::default();
+        let pagination = PaginationParams { limit: Some(2), offset: None };
+        let ids = |files: &[UploadResponse]| files.iter().map(|f| f.id.as_u64_pair().1).collect::<Vec<_>>();
+        let mut files: Vec<UploadResponse> = (1..=5).map(|n| upload(n, "f", 1, "text/plain")).collect();
+
+        let (first, cursor) = query.paginate(query.apply(files.clone()), &pagination, None);
+        assert_eq!(ids(&first), vec![5, 4]);
+        let cursor = cursor.unwrap();
+
+        // A new upload and a deleted file don't shift the next page, as they would with offset=2
+        files.push(upload(6, "f", 1, "text/plain"));
+        files.retain(|file| file.id != Uuid::from_u64_pair(0, 4));
+        let after = FileListQuery { after: Some(cursor), ..Default::default() };
+        let decoded = after.cursor(&pagination).unwrap();
+        let (second, cursor) = after.paginate(after.apply(files.clone()), &pagination, decoded);
+        assert_eq!(ids(&second), vec![3, 2]);
+
+        let last = FileListQuery { after: cursor, ..Default::default() };
+        let decoded = last.cursor(&pagination).unwrap();
+        let (third, cursor) = last.paginate(last.apply(files), &pagination, decoded);
+        assert_eq!((ids(&third), cursor), (vec![1], None));
+
+        let bad = FileListQuery { after: Some("not-a-cursor".to_string()), ..Default::default() };
+        assert!(bad.cursor(&pagination).is_err());
+        let by_size = FileListQuery { sort: Some(FileSortField::Size), after: Some(String::new()), ..Default::default() };
+        assert!(by_size.cursor(&pagination).is_err());
+    }
+
+    #[test]
+    fn test_file_cursor_keeps_sub_microsecond_order() {
+        let query = FileListQuery::default();
+        let pagination = PaginationParams { limit: Some(1), offset: None };
+        // Three uploads within the same microsecond, with ids in the opposite order to their timestamps
+        let files: Vec<UploadResponse> = (1..=3)
+            .map(|n| UploadResponse {
+                timestamp: DateTime::from_timestamp(1_700_000_000, 100 * n as u32).unwrap(),
+                ..upload(10 - n, "f", 1, "text/plain")
+            })
+            .collect();
+
+        let cursor = FileCursor::of(&files[0]);
+        assert_eq!(FileCursor::decode(&cursor.encode()).unwrap().timestamp, files[0].timestamp);
+
+        let mut seen = Vec::new();
+        let mut after = None;
+        loop {
+            let page_query = FileListQuery { after: after.take(), ..Default::default() };
+            let decoded = page_query.cursor(&pagination).unwrap();
+            let (page, next) = query.paginate(page_query.apply(files.clone()), &pagination, decoded);
+            seen.extend(page.iter().map(|f| f.id.as_u64_pair().1));
+            match next {
+                Some(next) => after = Some(next),
+                None => break,
+            }
+        }
+        assert_eq!(seen, vec![7, 8, 9]);
+    }
+
+    #[test]
+    fn test_tag_filter_and_metadata_limits() {
+        let metadata = HashMap::from([("project".to_string(), "alpha:beta".to_string())]);
+        assert!(matches_tag(&metadata, None));
+        assert!(matches_tag(&metadata, Some("project:alpha:beta")));
+        assert!(!matches_tag(&metadata, Some("project:alpha")));
+        assert!(!matches_tag(&metadata, Some("project")));
+        assert_eq!(parse_tag_filter(":alpha"), None);
+
+        assert!(validate_metadata(&metadata).is_ok());
+        let too_many: HashMap<String, String> = (0..=MAX_METADATA_TAGS).map(|i| (i.to_string(), String::new())).collect();
+        assert!(validate_metadata(&too_many).is_err());
+        let long_value = HashMap::from([("note".to_string(), "x".repeat(MAX_METADATA_VALUE_LENGTH + 1))]);
+        assert!(validate_metadata(&long_value).is_err());
+        let colon_key = HashMap::from([("a:b".to_string(), "c".to_string())]);
+        assert!(validate_metadata(&colon_key).is_err());
+    }
+}
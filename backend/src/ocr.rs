@@ -0,0 +1,101 @@
+// I am importing the HTTP client and serde types used to talk to the OCR service
+use reqwest::Client;
+use serde::Deserialize;
+use std::time::Duration;
+
+// I am importing my own error types
+use crate::error::{AppError, Result};
+
+// I am defining the JSON the OCR service answers with
+#[derive(Debug, Deserialize)]
+struct OcrServiceResponse {
+    text: String,
+}
+
+// I am defining the client for the external OCR service, which receives raw image bytes and returns text
+pub struct OcrClient {
+    client: Client,
+    url: Option<String>,
+    api_key: Option<String>,
+}
+
+impl OcrClient {
+    pub fn new(url: Option<String>, api_key: Option<String>) -> Self {
+        Self {
+            client: Client::builder()
+                .timeout(Duration::from_secs(60))
+                .build()
+                .unwrap_or_default(),
+            url,
+            api_key,
+        }
+    }
+
+    // I am reading the service location from OCR_SERVICE_URL and an optional OCR_SERVICE_API_KEY
+    pub fn from_env() -> Self {
+        Self::new(
+            std::env::var("OCR_SERVICE_URL").ok().filter(|url| !url.is_empty()),
+            std::env::var("OCR_SERVICE_API_KEY").ok().filter(|key| !key.is_empty()),
+        )
+    }
+
+    // I am sending an image to the OCR service and returning the text it found
+    pub async fn extract_text(&self, data: Vec<u8>, content_type: &str) -> Result<String> {
+        let url = self
+            .url
+            .as_deref()
+            .ok_or_else(|| AppError::ConfigError("OCR is not configured; set OCR_SERVICE_URL".to_string()))?;
+
+        let mut request = self.client.post(url).header("Content-Type", content_type).body(data);
+        if let Some(api_key) = &self.api_key {
+            request = request.header("Authorization", format!("Bearer {}", api_key));
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| AppError::ExternalServiceError(format!("OCR request failed: {}", e)))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(AppError::ExternalServiceError(format!(
+                "OCR service returned {}: {}",
+                status, error_text
+            )));
+        }
+
+        let parsed: OcrServiceResponse = response
+            .json()
+            .await
+            .map_err(|e| AppError::ExternalServiceError(format!("Failed to parse OCR response: {}", e)))?;
+
+        Ok(parsed.text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::{matchers::{header, method}, Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_extract_text_from_ocr_service() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header("Content-Type", "image/png"))
+            .and(header("Authorization", "Bearer ocr-key"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "text": "INVOICE #42" })))
+            .mount(&server)
+            .await;
+
+        let client = OcrClient::new(Some(server.uri()), Some("ocr-key".to_string()));
+        assert_eq!(client.extract_text(vec![1, 2, 3], "image/png").await.unwrap(), "INVOICE #42");
+
+        let unconfigured = OcrClient::new(None, None);
+        assert!(matches!(
+            unconfigured.extract_text(vec![1, 2, 3], "image/png").await,
+            Err(AppError::ConfigError(_))
+        ));
+    }
+}
@@ -0,0 +1,309 @@
+// I am importing the HTTP client and serde types used to talk to Ollama
+use axum::async_trait;
+use chrono::Utc;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+// I am importing my own error, id and model types
+use crate::{
+    error::{AppError, Result},
+    ids::IdGenerator,
+    llm::{summary_request, LlmProvider},
+    models::{ChatCompletionRequest, ChatCompletionResponse, OpenAIMessage, TokenUsage},
+    openai::{parse_allowed_models, Summary},
+};
+
+// I am defining the body of Ollama's /api/chat, which takes OpenAI-style role/content messages
+#[derive(Debug, Serialize)]
+struct OllamaChatRequest<'a> {
+    model: &'a str,
+    messages: Vec<OpenAIMessage>,
+    stream: bool,
+    options: OllamaOptions,
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
+    // Ollama's name for max_tokens
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<u32>,
+}
+
+// I am defining the non-streaming /api/chat reply; the token counts are missing when the prompt was cached
+#[derive(Debug, Deserialize)]
+struct OllamaChatResponse {
+    model: String,
+    message: OpenAIMessage,
+    #[serde(default)]
+    prompt_eval_count: u32,
+    #[serde(default)]
+    eval_count: u32,
+}
+
+// I am defining the client for a local or self-hosted Ollama server
+pub struct OllamaService {
+    client: Client,
+    base_url: String,
+    default_model: String,
+    // Ollama model names clients may ask for; OpenAI names mean nothing to an Ollama server
+    allowed_models: HashSet<String>,
+    timeout: Duration,
+    id_generator: Arc<dyn IdGenerator>,
+}
+
+impl OllamaService {
+    pub fn new(
+        base_url: String,
+        default_model: String,
+        timeout: Duration,
+        id_generator: Arc<dyn IdGenerator>,
+    ) -> Result<Self> {
+        let client = Client::builder()
+            .build()
+            .map_err(|e| AppError::HttpClientError(format!("Failed to create HTTP client: {}", e)))?;
+
+        Ok(Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            allowed_models: HashSet::from([default_model.clone()]),
+            default_model,
+            timeout,
+            id_generator,
+        })
+    }
+
+    // I am reading OLLAMA_BASE_URL, OLLAMA_MODEL, OLLAMA_ALLOWED_MODELS and OLLAMA_TIMEOUT_SECS, defaulting to a
+    // local server that only offers OLLAMA_MODEL
+    pub fn from_env(id_generator: Arc<dyn IdGenerator>) -> Result<Self> {
+        let service = Self::new(
+            std::env::var("OLLAMA_BASE_URL").unwrap_or_else(|_| "http://localhost:11434".to_string()),
+            std::env::var("OLLAMA_MODEL").unwrap_or_else(|_| "llama3.2".to_string()),
+            Duration::from_secs(
+                std::env::var("OLLAMA_TIMEOUT_SECS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(120),
+            ),
+            id_generator,
+        )?;
+
+        let listed = std::env::var("OLLAMA_ALLOWED_MODELS")
+            .map(|list| parse_allowed_models(&list))
+            .unwrap_or_default();
+        Ok(service.with_allowed_models(listed))
+    }
+
+    // I am adding models clients may request on top of the default one
+    pub fn with_allowed_models(mut self, models: HashSet<String>) -> Self {
+        self.allowed_models.extend(models);
+        self
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OllamaService {
+    async fn chat_completion(
+        &self,
+        request: ChatCompletionRequest,
+        history: &[OpenAIMessage],
+    ) -> Result<ChatCompletionResponse> {
+        if request.base_url_override.is_some() {
+            return Err(AppError::ValidationError(
+                "base_url_override: Base URL overrides are not supported by the Ollama provider".to_string(),
+            ));
+        }
+        if let Some(model) = request.model.as_deref().filter(|model| !self.allowed_models.contains(*model)) {
+            return Err(AppError::ValidationError(format!("model: Invalid model specified: {}", model)));
+        }
+
+        let mut messages = Vec::new();
+        if let Some(system_prompt) = &request.system_prompt {
+            messages.push(OpenAIMessage {
+                role: "system".to_string(),
+                content: system_prompt.clone(),
+            });
+        }
+        messages.extend_from_slice(history);
+        messages.push(OpenAIMessage {
+            role: "user".to_string(),
+            content: request.content.clone(),
+        });
+
+        let body = OllamaChatRequest {
+            model: request.model.as_deref().unwrap_or(&self.default_model),
+            messages,
+            stream: false,
+            options: OllamaOptions {
+                temperature: request.temperature,
+                num_predict: request.max_tokens,
+            },
+        };
+
+        let url = format!("{}/api/chat", self.base_url);
+        tracing::info!("Sending request to Ollama: {}", url);
+
+        let response = self
+            .client
+            .post(&url)
+            .json(&body)
+            .timeout(request.timeout_seconds.map(Duration::from_secs).unwrap_or(self.timeout))
+            .send()
+            .await
+            .map_err(|e| if e.is_timeout() {
+                AppError::TimeoutError
+            } else {
+                AppError::ExternalServiceError(format!("Ollama request failed: {}", e))
+            })?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(AppError::ExternalServiceError(format!(
+                "Ollama returned {}: {}",
+                status, error_text
+            )));
+        }
+
+        let parsed: OllamaChatResponse = response
+            .json()
+            .await
+            .map_err(|e| AppError::ExternalServiceError(format!("Failed to parse Ollama response: {}", e)))?;
+
+        Ok(ChatCompletionResponse {
+            id: self.id_generator.new_id(),
+            content: parsed.message.content,
+            model: parsed.model,
+            usage: TokenUsage {
+                prompt_tokens: parsed.prompt_eval_count,
+                completion_tokens: parsed.eval_count,
+                total_tokens: parsed.prompt_eval_count + parsed.eval_count,
+            },
+            conversation_id: None,
+            timestamp: Utc::now().to_rfc3339(),
+        })
+    }
+
+    async fn summarize_text(&self, content: &str, max_length: usize, language: Option<&str>) -> Result<Summary> {
+        let request = summary_request(content, max_length, self.default_model.clone(), language);
+        let completion = self.chat_completion(request, &[]).await?;
+
+        Ok(Summary {
+            text: completion.content,
+            model: completion.model,
+            usage: completion.usage,
+        })
+    }
+
+    fn summary_model(&self) -> &str {
+        &self.default_model
+    }
+
+    fn allowed_models(&self) -> HashSet<String> {
+        self.allowed_models.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ids::RandomIdGenerator;
+    use wiremock::{matchers::{body_partial_json, method, path}, Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_chat_completion_uses_ollama_chat_api() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/chat"))
+            .and(body_partial_json(serde_json::json!({
+                "model": "llama3.2",
+                "stream": false,
+                "messages": [
+                    { "role": "user", "content": "Earlier question" },
+                    { "role": "assistant", "content": "Earlier answer" },
+                    { "role": "user", "content": "Hello" }
+                ],
+                "options": { "num_predict": 50 }
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "model": "llama3.2",
+                "created_at": "2024-01-01T00:00:00Z",
+                "message": { "role": "assistant", "content": "Hi from Ollama" },
+                "done": true,
+                "prompt_eval_count": 12,
+                "eval_count": 4
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let service = OllamaService::new(
+            server.uri(),
+            "llama3.2".to_string(),
+            Duration::from_secs(5),
+            Arc::new(RandomIdGenerator),
+        )
+        .unwrap();
+        let provider: &dyn LlmProvider = &service;
+
+        let history = [
+            OpenAIMessage { role: "user".to_string(), content: "Earlier question".to_string() },
+            OpenAIMessage { role: "assistant".to_string(), content: "Earlier answer".to_string() },
+        ];
+        let request = ChatCompletionRequest {
+            content: "Hello".to_string(),
+            model: None,
+            temperature: None,
+            max_tokens: Some(50),
+            system_prompt: None,
+            base_url_override: None,
+            conversation_id: None,
+            timeout_seconds: None,
+        };
+
+        let response = provider.chat_completion(request, &history).await.unwrap();
+        assert_eq!(response.content, "Hi from Ollama");
+        assert_eq!(response.model, "llama3.2");
+        assert_eq!(response.usage.total_tokens, 16);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_other_providers_models_and_streaming() {
+        let server = MockServer::start().await;
+        let service = OllamaService::new(
+            server.uri(),
+            "llama3.2".to_string(),
+            Duration::from_secs(5),
+            Arc::new(RandomIdGenerator),
+        )
+        .unwrap()
+        .with_allowed_models(HashSet::from(["mistral".to_string()]));
+        let provider: &dyn LlmProvider = &service;
+        assert_eq!(provider.allowed_models(), HashSet::from(["llama3.2".to_string(), "mistral".to_string()]));
+
+        let request = ChatCompletionRequest {
+            content: "Hello".to_string(),
+            model: Some("gpt-4o-mini".to_string()),
+            temperature: None,
+            max_tokens: None,
+            system_prompt: None,
+            base_url_override: None,
+            conversation_id: None,
+            timeout_seconds: None,
+        };
+        assert!(matches!(
+            provider.chat_completion(request.clone(), &[]).await,
+            Err(AppError::ValidationError(_))
+        ));
+        assert!(matches!(
+            provider.chat_completion_stream(request, &[]).await,
+            Err(AppError::BadRequestError(_))
+        ));
+        // Neither call reached the server
+        assert!(server.received_requests().await.unwrap().is_empty());
+    }
+}
@@ -1,31 +1,391 @@
-use reqwest::Client;
+use axum::async_trait;
+use rand::Rng;
+use reqwest::{header::RETRY_AFTER, Client, RequestBuilder, StatusCode};
+use std::collections::{BTreeSet, HashSet};
+use std::sync::Arc;
 use std::time::Duration;
+use futures_util::{Stream, StreamExt};
+use tokio::sync::{mpsc, AcquireError, OwnedSemaphorePermit, Semaphore};
 use crate::{
     error::{AppError, Result},
+    ids::IdGenerator,
+    llm::{summary_request, ChatEventStream, LlmProvider},
+    telemetry,
     models::{
         ChatCompletionRequest, ChatCompletionResponse, TokenUsage,
-        OpenAIChatRequest, OpenAIChatResponse, OpenAIMessage, OpenAIConfig
+        OpenAIChatRequest, OpenAIChatResponse, OpenAIEmbeddingRequest, OpenAIEmbeddingResponse, OpenAIMessage, OpenAIConfig, OpenAIModerationRequest, OpenAIModerationResponse, OpenAIStreamChunk,
+        OpenAIResponseFormat, OpenAIStreamOptions,
     },
 };
 use chrono::Utc;
-use uuid::Uuid;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+// Outbound calls are split into two pools so background analysis can't starve user-facing requests
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConcurrencyPool {
+    Interactive,
+    Background,
+}
+
+// I am defining the events a streamed chat completion produces
+#[derive(Debug)]
+pub enum ChatStreamEvent {
+    Delta(String),
+    Done { model: String, usage: Option<TokenUsage> },
+}
+
+// I am defining a generated summary together with what it cost
+#[derive(Debug)]
+pub struct Summary {
+    pub text: String,
+    pub model: String,
+    pub usage: TokenUsage,
+}
+
+// I am defining an embedding vector together with what it cost
+#[derive(Debug)]
+pub struct Embedding {
+    pub vector: Vec<f32>,
+    pub model: String,
+    pub usage: TokenUsage,
+}
+
+// Models accepted by /embeddings
+pub const EMBEDDING_MODELS: &[&str] = &["text-embedding-3-small", "text-embedding-3-large", "text-embedding-ada-002"];
+
+// I am defining one parsed "data:" line of OpenAI's server-sent event stream
+#[derive(Debug)]
+enum StreamLine {
+    Chunk(OpenAIStreamChunk),
+    Done,
+}
+
+fn parse_stream_line(line: &str) -> Option<StreamLine> {
+    let data = line.trim().strip_prefix("data:")?.trim();
+    if data == "[DONE]" {
+        return Some(StreamLine::Done);
+    }
+
+    match serde_json::from_str(data) {
+        Ok(chunk) => Some(StreamLine::Chunk(chunk)),
+        Err(e) => {
+            tracing::warn!("Skipping unparseable OpenAI stream chunk: {}", e);
+            None
+        }
+    }
+}
+
+// I am taking the next complete line off the raw stream buffer. Bytes are only decoded once their line is
+// complete, so a multibyte character split across network chunks is never mangled
+fn take_stream_line(buffer: &mut Vec<u8>) -> Option<String> {
+    let newline = buffer.iter().position(|&byte| byte == b'\n')?;
+    let line: Vec<u8> = buffer.drain(..=newline).collect();
+    Some(String::from_utf8_lossy(&line).into_owned())
+}
+
+// Models accepted from clients when OPENAI_ALLOWED_MODELS is not set
+const DEFAULT_ALLOWED_MODELS: &[&str] = &["gpt-3.5-turbo", "gpt-4", "gpt-4-turbo", "gpt-4o", "gpt-4o-mini"];
+
+// I am parsing a comma-separated model list, ignoring blanks and surrounding whitespace
+pub fn parse_allowed_models(list: &str) -> HashSet<String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|model| !model.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+// I am loading the models clients may request, falling back to the built-in list
+pub fn allowed_models_from_env() -> HashSet<String> {
+    let models = std::env::var("OPENAI_ALLOWED_MODELS")
+        .map(|list| parse_allowed_models(&list))
+        .unwrap_or_default();
+
+    if models.is_empty() {
+        DEFAULT_ALLOWED_MODELS.iter().map(|model| model.to_string()).collect()
+    } else {
+        models
+    }
+}
+
+// Longest we will sleep between retries, even if OpenAI asks for more
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+    )
+}
+
+// Appended to the scan prompt in structured mode; JSON mode also requires the word "JSON" in the prompt
+const STRUCTURED_SCAN_INSTRUCTIONS: &str = "Respond only with a valid JSON object with exactly these keys: \"summary\" (a one-sentence string), \"key_information\" (an object of the fields you extracted), and \"insights\" (an array of strings).";
+
+// I am parsing a JSON-mode reply, treating anything but a JSON object as malformed
+fn parse_structured_analysis(content: &str) -> Result<serde_json::Value> {
+    match serde_json::from_str::<serde_json::Value>(content) {
+        Ok(value) if value.is_object() => Ok(value),
+        Ok(_) => Err(AppError::OpenAIError("Model returned JSON that is not an object".to_string())),
+        Err(e) => Err(AppError::OpenAIError(format!("Model returned malformed JSON: {}", e))),
+    }
+}
+
+// Same rough estimate summarize uses to turn a character budget into max_tokens
+const MOCK_CHARS_PER_TOKEN: usize = 3;
+
+// I am deriving a stable vector from the text's hash, so identical inputs embed identically in mock mode
+fn mock_embedding(text: &str) -> Vec<f32> {
+    Sha256::digest(text.as_bytes())
+        .iter()
+        .map(|byte| f32::from(*byte) / 255.0 - 0.5)
+        .collect()
+}
+
+// I am exposing an event channel as a stream, so real and mock replies share one stream type
+fn receiver_stream(receiver: mpsc::Receiver<Result<ChatStreamEvent>>) -> impl Stream<Item = Result<ChatStreamEvent>> {
+    futures_util::stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|event| (event, receiver))
+    })
+}
+
+// I am picking how long to wait before retry number `attempt`, preferring OpenAI's Retry-After
+fn retry_delay(attempt: u32, base_delay: Duration, retry_after: Option<Duration>) -> Duration {
+    let delay = retry_after.unwrap_or_else(|| {
+        let backoff = base_delay.saturating_mul(2u32.saturating_pow(attempt));
+        let jitter_ms = rand::thread_rng().gen_range(0..=base_delay.as_millis() as u64);
+        backoff + Duration::from_millis(jitter_ms)
+    });
+    delay.min(MAX_RETRY_DELAY)
+}
 
 pub struct OpenAIService {
     client: Client,
     config: OpenAIConfig,
+    interactive_permits: Arc<Semaphore>,
+    background_permits: Arc<Semaphore>,
+    // Shared by both pools, bounding the total number of calls in flight
+    global_permits: Arc<Semaphore>,
+    id_generator: Arc<dyn IdGenerator>,
+}
+
+// I am defining how long a call to OpenAI may take
+#[derive(Debug, Clone, Copy)]
+enum Deadline {
+    // Until the whole response has been read
+    Response(Duration),
+    // Until the response headers arrive; a streamed body is read under its own idle timeout
+    Headers(Duration),
+}
+
+// I am defining the right to make one OpenAI call: a slot in its pool and one under the global cap
+pub struct OpenAIPermit {
+    _pool: OwnedSemaphorePermit,
+    _global: OwnedSemaphorePermit,
 }
 
 impl OpenAIService {
-    pub fn new(config: OpenAIConfig) -> Result<Self> {
+    pub fn new(config: OpenAIConfig, id_generator: Arc<dyn IdGenerator>) -> Result<Self> {
+        // Deadlines are set per request instead, since a streamed reply may rightly take longer than any of them
         let client = Client::builder()
-            .timeout(Duration::from_secs(config.timeout_seconds))
+            .connect_timeout(Duration::from_secs(config.timeout_seconds))
             .build()
             .map_err(|e| AppError::HttpClientError(format!("Failed to create HTTP client: {}", e)))?;
 
-        Ok(Self { client, config })
+        let interactive_permits = Arc::new(Semaphore::new(config.max_interactive_concurrency.max(1)));
+        let background_permits = Arc::new(Semaphore::new(config.max_background_concurrency.max(1)));
+        let global_permits = Arc::new(Semaphore::new(config.max_concurrency.max(1)));
+
+        Ok(Self {
+            client,
+            config,
+            interactive_permits,
+            background_permits,
+            global_permits,
+            id_generator,
+        })
+    }
+
+    fn pool_semaphore(&self, pool: ConcurrencyPool) -> &Arc<Semaphore> {
+        match pool {
+            ConcurrencyPool::Interactive => &self.interactive_permits,
+            ConcurrencyPool::Background => &self.background_permits,
+        }
+    }
+
+    fn pool_size(&self, pool: ConcurrencyPool) -> usize {
+        match pool {
+            ConcurrencyPool::Interactive => self.config.max_interactive_concurrency.max(1),
+            ConcurrencyPool::Background => self.config.max_background_concurrency.max(1),
+        }
+    }
+
+    // Number of outbound calls currently holding a permit from the given pool
+    pub fn in_flight(&self, pool: ConcurrencyPool) -> usize {
+        self.pool_size(pool) - self.pool_semaphore(pool).available_permits()
+    }
+
+    // I am waiting for room in the pool and under the global cap, throttling with RateLimitError
+    // rather than queueing forever when OpenAI calls back up
+    async fn acquire_permit(&self, pool: ConcurrencyPool) -> Result<OpenAIPermit> {
+        let acquire = async {
+            let pool_permit = self.pool_semaphore(pool).clone().acquire_owned().await?;
+            let global_permit = self.global_permits.clone().acquire_owned().await?;
+            Ok(OpenAIPermit { _pool: pool_permit, _global: global_permit })
+        };
+
+        match tokio::time::timeout(Duration::from_millis(self.config.permit_timeout_ms), acquire).await {
+            Ok(result) => result
+                .map_err(|e: AcquireError| AppError::InternalError(format!("OpenAI concurrency pool closed: {}", e))),
+            Err(_) => {
+                tracing::warn!("Timed out waiting for an OpenAI concurrency permit ({:?} pool)", pool);
+                Err(AppError::RateLimitError)
+            }
+        }
+    }
+
+    // I am adding the API key, plus the organization and project headers when they are configured
+    fn authorized(&self, request: RequestBuilder) -> RequestBuilder {
+        let mut request = request.header("Authorization", format!("Bearer {}", self.config.api_key));
+        if let Some(organization) = &self.config.organization {
+            request = request.header("OpenAI-Organization", organization);
+        }
+        if let Some(project) = &self.config.project {
+            request = request.header("OpenAI-Project", project);
+        }
+        request
+    }
+
+    // I am pinging the models endpoint to confirm OpenAI is reachable; None means no API key is configured
+    pub async fn check_ready(&self) -> Option<Result<()>> {
+        if self.config.api_key.is_empty() {
+            return None;
+        }
+
+        let base_url = self.config.base_url.as_deref().unwrap_or("https://api.openai.com");
+        let result = self
+            .authorized(self.client.get(format!("{}/v1/models", base_url)))
+            .timeout(Duration::from_secs(5))
+            .send()
+            .await
+            .map_err(|e| AppError::OpenAIError(format!("Request failed: {}", e)))
+            .and_then(|response| match response.status() {
+                status if status.is_success() => Ok(()),
+                status => Err(AppError::OpenAIError(format!("Models endpoint returned {}", status))),
+            });
+
+        Some(result)
+    }
+
+    async fn chat_completion_in_pool(
+        &self,
+        request: ChatCompletionRequest,
+        history: &[OpenAIMessage],
+        pool: ConcurrencyPool,
+    ) -> Result<ChatCompletionResponse> {
+        let base_url = self.resolve_base_url(request.base_url_override.as_deref())?;
+        let openai_request = self.build_chat_request(&request, history, false);
+        let timeout = self.request_timeout(request.timeout_seconds);
+        self.send_completion(base_url, &openai_request, pool, timeout).await
+    }
+
+    fn request_timeout(&self, timeout_seconds: Option<u64>) -> Duration {
+        Duration::from_secs(timeout_seconds.unwrap_or(self.config.timeout_seconds))
+    }
+
+    // I am sending a non-streaming request within the given pool, giving up with TimeoutError after `timeout`
+    async fn send_completion(
+        &self,
+        base_url: &str,
+        openai_request: &OpenAIChatRequest,
+        pool: ConcurrencyPool,
+        timeout: Duration,
+    ) -> Result<ChatCompletionResponse> {
+        if self.config.mock_mode {
+            return Ok(self.mock_completion(openai_request));
+        }
+
+        // The deadline covers waiting for a permit and any retries, not just a single attempt
+        tokio::time::timeout(timeout, self.fetch_completion(base_url, openai_request, pool, timeout))
+            .await
+            .map_err(|_| AppError::TimeoutError)?
+    }
+
+    // I am reading the first choice of a non-streaming reply
+    async fn fetch_completion(
+        &self,
+        base_url: &str,
+        openai_request: &OpenAIChatRequest,
+        pool: ConcurrencyPool,
+        timeout: Duration,
+    ) -> Result<ChatCompletionResponse> {
+        let _permit = self.acquire_permit(pool).await?;
+
+        let response = self.send_chat_request(base_url, openai_request, Deadline::Response(timeout)).await?;
+
+        let openai_response: OpenAIChatResponse = response
+            .json()
+            .await
+            .map_err(|e| AppError::OpenAIError(format!("Failed to parse response: {}", e)))?;
+
+        // Extract the content from the first choice
+        let content = openai_response
+            .choices
+            .first()
+            .map(|choice| choice.message.content.clone())
+            .unwrap_or_else(|| "No response generated".to_string());
+
+        let response = ChatCompletionResponse {
+            id: self.id_generator.new_id(),
+            content,
+            model: openai_response.model,
+            usage: TokenUsage {
+                prompt_tokens: openai_response.usage.prompt_tokens,
+                completion_tokens: openai_response.usage.completion_tokens,
+                total_tokens: openai_response.usage.total_tokens,
+            },
+            conversation_id: None,
+            timestamp: Utc::now().to_rfc3339(),
+        };
+
+        tracing::info!(
+            "OpenAI API response received. Tokens used: {}",
+            response.usage.total_tokens
+        );
+
+        Ok(response)
+    }
+
+    // I am answering without OpenAI in mock mode: the last message echoed back, cut to max_tokens, at zero cost
+    fn mock_completion(&self, openai_request: &OpenAIChatRequest) -> ChatCompletionResponse {
+        let input = openai_request.messages.last().map(|message| message.content.as_str()).unwrap_or_default();
+        let reply = format!("Mock response to {} characters of input: {}", input.chars().count(), input);
+
+        let content = if openai_request.response_format.is_some() {
+            // JSON mode callers parse the reply, so give them the object shape they asked for
+            serde_json::json!({ "summary": reply, "key_information": {}, "insights": [] }).to_string()
+        } else {
+            match openai_request.max_tokens {
+                Some(max_tokens) => reply.chars().take(max_tokens as usize * MOCK_CHARS_PER_TOKEN).collect(),
+                None => reply,
+            }
+        };
+
+        ChatCompletionResponse {
+            id: self.id_generator.new_id(),
+            content,
+            model: openai_request.model.clone(),
+            usage: TokenUsage::default(),
+            conversation_id: None,
+            timestamp: Utc::now().to_rfc3339(),
+        }
     }
 
-    pub async fn chat_completion(&self, request: ChatCompletionRequest) -> Result<ChatCompletionResponse> {
+    // I am translating our chat request into the OpenAI wire format
+    fn build_chat_request(&self, request: &ChatCompletionRequest, history: &[OpenAIMessage], stream: bool) -> OpenAIChatRequest {
         let model = request.model.as_deref().unwrap_or(&self.config.default_model);
         
         // Prepare messages for OpenAI API
@@ -39,110 +399,942 @@ impl OpenAIService {
             });
         }
         
+        // Add earlier turns of the conversation
+        messages.extend_from_slice(history);
+
         // Add user message
         messages.push(OpenAIMessage {
             role: "user".to_string(),
             content: request.content.clone(),
         });
 
-        let openai_request = OpenAIChatRequest {
+        OpenAIChatRequest {
             model: model.to_string(),
             messages,
-            temperature: request.temperature,
-            max_tokens: request.max_tokens,
+            // Values the request sets win over the configured defaults
+            temperature: request.temperature.or(self.config.default_temperature),
+            max_tokens: request.max_tokens.or(self.config.default_max_tokens),
+            stream: stream.then_some(true),
+            stream_options: stream.then_some(OpenAIStreamOptions { include_usage: true }),
+            response_format: None,
+        }
+    }
+
+    // I am picking the per-request base URL when overrides are enabled, otherwise the configured one
+    // The API key goes wherever the request does, so an override must name a host the operator listed
+    fn resolve_base_url<'a>(&'a self, base_url_override: Option<&'a str>) -> Result<&'a str> {
+        match base_url_override {
+            Some(_) if self.config.base_url_override_hosts.is_empty() => Err(AppError::ValidationError(
+                "base_url_override: Base URL overrides are disabled on this server".to_string(),
+            )),
+            Some(base_url) if !self.is_allowed_override(base_url) => Err(AppError::ValidationError(
+                "base_url_override: Base URL host is not in BASE_URL_OVERRIDE_HOSTS".to_string(),
+            )),
+            Some(base_url) => Ok(base_url.trim_end_matches('/')),
+            None => Ok(self.config.base_url.as_deref().unwrap_or("https://api.openai.com")),
+        }
+    }
+
+    // Entries match a host on any port ("proxy.example.com") or one port only ("127.0.0.1:8080")
+    fn is_allowed_override(&self, base_url: &str) -> bool {
+        let Ok(url) = reqwest::Url::parse(base_url) else {
+            return false;
+        };
+        let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) else {
+            return false;
         };
+        let host = host.to_ascii_lowercase();
+        let hosts = &self.config.base_url_override_hosts;
+        matches!(url.scheme(), "http" | "https")
+            && (hosts.contains(&host) || hosts.contains(&format!("{}:{}", host, port)))
+    }
 
-        let base_url = self.config.base_url.as_deref().unwrap_or("https://api.openai.com");
-        let url = format!("{}/v1/chat/completions", base_url);
+    // I am posting a chat request to OpenAI
+    async fn send_chat_request(
+        &self,
+        base_url: &str,
+        openai_request: &OpenAIChatRequest,
+        deadline: Deadline,
+    ) -> Result<reqwest::Response> {
+        self.send_openai_request(base_url, "chat/completions", openai_request, deadline).await
+    }
 
-        tracing::info!("Sending request to OpenAI API: {}", url);
+    // I am posting to an OpenAI endpoint, retrying transient failures and turning other errors into AppErrors
+    async fn send_openai_request<T: Serialize>(
+        &self,
+        base_url: &str,
+        endpoint: &str,
+        body: &T,
+        deadline: Deadline,
+    ) -> Result<reqwest::Response> {
+        let url = format!("{}/v1/{}", base_url, endpoint);
+        let base_delay = Duration::from_millis(self.config.retry_base_delay_ms);
+        let mut attempt = 0;
 
-        let response = self
-            .client
-            .post(&url)
-            .header("Authorization", format!("Bearer {}", self.config.api_key))
-            .header("Content-Type", "application/json")
-            .json(&openai_request)
-            .send()
-            .await
-            .map_err(|e| AppError::OpenAIError(format!("Request failed: {}", e)))?;
+        loop {
+            tracing::info!("Sending request to OpenAI API: {}", url);
+
+            let request = self
+                .authorized(self.client.post(&url))
+                .header("Content-Type", "application/json")
+                .json(body);
+            let sent = match deadline {
+                Deadline::Response(timeout) => request.timeout(timeout).send().await,
+                Deadline::Headers(timeout) => match tokio::time::timeout(timeout, request.send()).await {
+                    Ok(sent) => sent,
+                    Err(_) => {
+                        telemetry::record_openai_request(endpoint, "timeout".to_string());
+                        return Err(AppError::TimeoutError);
+                    }
+                },
+            };
+            let response = sent.map_err(|e| if e.is_timeout() {
+                telemetry::record_openai_request(endpoint, "timeout".to_string());
+                AppError::TimeoutError
+            } else {
+                telemetry::record_openai_request(endpoint, "error".to_string());
+                AppError::OpenAIError(format!("Request failed: {}", e))
+            })?;
 
-        if !response.status().is_success() {
             let status = response.status();
+            telemetry::record_openai_request(endpoint, status.as_u16().to_string());
+            if status.is_success() {
+                return Ok(response);
+            }
+
+            if is_retryable_status(status) && attempt < self.config.max_retries {
+                let retry_after = response
+                    .headers()
+                    .get(RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.trim().parse::<u64>().ok())
+                    .map(Duration::from_secs);
+                let delay = retry_delay(attempt, base_delay, retry_after);
+                attempt += 1;
+
+                tracing::warn!(
+                    "OpenAI returned {}, retrying in {:?} (attempt {} of {})",
+                    status, delay, attempt, self.config.max_retries
+                );
+                tokio::time::sleep(delay).await;
+                continue;
+            }
+
             let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
             return Err(AppError::OpenAIError(format!(
                 "API request failed with status {}: {}",
                 status, error_text
             )));
         }
+    }
 
-        let openai_response: OpenAIChatResponse = response
+    async fn stream_chat_request(
+        &self,
+        base_url: &str,
+        openai_request: OpenAIChatRequest,
+    ) -> Result<impl Stream<Item = Result<ChatStreamEvent>>> {
+        if self.config.mock_mode {
+            let completion = self.mock_completion(&openai_request);
+            let (sender, receiver) = mpsc::channel(2);
+            let _ = sender.try_send(Ok(ChatStreamEvent::Delta(completion.content)));
+            let _ = sender.try_send(Ok(ChatStreamEvent::Done { model: completion.model, usage: Some(completion.usage) }));
+            return Ok(receiver_stream(receiver));
+        }
+
+        let permit = self.acquire_permit(ConcurrencyPool::Interactive).await?;
+
+        // A reply may stream for longer than the timeout; it only has to start, and keep coming, within it
+        let timeout = self.request_timeout(None);
+        let response = self.send_chat_request(base_url, &openai_request, Deadline::Headers(timeout)).await?;
+
+        let (sender, receiver) = mpsc::channel(32);
+        tokio::spawn(async move {
+            // The permit is held until the upstream stream is fully drained
+            let _permit = permit;
+            let mut body = response.bytes_stream();
+            let mut buffer = Vec::new();
+            let mut model = openai_request.model;
+            let mut usage = None;
+
+            loop {
+                let chunk = match tokio::time::timeout(timeout, body.next()).await {
+                    Ok(Some(Ok(chunk))) => chunk,
+                    Ok(None) => break,
+                    Ok(Some(Err(e))) => {
+                        let _ = sender.send(Err(AppError::OpenAIError(format!("Stream interrupted: {}", e)))).await;
+                        return;
+                    }
+                    Err(_) => {
+                        let _ = sender.send(Err(AppError::TimeoutError)).await;
+                        return;
+                    }
+                };
+                buffer.extend_from_slice(&chunk);
+
+                while let Some(line) = take_stream_line(&mut buffer) {
+                    match parse_stream_line(&line) {
+                        Some(StreamLine::Chunk(chunk)) => {
+                            model = chunk.model;
+                            if let Some(chunk_usage) = chunk.usage {
+                                usage = Some(TokenUsage {
+                                    prompt_tokens: chunk_usage.prompt_tokens,
+                                    completion_tokens: chunk_usage.completion_tokens,
+                                    total_tokens: chunk_usage.total_tokens,
+                                });
+                            }
+                            for content in chunk.choices.into_iter().filter_map(|choice| choice.delta.content) {
+                                if sender.send(Ok(ChatStreamEvent::Delta(content))).await.is_err() {
+                                    // The client went away, so stop reading from OpenAI
+                                    return;
+                                }
+                            }
+                        }
+                        Some(StreamLine::Done) | None => {}
+                    }
+                }
+            }
+
+            let _ = sender.send(Ok(ChatStreamEvent::Done { model, usage })).await;
+        });
+
+        Ok(receiver_stream(receiver))
+    }
+
+    pub async fn create_embedding(&self, text: &str, model: Option<&str>) -> Result<Embedding> {
+        self.create_embedding_in_pool(text, model, ConcurrencyPool::Interactive).await
+    }
+
+    // I am embedding a scan's raw data with the default model so scans can later be ranked by similarity
+    pub async fn embed_scan_data(&self, data: &str) -> Result<Embedding> {
+        self.create_embedding_in_pool(data, None, ConcurrencyPool::Background).await
+    }
+
+    async fn create_embedding_in_pool(&self, text: &str, model: Option<&str>, pool: ConcurrencyPool) -> Result<Embedding> {
+        if self.config.mock_mode {
+            return Ok(Embedding {
+                vector: mock_embedding(text),
+                model: model.unwrap_or(&self.config.embedding_model).to_string(),
+                usage: TokenUsage::default(),
+            });
+        }
+
+        let base_url = self.resolve_base_url(None)?;
+        let openai_request = OpenAIEmbeddingRequest {
+            model: model.unwrap_or(&self.config.embedding_model).to_string(),
+            input: text.to_string(),
+        };
+        let timeout = self.request_timeout(None);
+
+        let _permit = self.acquire_permit(pool).await?;
+        let response = self.send_openai_request(base_url, "embeddings", &openai_request, Deadline::Response(timeout)).await?;
+
+        let openai_response: OpenAIEmbeddingResponse = response
             .json()
             .await
-            .map_err(|e| AppError::OpenAIError(format!("Failed to parse response: {}", e)))?;
+            .map_err(|e| AppError::OpenAIError(format!("Failed to parse embedding response: {}", e)))?;
 
-        // Extract the content from the first choice
-        let content = openai_response
-            .choices
-            .first()
-            .map(|choice| choice.message.content.clone())
-            .unwrap_or_else(|| "No response generated".to_string());
+        let vector = openai_response
+            .data
+            .into_iter()
+            .next()
+            .map(|data| data.embedding)
+            .ok_or_else(|| AppError::OpenAIError("No embedding returned".to_string()))?;
 
-        let response = ChatCompletionResponse {
-            id: Uuid::new_v4(),
-            content,
+        Ok(Embedding {
+            vector,
             model: openai_response.model,
             usage: TokenUsage {
                 prompt_tokens: openai_response.usage.prompt_tokens,
-                completion_tokens: openai_response.usage.completion_tokens,
+                completion_tokens: 0,
                 total_tokens: openai_response.usage.total_tokens,
             },
-            timestamp: Utc::now().to_rfc3339(),
-        };
+        })
+    }
 
-        tracing::info!(
-            "OpenAI API response received. Tokens used: {}",
-            response.usage.total_tokens
-        );
+    // I am rejecting input that OpenAI's moderation endpoint flags; a no-op unless ENABLE_MODERATION=true
+    pub async fn check_moderation(&self, input: &str) -> Result<()> {
+        if !self.config.moderation_enabled || self.config.mock_mode {
+            return Ok(());
+        }
 
-        Ok(response)
+        let base_url = self.resolve_base_url(None)?;
+        let request = OpenAIModerationRequest { input: input.to_string() };
+        let timeout = self.request_timeout(None);
+
+        let _permit = self.acquire_permit(ConcurrencyPool::Interactive).await?;
+        let response = self.send_openai_request(base_url, "moderations", &request, Deadline::Response(timeout)).await?;
+
+        let moderation: OpenAIModerationResponse = response
+            .json()
+            .await
+            .map_err(|e| AppError::OpenAIError(format!("Failed to parse moderation response: {}", e)))?;
+
+        if !moderation.results.iter().any(|result| result.flagged) {
+            return Ok(());
+        }
+
+        // BTreeSet keeps the category list sorted and free of repeats across results
+        let categories: BTreeSet<&str> = moderation
+            .results
+            .iter()
+            .flat_map(|result| result.categories.iter())
+            .filter(|(_, flagged)| **flagged)
+            .map(|(name, _)| name.as_str())
+            .collect();
+        let categories = categories.into_iter().collect::<Vec<_>>().join(", ");
+
+        tracing::warn!("Moderation flagged input for: {}", categories);
+        Err(AppError::BadRequestError(format!("Content was flagged by moderation: {}", categories)))
     }
 
-    pub async fn summarize_text(&self, content: &str, max_length: usize) -> Result<String> {
-        let system_prompt = format!(
-            "You are a helpful assistant that summarizes text. Please provide a concise summary of the given text in approximately {} characters or less. Focus on the main points and key information.",
-            max_length
-        );
+    // I am choosing the configured prompt for this scan format, or the generic analysis prompt
+    fn scan_system_prompt(&self, format: &str) -> String {
+        match self.config.scan_prompts.get(format) {
+            Some(template) => template.replace("{format}", format),
+            None => format!(
+                "You are an expert at analyzing {} data. Please analyze the provided data and provide insights, extract key information, and identify any patterns or important details.",
+                format
+            ),
+        }
+    }
 
-        let request = ChatCompletionRequest {
-            content: content.to_string(),
+    fn scan_analysis_request(&self, data: &str, format: &str, system_prompt: String) -> ChatCompletionRequest {
+        ChatCompletionRequest {
+            content: format!("Please analyze this {} data: {}", format, data),
             model: Some(self.config.default_model.clone()),
-            temperature: Some(0.3), // Lower temperature for more consistent summaries
-            max_tokens: Some((max_length / 3) as u32), // Rough estimate: 1 token ≈ 3 characters
+            temperature: Some(0.5),
+            max_tokens: Some(1000),
             system_prompt: Some(system_prompt),
-        };
+            base_url_override: None,
+            conversation_id: None,
+            timeout_seconds: None,
+        }
+    }
+
+    pub async fn analyze_scan_data(&self, data: &str, format: &str) -> Result<ChatCompletionResponse> {
+        let request = self.scan_analysis_request(data, format, self.scan_system_prompt(format));
+
+        self.chat_completion_in_pool(request, &[], ConcurrencyPool::Background).await
+    }
+
+    // I am analyzing scan data in JSON mode, asking again whenever the model's reply is not a JSON object
+    pub async fn analyze_scan_data_structured(
+        &self,
+        data: &str,
+        format: &str,
+    ) -> Result<(ChatCompletionResponse, serde_json::Value)> {
+        let system_prompt = format!("{} {}", self.scan_system_prompt(format), STRUCTURED_SCAN_INSTRUCTIONS);
+        let request = self.scan_analysis_request(data, format, system_prompt);
+
+        let base_url = self.resolve_base_url(None)?;
+        let mut openai_request = self.build_chat_request(&request, &[], false);
+        openai_request.response_format = Some(OpenAIResponseFormat::json_object());
+        let timeout = self.request_timeout(None);
+
+        // Every attempt is billed, so usage covers the discarded replies too
+        let mut usage = TokenUsage::default();
+        let mut attempt = 0;
+        loop {
+            let mut completion = self
+                .send_completion(base_url, &openai_request, ConcurrencyPool::Background, timeout)
+                .await?;
+            usage.prompt_tokens += completion.usage.prompt_tokens;
+            usage.completion_tokens += completion.usage.completion_tokens;
+            usage.total_tokens += completion.usage.total_tokens;
+
+            match parse_structured_analysis(&completion.content) {
+                Ok(value) => {
+                    completion.usage = usage;
+                    return Ok((completion, value));
+                }
+                Err(e) if attempt < self.config.max_retries => {
+                    attempt += 1;
+                    tracing::warn!("{}, retrying (attempt {} of {})", e, attempt, self.config.max_retries);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+// I am serving user-facing chat and summaries from the interactive pool
+#[async_trait]
+impl LlmProvider for OpenAIService {
+    async fn chat_completion(
+        &self,
+        request: ChatCompletionRequest,
+        history: &[OpenAIMessage],
+    ) -> Result<ChatCompletionResponse> {
+        self.chat_completion_in_pool(request, history, ConcurrencyPool::Interactive).await
+    }
+
+    async fn summarize_text(&self, content: &str, max_length: usize, language: Option<&str>) -> Result<Summary> {
+        let request = summary_request(content, max_length, self.config.default_model.clone(), language);
+        let completion = self.chat_completion(request, &[]).await?;
 
-        let response = self.chat_completion(request).await?;
-        Ok(response.content)
+        Ok(Summary {
+            text: completion.content,
+            model: completion.model,
+            usage: completion.usage,
+        })
     }
 
-    pub async fn analyze_scan_data(&self, data: &str, format: &str) -> Result<String> {
-        let system_prompt = format!(
-            "You are an expert at analyzing {} data. Please analyze the provided data and provide insights, extract key information, and identify any patterns or important details.",
-            format
+    // I am streaming a chat completion, forwarding each content delta as soon as OpenAI sends it
+    async fn chat_completion_stream(
+        &self,
+        request: ChatCompletionRequest,
+        history: &[OpenAIMessage],
+    ) -> Result<ChatEventStream> {
+        let base_url = self.resolve_base_url(request.base_url_override.as_deref())?;
+        let openai_request = self.build_chat_request(&request, history, true);
+        Ok(Box::pin(self.stream_chat_request(base_url, openai_request).await?))
+    }
+
+    fn summary_model(&self) -> &str {
+        &self.config.default_model
+    }
+
+    fn allowed_models(&self) -> HashSet<String> {
+        allowed_models_from_env()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ids::RandomIdGenerator;
+    use std::collections::HashMap;
+    use wiremock::{matchers::{header, method, path}, Mock, MockServer, ResponseTemplate};
+
+    fn service_with_pools(interactive: usize, background: usize) -> OpenAIService {
+        OpenAIService::new(OpenAIConfig {
+            max_interactive_concurrency: interactive,
+            max_background_concurrency: background,
+            ..OpenAIConfig::default()
+        }, Arc::new(RandomIdGenerator))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_scan_prompt_uses_format_template() {
+        let service = OpenAIService::new(OpenAIConfig {
+            scan_prompts: HashMap::from([(
+                "qr".to_string(),
+                "Assess whether the URL in this {format} code is safe to open.".to_string(),
+            )]),
+            ..OpenAIConfig::default()
+        }, Arc::new(RandomIdGenerator))
+        .unwrap();
+
+        assert_eq!(
+            service.scan_system_prompt("qr"),
+            "Assess whether the URL in this qr code is safe to open."
         );
+        assert!(service.scan_system_prompt("ocr").starts_with("You are an expert at analyzing ocr data."));
+    }
+
+    #[test]
+    fn test_parse_allowed_models() {
+        let models = parse_allowed_models(" gpt-4o, o1-preview ,,gpt-4.1 ");
+        assert_eq!(models.len(), 3);
+        assert!(models.contains("o1-preview"));
+        assert!(models.contains("gpt-4.1"));
+        assert!(parse_allowed_models(" , ").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_background_pool_is_bounded_independently() {
+        let service = service_with_pools(2, 1);
 
-        let user_prompt = format!("Please analyze this {} data: {}", format, data);
+        let _background = service.acquire_permit(ConcurrencyPool::Background).await.unwrap();
+        assert_eq!(service.in_flight(ConcurrencyPool::Background), 1);
+        assert!(service.background_permits.clone().try_acquire_owned().is_err());
+
+        // Saturating the background pool leaves interactive capacity untouched
+        let _first = service.acquire_permit(ConcurrencyPool::Interactive).await.unwrap();
+        let _second = service.acquire_permit(ConcurrencyPool::Interactive).await.unwrap();
+        assert_eq!(service.in_flight(ConcurrencyPool::Interactive), 2);
+        assert_eq!(service.in_flight(ConcurrencyPool::Background), 1);
+    }
+
+    #[tokio::test]
+    async fn test_global_cap_throttles_both_pools() {
+        let service = OpenAIService::new(OpenAIConfig {
+            max_concurrency: 1,
+            permit_timeout_ms: 50,
+            ..OpenAIConfig::default()
+        }, Arc::new(RandomIdGenerator))
+        .unwrap();
+
+        let held = service.acquire_permit(ConcurrencyPool::Interactive).await.unwrap();
+        // The background pool has room of its own, but the shared cap is used up
+        assert!(matches!(
+            service.acquire_permit(ConcurrencyPool::Background).await,
+            Err(AppError::RateLimitError)
+        ));
+        // A call that gave up holds nothing from its pool
+        assert_eq!(service.in_flight(ConcurrencyPool::Background), 0);
+
+        drop(held);
+        assert!(service.acquire_permit(ConcurrencyPool::Background).await.is_ok());
+    }
+
+    fn service_for_mock(server: &MockServer, max_retries: u32) -> OpenAIService {
+        OpenAIService::new(OpenAIConfig {
+            api_key: "test-key".to_string(),
+            base_url: Some(server.uri()),
+            max_retries,
+            retry_base_delay_ms: 1,
+            ..OpenAIConfig::default()
+        }, Arc::new(RandomIdGenerator))
+        .unwrap()
+    }
+
+    fn chat_request() -> ChatCompletionRequest {
+        ChatCompletionRequest {
+            content: "Hello".to_string(),
+            model: None,
+            temperature: None,
+            max_tokens: None,
+            system_prompt: None,
+            base_url_override: None,
+            conversation_id: None,
+            timeout_seconds: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chat_completion_retries_after_rate_limit() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "0"))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion",
+                "created": 0,
+                "model": "gpt-4o-mini",
+                "choices": [{ "finish_reason": "stop", "message": { "role": "assistant", "content": "Hi there" } }],
+                "usage": { "prompt_tokens": 2, "completion_tokens": 3, "total_tokens": 5 }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let response = service_for_mock(&server, 3).chat_completion(chat_request(), &[]).await.unwrap();
+        assert_eq!(response.content, "Hi there");
+        assert_eq!(response.usage.total_tokens, 5);
+    }
+
+    #[tokio::test]
+    async fn test_chat_completion_fails_fast_on_client_error() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(401))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        assert!(service_for_mock(&server, 3).chat_completion(chat_request(), &[]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_chat_completion_sends_organization_and_project() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .and(header("OpenAI-Organization", "org-quickscan"))
+            .and(header("OpenAI-Project", "proj-scans"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion",
+                "created": 0,
+                "model": "gpt-4o-mini",
+                "choices": [{ "finish_reason": "stop", "message": { "role": "assistant", "content": "Billed" } }],
+                "usage": { "prompt_tokens": 2, "completion_tokens": 1, "total_tokens": 3 }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let service = OpenAIService::new(OpenAIConfig {
+            api_key: "test-key".to_string(),
+            base_url: Some(server.uri()),
+            organization: Some("org-quickscan".to_string()),
+            project: Some("proj-scans".to_string()),
+            ..OpenAIConfig::default()
+        }, Arc::new(RandomIdGenerator))
+        .unwrap();
+
+        let response = service.chat_completion(chat_request(), &[]).await.unwrap();
+        assert_eq!(response.content, "Billed");
+    }
+
+    #[tokio::test]
+    async fn test_moderation_rejects_flagged_input() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/moderations"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "id": "modr-1",
+                "model": "omni-moderation-latest",
+                "results": [{
+                    "flagged": true,
+                    "categories": { "harassment": true, "violence": true, "self-harm": false }
+                }]
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let service = OpenAIService::new(OpenAIConfig {
+            api_key: "test-key".to_string(),
+            base_url: Some(server.uri()),
+            moderation_enabled: true,
+            ..OpenAIConfig::default()
+        }, Arc::new(RandomIdGenerator))
+        .unwrap();
+
+        match service.check_moderation("something nasty").await {
+            Err(AppError::BadRequestError(message)) => assert!(message.ends_with("harassment, violence")),
+            other => panic!("expected a moderation rejection, got {:?}", other),
+        }
+
+        // With moderation off, nothing is sent (the mock expects exactly one call)
+        assert!(service_for_mock(&server, 0).check_moderation("something nasty").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_mock_mode_answers_without_calling_openai() {
+        let service = OpenAIService::new(OpenAIConfig {
+            // Nothing listens here, so any real request would fail
+            base_url: Some("http://127.0.0.1:9".to_string()),
+            mock_mode: true,
+            ..OpenAIConfig::default()
+        }, Arc::new(RandomIdGenerator))
+        .unwrap();
+
+        let response = service.chat_completion(chat_request(), &[]).await.unwrap();
+        assert_eq!(response.content, "Mock response to 5 characters of input: Hello");
+        assert_eq!(response.usage, TokenUsage::default());
+
+        let request = ChatCompletionRequest { max_tokens: Some(4), ..chat_request() };
+        let response = service.chat_completion(request, &[]).await.unwrap();
+        assert_eq!(response.content, "Mock respons");
+
+        let summary = service.summarize_text("A long document about mocks", 30, None).await.unwrap();
+        assert_eq!(summary.text.chars().count(), 30);
+
+        let (_, structured) = service.analyze_scan_data_structured("https://example.com", "qr").await.unwrap();
+        assert!(structured["summary"].is_string());
+
+        let first = service.create_embedding("same text", None).await.unwrap();
+        let second = service.create_embedding("same text", None).await.unwrap();
+        assert_eq!(first.vector, second.vector);
+    }
+
+    #[tokio::test]
+    async fn test_chat_completion_honors_per_request_timeout() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(3)))
+            .mount(&server)
+            .await;
 
         let request = ChatCompletionRequest {
-            content: user_prompt,
-            model: Some(self.config.default_model.clone()),
-            temperature: Some(0.5),
-            max_tokens: Some(1000),
-            system_prompt: Some(system_prompt),
+            timeout_seconds: Some(1),
+            ..chat_request()
+        };
+        let started = std::time::Instant::now();
+        assert!(matches!(
+            service_for_mock(&server, 0).chat_completion(request, &[]).await,
+            Err(AppError::TimeoutError)
+        ));
+        assert!(started.elapsed() < Duration::from_secs(3));
+    }
+
+    #[tokio::test]
+    async fn test_chat_completion_uses_base_url_override_only_for_listed_hosts() {
+        let default_server = MockServer::start().await;
+        let proxy = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion",
+                "created": 0,
+                "model": "gpt-4o-mini",
+                "choices": [{ "finish_reason": "stop", "message": { "role": "assistant", "content": "From proxy" } }],
+                "usage": { "prompt_tokens": 2, "completion_tokens": 3, "total_tokens": 5 }
+            })))
+            .expect(1)
+            .mount(&proxy)
+            .await;
+
+        let request = ChatCompletionRequest {
+            base_url_override: Some(proxy.uri()),
+            ..chat_request()
         };
 
-        let response = self.chat_completion(request).await?;
-        Ok(response.content)
+        let disabled = service_for_mock(&default_server, 0);
+        assert!(matches!(
+            disabled.chat_completion(request.clone(), &[]).await,
+            Err(AppError::ValidationError(_))
+        ));
+
+        // Listing some other host doesn't open the door to this one
+        let other_host = OpenAIService::new(OpenAIConfig {
+            base_url_override_hosts: HashSet::from(["proxy.example.com".to_string()]),
+            ..disabled.config.clone()
+        }, Arc::new(RandomIdGenerator))
+        .unwrap();
+        assert!(matches!(
+            other_host.chat_completion(request.clone(), &[]).await,
+            Err(AppError::ValidationError(_))
+        ));
+        let unlisted = ChatCompletionRequest {
+            base_url_override: Some("https://attacker.example.com".to_string()),
+            ..chat_request()
+        };
+        assert!(matches!(other_host.chat_completion(unlisted, &[]).await, Err(AppError::ValidationError(_))));
+
+        let proxy_host = proxy.uri().trim_start_matches("http://").to_string();
+        let enabled = OpenAIService::new(OpenAIConfig {
+            base_url_override_hosts: HashSet::from([proxy_host]),
+            ..disabled.config.clone()
+        }, Arc::new(RandomIdGenerator))
+        .unwrap();
+        let response = enabled.chat_completion(request, &[]).await.unwrap();
+        assert_eq!(response.content, "From proxy");
+        assert!(default_server.received_requests().await.unwrap().is_empty());
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_parse_structured_analysis_requires_object() {
+        let value = parse_structured_analysis(r#"{"summary": "A URL", "key_information": {}, "insights": []}"#).unwrap();
+        assert_eq!(value["summary"], "A URL");
+        assert!(parse_structured_analysis("[1, 2]").is_err());
+        assert!(parse_structured_analysis("Sure! Here is the JSON").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_structured_analysis_retries_malformed_json() {
+        let server = MockServer::start().await;
+        let reply = |content: &str| ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 0,
+            "model": "gpt-4o-mini",
+            "choices": [{ "finish_reason": "stop", "message": { "role": "assistant", "content": content } }],
+            "usage": { "prompt_tokens": 2, "completion_tokens": 3, "total_tokens": 5 }
+        }));
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(reply("{\"summary\": \"trunc"))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(reply(r#"{"summary": "A link", "key_information": {"url": "https://example.com"}, "insights": []}"#))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let (completion, value) = service_for_mock(&server, 1)
+            .analyze_scan_data_structured("https://example.com", "qr")
+            .await
+            .unwrap();
+        assert_eq!(value["key_information"]["url"], "https://example.com");
+        assert_eq!(completion.usage.total_tokens, 10);
+
+        let requests = server.received_requests().await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+        assert_eq!(body["response_format"]["type"], "json_object");
+    }
+
+    #[tokio::test]
+    async fn test_chat_defaults_fill_in_unset_temperature_and_max_tokens() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion",
+                "created": 0,
+                "model": "gpt-4o-mini",
+                "choices": [{ "finish_reason": "stop", "message": { "role": "assistant", "content": "Hi" } }],
+                "usage": { "prompt_tokens": 5, "completion_tokens": 1, "total_tokens": 6 }
+            })))
+            .mount(&server)
+            .await;
+
+        let service = OpenAIService::new(OpenAIConfig {
+            api_key: "test-key".to_string(),
+            base_url: Some(server.uri()),
+            max_retries: 0,
+            default_temperature: Some(0.2),
+            default_max_tokens: Some(256),
+            ..OpenAIConfig::default()
+        }, Arc::new(RandomIdGenerator))
+        .unwrap();
+
+        service.chat_completion(chat_request(), &[]).await.unwrap();
+        let explicit = ChatCompletionRequest { temperature: Some(1.1), max_tokens: Some(32), ..chat_request() };
+        service.chat_completion(explicit, &[]).await.unwrap();
+        // Without configured defaults nothing is sent, leaving OpenAI's own
+        service_for_mock(&server, 0).chat_completion(chat_request(), &[]).await.unwrap();
+
+        let bodies: Vec<serde_json::Value> = server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .map(|request| serde_json::from_slice(&request.body).unwrap())
+            .collect();
+        assert_eq!((bodies[0]["temperature"].as_f64(), bodies[0]["max_tokens"].as_u64()), (Some(0.2), Some(256)));
+        assert_eq!((bodies[1]["temperature"].as_f64(), bodies[1]["max_tokens"].as_u64()), (Some(1.1), Some(32)));
+        assert!(bodies[2].get("temperature").is_none() && bodies[2].get("max_tokens").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_create_embedding_defaults_model() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/embeddings"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "object": "list",
+                "data": [{ "object": "embedding", "index": 0, "embedding": [0.1, -0.2, 0.3] }],
+                "model": "text-embedding-3-small",
+                "usage": { "prompt_tokens": 4, "total_tokens": 4 }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let embedding = service_for_mock(&server, 0).create_embedding("hello world", None).await.unwrap();
+        assert_eq!(embedding.vector, vec![0.1, -0.2, 0.3]);
+        assert_eq!(embedding.usage, TokenUsage { prompt_tokens: 4, completion_tokens: 0, total_tokens: 4 });
+
+        let requests = server.received_requests().await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+        assert_eq!(body["model"], "text-embedding-3-small");
+        assert_eq!(body["input"], "hello world");
+    }
+
+    #[test]
+    fn test_parse_stream_lines() {
+        let line = r#"data: {"model":"gpt-4o-mini","choices":[{"delta":{"content":"Hel"}}],"usage":null}"#;
+        match parse_stream_line(line) {
+            Some(StreamLine::Chunk(chunk)) => {
+                assert_eq!(chunk.model, "gpt-4o-mini");
+                assert_eq!(chunk.choices[0].delta.content.as_deref(), Some("Hel"));
+                assert!(chunk.usage.is_none());
+            }
+            other => panic!("unexpected parse result: {:?}", other),
+        }
+
+        let usage_line = r#"data: {"model":"gpt-4o-mini","choices":[],"usage":{"prompt_tokens":3,"completion_tokens":5,"total_tokens":8}}"#;
+        match parse_stream_line(usage_line) {
+            Some(StreamLine::Chunk(chunk)) => assert_eq!(chunk.usage.unwrap().total_tokens, 8),
+            other => panic!("unexpected parse result: {:?}", other),
+        }
+
+        assert!(matches!(parse_stream_line("data: [DONE]"), Some(StreamLine::Done)));
+        assert!(parse_stream_line(": keep-alive").is_none());
+        assert!(parse_stream_line("").is_none());
+    }
+
+    // I am serving one streamed chat reply, sending each SSE line after its delay; wiremock can only delay a
+    // whole response, not drip-feed one
+    async fn slow_stream_server(lines: Vec<(Duration, String)>) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; 64 * 1024];
+            let _ = socket.read(&mut request).await;
+            let head = "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\ntransfer-encoding: chunked\r\n\r\n";
+            socket.write_all(head.as_bytes()).await.unwrap();
+            for (delay, line) in lines {
+                tokio::time::sleep(delay).await;
+                let chunk = format!("{}\n\n", line);
+                if socket.write_all(format!("{:x}\r\n{}\r\n", chunk.len(), chunk).as_bytes()).await.is_err() {
+                    return;
+                }
+            }
+            let _ = socket.write_all(b"0\r\n\r\n").await;
+        });
+        format!("http://{}", address)
+    }
+
+    async fn stream_events(lines: Vec<(Duration, String)>) -> Vec<Result<ChatStreamEvent>> {
+        let service = OpenAIService::new(OpenAIConfig {
+            api_key: "test-key".to_string(),
+            base_url: Some(slow_stream_server(lines).await),
+            timeout_seconds: 1,
+            ..OpenAIConfig::default()
+        }, Arc::new(RandomIdGenerator))
+        .unwrap();
+        service.chat_completion_stream(chat_request(), &[]).await.unwrap().collect().await
+    }
+
+    #[tokio::test]
+    async fn test_stream_may_outlast_the_timeout_but_not_stall() {
+        let delta = |text: &str| {
+            format!(r#"data: {{"model":"gpt-4o-mini","choices":[{{"delta":{{"content":"{}"}}}}]}}"#, text)
+        };
+
+        // Three pieces 600 ms apart take well over the one-second timeout, yet every gap is within it
+        let pause = Duration::from_millis(600);
+        let events = stream_events(vec![
+            (pause, delta("one ")),
+            (pause, delta("two ")),
+            (pause, delta("three")),
+            (pause, "data: [DONE]".to_string()),
+        ])
+        .await;
+        let text: String = events
+            .iter()
+            .filter_map(|event| match event {
+                Ok(ChatStreamEvent::Delta(text)) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(text, "one two three");
+        assert!(matches!(events.last(), Some(Ok(ChatStreamEvent::Done { .. }))));
+
+        // A reply that goes quiet for longer than the timeout is cut off
+        let events = stream_events(vec![(Duration::ZERO, delta("one ")), (Duration::from_millis(1500), delta("two"))]).await;
+        assert!(matches!(events.last(), Some(Err(AppError::TimeoutError))));
+    }
+
+    #[test]
+    fn test_stream_lines_keep_characters_split_across_chunks() {
+        let line = "data: {\"content\":\"café 日本\"}\n".as_bytes();
+        // Split inside the two-byte "é" and again inside the three-byte "日"
+        let split_e = line.iter().position(|&byte| byte == 0xC3).unwrap() + 1;
+        let split_ri = line.iter().position(|&byte| byte == 0xE6).unwrap() + 2;
+
+        let mut buffer = Vec::new();
+        let mut lines = Vec::new();
+        for chunk in [&line[..split_e], &line[split_e..split_ri], &line[split_ri..]] {
+            buffer.extend_from_slice(chunk);
+            while let Some(line) = take_stream_line(&mut buffer) {
+                lines.push(line);
+            }
+        }
+        assert_eq!(lines, vec!["data: {\"content\":\"café 日本\"}\n".to_string()]);
+        assert!(buffer.is_empty());
+    }
+}
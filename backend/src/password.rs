@@ -0,0 +1,203 @@
+// I am importing both password hashing backends
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+// I am importing my own error types
+use crate::error::{AppError, Result};
+
+// bcrypt only accepts work factors in this range
+const BCRYPT_COST_RANGE: std::ops::RangeInclusive<u32> = 4..=31;
+
+// I am defining which algorithm new password hashes are created with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordHashing {
+    Bcrypt { cost: u32 },
+    Argon2,
+}
+
+impl PasswordHashing {
+    // I am reading the algorithm from PASSWORD_HASH_ALGO and the bcrypt cost from BCRYPT_COST
+    pub fn from_env() -> Result<Self> {
+        match std::env::var("PASSWORD_HASH_ALGO").as_deref() {
+            Ok("argon2") => Ok(PasswordHashing::Argon2),
+            _ => Ok(PasswordHashing::Bcrypt {
+                cost: parse_bcrypt_cost(std::env::var("BCRYPT_COST").ok().as_deref())?,
+            }),
+        }
+    }
+
+    pub fn hash(&self, password: &str) -> Result<String> {
+        match self {
+            PasswordHashing::Bcrypt { cost } => bcrypt::hash(password, *cost)
+                .map_err(|e| AppError::InternalError(format!("Failed to hash password: {}", e))),
+            PasswordHashing::Argon2 => {
+                // Salts come from the OS RNG via a v4 UUID's 16 random bytes
+                let salt = SaltString::encode_b64(Uuid::new_v4().as_bytes())
+                    .map_err(|e| AppError::InternalError(format!("Failed to generate salt: {}", e)))?;
+                Argon2::default()
+                    .hash_password(password.as_bytes(), &salt)
+                    .map(|hash| hash.to_string())
+                    .map_err(|e| AppError::InternalError(format!("Failed to hash password: {}", e)))
+            }
+        }
+    }
+}
+
+// A mistyped cost would otherwise quietly change how expensive every new hash is, so it stops startup instead
+fn parse_bcrypt_cost(value: Option<&str>) -> Result<u32> {
+    let Some(value) = value else {
+        return Ok(bcrypt::DEFAULT_COST);
+    };
+
+    value
+        .trim()
+        .parse()
+        .ok()
+        .filter(|cost| BCRYPT_COST_RANGE.contains(cost))
+        .ok_or_else(|| {
+            AppError::ConfigError(format!(
+                "BCRYPT_COST must be a whole number between {} and {}, got {:?}",
+                BCRYPT_COST_RANGE.start(),
+                BCRYPT_COST_RANGE.end(),
+                value
+            ))
+        })
+}
+
+// I am verifying a password against a stored hash, picking the algorithm from the hash prefix
+pub fn verify_password(password: &str, password_hash: &str) -> Result<bool> {
+    if password_hash.starts_with("$argon2") {
+        let parsed = PasswordHash::new(password_hash)
+            .map_err(|e| AppError::InternalError(format!("Failed to parse password hash: {}", e)))?;
+        Ok(Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok())
+    } else {
+        bcrypt::verify(password, password_hash)
+            .map_err(|e| AppError::InternalError(format!("Failed to verify password: {}", e)))
+    }
+}
+
+// I am defining the rules new passwords must meet beyond the 8-128 character length on the request
+#[derive(Debug, Clone, Default)]
+pub struct PasswordPolicy {
+    pub require_uppercase: bool,
+    pub require_lowercase: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+    // Lowercased passwords that are refused outright
+    pub denylist: HashSet<String>,
+}
+
+impl PasswordPolicy {
+    // I am reading the REQUIRE_* flags and the optional COMMON_PASSWORDS_FILE (one password per line)
+    pub fn from_env() -> Result<Self> {
+        let flag = |name: &str| std::env::var(name).map(|v| v == "true").unwrap_or(false);
+
+        let denylist = match std::env::var("COMMON_PASSWORDS_FILE") {
+            Ok(path) if !path.is_empty() => {
+                let contents = std::fs::read_to_string(&path).map_err(|e| {
+                    AppError::ConfigError(format!("Cannot read COMMON_PASSWORDS_FILE {}: {}", path, e))
+                })?;
+                parse_denylist(&contents)
+            }
+            _ => HashSet::new(),
+        };
+
+        Ok(Self {
+            require_uppercase: flag("REQUIRE_UPPERCASE"),
+            require_lowercase: flag("REQUIRE_LOWERCASE"),
+            require_digit: flag("REQUIRE_DIGIT"),
+            require_symbol: flag("REQUIRE_SYMBOL"),
+            denylist,
+        })
+    }
+
+    // I am listing every rule the password breaks, so the user can fix them all at once
+    pub fn violations(&self, password: &str) -> Vec<String> {
+        let rules = [
+            (self.require_uppercase, password.chars().any(char::is_uppercase), "Password must contain an uppercase letter"),
+            (self.require_lowercase, password.chars().any(char::is_lowercase), "Password must contain a lowercase letter"),
+            (self.require_digit, password.chars().any(|c| c.is_ascii_digit()), "Password must contain a digit"),
+            // Anything that isn't a letter, digit or space counts as a symbol
+            (
+                self.require_symbol,
+                password.chars().any(|c| !c.is_alphanumeric() && !c.is_whitespace()),
+                "Password must contain a symbol",
+            ),
+            (
+                !self.denylist.is_empty(),
+                !self.denylist.contains(&password.to_lowercase()),
+                "Password is too common",
+            ),
+        ];
+
+        rules
+            .into_iter()
+            .filter(|(required, met, _)| *required && !met)
+            .map(|(_, _, message)| message.to_string())
+            .collect()
+    }
+}
+
+fn parse_denylist(contents: &str) -> HashSet<String> {
+    contents
+        .lines()
+        .map(|line| line.trim().to_lowercase())
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_and_verify_with_both_backends() {
+        let bcrypt_hash = PasswordHashing::Bcrypt { cost: *BCRYPT_COST_RANGE.start() }.hash("hunter22").unwrap();
+        let argon2_hash = PasswordHashing::Argon2.hash("hunter22").unwrap();
+        assert!(bcrypt_hash.starts_with("$2"));
+        assert!(argon2_hash.starts_with("$argon2id$"));
+
+        // Either kind of stored hash verifies, whichever backend is configured now
+        assert!(verify_password("hunter22", &bcrypt_hash).unwrap());
+        assert!(verify_password("hunter22", &argon2_hash).unwrap());
+        assert!(!verify_password("wrong", &bcrypt_hash).unwrap());
+        assert!(!verify_password("wrong", &argon2_hash).unwrap());
+    }
+
+    #[test]
+    fn test_bcrypt_cost_must_be_in_range() {
+        assert_eq!(parse_bcrypt_cost(None).unwrap(), bcrypt::DEFAULT_COST);
+        assert_eq!(parse_bcrypt_cost(Some(" 10 ")).unwrap(), 10);
+        for bad in ["3", "32", "twelve", ""] {
+            assert!(matches!(parse_bcrypt_cost(Some(bad)), Err(AppError::ConfigError(_))));
+        }
+    }
+
+    #[test]
+    fn test_policy_lists_every_broken_rule() {
+        assert!(PasswordPolicy::default().violations("password").is_empty());
+
+        let policy = PasswordPolicy {
+            require_uppercase: true,
+            require_lowercase: true,
+            require_digit: true,
+            require_symbol: true,
+            denylist: parse_denylist("Password1!\n\n  qwerty123  \n"),
+        };
+        assert_eq!(
+            policy.violations("lowercase only"),
+            vec![
+                "Password must contain an uppercase letter",
+                "Password must contain a digit",
+                "Password must contain a symbol",
+            ]
+        );
+        assert!(policy.violations("Tr0ub4dor&3").is_empty());
+        // The denylist matches regardless of case
+        assert_eq!(policy.violations("PASSWORD1!"), vec!["Password must contain a lowercase letter", "Password is too common"]);
+    }
+}
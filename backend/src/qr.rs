@@ -0,0 +1,127 @@
+// I am importing the URL parser used to spot web links among decoded payloads
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    barcode::{decode_barcodes, Barcode},
+    error::{AppError, Result},
+};
+
+// I am defining the kinds of code that can be read from an image
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Symbology {
+    Qr,
+    #[serde(rename = "ean_13")]
+    Ean13,
+    #[serde(rename = "code_128")]
+    Code128,
+}
+
+impl Symbology {
+    // The scan format a code of this kind belongs to
+    pub fn scan_format(self) -> &'static str {
+        match self {
+            Symbology::Qr => "qr",
+            Symbology::Ean13 | Symbology::Code128 => "barcode",
+        }
+    }
+}
+
+impl From<Barcode> for Symbology {
+    fn from(barcode: Barcode) -> Self {
+        match barcode {
+            Barcode::Ean13 => Symbology::Ean13,
+            Barcode::Code128 => Symbology::Code128,
+        }
+    }
+}
+
+// I am defining one code read from an image
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DecodedCode {
+    pub payload: String,
+    pub symbology: Symbology,
+    // Only set when the payload is an http(s) URL: whether it uses https
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub https: Option<bool>,
+}
+
+impl DecodedCode {
+    fn new(payload: String, symbology: Symbology) -> Self {
+        let https = Url::parse(&payload)
+            .ok()
+            .filter(|url| matches!(url.scheme(), "http" | "https"))
+            .map(|url| url.scheme() == "https");
+        Self { payload, symbology, https }
+    }
+}
+
+// I am finding and decoding every QR code and 1D barcode in an image, QR codes first; an image with no readable
+// code gives an empty list
+pub fn decode_codes(data: &[u8]) -> Result<Vec<DecodedCode>> {
+    let image = image::load_from_memory(data)
+        .map_err(|e| AppError::BadRequestError(format!("Image could not be decoded: {}", e)))?
+        .to_luma8();
+    let barcodes = decode_barcodes(&image);
+
+    let mut prepared = rqrr::PreparedImage::prepare(image);
+    let codes = prepared
+        .detect_grids()
+        .into_iter()
+        .filter_map(|grid| match grid.decode() {
+            Ok((_, payload)) => Some(DecodedCode::new(payload, Symbology::Qr)),
+            // Finder patterns can turn up in ordinary pictures; a grid that doesn't decode is just not a code
+            Err(e) => {
+                tracing::debug!("Skipping QR grid that failed to decode: {}", e);
+                None
+            }
+        })
+        .chain(barcodes.into_iter().map(|(barcode, payload)| DecodedCode::new(payload, barcode.into())))
+        .collect();
+    Ok(codes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decodes_qr_codes_and_flags_https() {
+        let codes = decode_codes(include_bytes!("../testdata/qr_github.gif")).unwrap();
+        assert_eq!(
+            codes,
+            vec![DecodedCode {
+                payload: "https://github.com/WanzenBug/rqrr".to_string(),
+                symbology: Symbology::Qr,
+                https: Some(true),
+            }]
+        );
+
+        assert_eq!(DecodedCode::new("http://example.com".to_string(), Symbology::Code128).https, Some(false));
+        assert_eq!(DecodedCode::new("WIFI:S:office;;".to_string(), Symbology::Qr).https, None);
+
+        // A valid image without a code is an empty result; bytes that aren't an image are refused
+        let blank = image::GrayImage::from_pixel(64, 64, image::Luma([255]));
+        let mut png = Vec::new();
+        blank.write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png).unwrap();
+        assert!(decode_codes(&png).unwrap().is_empty());
+        assert!(matches!(decode_codes(b"not an image"), Err(AppError::BadRequestError(_))));
+    }
+
+    #[test]
+    fn test_decodes_barcodes_alongside_qr_codes() {
+        let mut png = Vec::new();
+        crate::barcode::tests::render_ean13("4006381333931")
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+
+        let codes = decode_codes(&png).unwrap();
+        assert_eq!(
+            codes,
+            vec![DecodedCode { payload: "4006381333931".to_string(), symbology: Symbology::Ean13, https: None }]
+        );
+        assert_eq!(codes[0].symbology.scan_format(), "barcode");
+        assert_eq!(Symbology::Qr.scan_format(), "qr");
+    }
+}
@@ -0,0 +1,123 @@
+// I am importing the types needed to track request counts per client IP
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header::RETRY_AFTER, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use dashmap::DashMap;
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::error::AppError;
+
+const WINDOW: Duration = Duration::from_secs(60);
+const PRUNE_THRESHOLD: usize = 10_000;
+
+// I am defining a fixed-window rate limiter keyed by client IP
+pub struct RateLimiter {
+    limit_per_minute: u32,
+    windows: DashMap<IpAddr, (Instant, u32)>,
+}
+
+impl RateLimiter {
+    pub fn new(limit_per_minute: u32) -> Self {
+        Self {
+            limit_per_minute,
+            windows: DashMap::new(),
+        }
+    }
+
+    // I am reading the per-minute limit from the environment; 0 disables rate limiting
+    pub fn from_env() -> Self {
+        let limit_per_minute = std::env::var("RATE_LIMIT_PER_MINUTE")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(20);
+        Self::new(limit_per_minute)
+    }
+
+    // I am counting a request from this IP, returning how long to wait if it is over the limit
+    pub fn check(&self, ip: IpAddr) -> std::result::Result<(), Duration> {
+        if self.limit_per_minute == 0 {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        if self.windows.len() > PRUNE_THRESHOLD {
+            self.windows.retain(|_, (started, _)| now.duration_since(*started) < WINDOW);
+        }
+
+        let mut entry = self.windows.entry(ip).or_insert((now, 0));
+        let (started, count) = entry.value_mut();
+
+        if now.duration_since(*started) >= WINDOW {
+            *started = now;
+            *count = 0;
+        }
+
+        if *count >= self.limit_per_minute {
+            return Err(WINDOW.saturating_sub(now.duration_since(*started)));
+        }
+
+        *count += 1;
+        Ok(())
+    }
+}
+
+// I am rejecting requests over the limit with the standard rate limit error and a Retry-After header
+pub async fn enforce_rate_limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+
+    match limiter.check(ip) {
+        Ok(()) => next.run(request).await,
+        Err(retry_after) => {
+            tracing::warn!("Rate limit exceeded for {}", ip);
+            let mut response = AppError::RateLimitError.into_response();
+            let seconds = retry_after.as_secs().max(1);
+            if let Ok(value) = HeaderValue::from_str(&seconds.to_string()) {
+                response.headers_mut().insert(RETRY_AFTER, value);
+            }
+            response
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limit_is_enforced_per_ip() {
+        let limiter = RateLimiter::new(2);
+        let first = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let second = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+
+        assert!(limiter.check(first).is_ok());
+        assert!(limiter.check(first).is_ok());
+        let retry_after = limiter.check(first).unwrap_err();
+        assert!(retry_after <= WINDOW);
+
+        assert!(limiter.check(second).is_ok());
+    }
+
+    #[test]
+    fn test_zero_limit_disables_rate_limiting() {
+        let limiter = RateLimiter::new(0);
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        for _ in 0..100 {
+            assert!(limiter.check(ip).is_ok());
+        }
+    }
+}
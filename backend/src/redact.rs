@@ -0,0 +1,46 @@
+// I am masking personal data before it reaches the logs; LOG_REDACT=false shows email addresses for debugging,
+// but user-supplied content is never logged in full
+use std::sync::OnceLock;
+
+static ENABLED: OnceLock<bool> = OnceLock::new();
+
+fn enabled() -> bool {
+    *ENABLED.get_or_init(|| std::env::var("LOG_REDACT").map(|v| v != "false").unwrap_or(true))
+}
+
+// I am formatting an email address for logs, e.g. "j***@example.com"
+pub fn email(email: &str) -> String {
+    if enabled() {
+        mask_email(email)
+    } else {
+        email.to_string()
+    }
+}
+
+// I am formatting user-supplied text (documents, prompts, scan data, model output) for logs, keeping only its length
+pub fn content(content: &str) -> String {
+    format!("<{} chars redacted>", content.chars().count())
+}
+
+fn mask_email(email: &str) -> String {
+    match email.rsplit_once('@') {
+        Some((local, domain)) => {
+            let first: String = local.chars().take(1).collect();
+            format!("{}***@{}", first, domain)
+        }
+        None => "***".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_masks_email_local_part_and_content() {
+        assert_eq!(mask_email("jane.doe@example.com"), "j***@example.com");
+        assert_eq!(mask_email("@example.com"), "***@example.com");
+        assert_eq!(mask_email("not-an-email"), "***");
+        assert_eq!(content("Quarterly results: revenue up 4%"), "<32 chars redacted>");
+    }
+}
@@ -0,0 +1,424 @@
+// I am importing the collections, paths, and async primitives needed to persist the file registry
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use tokio::{fs, sync::RwLock};
+use uuid::Uuid;
+
+use crate::{
+    models::FileStatsResponse,
+    storage::{StorageService, StorageType, StoredFile, UNKNOWN_CONTENT_TYPE},
+};
+
+// I am defining the file registry, which maps file ids to their stored metadata and survives restarts
+pub struct FileRegistry {
+    files: RwLock<HashMap<Uuid, StoredFile>>,
+    path: PathBuf,
+}
+
+impl FileRegistry {
+    // I am loading the registry from its JSON file, dropping temporary files that have vanished from disk
+    pub async fn load(path: PathBuf) -> Result<Self> {
+        let mut files: HashMap<Uuid, StoredFile> = match fs::read(&path).await {
+            Ok(contents) => serde_json::from_slice(&contents)
+                .context("Failed to parse file registry")?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e).context("Failed to read file registry"),
+        };
+
+        let before = files.len();
+        files.retain(|_, file| {
+            !matches!(file.storage_type, StorageType::Temporary) || Path::new(&file.storage_path).exists()
+        });
+        if files.len() < before {
+            tracing::info!("Dropped {} registry entries whose files no longer exist", before - files.len());
+        }
+
+        let registry = Self {
+            files: RwLock::new(files),
+            path,
+        };
+        registry.persist(&*registry.files.read().await).await?;
+
+        Ok(registry)
+    }
+
+    // I am reading the registry location from the environment, defaulting next to the upload directory
+    pub fn path_from_env() -> PathBuf {
+        std::env::var("FILE_REGISTRY_PATH")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| std::env::temp_dir().join("quickscan_file_registry.json"))
+    }
+
+    pub async fn get(&self, id: &Uuid) -> Option<StoredFile> {
+        self.files.read().await.get(id).cloned()
+    }
+
+    pub async fn list(&self) -> Vec<StoredFile> {
+        self.files.read().await.values().cloned().collect()
+    }
+
+    // I am finding a file the owner already uploaded with identical contents
+    pub async fn find_duplicate(&self, owner_id: Uuid, content_hash: &str) -> Option<StoredFile> {
+        self.files
+            .read()
+            .await
+            .values()
+            .find(|file| file.owner_id == Some(owner_id) && file.content_hash == content_hash && !file.is_trashed())
+            .cloned()
+    }
+
+    // I am totalling one owner's files by count, bytes, content type and storage type
+    pub async fn stats(&self, owner_id: Uuid) -> FileStatsResponse {
+        let files = self.files.read().await;
+        let mut stats = FileStatsResponse::default();
+        for file in files.values().filter(|file| file.owner_id == Some(owner_id) && !file.is_trashed()) {
+            stats.total_count += 1;
+            stats.total_bytes += file.file_size;
+            let content_type = file.content_type.clone().unwrap_or_else(|| UNKNOWN_CONTENT_TYPE.to_string());
+            *stats.by_content_type.entry(content_type).or_default() += 1;
+            // Debug gives the variant name, matching how storage_type is serialized elsewhere
+            *stats.by_storage_type.entry(format!("{:?}", file.storage_type)).or_default() += 1;
+        }
+        stats
+    }
+
+    pub async fn insert(&self, file: StoredFile) -> Result<()> {
+        let mut files = self.files.write().await;
+        files.insert(file.id, file);
+        self.persist(&files).await
+    }
+
+    pub async fn remove(&self, id: &Uuid) -> Result<Option<StoredFile>> {
+        let mut files = self.files.write().await;
+        let removed = files.remove(id);
+        if removed.is_some() {
+            self.persist(&files).await?;
+        }
+        Ok(removed)
+    }
+
+    // I am replacing a file's metadata tags, returning the updated entry
+    pub async fn set_metadata(&self, id: &Uuid, metadata: HashMap<String, String>) -> Result<Option<StoredFile>> {
+        let mut files = self.files.write().await;
+        let Some(file) = files.get_mut(id) else {
+            return Ok(None);
+        };
+        file.metadata = metadata;
+        let updated = file.clone();
+        self.persist(&files).await?;
+        Ok(Some(updated))
+    }
+
+    // I am changing a file's display name, returning the updated entry; where it is stored stays the same
+    pub async fn set_filename(&self, id: &Uuid, filename: String) -> Result<Option<StoredFile>> {
+        let mut files = self.files.write().await;
+        let Some(file) = files.get_mut(id) else {
+            return Ok(None);
+        };
+        file.filename = filename;
+        let updated = file.clone();
+        self.persist(&files).await?;
+        Ok(Some(updated))
+    }
+
+    // I am moving a file to the trash, or back out of it with None, returning the updated entry
+    pub async fn set_deleted_at(&self, id: &Uuid, deleted_at: Option<DateTime<Utc>>) -> Result<Option<StoredFile>> {
+        let mut files = self.files.write().await;
+        let Some(file) = files.get_mut(id) else {
+            return Ok(None);
+        };
+        file.deleted_at = deleted_at;
+        let updated = file.clone();
+        self.persist(&files).await?;
+        Ok(Some(updated))
+    }
+
+    // I am deleting files that have sat in the trash longer than the retention period, returning the removed ids;
+    // a file whose contents can't be deleted keeps its entry so the next cleanup tries again
+    pub async fn purge_trash(&self, storage: &StorageService, retention_days: u64) -> Result<Vec<Uuid>> {
+        let cutoff = Utc::now() - chrono::Duration::days(retention_days as i64);
+        let expired: Vec<StoredFile> = self.files
+            .read()
+            .await
+            .values()
+            .filter(|file| file.deleted_at.is_some_and(|deleted_at| deleted_at <= cutoff))
+            .cloned()
+            .collect();
+
+        let mut purged = Vec::new();
+        for file in expired {
+            match storage.delete_file(&file).await {
+                Ok(()) => purged.push(file.id),
+                Err(e) => tracing::warn!("Failed to purge trashed file {}: {}", file.id, e),
+            }
+        }
+
+        if !purged.is_empty() {
+            let mut files = self.files.write().await;
+            files.retain(|id, _| !purged.contains(id));
+            self.persist(&files).await?;
+        }
+        tracing::info!("Purged {} files from the trash", purged.len());
+        Ok(purged)
+    }
+
+    // I am dropping every entry stored at one of the given paths, returning the removed ids
+    pub async fn remove_by_storage_paths(&self, paths: &HashSet<String>) -> Result<Vec<Uuid>> {
+        let mut files = self.files.write().await;
+        let removed: Vec<Uuid> = files
+            .values()
+            .filter(|file| paths.contains(&file.storage_path))
+            .map(|file| file.id)
+            .collect();
+        if !removed.is_empty() {
+            files.retain(|id, _| !removed.contains(id));
+            self.persist(&files).await?;
+        }
+        Ok(removed)
+    }
+
+    // I am deleting expired temporary files and dropping their entries so no ghosts are left behind, returning the removed ids
+    pub async fn cleanup_expired(&self, storage: &StorageService, max_age_hours: u64) -> Result<Vec<Uuid>> {
+        let deleted = storage.cleanup_expired_temp_files(max_age_hours).await?;
+        let paths: HashSet<String> = deleted
+            .iter()
+            .map(|path| path.to_string_lossy().to_string())
+            .collect();
+
+        let removed = self.remove_by_storage_paths(&paths).await?;
+        tracing::info!(
+            "Cleanup deleted {} expired temporary files, {} of them registered",
+            deleted.len(),
+            removed.len()
+        );
+        Ok(removed)
+    }
+
+    // I am deleting temporary files left without a registry entry, returning their paths
+    pub async fn cleanup_orphans(&self, storage: &StorageService) -> Result<Vec<PathBuf>> {
+        let referenced: HashSet<String> = self.files
+            .read()
+            .await
+            .values()
+            .filter(|file| matches!(file.storage_type, StorageType::Temporary))
+            .filter_map(|file| Path::new(&file.storage_path).file_name())
+            .map(|name| name.to_string_lossy().to_string())
+            .collect();
+
+        storage.remove_orphaned_temp_files(&referenced).await
+    }
+
+    // I am writing the registry to a temp file and renaming it so readers never see a partial write
+    async fn persist(&self, files: &HashMap<Uuid, StoredFile>) -> Result<()> {
+        let path = &self.path;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await
+                .context("Failed to create file registry directory")?;
+        }
+
+        let contents = serde_json::to_vec_pretty(files)
+            .context("Failed to serialize file registry")?;
+        let staging_path = path.with_extension("json.tmp");
+        fs::write(&staging_path, contents).await
+            .context("Failed to write file registry")?;
+        fs::rename(&staging_path, path).await
+            .context("Failed to replace file registry")?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ids::DeterministicIdGenerator, storage::{test_storage_config, StorageConfig}};
+    use std::sync::Arc;
+
+    fn temporary_file(id: Uuid, storage_path: &Path) -> StoredFile {
+        StoredFile {
+            id,
+            filename: "notes.txt".to_string(),
+            file_size: 5,
+            content_type: None,
+            storage_path: storage_path.to_string_lossy().to_string(),
+            storage_type: StorageType::Temporary,
+            timestamp: chrono::Utc::now(),
+            download_url: None,
+            owner_id: None,
+            content_hash: String::new(),
+            metadata: HashMap::new(),
+            deleted_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_registry_survives_reload_and_drops_missing_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry_path = dir.path().join("registry.json");
+        let kept_path = dir.path().join("kept.txt");
+        let missing_path = dir.path().join("missing.txt");
+        std::fs::write(&kept_path, b"hello").unwrap();
+        std::fs::write(&missing_path, b"hello").unwrap();
+
+        let kept = Uuid::new_v4();
+        let missing = Uuid::new_v4();
+        let registry = FileRegistry::load(registry_path.clone()).await.unwrap();
+        registry.insert(temporary_file(kept, &kept_path)).await.unwrap();
+        registry.insert(temporary_file(missing, &missing_path)).await.unwrap();
+        drop(registry);
+
+        std::fs::remove_file(&missing_path).unwrap();
+
+        let reloaded = FileRegistry::load(registry_path).await.unwrap();
+        assert!(reloaded.get(&kept).await.is_some());
+        assert!(reloaded.get(&missing).await.is_none());
+        assert_eq!(reloaded.list().await.len(), 1);
+
+        assert!(reloaded.remove(&kept).await.unwrap().is_some());
+        assert!(reloaded.list().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_remove_by_storage_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry_path = dir.path().join("registry.json");
+        let expired_path = dir.path().join("expired.txt");
+        let fresh_path = dir.path().join("fresh.txt");
+
+        let registry = FileRegistry::load(registry_path.clone()).await.unwrap();
+        let fresh = Uuid::new_v4();
+        registry.insert(temporary_file(Uuid::new_v4(), &expired_path)).await.unwrap();
+        registry.insert(temporary_file(fresh, &fresh_path)).await.unwrap();
+
+        let paths = HashSet::from([expired_path.to_string_lossy().to_string()]);
+        assert_eq!(registry.remove_by_storage_paths(&paths).await.unwrap().len(), 1);
+        assert!(registry.remove_by_storage_paths(&paths).await.unwrap().is_empty());
+
+        let remaining = registry.list().await;
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].id, fresh);
+
+        // The removal was persisted, not just applied in memory
+        let on_disk: HashMap<Uuid, StoredFile> = serde_json::from_slice(&std::fs::read(registry_path).unwrap()).unwrap();
+        assert_eq!(on_disk.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_expired_removes_cleaned_files_from_registry() {
+        let dir = tempfile::tempdir().unwrap();
+        let upload_dir = dir.path().join("uploads");
+        let storage = StorageService::new(test_storage_config(&upload_dir), Arc::new(DeterministicIdGenerator::new(1))).unwrap();
+        let registry = FileRegistry::load(dir.path().join("registry.json")).await.unwrap();
+
+        let expired = storage.store_file("old.txt", None, b"old").await.unwrap();
+        let fresh = storage.store_file("new.txt", None, b"new").await.unwrap();
+        registry.insert(expired.clone()).await.unwrap();
+        registry.insert(fresh.clone()).await.unwrap();
+
+        // Backdate one file past the cutoff
+        std::fs::File::options()
+            .write(true)
+            .open(&expired.storage_path)
+            .unwrap()
+            .set_modified(std::time::SystemTime::now() - std::time::Duration::from_secs(48 * 3600))
+            .unwrap();
+
+        let removed = registry.cleanup_expired(&storage, 24).await.unwrap();
+        assert_eq!(removed, vec![expired.id]);
+        assert!(registry.get(&expired.id).await.is_none());
+        assert!(registry.get(&fresh.id).await.is_some());
+        assert!(!Path::new(&expired.storage_path).exists());
+    }
+
+    #[tokio::test]
+    async fn test_stats_count_only_the_owners_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = FileRegistry::load(dir.path().join("registry.json")).await.unwrap();
+        let owner_id = Uuid::new_v4();
+
+        let owned = |file_size, content_type: Option<&str>, storage_type| StoredFile {
+            file_size,
+            content_type: content_type.map(str::to_string),
+            storage_type,
+            owner_id: Some(owner_id),
+            ..temporary_file(Uuid::new_v4(), dir.path())
+        };
+        registry.insert(owned(100, Some("image/png"), StorageType::Temporary)).await.unwrap();
+        registry.insert(owned(50, Some("image/png"), StorageType::S3)).await.unwrap();
+        registry.insert(owned(7, None, StorageType::Temporary)).await.unwrap();
+        registry.insert(temporary_file(Uuid::new_v4(), dir.path())).await.unwrap();
+
+        let stats = registry.stats(owner_id).await;
+        assert_eq!((stats.total_count, stats.total_bytes), (3, 157));
+        assert_eq!(stats.by_content_type["image/png"], 2);
+        assert_eq!(stats.by_content_type[UNKNOWN_CONTENT_TYPE], 1);
+        assert_eq!(stats.by_storage_type["Temporary"], 2);
+        assert_eq!(stats.by_storage_type["S3"], 1);
+
+        assert_eq!(registry.stats(Uuid::new_v4()).await.total_count, 0);
+    }
+
+
+    #[tokio::test]
+    async fn test_cleanup_orphans_keeps_registered_and_foreign_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let uploads = dir.path().join("uploads");
+        let storage = StorageService::new(StorageConfig {
+            orphan_min_age_hours: 0,
+            ..test_storage_config(&uploads)
+        }, Arc::new(DeterministicIdGenerator::new(5))).unwrap();
+        let registry = FileRegistry::load(dir.path().join("registry.json")).await.unwrap();
+
+        let registered = storage.store_file("kept.txt", None, b"hello").await.unwrap();
+        registry.insert(registered.clone()).await.unwrap();
+        let orphan = storage.store_file("crashed.txt", None, b"partial").await.unwrap();
+        let foreign = uploads.join("README.txt");
+        std::fs::write(&foreign, b"not ours").unwrap();
+
+        let removed = registry.cleanup_orphans(&storage).await.unwrap();
+        assert_eq!(removed, vec![PathBuf::from(&orphan.storage_path)]);
+        assert!(Path::new(&registered.storage_path).exists());
+        assert!(foreign.exists());
+    }
+
+    #[tokio::test]
+    async fn test_trashed_files_are_hidden_then_purged() {
+        let dir = tempfile::tempdir().unwrap();
+        let uploads = dir.path().join("uploads");
+        let storage = StorageService::new(test_storage_config(&uploads), Arc::new(DeterministicIdGenerator::new(6))).unwrap();
+        let registry = FileRegistry::load(dir.path().join("registry.json")).await.unwrap();
+        let owner_id = Uuid::new_v4();
+
+        let mut stored = Vec::new();
+        for name in ["old.txt", "recent.txt"] {
+            let file = StoredFile {
+                owner_id: Some(owner_id),
+                content_hash: name.to_string(),
+                ..storage.store_file(name, None, b"hello").await.unwrap()
+            };
+            registry.insert(file.clone()).await.unwrap();
+            stored.push(file);
+        }
+        let (old, recent) = (&stored[0], &stored[1]);
+
+        let trashed = registry.set_deleted_at(&old.id, Some(Utc::now() - chrono::Duration::days(31))).await.unwrap();
+        assert!(trashed.unwrap().is_trashed());
+        registry.set_deleted_at(&recent.id, Some(Utc::now())).await.unwrap();
+        assert_eq!(registry.stats(owner_id).await.total_count, 0);
+        assert!(registry.find_duplicate(owner_id, "old.txt").await.is_none());
+
+        // Only the file trashed before the retention window is deleted
+        assert_eq!(registry.purge_trash(&storage, 30).await.unwrap(), vec![old.id]);
+        assert!(registry.get(&old.id).await.is_none());
+        assert!(!Path::new(&old.storage_path).exists());
+        assert!(Path::new(&recent.storage_path).exists());
+
+        let restored = registry.set_deleted_at(&recent.id, None).await.unwrap().unwrap();
+        assert!(!restored.is_trashed());
+        assert_eq!(registry.stats(owner_id).await.total_count, 1);
+        assert!(registry.purge_trash(&storage, 0).await.unwrap().is_empty());
+    }
+
+}
@@ -0,0 +1,252 @@
+// I am importing the file and lock types used to assemble uploads that arrive in pieces
+use chrono::{DateTime, Duration, Utc};
+use dashmap::DashMap;
+use std::{io::SeekFrom, path::PathBuf, sync::Arc};
+use tokio::{
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+    sync::Mutex,
+};
+use uuid::Uuid;
+
+use crate::{
+    error::{AppError, Result},
+    models::{ResumableUploadResponse, UploadResponse},
+};
+
+// tus-style headers: where the next chunk starts, and the size declared when the upload began
+pub const UPLOAD_OFFSET_HEADER: &str = "Upload-Offset";
+pub const UPLOAD_LENGTH_HEADER: &str = "Upload-Length";
+// How long an unfinished upload may sit idle before it and its partial data are dropped
+pub const RESUMABLE_UPLOAD_TTL_HOURS: i64 = 24;
+
+// I am defining an upload in progress; its received bytes live in a partial file on disk
+#[derive(Debug, Clone)]
+pub struct ResumableUpload {
+    pub id: Uuid,
+    pub owner_id: Uuid,
+    pub filename: String,
+    pub content_type: Option<String>,
+    pub length: u64,
+    pub offset: u64,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl ResumableUpload {
+    pub fn is_complete(&self) -> bool {
+        self.offset == self.length
+    }
+
+    pub fn response(&self, file: Option<UploadResponse>) -> ResumableUploadResponse {
+        ResumableUploadResponse {
+            id: self.id,
+            filename: self.filename.clone(),
+            offset: self.offset,
+            length: self.length,
+            expires_at: self.expires_at,
+            file,
+        }
+    }
+}
+
+// I am tracking resumable uploads; each one has its own lock so concurrent chunks can't interleave
+pub struct ResumableUploadStore {
+    dir: PathBuf,
+    uploads: DashMap<Uuid, Arc<Mutex<ResumableUpload>>>,
+    ttl: Duration,
+}
+
+impl ResumableUploadStore {
+    pub fn new(dir: PathBuf, ttl: Duration) -> Self {
+        Self {
+            dir,
+            uploads: DashMap::new(),
+            ttl,
+        }
+    }
+
+    // I am keeping partial uploads in the temp directory unless RESUMABLE_UPLOAD_DIR is set
+    pub fn dir_from_env() -> PathBuf {
+        std::env::var("RESUMABLE_UPLOAD_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| std::env::temp_dir().join("quickscan_resumable_uploads"))
+    }
+
+    fn path(&self, id: Uuid) -> PathBuf {
+        self.dir.join(format!("{}.part", id))
+    }
+
+    // I am starting an upload with an empty partial file
+    pub async fn create(
+        &self,
+        id: Uuid,
+        owner_id: Uuid,
+        filename: String,
+        content_type: Option<String>,
+        length: u64,
+    ) -> anyhow::Result<ResumableUpload> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        tokio::fs::File::create(self.path(id)).await?;
+
+        let upload = ResumableUpload {
+            id,
+            owner_id,
+            filename,
+            content_type,
+            length,
+            offset: 0,
+            expires_at: Utc::now() + self.ttl,
+        };
+        self.uploads.insert(id, Arc::new(Mutex::new(upload.clone())));
+        Ok(upload)
+    }
+
+    // I am finding one of the user's unexpired uploads; anyone else's look like they don't exist
+    pub async fn get(&self, id: Uuid, owner_id: Uuid) -> Result<Arc<Mutex<ResumableUpload>>> {
+        let not_found = || AppError::NotFoundError("Upload not found".to_string());
+        let upload = self.uploads.get(&id).map(|entry| entry.value().clone()).ok_or_else(not_found)?;
+
+        let guard = upload.lock().await;
+        if guard.owner_id != owner_id || guard.expires_at <= Utc::now() {
+            return Err(not_found());
+        }
+        drop(guard);
+        Ok(upload)
+    }
+
+    // I am appending a chunk that starts exactly where the received bytes end, returning the new offset
+    pub async fn append(&self, upload: &mut ResumableUpload, offset: u64, data: &[u8]) -> Result<u64> {
+        // A request that queued behind the final chunk finds the upload already stored and forgotten
+        if upload.is_complete() {
+            return Err(AppError::NotFoundError("Upload not found".to_string()));
+        }
+        if offset != upload.offset {
+            return Err(AppError::ConflictError(format!(
+                "{} {} does not match the current offset {}",
+                UPLOAD_OFFSET_HEADER, offset, upload.offset
+            )));
+        }
+        if offset + data.len() as u64 > upload.length {
+            return Err(AppError::ValidationError(format!(
+                "Chunk would exceed the declared {} of {} bytes",
+                UPLOAD_LENGTH_HEADER, upload.length
+            )));
+        }
+
+        let write = async {
+            let mut file = tokio::fs::OpenOptions::new().write(true).open(self.path(upload.id)).await?;
+            // Drop anything a failed earlier write left past the recorded offset
+            file.set_len(offset).await?;
+            file.seek(SeekFrom::Start(offset)).await?;
+            file.write_all(data).await?;
+            file.sync_data().await
+        };
+        write.await.map_err(|e| AppError::StorageError(format!("Failed to write upload chunk: {}", e)))?;
+
+        upload.offset = offset + data.len() as u64;
+        upload.expires_at = Utc::now() + self.ttl;
+        Ok(upload.offset)
+    }
+
+    // I am returning the upload's first `window` bytes (all of it, if shorter) when the chunk about to be appended
+    // at `offset` is the one that completes them, so the file's type can be checked before the rest is sent
+    pub async fn completed_head(&self, upload: &ResumableUpload, offset: u64, data: &[u8], window: u64) -> Result<Option<Vec<u8>>> {
+        let window = window.min(upload.length);
+        if offset != upload.offset || offset >= window || offset + (data.len() as u64) < window {
+            return Ok(None);
+        }
+
+        let mut head = Vec::with_capacity(window as usize);
+        let read = async {
+            let file = tokio::fs::File::open(self.path(upload.id)).await?;
+            file.take(offset).read_to_end(&mut head).await
+        };
+        read.await.map_err(|e| AppError::StorageError(format!("Failed to read upload head: {}", e)))?;
+        head.extend_from_slice(&data[..(window - offset) as usize]);
+        Ok(Some(head))
+    }
+
+    // I am handing back a finished upload's bytes and forgetting it
+    pub async fn take_completed(&self, upload: &ResumableUpload) -> Result<Vec<u8>> {
+        let data = tokio::fs::read(self.path(upload.id))
+            .await
+            .map_err(|e| AppError::StorageError(format!("Failed to read assembled upload: {}", e)))?;
+        self.remove(upload.id).await;
+        Ok(data)
+    }
+
+    pub async fn remove(&self, id: Uuid) {
+        self.uploads.remove(&id);
+        if let Err(e) = tokio::fs::remove_file(self.path(id)).await {
+            tracing::warn!("Failed to remove partial upload {}: {}", id, e);
+        }
+    }
+
+    // I am dropping uploads left idle past their expiry along with their partial files
+    pub async fn prune_expired(&self) -> usize {
+        let now = Utc::now();
+        let mut expired = Vec::new();
+        for entry in self.uploads.iter() {
+            // An upload busy with a chunk isn't idle
+            if let Ok(upload) = entry.value().try_lock() {
+                if upload.expires_at <= now {
+                    expired.push(upload.id);
+                }
+            }
+        }
+
+        for id in &expired {
+            self.remove(*id).await;
+        }
+        expired.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_chunks_append_at_the_current_offset_only() {
+        let dir = std::env::temp_dir().join(format!("quickscan_resumable_test_{}", Uuid::new_v4()));
+        let store = ResumableUploadStore::new(dir.clone(), Duration::hours(1));
+        let owner_id = Uuid::new_v4();
+        let id = Uuid::new_v4();
+        store.create(id, owner_id, "notes.txt".to_string(), None, 11).await.unwrap();
+
+        assert!(matches!(store.get(id, Uuid::new_v4()).await, Err(AppError::NotFoundError(_))));
+        let upload = store.get(id, owner_id).await.unwrap();
+        let mut upload = upload.lock().await;
+
+        assert_eq!(store.append(&mut upload, 0, b"hello ").await.unwrap(), 6);
+        // A resent chunk is refused rather than written twice
+        assert!(matches!(store.append(&mut upload, 0, b"hello ").await, Err(AppError::ConflictError(_))));
+        assert!(matches!(store.append(&mut upload, 6, b"world!").await, Err(AppError::ValidationError(_))));
+        assert_eq!(store.append(&mut upload, 6, b"world").await.unwrap(), 11);
+        assert!(upload.is_complete());
+
+        assert_eq!(store.take_completed(&upload).await.unwrap(), b"hello world");
+        drop(upload);
+        assert!(store.get(id, owner_id).await.is_err());
+
+        // The head is only handed out by the chunk that completes it, earlier bytes read back from disk
+        let sniffed = Uuid::new_v4();
+        store.create(sniffed, owner_id, "tool.exe".to_string(), None, 20).await.unwrap();
+        let upload = store.get(sniffed, owner_id).await.unwrap();
+        let mut upload = upload.lock().await;
+        assert_eq!(store.completed_head(&upload, 0, b"MZ", 8).await.unwrap(), None);
+        store.append(&mut upload, 0, b"MZ").await.unwrap();
+        assert_eq!(store.completed_head(&upload, 2, b"\x90\0\x03\0\0\0\x04\0", 8).await.unwrap(), Some(b"MZ\x90\0\x03\0\0\0".to_vec()));
+        store.append(&mut upload, 2, b"\x90\0\x03\0\0\0\x04\0").await.unwrap();
+        assert_eq!(store.completed_head(&upload, 10, b"rest", 8).await.unwrap(), None);
+        drop(upload);
+        store.remove(sniffed).await;
+
+        let expiring = ResumableUploadStore::new(dir.clone(), Duration::zero());
+        expiring.create(id, owner_id, "notes.txt".to_string(), None, 5).await.unwrap();
+        assert!(expiring.get(id, owner_id).await.is_err());
+        assert_eq!(expiring.prune_expired().await, 1);
+        assert!(!dir.join(format!("{}.part", id)).exists());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -1,40 +1,142 @@
 // I am importing the necessary routing macros and types from Axum
 use axum::{
-    routing::{get, post, delete},
+    extract::DefaultBodyLimit,
+    middleware,
+    routing::{get, post, patch, delete},
     Router,
 };
+use std::sync::Arc;
+use tower_http::limit::RequestBodyLimitLayer;
 
 // I am importing all the handler functions and the application state from my handlers module
 use crate::handlers::{
-    health_check, create_scan, get_scan, list_scans, delete_scan, upload_file,
-    download_file, get_file_download_url, list_files, delete_file, cleanup_temp_files,
-    summarize_document, chat_completion, AppState,
+    health_check, readiness_check, metrics, config_options, openapi_spec, api_docs, create_scan, get_scan, reanalyze_scan, list_scans, delete_scan, delete_all_scans, search_scans, upload_file, upload_from_url,
+    init_resumable_upload, append_resumable_upload, resumable_upload_offset,
+    get_file_metadata, file_stats, rename_file, download_file, get_file_thumbnail, get_file_download_url, list_files, delete_file, restore_file, migrate_file, cleanup_temp_files,
+    summarize_document, summarize_file, ocr_file, decode_file, analyze_csv_file, chat_completion, chat_stream, chat_ws, create_embedding, usage_report, list_users, my_usage, AppState,
     // Authentication handlers
-    register, login, token_login, verify_token, get_current_user,
+    register, verify_email, login, token_login, refresh_token, forgot_password, reset_password, change_password, verify_token, get_current_user, login_history, logout, delete_current_user,
 };
+use crate::{
+    auth::{require_role, AuthService, RequireRole},
+    models::UserRole,
+    rate_limit::{enforce_rate_limit, RateLimiter},
+    telemetry::track_requests,
+};
+
+// Extra room on top of the file itself for multipart boundaries and field headers
+const MULTIPART_OVERHEAD_BYTES: usize = 64 * 1024;
+
+// Summaries accept up to 50,000 characters, which comfortably fits in 1MB of JSON
+const DEFAULT_MAX_JSON_BODY_BYTES: usize = 1024 * 1024;
+
+// I am capping request bodies on a router; oversized requests are answered with 413 before any handler runs
+fn with_body_limit<S: Clone + Send + Sync + 'static>(router: Router<S>, max_bytes: usize) -> Router<S> {
+    router
+        // Our own limit replaces axum's 2MB extractor default so the configured value is the one that applies
+        .layer(DefaultBodyLimit::disable())
+        .layer(RequestBodyLimitLayer::new(max_bytes))
+}
 
 // I am defining a function to create all the API routes for my application
-pub fn create_routes() -> Router<AppState> {
+pub fn create_routes(max_upload_bytes: u64, max_files_per_upload: usize, auth_service: Arc<AuthService>) -> Router<AppState> {
+    // I am sharing one per-IP limiter across the endpoints that call OpenAI
+    let rate_limiter = Arc::new(RateLimiter::from_env());
+    let rate_limited = || middleware::from_fn_with_state(rate_limiter.clone(), enforce_rate_limit);
+
+    // I am guarding privileged routes so only admins reach their handlers
+    let admin_only = || middleware::from_fn_with_state((auth_service.clone(), RequireRole(UserRole::Admin)), require_role);
+
+    let max_json_body_bytes = std::env::var("MAX_JSON_BODY_BYTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MAX_JSON_BODY_BYTES);
+
+    // Uploads get their own, larger limit with room for a full-size copy of every file the request may carry
+    let upload_routes = with_body_limit(
+        Router::new()
+            .route("/upload", post(upload_file))
+            .route("/upload/init", post(init_resumable_upload))
+            .route("/upload/:id", patch(append_resumable_upload).head(resumable_upload_offset)),
+        (max_upload_bytes as usize).saturating_mul(max_files_per_upload) + MULTIPART_OVERHEAD_BYTES,
+    );
+
     // I am building the router and mapping each endpoint to its handler
-    Router::new()
+    let api_routes = Router::new()
         .route("/health", get(health_check))
+        .route("/health/ready", get(readiness_check))
+        .route("/metrics", get(metrics))
+        .route("/openapi.json", get(openapi_spec))
+        .route("/docs", get(api_docs))
+        .route("/config/options", get(config_options))
         // Authentication routes
         .route("/auth/register", post(register))
+        .route("/auth/verify-email", post(verify_email))
         .route("/auth/login", post(login))
         .route("/auth/token", post(token_login))
+        .route("/auth/refresh", post(refresh_token))
+        .route("/auth/forgot-password", post(forgot_password))
+        .route("/auth/reset-password", post(reset_password))
+        .route("/auth/change-password", post(change_password))
         .route("/auth/verify", post(verify_token))
         .route("/auth/me", get(get_current_user))
+        .route("/auth/me", delete(delete_current_user))
+        .route("/auth/me/logins", get(login_history))
+        .route("/auth/logout", post(logout))
         // Existing routes
-        .route("/scans", post(create_scan))
+        .route("/scans", post(create_scan).layer(rate_limited()))
         .route("/scans", get(list_scans))
+        .route("/scans", delete(delete_all_scans))
+        .route("/scans/search", get(search_scans).layer(rate_limited()))
         .route("/scans/:id", get(get_scan))
         .route("/scans/:id", delete(delete_scan))
-        .route("/upload", post(upload_file))
+        .route("/scans/:id/reanalyze", post(reanalyze_scan).layer(rate_limited()))
+        .route("/upload/from-url", post(upload_from_url).layer(rate_limited()))
         .route("/files", get(list_files))
+        .route("/files/stats", get(file_stats))
         .route("/files/:id/download", get(download_file))
         .route("/files/:id/url", get(get_file_download_url))
+        .route("/files/:id/thumbnail", get(get_file_thumbnail))
+        .route("/files/:id", get(get_file_metadata))
         .route("/files/:id", delete(delete_file))
-        .route("/files/cleanup", post(cleanup_temp_files))
-        .route("/summarize", post(summarize_document))
-        .route("/chat/completion", post(chat_completion))
+        .route("/files/:id", patch(rename_file))
+        .route("/files/:id/migrate", post(migrate_file))
+        .route("/files/:id/restore", post(restore_file))
+        .route("/files/cleanup", post(cleanup_temp_files).layer(admin_only()))
+        .route("/summarize", post(summarize_document).layer(rate_limited()))
+        .route("/files/:id/summarize", post(summarize_file).layer(rate_limited()))
+        .route("/files/:id/ocr", post(ocr_file).layer(rate_limited()))
+        .route("/files/:id/decode", post(decode_file).layer(rate_limited()))
+        .route("/files/:id/analyze-csv", post(analyze_csv_file).layer(rate_limited()))
+        .route("/chat/completion", post(chat_completion).layer(rate_limited()))
+        .route("/chat/stream", post(chat_stream).layer(rate_limited()))
+        .route("/chat/ws", get(chat_ws).layer(rate_limited()))
+        .route("/embeddings", post(create_embedding).layer(rate_limited()))
+        .route("/usage/me", get(my_usage))
+        // Admin routes
+        .route("/admin/usage", get(usage_report).layer(admin_only()))
+        .route("/admin/users", get(list_users).layer(admin_only()));
+
+    with_body_limit(api_routes, max_json_body_bytes)
+        .merge(upload_routes)
+        .route_layer(middleware::from_fn(track_requests))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::{Request, StatusCode}};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_body_limit_rejects_oversized_requests() {
+        let app = with_body_limit(Router::new().route("/echo", post(|body: String| async move { body })), 16);
+        let request = |body: &'static str| Request::builder().method("POST").uri("/echo").body(Body::from(body)).unwrap();
+
+        let small = app.clone().oneshot(request("tiny")).await.unwrap();
+        assert_eq!(small.status(), StatusCode::OK);
+
+        let large = app.oneshot(request("this body is far too long")).await.unwrap();
+        assert_eq!(large.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
 } 
\ No newline at end of file
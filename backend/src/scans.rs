@@ -0,0 +1,234 @@
+// I am importing the concurrent map used to hold scans and the scan model
+use dashmap::DashMap;
+use uuid::Uuid;
+
+use crate::models::ScanResponse;
+
+// I am pairing each scan with the user who created it and the embedding of its data
+struct OwnedScan {
+    owner_id: Uuid,
+    scan: ScanResponse,
+    // None when embedding failed or OpenAI is not configured
+    embedding: Option<Vec<f32>>,
+}
+
+// I am defining the scan store, keeping scans in memory until a real database is wired in
+#[derive(Default)]
+pub struct ScanStore {
+    scans: DashMap<Uuid, OwnedScan>,
+}
+
+impl ScanStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // I am saving a scan, replacing any existing scan with the same id
+    pub fn insert(&self, owner_id: Uuid, scan: ScanResponse, embedding: Option<Vec<f32>>) {
+        self.scans.insert(scan.id, OwnedScan { owner_id, scan, embedding });
+    }
+
+    // I am ranking the user's embedded scans by cosine similarity to the query, best first; scans without a
+    // comparable embedding are skipped
+    pub fn search(&self, owner_id: Uuid, query: &[f32], limit: usize) -> Vec<(ScanResponse, f32)> {
+        let mut matches: Vec<(ScanResponse, f32)> = self
+            .scans
+            .iter()
+            .filter(|entry| entry.owner_id == owner_id)
+            .filter_map(|entry| {
+                let score = cosine_similarity(query, entry.embedding.as_deref()?)?;
+                Some((entry.scan.clone(), score))
+            })
+            .collect();
+        matches.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.id.cmp(&b.0.id)));
+        matches.truncate(limit);
+        matches
+    }
+
+    // I am finding one of the user's scans; anyone else's look like they don't exist
+    pub fn get(&self, owner_id: Uuid, id: &Uuid) -> Option<ScanResponse> {
+        self.scans
+            .get(id)
+            .filter(|entry| entry.owner_id == owner_id)
+            .map(|entry| entry.scan.clone())
+    }
+
+    // I am changing one of the user's scans in place, keeping its owner and embedding; None if they have no such scan
+    pub fn update(&self, owner_id: Uuid, id: &Uuid, change: impl FnOnce(&mut ScanResponse)) -> Option<ScanResponse> {
+        let mut entry = self.scans.get_mut(id).filter(|entry| entry.owner_id == owner_id)?;
+        change(&mut entry.scan);
+        Some(entry.scan.clone())
+    }
+
+    // I am listing the user's scans, oldest first, so the order is stable between calls
+    pub fn list(&self, owner_id: Uuid) -> Vec<ScanResponse> {
+        let mut scans: Vec<ScanResponse> = self
+            .scans
+            .iter()
+            .filter(|entry| entry.owner_id == owner_id)
+            .map(|entry| entry.scan.clone())
+            .collect();
+        scans.sort_by(|a, b| a.timestamp.cmp(&b.timestamp).then(a.id.cmp(&b.id)));
+        scans
+    }
+
+    pub fn remove(&self, owner_id: Uuid, id: &Uuid) -> Option<ScanResponse> {
+        self.scans
+            .remove_if(id, |_, entry| entry.owner_id == owner_id)
+            .map(|(_, entry)| entry.scan)
+    }
+
+    // I am deleting every scan the user created, returning how many were removed
+    pub fn remove_all_for_owner(&self, owner_id: Uuid) -> usize {
+        let mut removed = 0;
+        self.scans.retain(|_, entry| {
+            let keep = entry.owner_id != owner_id;
+            if !keep {
+                removed += 1;
+            }
+            keep
+        });
+        removed
+    }
+}
+
+// Vectors from different models have different lengths and can't be compared
+fn cosine_similarity(a: &[f32], b: &[f32]) -> Option<f32> {
+    if a.len() != b.len() {
+        return None;
+    }
+
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return None;
+    }
+
+    Some(dot / (norm_a * norm_b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_scan_round_trip() {
+        let store = ScanStore::new();
+        let scan = ScanResponse {
+            id: Uuid::new_v4(),
+            data: "hello".to_string(),
+            format: "text".to_string(),
+            timestamp: chrono::Utc::now(),
+            status: "analyzed".to_string(),
+            analysis: Some("a greeting".to_string()),
+            structured_analysis: None,
+            metadata: HashMap::new(),
+            codes: None,
+        };
+
+        let owner = Uuid::new_v4();
+        store.insert(owner, scan.clone(), None);
+        let fetched = store.get(owner, &scan.id).unwrap();
+        assert_eq!(fetched.data, "hello");
+        assert_eq!(fetched.analysis.as_deref(), Some("a greeting"));
+        assert_eq!(store.list(owner).len(), 1);
+
+        let updated = store.update(owner, &scan.id, |scan| scan.analysis = Some("a warm greeting".to_string())).unwrap();
+        assert_eq!(updated.analysis.as_deref(), Some("a warm greeting"));
+        assert_eq!(store.get(owner, &scan.id).unwrap().analysis, updated.analysis);
+        assert!(store.update(owner, &Uuid::new_v4(), |_| {}).is_none());
+
+        assert!(store.remove(owner, &scan.id).is_some());
+        assert!(store.get(owner, &scan.id).is_none());
+        assert!(store.list(owner).is_empty());
+    }
+
+    #[test]
+    fn test_other_users_scans_are_invisible() {
+        let store = ScanStore::new();
+        let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+        let scan = ScanResponse {
+            id: Uuid::new_v4(),
+            data: "private".to_string(),
+            format: "text".to_string(),
+            timestamp: chrono::Utc::now(),
+            status: "processed".to_string(),
+            analysis: None,
+            structured_analysis: None,
+            metadata: HashMap::new(),
+            codes: None,
+        };
+        store.insert(alice, scan.clone(), None);
+
+        assert!(store.get(bob, &scan.id).is_none());
+        assert!(store.list(bob).is_empty());
+        assert!(store.remove(bob, &scan.id).is_none());
+        assert!(store.update(bob, &scan.id, |scan| scan.analysis = Some("overwritten".to_string())).is_none());
+        // Bob's attempt left Alice's scan in place
+        let kept = store.get(alice, &scan.id).unwrap();
+        assert_eq!((kept.data.as_str(), kept.analysis), ("private", None));
+    }
+
+    #[test]
+    fn test_remove_all_for_owner_keeps_other_users_scans() {
+        let store = ScanStore::new();
+        let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+        let scan = |data: &str| ScanResponse {
+            id: Uuid::new_v4(),
+            data: data.to_string(),
+            format: "text".to_string(),
+            timestamp: chrono::Utc::now(),
+            status: "processed".to_string(),
+            analysis: None,
+            structured_analysis: None,
+            metadata: HashMap::new(),
+            codes: None,
+        };
+
+        store.insert(alice, scan("one"), None);
+        store.insert(alice, scan("two"), None);
+        store.insert(bob, scan("three"), None);
+
+        assert_eq!(store.remove_all_for_owner(alice), 2);
+        assert_eq!(store.remove_all_for_owner(alice), 0);
+        assert!(store.list(alice).is_empty());
+        let remaining = store.list(bob);
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].data, "three");
+    }
+
+    #[test]
+    fn test_search_ranks_by_cosine_similarity() {
+        let store = ScanStore::new();
+        let owner = Uuid::new_v4();
+        let scan = |data: &str| ScanResponse {
+            id: Uuid::new_v4(),
+            data: data.to_string(),
+            format: "text".to_string(),
+            timestamp: chrono::Utc::now(),
+            status: "analyzed".to_string(),
+            analysis: None,
+            structured_analysis: None,
+            metadata: HashMap::new(),
+            codes: None,
+        };
+
+        store.insert(owner, scan("close"), Some(vec![0.9, 0.1]));
+        store.insert(owner, scan("exact"), Some(vec![2.0, 0.0]));
+        store.insert(owner, scan("opposite"), Some(vec![-1.0, 0.0]));
+        store.insert(owner, scan("unembedded"), None);
+        store.insert(owner, scan("other model"), Some(vec![1.0, 0.0, 0.0]));
+        // A closer match from another user never shows up
+        store.insert(Uuid::new_v4(), scan("someone else's"), Some(vec![1.0, 0.0]));
+
+        let results = store.search(owner, &[1.0, 0.0], 10);
+        let ranked: Vec<&str> = results.iter().map(|(scan, _)| scan.data.as_str()).collect();
+        assert_eq!(ranked, vec!["exact", "close", "opposite"]);
+        assert!((results[0].1 - 1.0).abs() < 1e-6);
+        assert!((results[2].1 + 1.0).abs() < 1e-6);
+
+        assert_eq!(store.search(owner, &[1.0, 0.0], 1).len(), 1);
+    }
+}
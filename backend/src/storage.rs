@@ -1,10 +1,16 @@
 // I am importing the necessary libraries for file paths, time, serialization, async file I/O, UUIDs, and error handling
-use std::path::PathBuf;
-use chrono::Utc;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use tokio::fs;
+use std::io::SeekFrom;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use sha2::{Digest, Sha256};
 use uuid::Uuid;
 use anyhow::{Context, Result};
+use crate::{download_tokens::DownloadTokenSigner, encryption::FileCipher, error::AppError, ids::IdGenerator, s3::S3Presigner};
 
 // I am defining the structure for a stored file, including metadata and storage details
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -15,15 +21,38 @@ pub struct StoredFile {
     pub content_type: Option<String>,
     pub storage_path: String,
     pub storage_type: StorageType,
-    pub timestamp: String,
+    pub timestamp: DateTime<Utc>,
     pub download_url: Option<String>,
+    // Files without an owner predate per-user scoping and stay publicly accessible
+    #[serde(default)]
+    pub owner_id: Option<Uuid>,
+    // Hex-encoded SHA-256 of the file contents, used to spot duplicate uploads
+    #[serde(default)]
+    pub content_hash: String,
+    // Caller-defined tags such as project=alpha
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+    // Set when the file is moved to the trash; its contents stay in storage until it is purged
+    #[serde(default)]
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+impl StoredFile {
+    pub fn is_accessible_by(&self, user_id: Uuid) -> bool {
+        self.owner_id.is_none_or(|owner_id| owner_id == user_id)
+    }
+
+    pub fn is_trashed(&self) -> bool {
+        self.deleted_at.is_some()
+    }
 }
 
 // I am defining the types of storage supported by my backend
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum StorageType {
     Temporary,
     Supabase,
+    S3,
 }
 
 // I am defining the configuration for the storage service, including environment-based options
@@ -34,13 +63,71 @@ pub struct StorageConfig {
     pub supabase_url: Option<String>,
     pub supabase_key: Option<String>,
     pub supabase_bucket: Option<String>,
+    pub s3_region: Option<String>,
+    pub s3_bucket: Option<String>,
+    pub s3_access_key_id: Option<String>,
+    pub s3_secret_access_key: Option<String>,
+    pub s3_session_token: Option<String>,
+    pub s3_endpoint: Option<String>,
+    pub max_upload_bytes: u64,
+    pub max_files_per_upload: usize,
+    // Temporary files older than this are removed by cleanup
+    pub temp_file_max_age_hours: u64,
+    // How often the background cleanup runs; None leaves cleanup to POST /files/cleanup
+    pub cleanup_interval_secs: Option<u64>,
+    // Remove temporary files the registry doesn't know about (left by crashed uploads) at startup
+    pub clean_orphans_on_start: bool,
+    // Orphans younger than this are kept, in case another instance is still writing them
+    pub orphan_min_age_hours: u64,
+    // Trashed files older than this are purged from storage by cleanup
+    pub trash_retention_days: u64,
+    pub allowed_content_types: HashSet<String>,
+    // Encrypt temporary files with AES-256-GCM under encryption_key (64 hex characters)
+    pub encrypt_at_rest: bool,
+    pub encryption_key: Option<String>,
+    // HMAC key for temporary-storage download links; a random one is used when unset
+    pub download_signing_key: Option<String>,
 }
 
+pub const DEFAULT_MAX_UPLOAD_BYTES: u64 = 10 * 1024 * 1024;
+
+pub const DEFAULT_MAX_FILES_PER_UPLOAD: usize = 10;
+
+pub const DEFAULT_TEMP_FILE_MAX_AGE_HOURS: u64 = 24;
+
+pub const DEFAULT_ORPHAN_MIN_AGE_HOURS: u64 = 1;
+
+pub const DEFAULT_TRASH_RETENTION_DAYS: u64 = 30;
+
+// Content types accepted when ALLOWED_CONTENT_TYPES is not set
+const DEFAULT_ALLOWED_CONTENT_TYPES: &[&str] = &[
+    "image/jpeg",
+    "image/png",
+    "image/gif",
+    "image/webp",
+    "image/heif",
+    "application/pdf",
+    "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    "text/plain",
+];
+
+// Clients send this when they don't know the type, so it is not treated as a claim
+pub const UNKNOWN_CONTENT_TYPE: &str = "application/octet-stream";
+// How much of a streamed upload is held back to sniff its type; some signatures sit past the first few bytes
+pub const CONTENT_SNIFF_BYTES: usize = 8 * 1024;
+
+// Presigned URLs used for our own server-side S3 requests only need to live long enough to be sent
+const S3_REQUEST_EXPIRY_SECONDS: u64 = 300;
+
+// Readiness probes should answer quickly even when a backend hangs
+const READINESS_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
 impl Default for StorageConfig {
     // I am providing default configuration, reading from environment variables if available
     fn default() -> Self {
         let storage_type = match std::env::var("STORAGE_TYPE").as_deref() {
             Ok("supabase") => StorageType::Supabase,
+            Ok("s3") => StorageType::S3,
             _ => StorageType::Temporary,
         };
 
@@ -50,27 +137,255 @@ impl Default for StorageConfig {
             supabase_url: std::env::var("SUPABASE_URL").ok(),
             supabase_key: std::env::var("SUPABASE_ANON_KEY").ok(),
             supabase_bucket: std::env::var("SUPABASE_BUCKET").unwrap_or_else(|_| "uploads".to_string()).into(),
+            s3_region: std::env::var("AWS_REGION").ok(),
+            s3_bucket: std::env::var("AWS_BUCKET").ok(),
+            s3_access_key_id: std::env::var("AWS_ACCESS_KEY_ID").ok(),
+            s3_secret_access_key: std::env::var("AWS_SECRET_ACCESS_KEY").ok(),
+            s3_session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+            s3_endpoint: std::env::var("AWS_ENDPOINT_URL").ok(),
+            max_upload_bytes: std::env::var("MAX_UPLOAD_BYTES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_MAX_UPLOAD_BYTES),
+            max_files_per_upload: std::env::var("MAX_FILES_PER_UPLOAD")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_MAX_FILES_PER_UPLOAD),
+            temp_file_max_age_hours: std::env::var("TEMP_FILE_MAX_AGE_HOURS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_TEMP_FILE_MAX_AGE_HOURS),
+            cleanup_interval_secs: std::env::var("CLEANUP_INTERVAL_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|secs| *secs > 0),
+            clean_orphans_on_start: std::env::var("CLEAN_ORPHANS_ON_START")
+                .map(|v| v == "true")
+                .unwrap_or(false),
+            orphan_min_age_hours: std::env::var("ORPHAN_MIN_AGE_HOURS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_ORPHAN_MIN_AGE_HOURS),
+            trash_retention_days: std::env::var("TRASH_RETENTION_DAYS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_TRASH_RETENTION_DAYS),
+            allowed_content_types: allowed_content_types_from_env(),
+            encrypt_at_rest: std::env::var("ENCRYPT_AT_REST")
+                .map(|v| v == "true")
+                .unwrap_or(false),
+            encryption_key: std::env::var("STORAGE_ENCRYPTION_KEY").ok(),
+            download_signing_key: std::env::var("DOWNLOAD_SIGNING_KEY").ok(),
         }
     }
 }
 
+// I am building a temporary-storage config in dir with every other setting at its default, ignoring the
+// environment so tests behave the same wherever they run
+#[cfg(test)]
+pub(crate) fn test_storage_config(dir: &Path) -> StorageConfig {
+    StorageConfig {
+        storage_type: StorageType::Temporary,
+        temp_dir: Some(dir.to_path_buf()),
+        supabase_url: None,
+        supabase_key: None,
+        supabase_bucket: Some("uploads".to_string()),
+        s3_region: None,
+        s3_bucket: None,
+        s3_access_key_id: None,
+        s3_secret_access_key: None,
+        s3_session_token: None,
+        s3_endpoint: None,
+        max_upload_bytes: DEFAULT_MAX_UPLOAD_BYTES,
+        max_files_per_upload: DEFAULT_MAX_FILES_PER_UPLOAD,
+        temp_file_max_age_hours: DEFAULT_TEMP_FILE_MAX_AGE_HOURS,
+        cleanup_interval_secs: None,
+        clean_orphans_on_start: false,
+        orphan_min_age_hours: DEFAULT_ORPHAN_MIN_AGE_HOURS,
+        trash_retention_days: DEFAULT_TRASH_RETENTION_DAYS,
+        allowed_content_types: DEFAULT_ALLOWED_CONTENT_TYPES.iter().map(|content_type| content_type.to_string()).collect(),
+        encrypt_at_rest: false,
+        encryption_key: None,
+        download_signing_key: None,
+    }
+}
+
+impl StorageConfig {
+    // I am checking that the selected backend has everything it needs, so misconfiguration stops startup
+    fn validate(&self) -> crate::error::Result<()> {
+        self.validate_backend(&self.storage_type)?;
+        self.file_cipher().map(|_| ())
+    }
+
+    // I am building the at-rest cipher when encryption is on, refusing a missing or malformed key
+    fn file_cipher(&self) -> crate::error::Result<Option<FileCipher>> {
+        if !self.encrypt_at_rest {
+            return Ok(None);
+        }
+
+        let key = self.encryption_key.as_deref().ok_or_else(|| {
+            AppError::ConfigError("ENCRYPT_AT_REST is enabled but STORAGE_ENCRYPTION_KEY is not set".to_string())
+        })?;
+        FileCipher::from_hex_key(key).map(Some)
+    }
+
+    // I am checking one backend's settings, which also lets files move to a backend other than the default
+    fn validate_backend(&self, storage_type: &StorageType) -> crate::error::Result<()> {
+        let required = match storage_type {
+            StorageType::Temporary => {
+                let temp_dir = self.temp_dir.as_ref()
+                    .ok_or_else(|| AppError::ConfigError("Temporary directory not configured".to_string()))?;
+                std::fs::create_dir_all(temp_dir).map_err(|e| {
+                    AppError::ConfigError(format!("Cannot create temporary directory {}: {}", temp_dir.display(), e))
+                })?;
+                return Ok(());
+            }
+            StorageType::Supabase => vec![
+                ("SUPABASE_URL", &self.supabase_url),
+                ("SUPABASE_ANON_KEY", &self.supabase_key),
+                ("SUPABASE_BUCKET", &self.supabase_bucket),
+            ],
+            StorageType::S3 => vec![
+                ("AWS_REGION", &self.s3_region),
+                ("AWS_BUCKET", &self.s3_bucket),
+                ("AWS_ACCESS_KEY_ID", &self.s3_access_key_id),
+                ("AWS_SECRET_ACCESS_KEY", &self.s3_secret_access_key),
+            ],
+        };
+
+        let missing: Vec<&str> = required
+            .into_iter()
+            .filter(|(_, value)| value.as_deref().is_none_or(|value| value.trim().is_empty()))
+            .map(|(name, _)| name)
+            .collect();
+
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(AppError::ConfigError(format!(
+                "{:?} storage is selected but {} not set",
+                storage_type,
+                missing.join(", ")
+            )))
+        }
+    }
+}
+
+// I am reading the comma-separated upload allow-list, falling back to images, PDFs, and plain text
+fn allowed_content_types_from_env() -> HashSet<String> {
+    let allowed: HashSet<String> = std::env::var("ALLOWED_CONTENT_TYPES")
+        .unwrap_or_default()
+        .split(',')
+        .map(|content_type| content_type.trim().to_ascii_lowercase())
+        .filter(|content_type| !content_type.is_empty())
+        .collect();
+
+    if allowed.is_empty() {
+        DEFAULT_ALLOWED_CONTENT_TYPES.iter().map(|content_type| content_type.to_string()).collect()
+    } else {
+        allowed
+    }
+}
+
+// I am detecting a file's type from its leading bytes; undetected UTF-8 counts as plain text
+pub fn detect_content_type(head: &[u8]) -> String {
+    if let Some(kind) = infer::get(head) {
+        return kind.mime_type().to_string();
+    }
+
+    // A multi-byte character may be cut off at the end of the sample, which is still valid text
+    match std::str::from_utf8(head) {
+        Ok(_) => "text/plain".to_string(),
+        Err(e) if e.error_len().is_none() => "text/plain".to_string(),
+        Err(_) => UNKNOWN_CONTENT_TYPE.to_string(),
+    }
+}
+
 // I am defining the main storage service, which handles file operations for both local and Supabase storage
 pub struct StorageService {
     config: StorageConfig,
     http_client: reqwest::Client,
+    id_generator: Arc<dyn IdGenerator>,
+    // Set when ENCRYPT_AT_REST is on; only temporary files are encrypted
+    cipher: Option<FileCipher>,
+    download_signer: DownloadTokenSigner,
 }
 
 impl StorageService {
-    // I am creating a new storage service with the given configuration
-    pub fn new(config: StorageConfig) -> Result<Self> {
+    // I am creating a new storage service with the given configuration, rejecting incomplete backend settings
+    pub fn new(config: StorageConfig, id_generator: Arc<dyn IdGenerator>) -> crate::error::Result<Self> {
+        config.validate()?;
+        let cipher = config.file_cipher()?;
+        let download_signer = DownloadTokenSigner::from_key(config.download_signing_key.as_deref());
+
         let http_client = reqwest::Client::new();
 
         Ok(Self {
             config,
             http_client,
+            id_generator,
+            cipher,
+            download_signer,
         })
     }
 
+    pub fn max_upload_bytes(&self) -> u64 {
+        self.config.max_upload_bytes
+    }
+
+    pub fn max_files_per_upload(&self) -> usize {
+        self.config.max_files_per_upload.max(1)
+    }
+
+    pub fn temp_file_max_age_hours(&self) -> u64 {
+        self.config.temp_file_max_age_hours
+    }
+
+    pub fn trash_retention_days(&self) -> u64 {
+        self.config.trash_retention_days
+    }
+
+    pub fn cleanup_interval_secs(&self) -> Option<u64> {
+        self.config.cleanup_interval_secs
+    }
+
+    pub fn clean_orphans_on_start(&self) -> bool {
+        self.config.clean_orphans_on_start && matches!(self.config.storage_type, StorageType::Temporary)
+    }
+
+    // I am checking a signed download link's token, as issued by get_download_url for temporary files
+    pub fn verify_download_token(&self, file_id: Uuid, token: &str, expires_at: i64) -> crate::error::Result<()> {
+        self.download_signer.verify(file_id, token, expires_at)
+    }
+
+    // I am sniffing an upload's real type, rejecting it if it's not allowed or contradicts the client's claim
+    pub fn validate_content_type(&self, claimed: Option<&str>, head: &[u8]) -> Result<String> {
+        let detected = detect_content_type(head);
+
+        if !self.config.allowed_content_types.contains(&detected) {
+            return Err(anyhow::anyhow!("Content type {} is not allowed", detected));
+        }
+
+        let claimed = claimed
+            .and_then(|claimed| claimed.split(';').next())
+            .map(|claimed| claimed.trim().to_ascii_lowercase())
+            .filter(|claimed| !claimed.is_empty() && claimed != UNKNOWN_CONTENT_TYPE);
+
+        if let Some(claimed) = claimed {
+            // Text has no magic bytes, so any text/* claim is consistent with detected plain text
+            let consistent = claimed == detected || (detected == "text/plain" && claimed.starts_with("text/"));
+            if !consistent {
+                return Err(anyhow::anyhow!(
+                    "Declared content type {} does not match the file contents ({})",
+                    claimed,
+                    detected
+                ));
+            }
+        }
+
+        Ok(detected)
+    }
+
     // I am storing a file, delegating to the appropriate backend (temporary or Supabase)
     pub async fn store_file(
         &self,
@@ -78,29 +393,65 @@ impl StorageService {
         content_type: Option<String>,
         data: &[u8],
     ) -> Result<StoredFile> {
-        let file_id = Uuid::new_v4();
+        let file_id = self.id_generator.new_id();
+        self.store_file_in(&self.config.storage_type, file_id, filename, content_type, data, Utc::now()).await
+    }
+
+    // I am copying a file into another backend under the same id, keeping its owner, hash and upload time
+    pub async fn copy_file_to(&self, stored_file: &StoredFile, target: &StorageType) -> Result<StoredFile> {
+        let data = self.get_file(stored_file).await?;
+        let copied = self.store_file_in(
+            target,
+            stored_file.id,
+            &stored_file.filename,
+            stored_file.content_type.clone(),
+            &data,
+            stored_file.timestamp,
+        ).await?;
+
+        Ok(StoredFile {
+            owner_id: stored_file.owner_id,
+            metadata: stored_file.metadata.clone(),
+            ..copied
+        })
+    }
+
+    // I am checking that a backend other than the default is configured before anything is sent to it
+    pub fn check_backend(&self, storage_type: &StorageType) -> crate::error::Result<()> {
+        self.config.validate_backend(storage_type)
+    }
+
+    async fn store_file_in(
+        &self,
+        storage_type: &StorageType,
+        file_id: Uuid,
+        filename: &str,
+        content_type: Option<String>,
+        data: &[u8],
+        timestamp: DateTime<Utc>,
+    ) -> Result<StoredFile> {
         let file_size = data.len() as u64;
-        let timestamp = Utc::now().to_rfc3339();
 
-        match self.config.storage_type {
+        match storage_type {
             StorageType::Temporary => {
                 self.store_temporary_file(file_id, filename, content_type, data, file_size, timestamp).await
             }
             StorageType::Supabase => {
                 self.store_supabase_file(file_id, filename, content_type, data, file_size, timestamp).await
             }
+            StorageType::S3 => {
+                self.store_s3_file(file_id, filename, content_type, data, file_size, timestamp).await
+            }
         }
     }
 
-    async fn store_temporary_file(
-        &self,
-        file_id: Uuid,
-        filename: &str,
-        content_type: Option<String>,
-        data: &[u8],
-        file_size: u64,
-        timestamp: String,
-    ) -> Result<StoredFile> {
+    // Encrypted files are sealed in one piece, so uploads are only streamed to disk when encryption is off
+    pub fn streams_uploads_to_disk(&self) -> bool {
+        matches!(self.config.storage_type, StorageType::Temporary) && self.cipher.is_none()
+    }
+
+    // I am resolving where a temporary file lives, making sure the temp directory exists
+    async fn temporary_file_path(&self, file_id: Uuid, filename: &str) -> Result<PathBuf> {
         let temp_dir = self.config.temp_dir.as_ref()
             .context("Temporary directory not configured")?;
 
@@ -110,11 +461,96 @@ impl StorageService {
 
         // Generate a safe filename
         let safe_filename = format!("{}_{}", file_id, sanitize_filename(filename));
-        let file_path = temp_dir.join(&safe_filename);
+        Ok(temp_dir.join(safe_filename))
+    }
 
-        // Write the file
-        fs::write(&file_path, data).await
-            .context("Failed to write file to temporary storage")?;
+    // I am checking that the configured backend can accept files right now
+    pub async fn check_ready(&self) -> Result<()> {
+        match self.config.storage_type {
+            StorageType::Temporary => {
+                let probe_path = self.temporary_file_path(self.id_generator.new_id(), "readiness_probe").await?;
+                fs::write(&probe_path, b"ok").await
+                    .context("Temporary directory is not writable")?;
+                fs::remove_file(&probe_path).await
+                    .context("Failed to remove readiness probe file")?;
+                Ok(())
+            }
+            StorageType::Supabase => {
+                let supabase_url = self.config.supabase_url.as_ref()
+                    .context("Supabase URL not configured")?;
+                let supabase_key = self.config.supabase_key.as_ref()
+                    .context("Supabase key not configured")?;
+                let bucket = self.config.supabase_bucket.as_ref()
+                    .context("Supabase bucket not configured")?;
+
+                let bucket_url = format!("{}/storage/v1/bucket/{}", supabase_url, bucket);
+                let response = self.http_client
+                    .get(&bucket_url)
+                    .header("Authorization", format!("Bearer {}", supabase_key))
+                    .timeout(READINESS_TIMEOUT)
+                    .send()
+                    .await
+                    .context("Failed to reach Supabase")?;
+
+                if !response.status().is_success() {
+                    return Err(anyhow::anyhow!("Supabase bucket check failed with status {}", response.status()));
+                }
+                Ok(())
+            }
+            StorageType::S3 => {
+                let head_url = self.s3_presigner()?.presign("HEAD", "", S3_REQUEST_EXPIRY_SECONDS);
+                let response = self.http_client
+                    .head(&head_url)
+                    .timeout(READINESS_TIMEOUT)
+                    .send()
+                    .await
+                    .context("Failed to reach S3")?;
+
+                if !response.status().is_success() {
+                    return Err(anyhow::anyhow!("S3 bucket check failed with status {}", response.status()));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // I am opening a temporary file that an upload can be streamed into chunk by chunk
+    pub async fn begin_temporary_upload(&self, filename: &str) -> Result<TemporaryUpload> {
+        let file_id = self.id_generator.new_id();
+        let path = self.temporary_file_path(file_id, filename).await?;
+        let staging_path = staging_path(&path);
+
+        let file = fs::File::create(&staging_path).await
+            .context("Failed to create file in temporary storage")?;
+
+        Ok(TemporaryUpload {
+            file_id,
+            filename: filename.to_string(),
+            path,
+            staging_path,
+            file,
+            bytes_written: 0,
+            hasher: Sha256::new(),
+        })
+    }
+
+    async fn store_temporary_file(
+        &self,
+        file_id: Uuid,
+        filename: &str,
+        content_type: Option<String>,
+        data: &[u8],
+        file_size: u64,
+        timestamp: DateTime<Utc>,
+    ) -> Result<StoredFile> {
+        let file_path = self.temporary_file_path(file_id, filename).await?;
+
+        // Write the file, sealed with its nonce in front when encryption at rest is on
+        match &self.cipher {
+            Some(cipher) => write_atomically(&file_path, &cipher.encrypt(data)?).await,
+            None => write_atomically(&file_path, data).await,
+        }
+        .context("Failed to write file to temporary storage")?;
 
         Ok(StoredFile {
             id: file_id,
@@ -125,6 +561,10 @@ impl StorageService {
             storage_type: StorageType::Temporary,
             timestamp,
             download_url: None,
+            owner_id: None,
+            content_hash: content_hash(data),
+            metadata: HashMap::new(),
+            deleted_at: None,
         })
     }
 
@@ -135,7 +575,7 @@ impl StorageService {
         content_type: Option<String>,
         data: &[u8],
         file_size: u64,
-        timestamp: String,
+        timestamp: DateTime<Utc>,
     ) -> Result<StoredFile> {
         let supabase_url = self.config.supabase_url.as_ref()
             .context("Supabase URL not configured")?;
@@ -179,14 +619,83 @@ impl StorageService {
             storage_type: StorageType::Supabase,
             timestamp,
             download_url: Some(download_url),
+            owner_id: None,
+            content_hash: content_hash(data),
+            metadata: HashMap::new(),
+            deleted_at: None,
+        })
+    }
+
+    // I am building a presigner for the configured S3 bucket
+    fn s3_presigner(&self) -> Result<S3Presigner> {
+        Ok(S3Presigner {
+            region: self.config.s3_region.clone().context("AWS region not configured")?,
+            bucket: self.config.s3_bucket.clone().context("AWS bucket not configured")?,
+            access_key_id: self.config.s3_access_key_id.clone().context("AWS access key not configured")?,
+            secret_access_key: self.config.s3_secret_access_key.clone()
+                .context("AWS secret access key not configured")?,
+            session_token: self.config.s3_session_token.clone(),
+            endpoint: self.config.s3_endpoint.clone(),
+        })
+    }
+
+    async fn store_s3_file(
+        &self,
+        file_id: Uuid,
+        filename: &str,
+        content_type: Option<String>,
+        data: &[u8],
+        file_size: u64,
+        timestamp: DateTime<Utc>,
+    ) -> Result<StoredFile> {
+        let presigner = self.s3_presigner()?;
+
+        // Generate a unique object key
+        let storage_path = format!("{}/{}", file_id, sanitize_filename(filename));
+        let upload_url = presigner.presign("PUT", &storage_path, S3_REQUEST_EXPIRY_SECONDS);
+
+        let mut request = self.http_client
+            .put(&upload_url)
+            .body(data.to_vec());
+
+        if let Some(content_type) = &content_type {
+            request = request.header("Content-Type", content_type);
+        }
+
+        let response = request.send().await
+            .context("Failed to upload file to S3")?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(anyhow::anyhow!("S3 upload failed: {}", error_text));
+        }
+
+        Ok(StoredFile {
+            id: file_id,
+            filename: filename.to_string(),
+            file_size,
+            content_type,
+            storage_path,
+            storage_type: StorageType::S3,
+            timestamp,
+            // Objects are private, so downloads always go through a fresh presigned URL
+            download_url: None,
+            owner_id: None,
+            content_hash: content_hash(data),
+            metadata: HashMap::new(),
+            deleted_at: None,
         })
     }
 
     pub async fn get_file(&self, stored_file: &StoredFile) -> Result<Vec<u8>> {
         match stored_file.storage_type {
             StorageType::Temporary => {
-                fs::read(&stored_file.storage_path).await
-                    .context("Failed to read file from temporary storage")
+                let data = fs::read(&stored_file.storage_path).await
+                    .context("Failed to read file from temporary storage")?;
+                match &self.cipher {
+                    Some(cipher) => cipher.decrypt(&data),
+                    None => Ok(data),
+                }
             }
             StorageType::Supabase => {
                 if let Some(download_url) = &stored_file.download_url {
@@ -208,7 +717,53 @@ impl StorageService {
                     Err(anyhow::anyhow!("No download URL available for Supabase file"))
                 }
             }
+            StorageType::S3 => {
+                let download_url = self.s3_presigner()?
+                    .presign("GET", &stored_file.storage_path, S3_REQUEST_EXPIRY_SECONDS);
+
+                let response = self.http_client
+                    .get(&download_url)
+                    .send()
+                    .await
+                    .context("Failed to download file from S3")?;
+
+                if !response.status().is_success() {
+                    return Err(anyhow::anyhow!("Failed to download file: HTTP {}", response.status()));
+                }
+
+                let bytes = response.bytes().await
+                    .context("Failed to read file bytes from S3")?;
+
+                Ok(bytes.to_vec())
+            }
+        }
+    }
+
+    // I am reading an inclusive byte range of a temporary file without loading the rest of it
+    pub async fn get_file_range(&self, stored_file: &StoredFile, start: u64, end: u64) -> Result<Vec<u8>> {
+        if !matches!(stored_file.storage_type, StorageType::Temporary) {
+            return Err(anyhow::anyhow!("Range reads are only supported for temporary storage"));
+        }
+
+        // GCM authenticates the whole file, so an encrypted file is decrypted in full and then sliced
+        if self.cipher.is_some() {
+            let data = self.get_file(stored_file).await?;
+            return data
+                .get(start as usize..=end as usize)
+                .map(<[u8]>::to_vec)
+                .ok_or_else(|| anyhow::anyhow!("Range is outside the file"));
         }
+
+        let mut file = fs::File::open(&stored_file.storage_path).await
+            .context("Failed to open file from temporary storage")?;
+        file.seek(SeekFrom::Start(start)).await
+            .context("Failed to seek within temporary file")?;
+
+        let mut data = vec![0; (end - start + 1) as usize];
+        file.read_exact(&mut data).await
+            .context("Failed to read range from temporary storage")?;
+
+        Ok(data)
     }
 
     pub async fn delete_file(&self, stored_file: &StoredFile) -> Result<()> {
@@ -239,6 +794,23 @@ impl StorageService {
                     return Err(anyhow::anyhow!("Supabase delete failed: {}", error_text));
                 }
 
+                Ok(())
+            }
+            StorageType::S3 => {
+                let delete_url = self.s3_presigner()?
+                    .presign("DELETE", &stored_file.storage_path, S3_REQUEST_EXPIRY_SECONDS);
+
+                let response = self.http_client
+                    .delete(&delete_url)
+                    .send()
+                    .await
+                    .context("Failed to delete file from S3")?;
+
+                if !response.status().is_success() {
+                    let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+                    return Err(anyhow::anyhow!("S3 delete failed: {}", error_text));
+                }
+
                 Ok(())
             }
         }
@@ -247,8 +819,8 @@ impl StorageService {
     pub async fn get_download_url(&self, stored_file: &StoredFile, expires_in: u64) -> Result<String> {
         match stored_file.storage_type {
             StorageType::Temporary => {
-                // For temporary files, return the file path for internal API download
-                Ok(format!("/api/files/{}/download", stored_file.id))
+                // For temporary files, return a signed link to the internal download endpoint
+                Ok(self.download_signer.signed_path(stored_file.id, expires_in))
             }
             StorageType::Supabase => {
                 let supabase_url = self.config.supabase_url.as_ref()
@@ -289,18 +861,23 @@ impl StorageService {
 
                 Ok(format!("{}{}", supabase_url, signed_response.signed_url))
             }
+            StorageType::S3 => {
+                // Presigned GET URLs are time-limited by expires_in
+                Ok(self.s3_presigner()?.presign("GET", &stored_file.storage_path, expires_in))
+            }
         }
     }
 
-    pub async fn cleanup_expired_temp_files(&self, max_age_hours: u64) -> Result<u64> {
+    // I am deleting temporary files older than the cutoff, returning the paths that were removed
+    pub async fn cleanup_expired_temp_files(&self, max_age_hours: u64) -> Result<Vec<PathBuf>> {
         if !matches!(self.config.storage_type, StorageType::Temporary) {
-            return Ok(0);
+            return Ok(Vec::new());
         }
 
         let temp_dir = self.config.temp_dir.as_ref()
             .context("Temporary directory not configured")?;
 
-        let mut deleted_count = 0;
+        let mut deleted = Vec::new();
         let cutoff_time = Utc::now() - chrono::Duration::hours(max_age_hours as i64);
 
         let mut entries = fs::read_dir(temp_dir).await
@@ -310,40 +887,438 @@ impl StorageService {
             let metadata = entry.metadata().await?;
             if let Ok(modified) = metadata.modified() {
                 let modified_time = chrono::DateTime::<Utc>::from(modified);
-                if modified_time < cutoff_time {
-                    if fs::remove_file(entry.path()).await.is_ok() {
-                        deleted_count += 1;
-                    }
+                if modified_time < cutoff_time && fs::remove_file(entry.path()).await.is_ok() {
+                    deleted.push(entry.path());
                 }
             }
         }
 
-        Ok(deleted_count)
+        Ok(deleted)
     }
-}
 
-// Helper function to sanitize filenames
-fn sanitize_filename(filename: &str) -> String {
-    filename
-        .chars()
-        .map(|c| {
-            if c.is_alphanumeric() || c == '.' || c == '-' || c == '_' {
-                c
-            } else {
-                '_'
+    // I am deleting temporary files no registry entry points at, such as partial uploads from a crash.
+    // Only names this service generates ("<uuid>_<filename>") are touched, so other files in the directory are safe
+    pub async fn remove_orphaned_temp_files(&self, referenced: &HashSet<String>) -> Result<Vec<PathBuf>> {
+        if !matches!(self.config.storage_type, StorageType::Temporary) {
+            return Ok(Vec::new());
+        }
+
+        let temp_dir = self.config.temp_dir.as_ref()
+            .context("Temporary directory not configured")?;
+
+        let mut deleted = Vec::new();
+        let cutoff_time = Utc::now() - chrono::Duration::hours(self.config.orphan_min_age_hours as i64);
+
+        let mut entries = match fs::read_dir(temp_dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(deleted),
+            Err(e) => return Err(e).context("Failed to read temporary directory"),
+        };
+
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let generated = name
+                .split_once('_')
+                .is_some_and(|(id, _)| Uuid::parse_str(id).is_ok());
+            if !generated || referenced.contains(&name) {
+                continue;
             }
+
+            let metadata = entry.metadata().await?;
+            let old_enough = metadata
+                .modified()
+                .is_ok_and(|modified| chrono::DateTime::<Utc>::from(modified) <= cutoff_time);
+            if metadata.is_file() && old_enough && fs::remove_file(entry.path()).await.is_ok() {
+                deleted.push(entry.path());
+            }
+        }
+
+        Ok(deleted)
+    }
+}
+
+// I am defining an in-progress streamed upload into temporary storage; it is written beside its
+// final path and only renamed into place once complete
+pub struct TemporaryUpload {
+    file_id: Uuid,
+    filename: String,
+    path: PathBuf,
+    staging_path: PathBuf,
+    file: fs::File,
+    bytes_written: u64,
+    hasher: Sha256,
+}
+
+impl TemporaryUpload {
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    pub async fn write_chunk(&mut self, chunk: &[u8]) -> Result<()> {
+        self.file.write_all(chunk).await
+            .context("Failed to write file to temporary storage")?;
+        self.bytes_written += chunk.len() as u64;
+        self.hasher.update(chunk);
+        Ok(())
+    }
+
+    // I am flushing the file to disk, moving it to its final path and describing it as a stored file
+    pub async fn finish(mut self, content_type: Option<String>) -> Result<StoredFile> {
+        if let Err(e) = self.file.flush().await {
+            remove_staging_file(&self.staging_path).await;
+            return Err(e).context("Failed to flush file to temporary storage");
+        }
+        drop(self.file);
+        if let Err(e) = fs::rename(&self.staging_path, &self.path).await {
+            remove_staging_file(&self.staging_path).await;
+            return Err(e).context("Failed to move file into temporary storage");
+        }
+
+        Ok(StoredFile {
+            id: self.file_id,
+            filename: self.filename,
+            file_size: self.bytes_written,
+            content_type,
+            storage_path: self.path.to_string_lossy().to_string(),
+            storage_type: StorageType::Temporary,
+            timestamp: Utc::now(),
+            download_url: None,
+            owner_id: None,
+            content_hash: hex::encode(self.hasher.finalize()),
+            metadata: HashMap::new(),
+            deleted_at: None,
         })
-        .collect()
+    }
+
+    // I am discarding a partial upload and removing whatever was written so far
+    pub async fn abort(self) {
+        drop(self.file);
+        remove_staging_file(&self.staging_path).await;
+    }
+}
+
+// I am naming the file a write goes to before it is renamed over `path`, e.g. "<final>.tmp.<uuid>".
+// It keeps the "<uuid>_" prefix, so staging files a crash leaves behind are swept up as orphans
+fn staging_path(path: &Path) -> PathBuf {
+    let mut staging = path.as_os_str().to_owned();
+    staging.push(format!(".tmp.{}", Uuid::new_v4()));
+    PathBuf::from(staging)
+}
+
+// I am writing a whole file under a staging name and renaming it into place, so concurrent readers
+// see either nothing or the complete file, never a partial one
+async fn write_atomically(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let staging_path = staging_path(path);
+    let written = match fs::write(&staging_path, data).await {
+        Ok(()) => fs::rename(&staging_path, path).await,
+        Err(e) => Err(e),
+    };
+    if written.is_err() {
+        remove_staging_file(&staging_path).await;
+    }
+    written
+}
+
+async fn remove_staging_file(staging_path: &Path) {
+    if let Err(e) = fs::remove_file(staging_path).await {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            tracing::warn!("Failed to remove partial upload {}: {}", staging_path.display(), e);
+        }
+    }
+}
+
+// Helper function to compute the hex-encoded SHA-256 of file contents
+pub fn content_hash(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+// Leaves room for the "<uuid>_" prefix within the 255-byte name limit most filesystems share
+const MAX_SANITIZED_FILENAME_BYTES: usize = 200;
+// Longest suffix kept as an extension when a long name is shortened
+const MAX_EXTENSION_BYTES: usize = 16;
+
+// I am making a filename safe for storage paths and URLs, keeping Unicode letters, digits and accents.
+// Only separators, "..", whitespace, control and bidi-override characters, and characters reserved by
+// Windows or URLs become "_"; runs of "_" are collapsed and the result is capped in length
+pub fn sanitize_filename(filename: &str) -> String {
+    let mut sanitized = String::with_capacity(filename.len());
+    for c in filename.replace("..", "_").chars() {
+        let c = if is_unsafe_filename_char(c) { '_' } else { c };
+        if c == '_' && sanitized.ends_with('_') {
+            continue;
+        }
+        sanitized.push(c);
+    }
+    truncate_filename(sanitized, MAX_SANITIZED_FILENAME_BYTES)
+}
+
+fn is_unsafe_filename_char(c: char) -> bool {
+    c.is_control()
+        || c.is_whitespace()
+        || matches!(c, '/' | '\\' | '<' | '>' | ':' | '"' | '|' | '?' | '*' | '#' | '%')
+        // Bidi controls can make "invoice\u{202E}fdp.exe" display as "invoiceexe.pdf"
+        || matches!(c, '\u{200E}' | '\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}')
+}
+
+// I am shortening a name to at most `max_bytes`, on a character boundary, keeping a short extension
+fn truncate_filename(filename: String, max_bytes: usize) -> String {
+    if filename.len() <= max_bytes {
+        return filename;
+    }
+
+    let extension = match filename.rfind('.') {
+        Some(dot) if dot > 0 && filename.len() - dot <= MAX_EXTENSION_BYTES => &filename[dot..],
+        _ => "",
+    };
+    let stem = &filename[..filename.len() - extension.len()];
+    let mut end = max_bytes - extension.len();
+    while !stem.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{}", &stem[..end], extension)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ids::DeterministicIdGenerator;
 
     #[test]
     fn test_sanitize_filename() {
         assert_eq!(sanitize_filename("test file.txt"), "test_file.txt");
-        assert_eq!(sanitize_filename("../../../etc/passwd"), "______etc_passwd");
+        assert_eq!(sanitize_filename("../../../etc/passwd"), "_etc_passwd");
         assert_eq!(sanitize_filename("normal-file_name.jpg"), "normal-file_name.jpg");
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_sanitize_filename_keeps_unicode() {
+        assert_eq!(sanitize_filename("résumé.pdf"), "résumé.pdf");
+        // Decomposed accents are combining marks, not alphanumerics, and must survive too
+        assert_eq!(sanitize_filename("re\u{301}sume\u{301}.pdf"), "re\u{301}sume\u{301}.pdf");
+        assert_eq!(sanitize_filename("報告書 2024年.docx"), "報告書_2024年.docx");
+        assert_eq!(sanitize_filename("Отчёт (final).txt"), "Отчёт_(final).txt");
+        assert_eq!(sanitize_filename("a  b__c\0d?.txt"), "a_b_c_d_.txt");
+        assert_eq!(sanitize_filename("invoice\u{202E}fdp.exe"), "invoice_fdp.exe");
+    }
+
+    #[test]
+    fn test_sanitize_filename_caps_length_keeping_extension() {
+        let long = sanitize_filename(&format!("{}.pdf", "a".repeat(300)));
+        assert_eq!(long.len(), MAX_SANITIZED_FILENAME_BYTES);
+        assert!(long.ends_with("a.pdf"));
+
+        // Truncation never splits a multi-byte character
+        let accented = sanitize_filename(&format!("{}.pdf", "é".repeat(150)));
+        assert_eq!(accented, format!("{}.pdf", "é".repeat(98)));
+
+        let no_extension = sanitize_filename(&"文".repeat(100));
+        assert_eq!(no_extension, "文".repeat(66));
+    }
+
+    #[test]
+    fn test_stored_file_timestamp_round_trips_as_rfc3339() {
+        let stored = StoredFile {
+            id: Uuid::from_u64_pair(1, 1),
+            filename: "notes.txt".to_string(),
+            file_size: 5,
+            content_type: Some("text/plain".to_string()),
+            storage_path: "/tmp/notes.txt".to_string(),
+            storage_type: StorageType::Temporary,
+            timestamp: DateTime::parse_from_rfc3339("2024-01-01T12:00:00Z").unwrap().with_timezone(&Utc),
+            download_url: None,
+            owner_id: None,
+            content_hash: String::new(),
+            metadata: HashMap::new(),
+            deleted_at: None,
+        };
+
+        let json = serde_json::to_value(&stored).unwrap();
+        assert_eq!(json["timestamp"], "2024-01-01T12:00:00Z");
+        let parsed: StoredFile = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.timestamp, stored.timestamp);
+
+        // Registries written before the switch hold to_rfc3339() strings with an explicit offset
+        let mut legacy = serde_json::to_value(&stored).unwrap();
+        legacy["timestamp"] = "2024-01-01T12:00:00+00:00".into();
+        let parsed: StoredFile = serde_json::from_value(legacy).unwrap();
+        assert_eq!(parsed.timestamp, stored.timestamp);
+    }
+
+    #[tokio::test]
+    async fn test_store_file_uses_injected_id_generator() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = test_storage_config(temp_dir.path());
+        let service = StorageService::new(config, Arc::new(DeterministicIdGenerator::new(7))).unwrap();
+
+        let stored = service.store_file("notes.txt", None, b"hello").await.unwrap();
+        assert_eq!(stored.id, Uuid::from_u64_pair(7, 1));
+        assert!(stored.storage_path.ends_with(&format!("{}_notes.txt", stored.id)));
+        assert_eq!(
+            stored.content_hash,
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_streamed_upload_finish_and_abort() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = test_storage_config(temp_dir.path());
+        let service = StorageService::new(config, Arc::new(DeterministicIdGenerator::new(1))).unwrap();
+
+        let mut upload = service.begin_temporary_upload("report.txt").await.unwrap();
+        upload.write_chunk(b"hello ").await.unwrap();
+        upload.write_chunk(b"world").await.unwrap();
+        assert_eq!(upload.bytes_written(), 11);
+        // Nothing appears at the final path until the upload is complete
+        let (final_path, staging_path) = (upload.path.clone(), upload.staging_path.clone());
+        assert!(!final_path.exists() && staging_path.exists());
+        let stored = upload.finish(Some("text/plain".to_string())).await.unwrap();
+        assert!(final_path.exists() && !staging_path.exists());
+        assert_eq!(stored.file_size, 11);
+        assert_eq!(stored.content_hash, content_hash(b"hello world"));
+        assert_eq!(service.get_file(&stored).await.unwrap(), b"hello world");
+        assert_eq!(service.get_file_range(&stored, 6, 10).await.unwrap(), b"world");
+
+        let mut partial = service.begin_temporary_upload("partial.txt").await.unwrap();
+        partial.write_chunk(b"incomplete").await.unwrap();
+        let partial_path = partial.path.clone();
+        partial.abort().await;
+        assert!(!partial_path.exists());
+
+        // Only complete files are left in the directory, whether written whole or streamed
+        let whole = service.store_file("whole.txt", None, b"hello").await.unwrap();
+        let mut names: Vec<String> = std::fs::read_dir(temp_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        let mut expected = vec![stored.storage_path, whole.storage_path];
+        expected.sort();
+        assert_eq!(names, expected);
+    }
+
+    #[tokio::test]
+    async fn test_temporary_files_encrypted_at_rest() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = StorageConfig {
+            encrypt_at_rest: true,
+            encryption_key: Some("00112233445566778899aabbccddeeff00112233445566778899aabbccddeeff".to_string()),
+            ..test_storage_config(temp_dir.path())
+        };
+        let service = StorageService::new(config.clone(), Arc::new(DeterministicIdGenerator::new(3))).unwrap();
+        assert!(!service.streams_uploads_to_disk());
+
+        let stored = service.store_file("secret.txt", None, b"hello world").await.unwrap();
+        let on_disk = std::fs::read(&stored.storage_path).unwrap();
+        assert!(!on_disk.windows(5).any(|window| window == b"hello"));
+        assert_eq!(stored.file_size, 11);
+        assert_eq!(stored.content_hash, content_hash(b"hello world"));
+
+        assert_eq!(service.get_file(&stored).await.unwrap(), b"hello world");
+        assert_eq!(service.get_file_range(&stored, 6, 10).await.unwrap(), b"world");
+
+        let missing_key = StorageService::new(StorageConfig {
+            encryption_key: None,
+            ..config.clone()
+        }, Arc::new(DeterministicIdGenerator::new(4)));
+        assert!(matches!(missing_key, Err(AppError::ConfigError(_))));
+
+        let short_key = StorageService::new(StorageConfig {
+            encryption_key: Some("abcd".to_string()),
+            ..config
+        }, Arc::new(DeterministicIdGenerator::new(4)));
+        assert!(matches!(short_key, Err(AppError::ConfigError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_temporary_storage_readiness() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let ready = StorageService::new(test_storage_config(temp_dir.path()), Arc::new(DeterministicIdGenerator::new(1)))
+            .unwrap();
+        ready.check_ready().await.unwrap();
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+
+        // A regular file where the directory should be can never be written into
+        let blocker = temp_dir.path().join("not_a_dir");
+        let broken = StorageService::new(test_storage_config(&blocker), Arc::new(DeterministicIdGenerator::new(1))).unwrap();
+        std::fs::remove_dir(&blocker).unwrap();
+        std::fs::write(&blocker, b"x").unwrap();
+        assert!(broken.check_ready().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_copy_file_to_s3_keeps_identity() {
+        use wiremock::{matchers::{method, path}, Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        let temp_dir = tempfile::tempdir().unwrap();
+        let service = StorageService::new(StorageConfig {
+            s3_region: Some("us-east-1".to_string()),
+            s3_bucket: Some("uploads".to_string()),
+            s3_access_key_id: Some("key".to_string()),
+            s3_secret_access_key: Some("secret".to_string()),
+            s3_endpoint: Some(server.uri()),
+            supabase_url: None,
+            supabase_key: None,
+            ..test_storage_config(temp_dir.path())
+        }, Arc::new(DeterministicIdGenerator::new(3))).unwrap();
+
+        let mut original = service.store_file("notes.txt", Some("text/plain".to_string()), b"hello").await.unwrap();
+        original.owner_id = Some(Uuid::from_u64_pair(9, 9));
+        Mock::given(method("PUT"))
+            .and(path(format!("/uploads/{}/notes.txt", original.id)))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        service.check_backend(&StorageType::S3).unwrap();
+        let copied = service.copy_file_to(&original, &StorageType::S3).await.unwrap();
+        assert_eq!(copied.storage_type, StorageType::S3);
+        assert_eq!(copied.storage_path, format!("{}/notes.txt", original.id));
+        assert_eq!((copied.id, copied.owner_id, copied.timestamp), (original.id, original.owner_id, original.timestamp));
+        assert_eq!(copied.content_hash, original.content_hash);
+
+        // Supabase has no credentials here, so it can't be a target
+        assert!(matches!(service.check_backend(&StorageType::Supabase), Err(AppError::ConfigError(_))));
+    }
+
+    #[test]
+    fn test_new_rejects_incomplete_backend_config() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let supabase = StorageService::new(StorageConfig {
+            storage_type: StorageType::Supabase,
+            supabase_url: Some("https://example.supabase.co".to_string()),
+            ..test_storage_config(temp_dir.path())
+        }, Arc::new(DeterministicIdGenerator::new(1)));
+        match supabase {
+            Err(AppError::ConfigError(message)) => {
+                assert!(message.contains("SUPABASE_ANON_KEY"));
+                assert!(!message.contains("SUPABASE_URL"));
+            }
+            _ => panic!("expected a configuration error"),
+        }
+
+        // The temporary directory must be creatable, so a file in its place is rejected
+        let blocker = temp_dir.path().join("not_a_dir");
+        std::fs::write(&blocker, b"x").unwrap();
+        let temporary = StorageService::new(test_storage_config(&blocker), Arc::new(DeterministicIdGenerator::new(1)));
+        assert!(matches!(temporary, Err(AppError::ConfigError(_))));
+    }
+
+    #[test]
+    fn test_validate_content_type_sniffs_magic_bytes() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let service = StorageService::new(test_storage_config(temp_dir.path()), Arc::new(DeterministicIdGenerator::new(1))).unwrap();
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+        assert_eq!(service.validate_content_type(Some("image/png"), png).unwrap(), "image/png");
+        assert_eq!(service.validate_content_type(None, png).unwrap(), "image/png");
+        assert_eq!(service.validate_content_type(Some("application/octet-stream"), png).unwrap(), "image/png");
+        assert_eq!(service.validate_content_type(Some("text/markdown"), b"# Notes").unwrap(), "text/plain");
+
+        // Claiming a PDF while sending a PNG is rejected, as is anything off the allow-list
+        assert!(service.validate_content_type(Some("application/pdf"), png).is_err());
+        assert!(service.validate_content_type(None, b"MZ\x90\0\x03\0\0\0\x04\0\0\0\xff\xff").is_err());
+    }
+}
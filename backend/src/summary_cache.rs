@@ -0,0 +1,184 @@
+// I am importing the hashing and time types used to cache summaries of identical requests
+use chrono::{DateTime, Duration, Utc};
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, sync::Mutex};
+
+use crate::models::{SummarizeResponse, SummarizeStrategy};
+
+const DEFAULT_SUMMARY_CACHE_CAPACITY: usize = 1000;
+const DEFAULT_SUMMARY_CACHE_TTL_SECS: i64 = 3600;
+
+struct CachedSummary {
+    response: SummarizeResponse,
+    expires_at: DateTime<Utc>,
+    // Value of the store's use counter when this entry was last read or written
+    last_used: u64,
+}
+
+struct Entries {
+    summaries: HashMap<String, CachedSummary>,
+    uses: u64,
+}
+
+// I am caching summaries so re-summarizing the same document with the same settings skips the model;
+// it holds at most `capacity` entries, evicting the least recently used
+pub struct SummaryCache {
+    entries: Mutex<Entries>,
+    capacity: usize,
+    ttl: Duration,
+}
+
+impl SummaryCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            entries: Mutex::new(Entries { summaries: HashMap::new(), uses: 0 }),
+            capacity,
+            ttl,
+        }
+    }
+
+    // I am reading SUMMARY_CACHE_CAPACITY and SUMMARY_CACHE_TTL_SECS; either set to 0 turns caching off
+    pub fn from_env() -> Self {
+        Self::new(
+            std::env::var("SUMMARY_CACHE_CAPACITY")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_SUMMARY_CACHE_CAPACITY),
+            Duration::seconds(
+                std::env::var("SUMMARY_CACHE_TTL_SECS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(DEFAULT_SUMMARY_CACHE_TTL_SECS),
+            ),
+        )
+    }
+
+    fn enabled(&self) -> bool {
+        self.capacity > 0 && self.ttl > Duration::zero()
+    }
+
+    // I am hashing everything that shapes the summary, so the key stays small however long the content is
+    pub fn key(
+        content: &str,
+        max_length: usize,
+        model: &str,
+        strategy: SummarizeStrategy,
+        target_language: Option<&str>,
+    ) -> String {
+        let mut hasher = Sha256::new();
+        // Length prefixes keep ("ab", "c") and ("a", "bc") from hashing alike
+        let language = target_language.unwrap_or_default();
+        for part in [content, &max_length.to_string(), model, &format!("{:?}", strategy), language] {
+            hasher.update((part.len() as u64).to_le_bytes());
+            hasher.update(part.as_bytes());
+        }
+        hex::encode(hasher.finalize())
+    }
+
+    pub fn get(&self, key: &str) -> Option<SummarizeResponse> {
+        if !self.enabled() {
+            return None;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.uses += 1;
+        let uses = entries.uses;
+        let expired = match entries.summaries.get_mut(key) {
+            Some(cached) if cached.expires_at > Utc::now() => {
+                cached.last_used = uses;
+                return Some(cached.response.clone());
+            }
+            Some(_) => true,
+            None => false,
+        };
+        if expired {
+            entries.summaries.remove(key);
+        }
+        None
+    }
+
+    pub fn insert(&self, key: String, response: SummarizeResponse) {
+        if !self.enabled() {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.uses += 1;
+        let uses = entries.uses;
+
+        if !entries.summaries.contains_key(&key) && entries.summaries.len() >= self.capacity {
+            // Expired entries go first; otherwise the one used longest ago makes room
+            let now = Utc::now();
+            entries.summaries.retain(|_, cached| cached.expires_at > now);
+            if entries.summaries.len() >= self.capacity {
+                let oldest = entries
+                    .summaries
+                    .iter()
+                    .min_by_key(|(_, cached)| cached.last_used)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    entries.summaries.remove(&oldest);
+                }
+            }
+        }
+
+        entries.summaries.insert(
+            key,
+            CachedSummary {
+                response,
+                expires_at: Utc::now() + self.ttl,
+                last_used: uses,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TokenUsage;
+    use uuid::Uuid;
+
+    fn response(summary: &str) -> SummarizeResponse {
+        SummarizeResponse {
+            id: Uuid::new_v4(),
+            original_content: "content".to_string(),
+            summary: summary.to_string(),
+            original_length: 7,
+            summary_length: summary.len(),
+            usage: TokenUsage::default(),
+            chunks: 1,
+            cached: false,
+            target_language: None,
+            timestamp: Utc::now().to_rfc3339(),
+        }
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used_and_expired_entries() {
+        let key = |content: &str| SummaryCache::key(content, 200, "gpt-4o-mini", SummarizeStrategy::Auto, None);
+        assert_ne!(key("doc"), SummaryCache::key("doc", 300, "gpt-4o-mini", SummarizeStrategy::Auto, None));
+        assert_ne!(key("doc"), SummaryCache::key("doc", 200, "gpt-4o", SummarizeStrategy::Auto, None));
+        assert_ne!(key("doc"), SummaryCache::key("doc", 200, "gpt-4o-mini", SummarizeStrategy::Auto, Some("es")));
+
+        let cache = SummaryCache::new(2, Duration::hours(1));
+        cache.insert(key("a"), response("A"));
+        cache.insert(key("b"), response("B"));
+        // Reading "a" makes "b" the least recently used
+        assert_eq!(cache.get(&key("a")).unwrap().summary, "A");
+        cache.insert(key("c"), response("C"));
+
+        assert!(cache.get(&key("b")).is_none());
+        assert!(cache.get(&key("a")).is_some());
+        assert!(cache.get(&key("c")).is_some());
+
+        let expiring = SummaryCache::new(2, Duration::milliseconds(1));
+        expiring.insert(key("a"), response("A"));
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        assert!(expiring.get(&key("a")).is_none());
+
+        let disabled = SummaryCache::new(0, Duration::hours(1));
+        disabled.insert(key("a"), response("A"));
+        assert!(disabled.get(&key("a")).is_none());
+    }
+}
@@ -0,0 +1,223 @@
+// I am importing the Prometheus recorder and the request types the tracking middleware reads
+use axum::{
+    extract::{MatchedPath, Request},
+    http::HeaderValue,
+    middleware::Next,
+    response::Response,
+};
+use metrics::{counter, describe_counter, describe_gauge, describe_histogram, gauge, histogram, Unit};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use std::sync::OnceLock;
+use std::time::Instant;
+use tracing::{field, Instrument};
+use uuid::Uuid;
+
+use crate::models::TokenUsage;
+
+const REQUEST_DURATION: &str = "quickscan_http_request_duration_seconds";
+
+// Header carrying the id that ties a request's log lines together; echoed on every response
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+// Longer incoming ids are replaced rather than logged
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+// Seconds; the top buckets cover slow OpenAI-backed routes
+const REQUEST_DURATION_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+
+// I am installing the global Prometheus recorder on first use; counters recorded before then are dropped
+pub fn handle() -> &'static PrometheusHandle {
+    HANDLE.get_or_init(|| {
+        let handle = PrometheusBuilder::new()
+            .set_buckets_for_metric(Matcher::Full(REQUEST_DURATION.to_string()), REQUEST_DURATION_BUCKETS)
+            .expect("request duration buckets are not empty")
+            .install_recorder()
+            .expect("no other metrics recorder is installed");
+
+        describe_counter!("quickscan_http_requests_total", "HTTP requests answered, by route and status");
+        describe_histogram!(REQUEST_DURATION, Unit::Seconds, "Time spent answering HTTP requests, by route");
+        describe_counter!("quickscan_openai_requests_total", "Calls to the OpenAI API, by endpoint and outcome");
+        describe_counter!("quickscan_openai_tokens_total", "OpenAI tokens consumed, by model and kind");
+        describe_counter!("quickscan_uploads_total", "Uploaded files, by outcome");
+        describe_counter!("quickscan_upload_bytes_total", Unit::Bytes, "Bytes of newly stored uploads");
+        describe_counter!("quickscan_errors_total", "Error responses, by error type (storage_error, openai_error, ...)");
+        describe_gauge!("quickscan_openai_in_flight", "Outbound OpenAI calls currently holding a concurrency permit");
+
+        handle
+    })
+}
+
+// I am rendering every metric in the Prometheus text format, refreshing the in-flight gauges first
+pub fn render(interactive_in_flight: usize, background_in_flight: usize) -> String {
+    let handle = handle();
+    gauge!("quickscan_openai_in_flight", "pool" => "interactive").set(interactive_in_flight as f64);
+    gauge!("quickscan_openai_in_flight", "pool" => "background").set(background_in_flight as f64);
+    handle.render()
+}
+
+// I am keeping the caller's X-Request-Id when it is short printable ASCII, otherwise generating one
+fn request_id(request: &Request) -> String {
+    request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN && id.chars().all(|c| c.is_ascii_graphic()))
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string())
+}
+
+// I am counting and timing each request under its route template, so ids don't explode the label set.
+// The handler runs inside a span naming the route and request id, closed by one "request completed" line with status and latency
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let method = request.method().to_string();
+    let request_id = request_id(&request);
+    let span = tracing::info_span!(
+        "route",
+        route = %route,
+        method = %method,
+        path = %request.uri().path(),
+        request_id = %request_id,
+        status = field::Empty,
+        latency_ms = field::Empty,
+    );
+    let started = Instant::now();
+
+    let mut response = next.run(request).instrument(span.clone()).await;
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+
+    let elapsed = started.elapsed();
+    let status = response.status().as_u16();
+    span.record("status", status);
+    span.record("latency_ms", elapsed.as_secs_f64() * 1000.0);
+    span.in_scope(|| tracing::info!("request completed"));
+
+    counter!("quickscan_http_requests_total", "method" => method.clone(), "route" => route.clone(), "status" => status.to_string())
+        .increment(1);
+    histogram!(REQUEST_DURATION, "method" => method, "route" => route).record(elapsed.as_secs_f64());
+
+    response
+}
+
+// Outcome is the HTTP status OpenAI answered with, or "timeout"/"error" when no response arrived
+pub fn record_openai_request(endpoint: &str, outcome: String) {
+    counter!("quickscan_openai_requests_total", "endpoint" => endpoint.to_string(), "outcome" => outcome).increment(1);
+}
+
+pub fn record_openai_tokens(model: &str, usage: &TokenUsage) {
+    counter!("quickscan_openai_tokens_total", "model" => model.to_string(), "kind" => "prompt")
+        .increment(u64::from(usage.prompt_tokens));
+    counter!("quickscan_openai_tokens_total", "model" => model.to_string(), "kind" => "completion")
+        .increment(u64::from(usage.completion_tokens));
+}
+
+// Status is "uploaded", "deduplicated" or "failed"; only new uploads add to the byte count
+pub fn record_upload(status: &str, bytes: u64) {
+    counter!("quickscan_uploads_total", "status" => status.to_string()).increment(1);
+    if status == "uploaded" {
+        counter!("quickscan_upload_bytes_total").increment(bytes);
+    }
+}
+
+pub fn record_error(error_type: &'static str) {
+    counter!("quickscan_errors_total", "type" => error_type).increment(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::StatusCode, routing::get, Router};
+    use std::sync::{Arc, Mutex};
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_render_includes_recorded_metrics() {
+        handle();
+        record_upload("uploaded", 2048);
+        record_openai_tokens("gpt-4o-mini", &TokenUsage { prompt_tokens: 7, completion_tokens: 3, total_tokens: 10 });
+        record_error("storage_error");
+
+        let output = render(2, 1);
+        assert!(output.contains("quickscan_upload_bytes_total"));
+        assert!(output.contains(r#"quickscan_openai_tokens_total{model="gpt-4o-mini",kind="completion"}"#));
+        assert!(output.contains(r#"quickscan_errors_total{type="storage_error"}"#));
+        assert!(output.contains(r#"quickscan_openai_in_flight{pool="interactive"} 2"#));
+    }
+
+    // I am collecting formatted log output so the test can read it back
+    #[derive(Clone, Default)]
+    struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_each_request_logs_its_route_status_and_latency() {
+        let logs = CapturedLogs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let app = Router::new()
+            .route("/items/:id", get(|| async { StatusCode::NOT_FOUND }))
+            .route_layer(axum::middleware::from_fn(track_requests));
+        let request = axum::http::Request::builder().uri("/items/42").body(Body::empty()).unwrap();
+        app.oneshot(request).await.unwrap();
+
+        let output = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        let line = output.lines().find(|line| line.contains("request completed")).unwrap();
+        assert!(line.contains("route{route=/items/:id method=GET path=/items/42 request_id="), "{}", line);
+        assert!(line.contains(" status=404 latency_ms="), "{}", line);
+    }
+
+    #[tokio::test]
+    async fn test_json_logs_carry_span_fields_as_keys() {
+        let logs = CapturedLogs::default();
+        let writer = logs.clone();
+        // The same layout main.rs uses for LOG_FORMAT=json
+        let subscriber = tracing_subscriber::fmt()
+            .json()
+            .flatten_event(true)
+            .with_span_list(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let app = Router::new()
+            .route("/items/:id", get(|| async { StatusCode::OK }))
+            .route_layer(axum::middleware::from_fn(track_requests));
+        let request = axum::http::Request::builder()
+            .uri("/items/7")
+            .header(REQUEST_ID_HEADER, "abc-123")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "abc-123");
+
+        let output = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        let line = output.lines().find(|line| line.contains("request completed")).unwrap();
+        let entry: serde_json::Value = serde_json::from_str(line).unwrap();
+        assert_eq!(entry["message"], "request completed");
+        assert_eq!(entry["span"]["request_id"], "abc-123");
+        assert_eq!(entry["span"]["route"], "/items/:id");
+        assert_eq!(entry["span"]["status"], 200);
+    }
+}
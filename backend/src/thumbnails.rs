@@ -0,0 +1,150 @@
+// I am importing the image codec, path, and async file types used to build and cache thumbnails
+use image::{codecs::jpeg::JpegEncoder, ImageFormat};
+use std::io::Cursor;
+use std::path::PathBuf;
+use tokio::fs;
+use uuid::Uuid;
+
+// I am importing my own error types
+use crate::error::{AppError, Result};
+
+pub const DEFAULT_THUMBNAIL_WIDTH: u32 = 200;
+pub const MAX_THUMBNAIL_WIDTH: u32 = 1024;
+
+// I am defining a rendered thumbnail and the content type it is encoded as
+#[derive(Debug, Clone)]
+pub struct Thumbnail {
+    pub data: Vec<u8>,
+    pub content_type: &'static str,
+}
+
+impl Thumbnail {
+    fn extension(&self) -> &'static str {
+        if self.content_type == "image/jpeg" { "jpg" } else { "png" }
+    }
+}
+
+// I am resizing an image to the given width, keeping its aspect ratio and never upscaling
+pub fn render_thumbnail(data: &[u8], width: u32) -> Result<Thumbnail> {
+    let format = image::guess_format(data)
+        .map_err(|_| AppError::BadRequestError("Image format is not supported for thumbnails".to_string()))?;
+    let image = image::load_from_memory_with_format(data, format)
+        .map_err(|e| AppError::BadRequestError(format!("Image could not be decoded: {}", e)))?;
+
+    let width = width.min(image.width()).max(1);
+    let height = ((image.height() as u64 * width as u64) / image.width().max(1) as u64).max(1) as u32;
+    let resized = image.thumbnail(width, height);
+
+    // JPEG sources stay JPEG; everything else becomes PNG so transparency survives
+    let mut data = Vec::new();
+    let content_type = if format == ImageFormat::Jpeg {
+        resized
+            .to_rgb8()
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut data, 85))
+            .map_err(|e| AppError::InternalError(format!("Failed to encode thumbnail: {}", e)))?;
+        "image/jpeg"
+    } else {
+        resized
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+            .map_err(|e| AppError::InternalError(format!("Failed to encode thumbnail: {}", e)))?;
+        "image/png"
+    };
+
+    Ok(Thumbnail { data, content_type })
+}
+
+// I am caching rendered thumbnails on disk, keyed by file id and width
+pub struct ThumbnailCache {
+    dir: PathBuf,
+}
+
+impl ThumbnailCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    // I am placing the cache next to the temporary uploads unless THUMBNAIL_CACHE_DIR is set
+    pub fn dir_from_env() -> PathBuf {
+        std::env::var("THUMBNAIL_CACHE_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| std::env::temp_dir().join("quickscan_thumbnails"))
+    }
+
+    fn path(&self, file_id: Uuid, width: u32, extension: &str) -> PathBuf {
+        self.dir.join(format!("{}_{}.{}", file_id, width, extension))
+    }
+
+    pub async fn get(&self, file_id: Uuid, width: u32) -> Option<Thumbnail> {
+        for (extension, content_type) in [("jpg", "image/jpeg"), ("png", "image/png")] {
+            if let Ok(data) = fs::read(self.path(file_id, width, extension)).await {
+                return Some(Thumbnail { data, content_type });
+            }
+        }
+        None
+    }
+
+    // I am storing a thumbnail; failures only cost a re-render, so they are logged rather than returned
+    pub async fn put(&self, file_id: Uuid, width: u32, thumbnail: &Thumbnail) {
+        let path = self.path(file_id, width, thumbnail.extension());
+        let result = async {
+            fs::create_dir_all(&self.dir).await?;
+            fs::write(&path, &thumbnail.data).await
+        }
+        .await;
+
+        if let Err(e) = result {
+            tracing::warn!("Failed to cache thumbnail {}: {}", path.display(), e);
+        }
+    }
+
+    // I am removing every cached width for a file once the file itself is gone
+    pub async fn remove(&self, file_id: Uuid) {
+        let Ok(mut entries) = fs::read_dir(&self.dir).await else {
+            return;
+        };
+
+        let prefix = format!("{}_", file_id);
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            if entry.file_name().to_string_lossy().starts_with(&prefix) {
+                let _ = fs::remove_file(entry.path()).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, RgbaImage};
+
+    fn png_bytes(width: u32, height: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        DynamicImage::ImageRgba8(RgbaImage::new(width, height))
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+            .unwrap();
+        data
+    }
+
+    #[tokio::test]
+    async fn test_thumbnail_keeps_aspect_ratio_and_is_cached() {
+        let thumbnail = render_thumbnail(&png_bytes(400, 200), 100).unwrap();
+        assert_eq!(thumbnail.content_type, "image/png");
+        let decoded = image::load_from_memory(&thumbnail.data).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (100, 50));
+
+        // Small images are never upscaled
+        let small = image::load_from_memory(&render_thumbnail(&png_bytes(40, 20), 200).unwrap().data).unwrap();
+        assert_eq!(small.width(), 40);
+
+        assert!(matches!(render_thumbnail(b"not an image", 100), Err(AppError::BadRequestError(_))));
+
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ThumbnailCache::new(dir.path().to_path_buf());
+        let file_id = Uuid::new_v4();
+        assert!(cache.get(file_id, 100).await.is_none());
+        cache.put(file_id, 100, &thumbnail).await;
+        assert_eq!(cache.get(file_id, 100).await.unwrap().data, thumbnail.data);
+        cache.remove(file_id).await;
+        assert!(cache.get(file_id, 100).await.is_none());
+    }
+}
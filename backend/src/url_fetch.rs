@@ -0,0 +1,242 @@
+// I am importing the HTTP client and address types used to download files on a user's behalf
+use reqwest::{header, redirect, Client, StatusCode, Url};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use crate::error::{AppError, Result};
+
+const DEFAULT_URL_FETCH_TIMEOUT_SECS: u64 = 30;
+
+// Redirects are followed by hand so every hop goes through the same address checks
+const MAX_REDIRECTS: usize = 5;
+
+// I am defining a file downloaded from a remote URL
+#[derive(Debug)]
+pub struct FetchedFile {
+    pub filename: String,
+    // The Content-Type the remote server claimed; the bytes are still sniffed before storing
+    pub content_type: Option<String>,
+    pub data: Vec<u8>,
+}
+
+// I am defining the fetcher behind POST /upload/from-url; it only talks to public http(s) addresses,
+// so users can't make the server reach into its own network
+pub struct UrlFetcher {
+    timeout: Duration,
+}
+
+impl UrlFetcher {
+    pub fn new(timeout: Duration) -> Self {
+        Self { timeout }
+    }
+
+    // I am reading URL_FETCH_TIMEOUT_SECS, which bounds the whole download
+    pub fn from_env() -> Self {
+        Self::new(Duration::from_secs(
+            std::env::var("URL_FETCH_TIMEOUT_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_URL_FETCH_TIMEOUT_SECS),
+        ))
+    }
+
+    // I am downloading at most `max_bytes` from the URL, following a few redirects
+    pub async fn fetch(&self, url: &str, max_bytes: u64) -> Result<FetchedFile> {
+        let mut url = Url::parse(url).map_err(|e| AppError::ValidationError(format!("url: Invalid URL: {}", e)))?;
+
+        tokio::time::timeout(self.timeout, async {
+            for _ in 0..=MAX_REDIRECTS {
+                let response = self.get(&url).await?;
+                if response.status().is_redirection() {
+                    url = redirect_target(&url, &response)?;
+                    continue;
+                }
+                return read_body(url.clone(), response, max_bytes).await;
+            }
+            Err(AppError::BadRequestError(format!("URL redirected more than {} times", MAX_REDIRECTS)))
+        })
+        .await
+        .map_err(|_| AppError::ExternalServiceError(format!("Fetching the URL took longer than {} seconds", self.timeout.as_secs())))?
+    }
+
+    // I am sending one GET, pinned to an address that was checked, so DNS can't swap in a private one afterwards
+    async fn get(&self, url: &Url) -> Result<reqwest::Response> {
+        let addr = resolve_public(url).await?;
+        let host = url.host_str().unwrap_or_default();
+        let client = Client::builder()
+            .redirect(redirect::Policy::none())
+            // A proxy from HTTP(S)_PROXY would make the connection itself, bypassing the pinned address
+            .no_proxy()
+            .resolve(host, addr)
+            .build()
+            .map_err(|e| AppError::InternalError(format!("Failed to build HTTP client: {}", e)))?;
+
+        client
+            .get(url.clone())
+            .send()
+            .await
+            .map_err(|e| AppError::ExternalServiceError(format!("Failed to fetch URL: {}", e)))
+    }
+}
+
+// I am accepting only http(s) URLs whose host resolves solely to public addresses, returning one to connect to
+async fn resolve_public(url: &Url) -> Result<SocketAddr> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(AppError::ValidationError("url: Only http and https URLs can be fetched".to_string()));
+    }
+    let host = url
+        .host_str()
+        .ok_or_else(|| AppError::ValidationError("url: URL has no host".to_string()))?;
+    let port = url.port_or_known_default().unwrap_or(80);
+
+    // IPv6 literals come bracketed, which lookup_host doesn't accept
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|e| AppError::BadRequestError(format!("Could not resolve {}: {}", host, e)))?
+        .collect();
+
+    // Every address must be public, or a host listing one public and one private address could pick either
+    if addrs.is_empty() || addrs.iter().any(|addr| !is_public_ip(addr.ip())) {
+        return Err(AppError::BadRequestError("URL must point to a public address".to_string()));
+    }
+    Ok(addrs[0])
+}
+
+fn redirect_target(url: &Url, response: &reqwest::Response) -> Result<Url> {
+    let location = response
+        .headers()
+        .get(header::LOCATION)
+        .and_then(|value| value.to_str().ok())
+        .ok_or_else(|| AppError::ExternalServiceError("Redirect without a Location header".to_string()))?;
+    url.join(location)
+        .map_err(|e| AppError::ExternalServiceError(format!("Invalid redirect location: {}", e)))
+}
+
+// I am reading the body in chunks, stopping as soon as it passes the limit
+async fn read_body(url: Url, mut response: reqwest::Response, max_bytes: u64) -> Result<FetchedFile> {
+    let status = response.status();
+    if status != StatusCode::OK {
+        return Err(AppError::ExternalServiceError(format!("URL returned {}", status)));
+    }
+
+    let too_large = || AppError::ValidationError(format!("File size exceeds the {} byte upload limit", max_bytes));
+    if response.content_length().is_some_and(|length| length > max_bytes) {
+        return Err(too_large());
+    }
+
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.split(';').next().unwrap_or(value).trim().to_string())
+        .filter(|value| !value.is_empty());
+
+    let mut data = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| AppError::ExternalServiceError(format!("Failed to read URL body: {}", e)))?
+    {
+        if data.len() as u64 + chunk.len() as u64 > max_bytes {
+            return Err(too_large());
+        }
+        data.extend_from_slice(&chunk);
+    }
+
+    Ok(FetchedFile { filename: filename_from_url(&url), content_type, data })
+}
+
+// I am naming the file after the last path segment, e.g. "report.pdf" for https://host/docs/report.pdf
+fn filename_from_url(url: &Url) -> String {
+    url.path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .filter(|segment| !segment.is_empty())
+        .map(str::to_string)
+        .unwrap_or_else(|| "download".to_string())
+}
+
+// Loopback, private, link-local (including cloud metadata at 169.254.169.254), shared, and reserved ranges are all refused
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(mapped) => is_public_ipv4(mapped),
+            None => is_public_ipv6(ip),
+        },
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        || a == 0
+        // 100.64.0.0/10, carrier-grade NAT
+        || (a == 100 && (64..128).contains(&b))
+        // 198.18.0.0/15, benchmarking
+        || (a == 198 && (18..20).contains(&b))
+        // 240.0.0.0/4, reserved
+        || a >= 240)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    let segments = ip.segments();
+    let first = segments[0];
+    // 64:ff9b::/96 (NAT64) and 2002::/16 (6to4) reach the IPv4 address they embed, so that address decides
+    if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        let [.., a, b, c, d] = ip.octets();
+        return is_public_ipv4(Ipv4Addr::new(a, b, c, d));
+    }
+    if first == 0x2002 {
+        let [_, _, a, b, c, d, ..] = ip.octets();
+        return is_public_ipv4(Ipv4Addr::new(a, b, c, d));
+    }
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // fc00::/7, unique local
+        || (first & 0xfe00) == 0xfc00
+        // fe80::/10, link-local
+        || (first & 0xffc0) == 0xfe80
+        // 64:ff9b:1::/48, NAT64 for local networks
+        || (first == 0x64 && segments[1] == 0xff9b && segments[2] == 1)
+        // 2001:db8::/32, documentation
+        || (first == 0x2001 && segments[1] == 0x0db8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_only_public_http_urls_are_fetched() {
+        for ip in ["8.8.8.8", "1.1.1.1", "2606:4700:4700::1111", "64:ff9b::808:808", "2002:808:808::1"] {
+            assert!(is_public_ip(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in [
+            "127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "100.64.0.1", "0.0.0.0",
+            "::1", "fd00::1", "fe80::1", "::ffff:127.0.0.1", "64:ff9b::a9fe:a9fe", "64:ff9b::7f00:1",
+            "2002:a9fe:a9fe::1", "2002:c0a8:101::", "64:ff9b:1::808:808",
+        ] {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{}", ip);
+        }
+
+        let fetcher = UrlFetcher::new(Duration::from_secs(5));
+        for url in ["http://127.0.0.1:8080/admin", "http://localhost/", "http://[::1]/", "http://169.254.169.254/latest/meta-data"] {
+            assert!(matches!(fetcher.fetch(url, 1024).await, Err(AppError::BadRequestError(_))), "{}", url);
+        }
+        for url in ["ftp://example.com/file.txt", "file:///etc/passwd", "not a url"] {
+            assert!(matches!(fetcher.fetch(url, 1024).await, Err(AppError::ValidationError(_))), "{}", url);
+        }
+
+        let url = Url::parse("https://example.com/docs/report.pdf?v=2").unwrap();
+        assert_eq!(filename_from_url(&url), "report.pdf");
+        assert_eq!(filename_from_url(&Url::parse("https://example.com/").unwrap()), "download");
+    }
+}
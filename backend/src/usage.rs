@@ -0,0 +1,239 @@
+// I am importing the time, header, and locking types used to attribute OpenAI usage
+use axum::http::HeaderMap;
+use chrono::{DateTime, Duration, Utc};
+use dashmap::DashMap;
+use std::collections::HashMap;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+// I am importing my own auth service and models
+use crate::{
+    auth::{bearer_token, AuthService},
+    models::{TokenUsage, UsageByTag},
+    telemetry,
+};
+
+const UNATTRIBUTED_TAG: &str = "unattributed";
+
+// I am defining where the cost attribution tag for a request comes from
+#[derive(Debug, Clone)]
+pub enum CostTagSource {
+    // The value of a request header, e.g. X-Cost-Center
+    Header(String),
+    // The email domain of the authenticated user, standing in for their organisation
+    UserDomain,
+}
+
+impl CostTagSource {
+    // I am reading the tag source from the environment, defaulting to the X-Cost-Center header
+    pub fn from_env() -> Self {
+        match std::env::var("COST_TAG_SOURCE").as_deref() {
+            Ok("user_domain") => CostTagSource::UserDomain,
+            _ => CostTagSource::Header(
+                std::env::var("COST_TAG_HEADER").unwrap_or_else(|_| "X-Cost-Center".to_string()),
+            ),
+        }
+    }
+}
+
+// I am defining the USD price per 1K prompt and completion tokens for a model
+#[derive(Debug, Clone, Copy)]
+pub struct ModelPrice {
+    pub prompt_per_1k: f64,
+    pub completion_per_1k: f64,
+}
+
+// I am listing the known model prices; longer names come first so prefixes match the most specific model
+fn default_price_table() -> Vec<(&'static str, ModelPrice)> {
+    vec![
+        ("gpt-4o-mini", ModelPrice { prompt_per_1k: 0.00015, completion_per_1k: 0.0006 }),
+        ("gpt-4o", ModelPrice { prompt_per_1k: 0.0025, completion_per_1k: 0.01 }),
+        ("gpt-4-turbo", ModelPrice { prompt_per_1k: 0.01, completion_per_1k: 0.03 }),
+        ("gpt-4", ModelPrice { prompt_per_1k: 0.03, completion_per_1k: 0.06 }),
+        ("gpt-3.5-turbo", ModelPrice { prompt_per_1k: 0.0005, completion_per_1k: 0.0015 }),
+        ("text-embedding-3-small", ModelPrice { prompt_per_1k: 0.00002, completion_per_1k: 0.0 }),
+        ("text-embedding-3-large", ModelPrice { prompt_per_1k: 0.00013, completion_per_1k: 0.0 }),
+        ("text-embedding-ada-002", ModelPrice { prompt_per_1k: 0.0001, completion_per_1k: 0.0 }),
+    ]
+}
+
+// I am defining a single recorded OpenAI call
+#[derive(Debug, Clone)]
+struct UsageRecord {
+    tag: String,
+    model: String,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    recorded_at: DateTime<Utc>,
+}
+
+// I am defining the tracker that records token usage per attribution tag and per user
+pub struct UsageTracker {
+    records: RwLock<Vec<UsageRecord>>,
+    per_user: DashMap<Uuid, TokenUsage>,
+    prices: Vec<(&'static str, ModelPrice)>,
+    tag_source: CostTagSource,
+    retention: Duration,
+}
+
+impl UsageTracker {
+    pub fn new(tag_source: CostTagSource) -> Self {
+        Self {
+            records: RwLock::new(Vec::new()),
+            per_user: DashMap::new(),
+            prices: default_price_table(),
+            tag_source,
+            retention: Duration::days(30),
+        }
+    }
+
+    // I am extracting the attribution tag for a request from its headers
+    pub fn attribution_tag(&self, headers: &HeaderMap, auth_service: &AuthService) -> String {
+        let tag = match &self.tag_source {
+            CostTagSource::Header(name) => headers
+                .get(name.as_str())
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.trim().to_string()),
+            CostTagSource::UserDomain => bearer_token(headers)
+                .and_then(|token| auth_service.validate_token(token))
+                .ok()
+                .and_then(|claims| claims.email.split_once('@').map(|(_, domain)| domain.to_lowercase())),
+        };
+
+        tag.filter(|tag| !tag.is_empty())
+            .unwrap_or_else(|| UNATTRIBUTED_TAG.to_string())
+    }
+
+    // I am recording the usage of one OpenAI call against a tag, pruning records past retention
+    pub async fn record(&self, tag: &str, model: &str, usage: &TokenUsage) {
+        telemetry::record_openai_tokens(model, usage);
+
+        let now = Utc::now();
+        let mut records = self.records.write().await;
+        records.retain(|record| record.recorded_at > now - self.retention);
+        records.push(UsageRecord {
+            tag: tag.to_string(),
+            model: model.to_string(),
+            prompt_tokens: usage.prompt_tokens as u64,
+            completion_tokens: usage.completion_tokens as u64,
+            recorded_at: now,
+        });
+    }
+
+    // I am adding one OpenAI call to a user's running totals
+    pub fn record_for_user(&self, user_id: Uuid, usage: &TokenUsage) {
+        let mut totals = self.per_user.entry(user_id).or_default();
+        totals.prompt_tokens = totals.prompt_tokens.saturating_add(usage.prompt_tokens);
+        totals.completion_tokens = totals.completion_tokens.saturating_add(usage.completion_tokens);
+        totals.total_tokens = totals.total_tokens.saturating_add(usage.total_tokens);
+    }
+
+    // I am returning a user's accumulated usage, which is all zeroes if they have made no calls
+    pub fn usage_for_user(&self, user_id: Uuid) -> TokenUsage {
+        self.per_user
+            .get(&user_id)
+            .map(|totals| totals.clone())
+            .unwrap_or_default()
+    }
+
+    // I am pricing a call using the most specific matching model prefix; unknown models cost nothing
+    fn cost_of(&self, model: &str, prompt_tokens: u64, completion_tokens: u64) -> f64 {
+        self.prices
+            .iter()
+            .find(|(name, _)| model.starts_with(name))
+            .map(|(_, price)| {
+                prompt_tokens as f64 / 1000.0 * price.prompt_per_1k
+                    + completion_tokens as f64 / 1000.0 * price.completion_per_1k
+            })
+            .unwrap_or(0.0)
+    }
+
+    // I am aggregating tokens and cost per tag for every call recorded since the given time
+    pub async fn aggregate_since(&self, since: DateTime<Utc>) -> Vec<UsageByTag> {
+        let records = self.records.read().await;
+        let mut by_tag: HashMap<String, UsageByTag> = HashMap::new();
+
+        for record in records.iter().filter(|record| record.recorded_at >= since) {
+            let entry = by_tag.entry(record.tag.clone()).or_insert_with(|| UsageByTag {
+                tag: record.tag.clone(),
+                request_count: 0,
+                prompt_tokens: 0,
+                completion_tokens: 0,
+                total_tokens: 0,
+                estimated_cost_usd: 0.0,
+            });
+            entry.request_count += 1;
+            entry.prompt_tokens += record.prompt_tokens;
+            entry.completion_tokens += record.completion_tokens;
+            entry.total_tokens += record.prompt_tokens + record.completion_tokens;
+            entry.estimated_cost_usd += self.cost_of(&record.model, record.prompt_tokens, record.completion_tokens);
+        }
+
+        let mut summaries: Vec<UsageByTag> = by_tag.into_values().collect();
+        summaries.sort_by(|a, b| a.tag.cmp(&b.tag));
+        summaries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(prompt_tokens: u32, completion_tokens: u32) -> TokenUsage {
+        TokenUsage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_usage_aggregates_by_tag() {
+        let tracker = UsageTracker::new(CostTagSource::Header("X-Cost-Center".to_string()));
+        tracker.record("research", "gpt-4o-mini-2024-07-18", &usage(1000, 1000)).await;
+        tracker.record("research", "gpt-4o", &usage(1000, 0)).await;
+        tracker.record("support", "gpt-4", &usage(500, 500)).await;
+
+        let summaries = tracker.aggregate_since(Utc::now() - Duration::hours(1)).await;
+        assert_eq!(summaries.len(), 2);
+
+        let research = &summaries[0];
+        assert_eq!(research.tag, "research");
+        assert_eq!(research.request_count, 2);
+        assert_eq!(research.prompt_tokens, 2000);
+        assert_eq!(research.total_tokens, 3000);
+        assert!((research.estimated_cost_usd - (0.00015 + 0.0006 + 0.0025)).abs() < 1e-9);
+
+        let support = &summaries[1];
+        assert_eq!(support.tag, "support");
+        assert_eq!(support.total_tokens, 1000);
+        assert!((support.estimated_cost_usd - (0.015 + 0.03)).abs() < 1e-9);
+
+        assert!(tracker.aggregate_since(Utc::now() + Duration::hours(1)).await.is_empty());
+    }
+
+    #[test]
+    fn test_usage_accumulates_per_user() {
+        let tracker = UsageTracker::new(CostTagSource::UserDomain);
+        let alice = Uuid::new_v4();
+        let bob = Uuid::new_v4();
+
+        tracker.record_for_user(alice, &usage(10, 5));
+        tracker.record_for_user(alice, &usage(20, 1));
+
+        assert_eq!(tracker.usage_for_user(alice), usage(30, 6));
+        assert_eq!(tracker.usage_for_user(bob), TokenUsage::default());
+    }
+
+    #[test]
+    fn test_attribution_tag_from_header() {
+        let tracker = UsageTracker::new(CostTagSource::Header("X-Cost-Center".to_string()));
+        let auth_service = AuthService::default();
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(tracker.attribution_tag(&headers, &auth_service), "unattributed");
+
+        headers.insert("X-Cost-Center", "marketing".parse().unwrap());
+        assert_eq!(tracker.attribution_tag(&headers, &auth_service), "marketing");
+    }
+}
@@ -0,0 +1,169 @@
+// I am importing the HTTP client, serde, and the HMAC pieces used to sign webhook deliveries
+use hmac::{Hmac, Mac};
+use reqwest::Client;
+use serde::Serialize;
+use sha2::Sha256;
+use std::sync::Arc;
+use std::time::Duration;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// One delivery plus two retries
+const MAX_DELIVERY_ATTEMPTS: u32 = 3;
+
+const DEFAULT_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+pub const SIGNATURE_HEADER: &str = "X-QuickScan-Signature";
+// Unix seconds at which the delivery was sent; it is covered by the signature so old deliveries can't be replayed
+pub const TIMESTAMP_HEADER: &str = "X-QuickScan-Timestamp";
+
+// I am defining the JSON body every webhook receives
+#[derive(Debug, Serialize)]
+struct WebhookEvent<'a, T: Serialize> {
+    event: &'a str,
+    data: T,
+}
+
+// I am defining the notifier that posts upload and scan events to WEBHOOK_URL without holding up the request
+pub struct WebhookNotifier {
+    client: Client,
+    url: Option<String>,
+    secret: Option<String>,
+    retry_delay: Duration,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: Option<String>, secret: Option<String>, retry_delay: Duration) -> Self {
+        Self {
+            client: Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap_or_default(),
+            url,
+            secret,
+            retry_delay,
+        }
+    }
+
+    // I am reading the target from WEBHOOK_URL and the optional signing key from WEBHOOK_SECRET
+    pub fn from_env() -> Self {
+        Self::new(
+            std::env::var("WEBHOOK_URL").ok().filter(|url| !url.is_empty()),
+            std::env::var("WEBHOOK_SECRET").ok().filter(|secret| !secret.is_empty()),
+            DEFAULT_RETRY_DELAY,
+        )
+    }
+
+    // I am firing an event in the background; delivery failures are only logged
+    pub fn notify<T: Serialize>(self: &Arc<Self>, event: &str, data: T) {
+        if self.url.is_none() {
+            return;
+        }
+
+        let body = match serde_json::to_vec(&WebhookEvent { event, data }) {
+            Ok(body) => body,
+            Err(e) => {
+                tracing::warn!("Failed to serialize {} webhook: {}", event, e);
+                return;
+            }
+        };
+
+        let notifier = Arc::clone(self);
+        let event = event.to_string();
+        tokio::spawn(async move {
+            if let Err(e) = notifier.deliver(&body).await {
+                tracing::warn!("Giving up on {} webhook: {}", event, e);
+            }
+        });
+    }
+
+    // I am posting one event body, retrying failed attempts with a growing delay
+    async fn deliver(&self, body: &[u8]) -> anyhow::Result<()> {
+        let Some(url) = &self.url else {
+            return Ok(());
+        };
+
+        let mut attempt = 1;
+        loop {
+            let mut request = self.client
+                .post(url)
+                .header("Content-Type", "application/json")
+                .body(body.to_vec());
+            // Each attempt is stamped afresh, so a retry after a long delay still falls inside receivers' window
+            let timestamp = chrono::Utc::now().timestamp();
+            request = request.header(TIMESTAMP_HEADER, timestamp);
+            if let Some(secret) = &self.secret {
+                request = request.header(SIGNATURE_HEADER, format!("sha256={}", sign(secret, timestamp, body)));
+            }
+
+            let error = match request.send().await {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                Ok(response) => anyhow::anyhow!("webhook answered HTTP {}", response.status()),
+                Err(e) => anyhow::anyhow!("webhook request failed: {}", e),
+            };
+
+            if attempt >= MAX_DELIVERY_ATTEMPTS {
+                return Err(error);
+            }
+            tracing::warn!("{}, retrying (attempt {} of {})", error, attempt, MAX_DELIVERY_ATTEMPTS);
+            tokio::time::sleep(self.retry_delay * attempt).await;
+            attempt += 1;
+        }
+    }
+}
+
+// Receivers recompute this over "<timestamp>.<raw body>" with the shared secret to verify the sender
+fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(format!("{}.", timestamp).as_bytes());
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::{matchers::{header_exists, method}, Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_delivery_is_signed_and_retried() {
+        let server = MockServer::start().await;
+        let body = br#"{"event":"file.uploaded","data":{}}"#;
+
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(header_exists(SIGNATURE_HEADER))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let notifier = WebhookNotifier::new(Some(server.uri()), Some("shh".to_string()), Duration::from_millis(1));
+        notifier.deliver(body).await.unwrap();
+
+        // The signature covers the timestamp sent with it, so a receiver can verify both together
+        let delivered = server.received_requests().await.unwrap().pop().unwrap();
+        let timestamp: i64 = delivered.headers[TIMESTAMP_HEADER].to_str().unwrap().parse().unwrap();
+        assert!((chrono::Utc::now().timestamp() - timestamp).abs() < 60);
+        assert_eq!(
+            delivered.headers[SIGNATURE_HEADER].to_str().unwrap(),
+            format!("sha256={}", sign("shh", timestamp, body))
+        );
+        assert_ne!(sign("shh", timestamp, body), sign("shh", timestamp - 600, body));
+
+        // A receiver that never recovers exhausts the attempts and reports the last error
+        let failing = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(u64::from(MAX_DELIVERY_ATTEMPTS))
+            .mount(&failing)
+            .await;
+        let notifier = WebhookNotifier::new(Some(failing.uri()), None, Duration::from_millis(1));
+        assert!(notifier.deliver(body).await.is_err());
+    }
+}
@@ -5,8 +5,16 @@ struct APIResponse<T: Codable>: Codable {
     let success: Bool
     let data: T?
     let message: String?
+    let validationErrors: [String]?
+    let errorCode: String?
     let error: APIError?
     let timestamp: String?
+    
+    private enum CodingKeys: String, CodingKey {
+        case success, data, message, error, timestamp
+        case validationErrors = "validation_errors"
+        case errorCode = "error_code"
+    }
 }
 
 struct APIError: Codable {
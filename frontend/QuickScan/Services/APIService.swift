@@ -71,9 +71,9 @@ class APIService: ObservableObject {
             throw APIServiceError.serverError("Upload failed with status \(httpResponse.statusCode)")
         }
         
-        let apiResponse = try JSONDecoder().decode(APIResponse<UploadedFile>.self, from: responseData)
+        let apiResponse = try JSONDecoder().decode(APIResponse<[UploadedFile]>.self, from: responseData)
         
-        guard let uploadedFile = apiResponse.data else {
+        guard let uploadedFile = apiResponse.data?.first else {
             throw APIServiceError.noData
         }
         
@@ -276,9 +276,9 @@ class APIService: ObservableObject {
                         switch httpResponse.statusCode {
                         case 200...299:
                             // Success - parse response
-                            let apiResponse = try JSONDecoder().decode(APIResponse<UploadedFile>.self, from: data)
+                            let apiResponse = try JSONDecoder().decode(APIResponse<[UploadedFile]>.self, from: data)
                             
-                            guard let uploadedFile = apiResponse.data else {
+                            guard let uploadedFile = apiResponse.data?.first else {
                                 throw APIServiceError.noData
                             }
                             
@@ -92,19 +92,17 @@ class AuthService: ObservableObject {
             throw APIServiceError.serverError(message)
         }
         
-        let authResponse = try JSONDecoder().decode(APIResponse<AuthResponse>.self, from: data)
+        let registerResponse = try JSONDecoder().decode(APIResponse<User>.self, from: data)
         
-        guard let auth = authResponse.data else {
+        guard let user = registerResponse.data else {
             authState = .error("No data received")
             throw APIServiceError.noData
         }
         
-        // Save token and update state
-        saveToken(auth.token)
-        authState = .authenticated(auth.user)
-        currentUser = auth.user
+        // New accounts must verify their email before they can log in
+        authState = .unauthenticated
         
-        return auth.user
+        return user
     }
     
     func login(email: String, password: String) async throws -> User {